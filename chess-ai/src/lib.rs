@@ -6,16 +6,19 @@
 //! - 迭代加深
 //! - Zobrist 哈希
 //! - 置换表
+//! - 对局会话（跨回合复用置换表）
 //! - LLM 集成（可选，需要 `llm` feature）
 
 mod evaluate;
 mod search;
+mod session;
 mod transposition;
 mod zobrist;
 pub mod llm;
 
 pub use evaluate::Evaluator;
 pub use search::{AiEngine, AiConfig, Difficulty};
+pub use session::AiSession;
 pub use transposition::{TranspositionTable, TTEntry, EntryType, TTStats};
 pub use zobrist::ZobristTable;
 pub use llm::{LlmEngine, OllamaConfig, AiBackend, PromptTemplate};
@@ -454,6 +454,16 @@ impl AiEngine {
     pub fn clear_tt(&mut self) {
         self.tt.clear();
     }
+
+    /// 引擎配置
+    pub fn config(&self) -> &AiConfig {
+        &self.config
+    }
+
+    /// 局面的 Zobrist 哈希
+    pub fn position_hash(&self, state: &BoardState) -> u64 {
+        self.zobrist.hash(&state.board, state.current_turn)
+    }
 }
 
 #[cfg(test)]
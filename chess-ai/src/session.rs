@@ -0,0 +1,111 @@
+//! 对局会话
+//!
+//! 同一局棋的连续搜索复用同一个引擎，上一步积累的置换表在下一步继续使用。
+//! 每次搜索传入真实的对局历史；历史不再延续上次搜索的那条线时（悔棋、载入棋局、换局），
+//! 自动清空置换表重新开始
+
+use protocol::{BoardState, Difficulty, Move};
+
+use crate::search::{AiConfig, AiEngine};
+use crate::transposition::TTEntry;
+
+/// AI 对局会话
+pub struct AiSession {
+    engine: AiEngine,
+    /// 上次搜索时的对局局面哈希（按时间顺序，含当时的当前局面）
+    line: Vec<u64>,
+}
+
+impl AiSession {
+    /// 创建会话
+    pub fn new(config: AiConfig) -> Self {
+        Self {
+            engine: AiEngine::new(config),
+            line: Vec::new(),
+        }
+    }
+
+    /// 从难度创建会话
+    pub fn from_difficulty(difficulty: Difficulty) -> Self {
+        Self::new(AiConfig::from_difficulty(difficulty))
+    }
+
+    /// 从难度创建会话，置换表不超过 `max_tt_mb`（MB）
+    pub fn with_memory_limit(difficulty: Difficulty, max_tt_mb: usize) -> Self {
+        let mut config = AiConfig::from_difficulty(difficulty);
+        config.tt_size_mb = config.tt_size_mb.min(max_tt_mb.max(1));
+        Self::new(config)
+    }
+
+    /// 会话的难度
+    pub fn difficulty(&self) -> Difficulty {
+        self.engine.config().difficulty
+    }
+
+    /// 置换表占用的内存（字节）
+    pub fn memory_bytes(&self) -> usize {
+        self.engine.tt_stats().entries * std::mem::size_of::<Option<TTEntry>>()
+    }
+
+    /// 搜索最佳走法
+    ///
+    /// `history` 为当前局面之前的对局局面（按时间顺序，不含当前局面），用于检测跨回合重复
+    pub fn search(&mut self, state: &BoardState, history: &[BoardState]) -> Option<Move> {
+        let line: Vec<u64> = history
+            .iter()
+            .chain(std::iter::once(state))
+            .map(|s| self.engine.position_hash(s))
+            .collect();
+        if !line.starts_with(&self.line) {
+            self.engine.clear_tt();
+        }
+        self.line = line;
+
+        self.engine.search_with_history(state, history)
+    }
+
+    /// 清空会话（置换表与记录的对局历史）
+    pub fn reset(&mut self) {
+        self.engine.clear_tt();
+        self.line.clear();
+    }
+
+    /// 底层引擎
+    pub fn engine(&self) -> &AiEngine {
+        &self.engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 走一步后的局面
+    fn play(state: &BoardState, mv: Move) -> BoardState {
+        let mut next = state.clone();
+        next.board.move_piece(mv.from, mv.to);
+        next.switch_turn();
+        next
+    }
+
+    #[test]
+    fn test_session_keeps_table_across_moves() {
+        let mut session = AiSession::with_memory_limit(Difficulty::Easy, 1);
+        assert!(session.memory_bytes() <= 1024 * 1024);
+
+        let initial = BoardState::initial();
+        let reply = session.search(&initial, &[]).unwrap();
+        let after_reply = play(&initial, reply);
+        let first = session.engine().tt_stats().probes;
+        assert!(first > 0);
+
+        // 对局继续：置换表（及其统计）保留
+        session.search(&after_reply, std::slice::from_ref(&initial)).unwrap();
+        let second = session.engine().tt_stats().probes;
+        assert!(second > first);
+
+        // 悔棋回到之前的局面：历史不再延续，置换表清空
+        session.search(&initial, &[]);
+        assert!(session.engine().tt_stats().probes < second);
+    }
+}
@@ -1,11 +1,12 @@
 //! 本地 AI 系统
 //!
-//! 使用 Bevy 的 AsyncComputeTaskPool 在后台线程运行 AI 计算，不阻塞渲染
+//! 使用 Bevy 的 AsyncComputeTaskPool 在后台线程运行 AI 计算，不阻塞渲染。
+//! 一局棋复用同一个 AI 会话，置换表跨回合保留；悔棋或载入棋局后会话自动重置
 
 use bevy::prelude::*;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
-use chess_ai::AiEngine;
-use protocol::{BoardState, Difficulty, Move, Notation};
+use chess_ai::AiSession;
+use protocol::{BoardState, Move, Notation};
 use std::time::{Duration, Instant};
 
 use super::{ClientGame, GameMode};
@@ -15,12 +16,16 @@ use crate::GameState;
 /// AI 计算任务组件
 #[derive(Component)]
 pub struct AiComputeTask {
-    /// 异步任务
-    task: Task<Option<Move>>,
+    /// 异步任务（结果附带交还的会话）
+    task: Task<(AiSession, Option<Move>)>,
     /// 开始时间
     started_at: Instant,
 }
 
+/// 本局的 AI 会话（计算期间交给后台任务）
+#[derive(Resource, Default)]
+pub struct AiSessionSlot(Option<AiSession>);
+
 /// AI 思考状态（用于 UI 显示）
 #[derive(Resource, Default)]
 pub struct AiThinkingState {
@@ -52,6 +57,7 @@ pub struct LocalAiPlugin;
 impl Plugin for LocalAiPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(AiThinkingState::default())
+            .init_resource::<AiSessionSlot>()
             .add_message::<AiMoveEvent>()
             .add_systems(
                 Update,
@@ -73,10 +79,12 @@ fn cleanup_ai_tasks(
     mut commands: Commands,
     tasks: Query<Entity, With<AiComputeTask>>,
     mut thinking_state: ResMut<AiThinkingState>,
+    mut session: ResMut<AiSessionSlot>,
 ) {
     for entity in &tasks {
         commands.entity(entity).despawn();
     }
+    session.0 = None;
     thinking_state.is_thinking = false;
     thinking_state.started_at = None;
 }
@@ -87,6 +95,7 @@ fn trigger_ai_compute(
     mut commands: Commands,
     existing_tasks: Query<&AiComputeTask>,
     mut thinking_state: ResMut<AiThinkingState>,
+    mut session_slot: ResMut<AiSessionSlot>,
 ) {
     // 防止重复触发
     if !existing_tasks.is_empty() {
@@ -124,10 +133,17 @@ fn trigger_ai_compute(
 
     tracing::info!("AI 开始思考... 难度: {:?}, 历史局面数: {}", difficulty, history_states.len());
 
+    // 难度变化时换用新会话
+    let mut session = match session_slot.0.take() {
+        Some(session) if session.difficulty() == difficulty => session,
+        _ => AiSession::from_difficulty(difficulty),
+    };
+
     // 在后台线程池中计算（不阻塞渲染）
     let task_pool = AsyncComputeTaskPool::get();
     let task = task_pool.spawn(async move {
-        compute_ai_move(game_state, difficulty, history_states)
+        let best = session.search(&game_state, &history_states);
+        (session, best)
     });
 
     commands.spawn(AiComputeTask {
@@ -136,12 +152,6 @@ fn trigger_ai_compute(
     });
 }
 
-/// 计算 AI 走法（在后台线程运行）
-fn compute_ai_move(game_state: BoardState, difficulty: Difficulty, history_states: Vec<BoardState>) -> Option<Move> {
-    let mut engine = AiEngine::from_difficulty(difficulty);
-    engine.search_with_history(&game_state, &history_states)
-}
-
 /// 轮询 AI 结果（非阻塞）
 fn poll_ai_result(
     mut commands: Commands,
@@ -151,6 +161,7 @@ fn poll_ai_result(
     mut game: ResMut<ClientGame>,
     mut game_state: ResMut<NextState<GameState>>,
     settings: Res<GameSettings>,
+    mut session_slot: ResMut<AiSessionSlot>,
 ) {
     let ai_timeout_secs = settings.ai_timeout_secs as u64;
     
@@ -180,7 +191,8 @@ fn poll_ai_result(
         }
 
         // 任务已完成，使用 block_on 获取结果（此时不会阻塞，因为任务已完成）
-        let ai_move = bevy::tasks::block_on(&mut task_component.task);
+        let (session, ai_move) = bevy::tasks::block_on(&mut task_component.task);
+        session_slot.0 = Some(session);
         let elapsed = task_component.started_at.elapsed();
 
         // 更新思考状态
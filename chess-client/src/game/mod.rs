@@ -27,6 +27,7 @@ impl Plugin for GamePlugin {
                 Update,
                 (
                     update_local_timer,
+                    update_online_timer,
                     handle_mouse_input,
                     handle_game_events,
                     animate_pieces,
@@ -171,6 +172,24 @@ fn execute_local_move(game: &mut ResMut<ClientGame>, from: protocol::Position, t
     game.update_state(new_state, mv.from, mv.to, notation);
 }
 
+/// 在线对局的时钟走动（仅用于显示）
+///
+/// 超时由服务端判定，服务端定期推送的 TimeUpdate 会校正本地误差
+fn update_online_timer(mut game: ResMut<ClientGame>, time: Res<Time>) {
+    if game.is_local() || game.is_paused || game.game_result.is_some() {
+        return;
+    }
+    let Some(turn) = game.game_state.as_ref().map(|state| state.current_turn) else {
+        return;
+    };
+
+    let delta_ms = time.delta().as_millis() as u64;
+    match turn {
+        protocol::Side::Red => game.red_time_ms = game.red_time_ms.saturating_sub(delta_ms),
+        protocol::Side::Black => game.black_time_ms = game.black_time_ms.saturating_sub(delta_ms),
+    }
+}
+
 /// P1: 本地计时器系统（每帧更新）
 fn update_local_timer(
     mut game: ResMut<ClientGame>,
@@ -23,6 +23,10 @@ pub enum GameMode {
     OnlinePvP {
         room_id: RoomId,
     },
+    /// 观战在线对局（只读）
+    Spectating {
+        room_id: RoomId,
+    },
 }
 
 impl GameMode {
@@ -46,7 +50,7 @@ impl GameMode {
         match self {
             GameMode::LocalPvE { difficulty } => Some(*difficulty),
             GameMode::OnlinePvE { difficulty, .. } => Some(*difficulty),
-            GameMode::OnlinePvP { .. } | GameMode::LocalPvP => None,
+            GameMode::OnlinePvP { .. } | GameMode::LocalPvP | GameMode::Spectating { .. } => None,
         }
     }
 
@@ -56,6 +60,7 @@ impl GameMode {
             GameMode::LocalPvE { .. } | GameMode::LocalPvP => None,
             GameMode::OnlinePvE { room_id, .. } => Some(*room_id),
             GameMode::OnlinePvP { room_id } => Some(*room_id),
+            GameMode::Spectating { room_id } => Some(*room_id),
         }
     }
 }
@@ -81,6 +86,10 @@ pub struct ClientGame {
     pub red_time_ms: u64,
     /// 黑方剩余时间 (毫秒)
     pub black_time_ms: u64,
+    /// 红方读秒状态（在线对局）
+    pub red_byoyomi: protocol::ByoyomiState,
+    /// 黑方读秒状态（在线对局）
+    pub black_byoyomi: protocol::ByoyomiState,
     /// 棋谱记录
     pub move_history: Vec<MoveRecord>,
     /// 状态历史（用于本地悔棋）
@@ -91,6 +100,12 @@ pub struct ClientGame {
     pub waiting_undo_response: bool,
     /// 游戏结果
     pub game_result: Option<GameResult>,
+    /// 本方等级分变化（仅计分对局）
+    pub rating_change: Option<protocol::RatingChange>,
+    /// 对方提和，等待本方答复
+    pub incoming_draw_offer: bool,
+    /// 本方已提和，等待对方答复
+    pub draw_offer_sent: bool,
 }
 
 /// 走法记录
@@ -122,6 +137,9 @@ impl ClientGame {
         self.is_paused = false;
         self.waiting_undo_response = false;
         self.game_result = None;
+        self.red_byoyomi = protocol::ByoyomiState::default();
+        self.black_byoyomi = protocol::ByoyomiState::default();
+        self.clear_draw_offers();
     }
 
     /// 初始化新游戏（带自定义 FEN）
@@ -139,6 +157,57 @@ impl ClientGame {
         self.is_paused = false;
         self.waiting_undo_response = false;
         self.game_result = None;
+        self.red_byoyomi = protocol::ByoyomiState::default();
+        self.black_byoyomi = protocol::ByoyomiState::default();
+        self.clear_draw_offers();
+    }
+
+    /// 从初始局面和走法记录重建历史（用于服务端同步）
+    ///
+    /// 按服务端相同的规则重放走法，使 `move_history` 与 `state_history`
+    /// 与服务端保持一致
+    pub fn restore_history(&mut self, initial_fen: &str, moves: &[protocol::MoveRecord]) {
+        let mut state = match protocol::Fen::parse(initial_fen) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!("Invalid initial FEN from server: {:?}", e);
+                return;
+            }
+        };
+
+        self.initial_fen = Some(initial_fen.to_string());
+        self.move_history.clear();
+        self.state_history.clear();
+        self.state_history.push(state.clone());
+
+        for record in moves {
+            let (Some(from), Some(to)) = (record.from_position(), record.to_position()) else {
+                continue;
+            };
+            let captured = state.board.move_piece(from, to);
+            if captured.is_some() {
+                state.no_capture_count = 0;
+            } else {
+                state.no_capture_count += 1;
+            }
+            state.switch_turn();
+
+            self.state_history.push(state.clone());
+            self.move_history.push(MoveRecord {
+                notation: record.notation.clone(),
+                from,
+                to,
+            });
+        }
+
+        self.last_move = self.move_history.last().map(|r| (r.from, r.to));
+        self.clear_selection();
+    }
+
+    /// 开始观战（不属于任何一方，不能走棋）
+    pub fn start_spectating(&mut self, state: BoardState, room_id: RoomId) {
+        self.start_game(state, Side::Red, GameMode::Spectating { room_id });
+        self.player_side = None;
     }
 
     /// 初始化本地 PvE 游戏（无需网络）
@@ -213,6 +282,19 @@ impl ClientGame {
         self.last_move = Some((from, to));
         self.move_history.push(MoveRecord { notation, from, to });
         self.clear_selection();
+        // 未答复的提和随走棋撤回
+        self.clear_draw_offers();
+    }
+
+    /// 清除提和状态
+    pub fn clear_draw_offers(&mut self) {
+        self.incoming_draw_offer = false;
+        self.draw_offer_sent = false;
+    }
+
+    /// 是否是在线玩家对战
+    pub fn is_online_pvp(&self) -> bool {
+        matches!(self.game_mode, Some(GameMode::OnlinePvP { .. }))
     }
 
     /// 更新时间
@@ -281,6 +363,11 @@ impl ClientGame {
         self.game_mode.as_ref().map_or(false, |m| m.is_pve())
     }
 
+    /// 是否在观战
+    pub fn is_spectating(&self) -> bool {
+        matches!(self.game_mode, Some(GameMode::Spectating { .. }))
+    }
+
     /// 是否是本地模式
     pub fn is_local(&self) -> bool {
         self.game_mode.as_ref().map_or(false, |m| m.is_local())
@@ -7,7 +7,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
-use protocol::{ClientMessage, ServerMessage, TcpConnector, Connector};
+use protocol::{ClientMessage, RoomId, ServerMessage, TcpConnector, Connector};
 
 /// 连接超时时间
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
@@ -39,6 +39,8 @@ pub struct NetworkConnection {
     recv_queue: Arc<StdMutex<Vec<ServerMessage>>>,
     /// 是否正在运行
     running: Arc<AtomicBool>,
+    /// 是否由本端主动断开（区分意外断线）
+    closed: Arc<AtomicBool>,
     /// 主连接任务句柄（用于取消）
     task_handle: Arc<StdMutex<Option<JoinHandle<()>>>>,
 }
@@ -50,16 +52,28 @@ impl NetworkConnection {
             send_tx: Arc::new(StdMutex::new(None)),
             recv_queue: Arc::new(StdMutex::new(Vec::new())),
             running: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
             task_handle: Arc::new(StdMutex::new(None)),
         }
     }
 
-    /// 连接到服务器
+    /// 连接到服务器并登录
     /// 
     /// 如果已有连接，会先取消旧连接
-    pub fn connect(&self, addr: String, nickname: String) {
+    pub fn connect(&self, addr: String, nickname: String, password: Option<String>) {
+        self.start(addr, ClientMessage::Login { nickname, password });
+    }
+
+    /// 断线后重新连接，凭会话令牌回到原房间
+    pub fn reconnect(&self, addr: String, session_token: String, room_id: RoomId, last_seq: Option<u64>) {
+        self.start(addr, ClientMessage::Reconnect { session_token, room_id, last_seq });
+    }
+
+    /// 建立连接，连上后首先发送 `hello`（登录或重连消息）
+    fn start(&self, addr: String, hello: ClientMessage) {
         // 先取消旧任务
         self.abort_task();
+        self.closed.store(false, Ordering::SeqCst);
         
         // 清理旧状态
         if let Ok(mut tx) = self.send_tx.lock() {
@@ -75,7 +89,7 @@ impl NetworkConnection {
 
         // 在全局 Runtime 上 spawn 连接任务
         let handle = RUNTIME.spawn(async move {
-            if let Err(e) = connect_task(addr, nickname, send_tx, recv_queue, running).await {
+            if let Err(e) = connect_task(addr, hello, send_tx, recv_queue, running).await {
                 tracing::error!("Connection task error: {}", e);
             }
         });
@@ -89,6 +103,7 @@ impl NetworkConnection {
     /// 断开连接并清理资源
     pub fn disconnect(&self) {
         // 设置停止标志
+        self.closed.store(true, Ordering::SeqCst);
         self.running.store(false, Ordering::SeqCst);
         
         // 关闭发送通道（会导致写任务退出）
@@ -142,6 +157,11 @@ impl NetworkConnection {
         self.running.load(Ordering::SeqCst)
     }
     
+    /// 连接是否意外中断（不是本端主动断开的）
+    pub fn is_dropped(&self) -> bool {
+        !self.running.load(Ordering::SeqCst) && !self.closed.load(Ordering::SeqCst)
+    }
+
     /// 检查是否已连接
     pub fn is_connected(&self) -> bool {
         if let Ok(tx) = self.send_tx.lock() {
@@ -168,7 +188,7 @@ impl Drop for NetworkConnection {
 /// 连接任务：建立连接并启动读写循环
 async fn connect_task(
     addr: String,
-    nickname: String,
+    hello: ClientMessage,
     send_tx: Arc<StdMutex<Option<mpsc::UnboundedSender<ClientMessage>>>>,
     recv_queue: Arc<StdMutex<Vec<ServerMessage>>>,
     running: Arc<AtomicBool>,
@@ -202,8 +222,8 @@ async fn connect_task(
     
     running.store(true, Ordering::SeqCst);
     
-    // 发送登录消息
-    tx.send(ClientMessage::Login { nickname })?;
+    // 发送登录或重连消息
+    tx.send(hello)?;
     
     // 启动读写任务
     let running_write = running.clone();
@@ -237,25 +257,34 @@ async fn connect_task(
 }
 
 /// 写任务：从通道接收消息并发送到服务器
+///
+/// 同时按心跳间隔发送 Ping，避免空闲连接被服务端判定为超时
 async fn write_task(
     mut writer: protocol::FrameWriter<tokio::net::tcp::OwnedWriteHalf>,
     mut rx: mpsc::UnboundedReceiver<ClientMessage>,
     running: Arc<AtomicBool>,
 ) {
+    let mut heartbeat = tokio::time::interval(protocol::HEARTBEAT_INTERVAL);
+    // 第一次 tick 立即完成，跳过
+    heartbeat.tick().await;
+
     while running.load(Ordering::SeqCst) {
-        match rx.recv().await {
-            Some(msg) => {
-                tracing::trace!("Sending: {:?}", msg);
-                if let Err(e) = writer.send(&msg).await {
-                    tracing::error!("Failed to send message: {}", e);
-                    running.store(false, Ordering::SeqCst);
+        let msg = tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => msg,
+                None => {
+                    // 通道关闭
                     break;
                 }
-            }
-            None => {
-                // 通道关闭
-                break;
-            }
+            },
+            _ = heartbeat.tick() => ClientMessage::Ping,
+        };
+
+        tracing::trace!("Sending: {:?}", msg);
+        if let Err(e) = writer.send(&msg).await {
+            tracing::error!("Failed to send message: {}", e);
+            running.store(false, Ordering::SeqCst);
+            break;
         }
     }
     tracing::debug!("Write task ended");
@@ -8,6 +8,7 @@ pub use connection::*;
 
 use bevy::prelude::*;
 use protocol::{Position, ClientMessage, ServerMessage};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -30,6 +31,7 @@ impl Plugin for NetworkPlugin {
                     poll_network,
                     check_quick_match_timeout,
                     check_lobby_connect_timeout,
+                    check_connection_lost,
                 ),
             );
     }
@@ -48,10 +50,14 @@ pub enum PendingAction {
     #[default]
     None,
     /// 创建房间
-    CreateRoom { room_type: protocol::RoomType, preferred_side: Option<protocol::Side> },
+    CreateRoom {
+        room_type: protocol::RoomType,
+        preferred_side: Option<protocol::Side>,
+        time_control: protocol::TimeControl,
+    },
     /// 获取房间列表
     ListRooms,
-    /// 快速匹配（加入第一个可用房间或创建新房间）
+    /// 快速匹配（进入服务端匹配队列）
     QuickMatch,
 }
 
@@ -66,6 +72,12 @@ pub struct NetworkState {
     pub player_id: Option<protocol::PlayerId>,
     /// 房间 ID
     pub room_id: Option<protocol::RoomId>,
+    /// 是否为注册账号（注册账号创建的玩家对战默认计分）
+    pub registered: bool,
+    /// 会话令牌（重连时出示）
+    pub session_token: Option<String>,
+    /// 最后收到的房间事件序列号（重连时告知服务端）
+    pub last_seq: Option<u64>,
     /// 玩家昵称
     pub nickname: String,
     /// 登录成功后待执行的操作
@@ -74,22 +86,77 @@ pub struct NetworkState {
     pub current_room_type: Option<protocol::RoomType>,
     /// 房间列表（从服务器获取）
     pub room_list: Vec<protocol::RoomInfo>,
+    /// 比赛列表（从服务器获取）
+    pub tournament_list: Vec<protocol::TournamentInfo>,
+    /// 最近查看或推送的比赛详情
+    pub tournament: Option<protocol::TournamentInfo>,
+    /// 该比赛的排名
+    pub tournament_standings: Vec<protocol::TournamentStanding>,
+    /// 该比赛已配对的对局
+    pub tournament_pairings: Vec<protocol::TournamentPairing>,
     /// 是否正在快速匹配
     pub is_quick_matching: bool,
     /// 快速匹配开始时间（用于超时检测）
     pub quick_match_start: Option<Instant>,
+    /// 匹配队列中的等待人数
+    pub queue_players_waiting: u32,
+    /// 当前可接受的等级分差
+    pub queue_rating_window: u32,
+    /// 收到的聊天消息（进入新对局时清空）
+    pub chat_messages: Vec<protocol::ChatMessage>,
+    /// 已屏蔽聊天的玩家
+    pub muted_players: HashSet<protocol::PlayerId>,
+    /// 正在同步的历史对局（对局 ID -> 己方执子方）
+    pub history_pending: HashMap<u64, protocol::Side>,
+    /// 本次同步新导入的历史对局数
+    pub history_imported: u32,
     /// 连接错误信息
     pub connection_error: Option<String>,
     /// 大厅连接开始时间（用于超时检测）
     pub lobby_connect_start: Option<Instant>,
+    /// 对局中意外断线后的自动重连进度
+    pub reconnect: Option<ReconnectAttempt>,
 }
 
+/// 自动重连进度
+#[derive(Clone, Copy, Debug)]
+pub struct ReconnectAttempt {
+    /// 已尝试次数
+    pub attempts: u32,
+    /// 最近一次尝试的时间
+    pub last_attempt: Instant,
+}
+
+impl NetworkState {
+    /// 创建房间时是否申请计分（仅注册账号的玩家对战）
+    pub fn wants_rated(&self, room_type: &protocol::RoomType) -> bool {
+        self.registered && matches!(room_type, protocol::RoomType::PvP)
+    }
+
+    /// 记录聊天消息（只保留最近的若干条）
+    fn push_chat(&mut self, message: protocol::ChatMessage) {
+        if self.chat_messages.len() >= MAX_CHAT_MESSAGES {
+            self.chat_messages.remove(0);
+        }
+        self.chat_messages.push(message);
+    }
+}
+
+/// 客户端保留的聊天消息条数
+const MAX_CHAT_MESSAGES: usize = 100;
+
 /// 快速匹配超时时间（秒）
-const QUICK_MATCH_TIMEOUT_SECS: u64 = 10;
+const QUICK_MATCH_TIMEOUT_SECS: u64 = 120;
 
 /// 大厅连接超时时间（秒）
 const LOBBY_CONNECT_TIMEOUT_SECS: u64 = 10;
 
+/// 自动重连的间隔（秒）
+const RECONNECT_INTERVAL_SECS: u64 = 3;
+
+/// 自动重连的最多尝试次数（覆盖服务器重启的时间）
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
 /// 连接状态
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConnectionStatus {
@@ -104,17 +171,34 @@ pub enum ConnectionStatus {
 #[derive(Message, Clone, Debug)]
 pub enum NetworkEvent {
     /// 连接服务器
-    Connect { addr: String, nickname: String },
+    Connect {
+        addr: String,
+        nickname: String,
+        /// 账号密码（None 表示游客登录）
+        password: Option<String>,
+    },
     /// 断开连接
     Disconnect,
     /// 创建房间
-    CreateRoom { room_type: protocol::RoomType, preferred_side: Option<protocol::Side> },
+    CreateRoom {
+        room_type: protocol::RoomType,
+        preferred_side: Option<protocol::Side>,
+        time_control: protocol::TimeControl,
+    },
     /// 加入房间
     JoinRoom { room_id: protocol::RoomId },
     /// 离开房间
     LeaveRoom,
     /// 获取房间列表
     ListRooms,
+    /// 观战房间
+    SpectateRoom { room_id: protocol::RoomId },
+    /// 停止观战
+    StopSpectating,
+    /// 发送聊天消息
+    SendChat { channel: protocol::ChatChannel, text: String },
+    /// 屏蔽或取消屏蔽玩家
+    SetMuted { player_id: protocol::PlayerId, muted: bool },
     /// 发送走棋
     SendMove { from: Position, to: Position },
     /// 发送悔棋请求
@@ -123,6 +207,10 @@ pub enum NetworkEvent {
     RespondUndo { accept: bool },
     /// 发送认输
     SendResign,
+    /// 提和
+    OfferDraw,
+    /// 响应对方的提和
+    RespondDraw { accept: bool },
     /// 发送暂停
     SendPause,
     /// 发送继续
@@ -131,6 +219,8 @@ pub enum NetworkEvent {
     SaveGame,
     /// 加载游戏
     LoadGame { game_id: String },
+    /// 将服务端归档的历史对局同步到本地棋谱
+    SyncGameHistory,
 }
 
 /// 服务器消息事件（收到服务器消息后触发）
@@ -146,40 +236,55 @@ fn handle_network_events(
 ) {
     for event in events.read() {
         match event {
-            NetworkEvent::Connect { addr, nickname } => {
+            NetworkEvent::Connect { addr, nickname, password } => {
                 network.server_addr = addr.clone();
                 network.nickname = nickname.clone();
                 network.status = ConnectionStatus::Connecting;
                 network.connection_error = None;  // 清除之前的错误
+                network.reconnect = None;
                 network.lobby_connect_start = Some(Instant::now());  // 开始计时
                 game_state.set(GameState::Connecting);
                 
                 tracing::info!("Connecting to {} as {}", addr, nickname);
                 
                 // 使用全局 Runtime 连接
-                conn_handle.connection.connect(addr.clone(), nickname.clone());
+                conn_handle
+                    .connection
+                    .connect(addr.clone(), nickname.clone(), password.clone());
             }
             NetworkEvent::Disconnect => {
+                // 主动断开：放弃会话，不再自动重连
                 network.status = ConnectionStatus::Disconnected;
+                network.reconnect = None;
                 network.player_id = None;
+                network.registered = false;
+                network.session_token = None;
                 network.room_id = None;
+                network.last_seq = None;
+                network.chat_messages.clear();
+                network.muted_players.clear();
                 network.lobby_connect_start = None;  // 清除计时器
                 conn_handle.connection.disconnect();
                 game_state.set(GameState::Menu);
             }
-            NetworkEvent::CreateRoom { room_type, preferred_side } => {
+            NetworkEvent::CreateRoom { room_type, preferred_side, time_control } => {
                 // 保存房间类型
                 network.current_room_type = Some(room_type.clone());
                 
                 let msg = ClientMessage::CreateRoom {
                     room_type: room_type.clone(),
                     preferred_side: *preferred_side,
+                    rated: network.wants_rated(room_type),
+                    time_control: *time_control,
+                    rules: protocol::RuleSet::default(),
+                    initial_fen: None,
+                    access: protocol::RoomAccess::Public,
                 };
                 conn_handle.connection.queue_send(msg);
                 tracing::info!("Creating room: {:?}", room_type);
             }
             NetworkEvent::JoinRoom { room_id } => {
-                let msg = ClientMessage::JoinRoom { room_id: *room_id };
+                let msg = ClientMessage::JoinRoom { room_id: *room_id, password: None };
                 conn_handle.connection.queue_send(msg);
                 tracing::info!("Joining room: {:?}", room_id);
             }
@@ -187,11 +292,28 @@ fn handle_network_events(
                 let msg = ClientMessage::LeaveRoom;
                 conn_handle.connection.queue_send(msg);
                 network.room_id = None;
+                network.last_seq = None;
             }
             NetworkEvent::ListRooms => {
                 let msg = ClientMessage::ListRooms;
                 conn_handle.connection.queue_send(msg);
             }
+            NetworkEvent::SpectateRoom { room_id } => {
+                let msg = ClientMessage::SpectateRoom { room_id: *room_id };
+                conn_handle.connection.queue_send(msg);
+                tracing::info!("Spectating room: {:?}", room_id);
+            }
+            NetworkEvent::StopSpectating => {
+                conn_handle.connection.queue_send(ClientMessage::StopSpectating);
+            }
+            NetworkEvent::SendChat { channel, text } => {
+                let msg = ClientMessage::SendChat { channel: *channel, text: text.clone() };
+                conn_handle.connection.queue_send(msg);
+            }
+            NetworkEvent::SetMuted { player_id, muted } => {
+                let msg = ClientMessage::SetMuted { player_id: *player_id, muted: *muted };
+                conn_handle.connection.queue_send(msg);
+            }
             NetworkEvent::SendMove { from, to } => {
                 let msg = ClientMessage::MakeMove { from: *from, to: *to };
                 conn_handle.connection.queue_send(msg);
@@ -209,6 +331,13 @@ fn handle_network_events(
                 let msg = ClientMessage::Resign;
                 conn_handle.connection.queue_send(msg);
             }
+            NetworkEvent::OfferDraw => {
+                conn_handle.connection.queue_send(ClientMessage::OfferDraw);
+            }
+            NetworkEvent::RespondDraw { accept } => {
+                let msg = ClientMessage::RespondDraw { accept: *accept };
+                conn_handle.connection.queue_send(msg);
+            }
             NetworkEvent::SendPause => {
                 let msg = ClientMessage::PauseGame;
                 conn_handle.connection.queue_send(msg);
@@ -225,6 +354,12 @@ fn handle_network_events(
                 let msg = ClientMessage::LoadGame { game_id: game_id.clone() };
                 conn_handle.connection.queue_send(msg);
             }
+            NetworkEvent::SyncGameHistory => {
+                network.history_pending.clear();
+                network.history_imported = 0;
+                conn_handle.connection.queue_send(list_my_games(0));
+                tracing::info!("Syncing game history");
+            }
         }
     }
 }
@@ -239,8 +374,10 @@ fn handle_server_messages(
 ) {
     for ServerMessageEvent(msg) in events.read() {
         match msg {
-            ServerMessage::LoginSuccess { player_id } => {
+            ServerMessage::LoginSuccess { player_id, guest, session_token } => {
                 network.player_id = Some(*player_id);
+                network.registered = !*guest;
+                network.session_token = Some(session_token.clone());
                 network.status = ConnectionStatus::Connected;
                 network.lobby_connect_start = None;  // 登录成功，清除超时计时器
                 network.connection_error = None;  // 清除错误
@@ -251,13 +388,18 @@ fn handle_server_messages(
                     PendingAction::None => {
                         game_state.set(GameState::Lobby);
                     }
-                    PendingAction::CreateRoom { room_type, preferred_side } => {
+                    PendingAction::CreateRoom { room_type, preferred_side, time_control } => {
                         // 保存房间类型
                         network.current_room_type = Some(room_type.clone());
                         
                         let msg = ClientMessage::CreateRoom {
+                            rated: network.wants_rated(&room_type),
                             room_type,
                             preferred_side,
+                            time_control,
+                            rules: protocol::RuleSet::default(),
+                            initial_fen: None,
+                            access: protocol::RoomAccess::Public,
                         };
                         conn_handle.connection.queue_send(msg);
                         tracing::info!("Creating room after login");
@@ -268,12 +410,15 @@ fn handle_server_messages(
                         game_state.set(GameState::Lobby);
                     }
                     PendingAction::QuickMatch => {
-                        // 快速匹配：先获取房间列表
+                        // 快速匹配：进入服务端匹配队列，由服务端按等级分配对
                         network.is_quick_matching = true;
                         network.quick_match_start = Some(Instant::now());
-                        let msg = ClientMessage::ListRooms;
+                        let msg = ClientMessage::EnterQueue {
+                            time_control: protocol::TimeControl::default(),
+                            rated: network.registered,
+                        };
                         conn_handle.connection.queue_send(msg);
-                        tracing::info!("Quick match: fetching room list");
+                        tracing::info!("Quick match: entering queue");
                     }
                 }
             }
@@ -286,35 +431,88 @@ fn handle_server_messages(
                 game.player_side = Some(*side);
                 tracing::info!("Joined room {:?} as {:?}", room_id, side);
             }
-            ServerMessage::GameStarted { initial_state, your_side, .. } => {
-                // 将 RoomType 转换为 GameMode
-                let room_type = network.current_room_type.clone().unwrap_or(protocol::RoomType::PvP);
+            ServerMessage::GameStarted { initial_state, your_side, initial_fen, moves, .. } => {
                 let room_id = network.room_id.unwrap_or(0);
-                let game_mode = match room_type {
-                    protocol::RoomType::PvE(difficulty) => {
-                        crate::game::GameMode::OnlinePvE { room_id, difficulty }
-                    }
-                    protocol::RoomType::PvP => {
-                        crate::game::GameMode::OnlinePvP { room_id }
-                    }
-                };
+                let game_mode = online_game_mode(&network, room_id);
                 game.start_game(initial_state.clone(), *your_side, game_mode);
+                game.restore_history(initial_fen, moves);
+                network.chat_messages.clear();
                 game_state.set(GameState::Playing);
                 tracing::info!("Game started!");
             }
+            ServerMessage::ReconnectSuccess {
+                room_id,
+                game_state: snapshot,
+                your_side,
+                red_time_ms,
+                black_time_ms,
+                initial_fen,
+                moves,
+                replay_complete,
+                chat_history,
+                ..
+            } => {
+                network.room_id = Some(*room_id);
+                network.chat_messages = chat_history.clone();
+                if network.reconnect.take().is_some() {
+                    network.push_chat(system_message("已重新连接"));
+                }
+                // 重放完整时保留本地状态，错过的事件随后逐条应用；否则以快照为准
+                if !*replay_complete || game.game_state.is_none() {
+                    let game_mode = online_game_mode(&network, *room_id);
+                    game.start_game(snapshot.clone(), *your_side, game_mode);
+                    game.restore_history(initial_fen, moves);
+                }
+                game.update_time(*red_time_ms, *black_time_ms);
+                game_state.set(GameState::Playing);
+                tracing::info!("Reconnected to room {:?}", room_id);
+            }
+            ServerMessage::GameLoaded {
+                room_id,
+                game_state: loaded,
+                your_side,
+                initial_fen,
+                moves,
+                red_time_ms,
+                black_time_ms,
+            } => {
+                network.room_id = Some(*room_id);
+                let game_mode = online_game_mode(&network, *room_id);
+                game.start_game(loaded.clone(), *your_side, game_mode);
+                game.restore_history(initial_fen, moves);
+                game.update_time(*red_time_ms, *black_time_ms);
+                game_state.set(GameState::Playing);
+                tracing::info!("Game loaded into room {:?}", room_id);
+            }
             ServerMessage::MoveMade { from, to, new_state, notation } => {
                 game.update_state(new_state.clone(), *from, *to, notation.clone());
             }
-            ServerMessage::TimeUpdate { red_time_ms, black_time_ms } => {
+            ServerMessage::TimeUpdate { red_time_ms, black_time_ms, red_byoyomi, black_byoyomi } => {
                 game.update_time(*red_time_ms, *black_time_ms);
+                game.red_byoyomi = *red_byoyomi;
+                game.black_byoyomi = *black_byoyomi;
+            }
+            ServerMessage::DrawOffered { by } => {
+                if game.player_side != Some(*by) {
+                    game.incoming_draw_offer = true;
+                }
+            }
+            ServerMessage::DrawDeclined => {
+                game.draw_offer_sent = false;
+                tracing::info!("Draw offer declined");
             }
             ServerMessage::UndoApproved { new_state } => {
                 // PvE 模式悔棋 2 步（玩家+AI），PvP 模式悔棋 1 步
                 let steps = if game.is_pve() { 2 } else { 1 };
                 game.undo(new_state.clone(), steps);
             }
-            ServerMessage::GameOver { result } => {
+            ServerMessage::GameOver { result, rating_changes } => {
                 game.set_result(result.clone());
+                let player_side = game.player_side;
+                game.rating_change = rating_changes
+                    .iter()
+                    .find(|change| Some(change.side) == player_side)
+                    .copied();
                 game_state.set(GameState::GameOver);
                 tracing::info!("Game over: {:?}", result);
             }
@@ -327,37 +525,189 @@ fn handle_server_messages(
             ServerMessage::RoomList { rooms } => {
                 tracing::info!("Received room list: {} rooms", rooms.len());
                 network.room_list = rooms.clone();
-                
-                // 如果正在快速匹配，自动加入或创建房间
-                if network.is_quick_matching {
-                    network.is_quick_matching = false;
-                    network.quick_match_start = None;  // 清除超时计时器
-                    
-                    // 查找等待中的 PvP 房间
-                    let waiting_room = rooms.iter().find(|r| {
-                        matches!(r.room_type, protocol::RoomType::PvP) && 
-                        r.state == protocol::RoomState::Waiting
-                    });
-                    
-                    if let Some(room) = waiting_room {
-                        // 加入已有房间
-                        let msg = ClientMessage::JoinRoom { room_id: room.id };
-                        conn_handle.connection.queue_send(msg);
-                        tracing::info!("Quick match: joining room {:?}", room.id);
-                    } else {
-                        // 没有可用房间，创建新房间
-                        network.current_room_type = Some(protocol::RoomType::PvP);
-                        let msg = ClientMessage::CreateRoom {
-                            room_type: protocol::RoomType::PvP,
-                            preferred_side: None,
-                        };
-                        conn_handle.connection.queue_send(msg);
-                        tracing::info!("Quick match: creating new room");
+            }
+            ServerMessage::TournamentList { tournaments } => {
+                tracing::info!("Received tournament list: {} tournaments", tournaments.len());
+                network.tournament_list = tournaments.clone();
+            }
+            ServerMessage::TournamentDetails { tournament, standings, pairings } => {
+                tracing::info!(
+                    "Tournament {} ({:?}): round {}/{}",
+                    tournament.name,
+                    tournament.status,
+                    tournament.current_round,
+                    tournament.total_rounds
+                );
+                network.tournament = Some(tournament.clone());
+                network.tournament_standings = standings.clone();
+                network.tournament_pairings = pairings.clone();
+            }
+            ServerMessage::QueueStatus { players_waiting, rating_window, .. } => {
+                network.queue_players_waiting = *players_waiting;
+                network.queue_rating_window = *rating_window;
+            }
+            ServerMessage::SpectateStarted {
+                room_id,
+                game_state: snapshot,
+                red_time_ms,
+                black_time_ms,
+                initial_fen,
+                moves,
+                last_seq,
+                chat_history,
+                ..
+            } => {
+                network.room_id = Some(*room_id);
+                network.last_seq = Some(*last_seq);
+                network.chat_messages = chat_history.clone();
+                game.start_spectating(snapshot.clone(), *room_id);
+                game.restore_history(initial_fen, moves);
+                game.update_time(*red_time_ms, *black_time_ms);
+                game_state.set(GameState::Playing);
+                tracing::info!("Spectating room {:?}", room_id);
+            }
+            ServerMessage::SpectateStopped { room_id } => {
+                network.room_id = None;
+                network.last_seq = None;
+                // 对局已结束时停留在结算界面
+                if game.game_result.is_none() {
+                    game.reset();
+                    conn_handle.connection.queue_send(ClientMessage::ListRooms);
+                    game_state.set(GameState::Lobby);
+                }
+                tracing::info!("Stopped spectating room {:?}", room_id);
+            }
+            ServerMessage::RoomClosed { room_id, reason } => {
+                network.room_id = None;
+                network.last_seq = None;
+                game.reset();
+                network.push_chat(system_message(reason));
+                conn_handle.connection.queue_send(ClientMessage::ListRooms);
+                game_state.set(GameState::Lobby);
+                tracing::info!("Room {:?} closed by server: {}", room_id, reason);
+            }
+            ServerMessage::Chat(message) => {
+                network.push_chat(message.clone());
+            }
+            ServerMessage::MuteUpdated { player_id, muted } => {
+                if *muted {
+                    network.muted_players.insert(*player_id);
+                } else {
+                    network.muted_players.remove(player_id);
+                }
+                // 屏蔽后同时隐藏已收到的消息
+                let muted_players = network.muted_players.clone();
+                network.chat_messages.retain(|m| !muted_players.contains(&m.from_id));
+            }
+            ServerMessage::MatchFound { room_id, your_side } => {
+                network.is_quick_matching = false;
+                network.quick_match_start = None;  // 清除超时计时器
+                network.room_id = Some(*room_id);
+                network.current_room_type = Some(protocol::RoomType::PvP);
+                game.player_side = Some(*your_side);
+                tracing::info!("Quick match: matched into room {:?} as {:?}", room_id, your_side);
+            }
+            ServerMessage::MyGames { games, page, total } => {
+                let storage = match crate::storage::StorageManager::new() {
+                    Ok(storage) => storage,
+                    Err(e) => {
+                        tracing::error!("初始化存储失败: {}", e);
+                        continue;
+                    }
+                };
+                // 只获取本地还没有的对局
+                for summary in games {
+                    if !storage.has_archived_game(summary.game_id) {
+                        network.history_pending.insert(summary.game_id, summary.your_side);
+                        conn_handle.connection.queue_send(ClientMessage::FetchGame { game_id: summary.game_id });
                     }
                 }
+                if (*page as u64 + 1) * (protocol::MAX_HISTORY_PAGE_SIZE as u64) < *total as u64 {
+                    conn_handle.connection.queue_send(list_my_games(page + 1));
+                }
+            }
+            ServerMessage::GameFetched { game_id, record_json } => {
+                let Some(side) = network.history_pending.remove(game_id) else {
+                    continue;
+                };
+                let imported = crate::storage::StorageManager::new()
+                    .and_then(|storage| storage.import_archived_game(*game_id, record_json, side));
+                match imported {
+                    Ok(_) => network.history_imported += 1,
+                    Err(e) => tracing::error!("导入历史对局 {} 失败: {}", game_id, e),
+                }
+                if network.history_pending.is_empty() {
+                    tracing::info!("Game history synced: {} new games", network.history_imported);
+                }
             }
             ServerMessage::Error { code, message } => {
                 tracing::error!("Server error {:?}: {}", code, message);
+                // 重连被拒绝（会话失效或对局已结束），无法恢复
+                if network.reconnect.is_some() {
+                    conn_handle.connection.disconnect();
+                    connection_lost(&mut network, &mut game, &mut game_state, message);
+                    continue;
+                }
+                // 提和被服务端拒绝（频率或次数限制）
+                if game.draw_offer_sent
+                    && matches!(code, protocol::ErrorCode::DrawOfferNotAllowed | protocol::ErrorCode::RateLimited)
+                {
+                    game.draw_offer_sent = false;
+                }
+            }
+            ServerMessage::Pong => {}
+            ServerMessage::Announcement { text } => {
+                tracing::info!("Server announcement: {}", text);
+                network.push_chat(system_message(text));
+            }
+            ServerMessage::ServerRestarting { grace_secs } => {
+                tracing::info!("Server restarting, games kept for {}s", grace_secs);
+                let text = format!("服务器即将重启，对局已保存，请在 {} 秒内重新连接", grace_secs);
+                network.push_chat(system_message(&text));
+            }
+            ServerMessage::ChallengeReceived { challenge } => {
+                let kind = if challenge.rated { "计分" } else { "不计分" };
+                let text = format!("{} 向你发起{}挑战（{} 秒内有效）", challenge.challenger, kind, challenge.expires_in_secs);
+                network.push_chat(system_message(&text));
+            }
+            ServerMessage::ChallengeClosed { reason, .. } => {
+                network.push_chat(system_message(reason));
+            }
+            ServerMessage::FriendRequestReceived { nickname } => {
+                let text = format!("{} 请求加你为好友", nickname);
+                network.push_chat(system_message(&text));
+            }
+            ServerMessage::CorrespondenceUpdate { game: update, .. } => {
+                let opponent = match update.your_side {
+                    protocol::Side::Red => &update.black_player,
+                    protocol::Side::Black => &update.red_player,
+                };
+                let text = if update.result.is_some() {
+                    format!("与 {} 的通信对局 #{} 已结束", opponent, update.id)
+                } else if update.invited_by.is_some_and(|side| side != update.your_side) {
+                    format!("{} 邀请你下通信对局（每步 {} 天）", opponent, update.days_per_move)
+                } else if update.invited_by.is_none() && update.game_state.current_turn == update.your_side {
+                    format!("与 {} 的通信对局 #{} 轮到你走棋", opponent, update.id)
+                } else {
+                    continue;
+                };
+                network.push_chat(system_message(&text));
+            }
+            ServerMessage::CorrespondenceClosed { reason, .. } => {
+                network.push_chat(system_message(reason));
+            }
+            ServerMessage::Kicked { reason } => {
+                tracing::warn!("Kicked by server: {}", reason);
+                // 会话已被注销，不再尝试重连
+                network.status = ConnectionStatus::Error;
+                network.connection_error = Some(reason.clone());
+                network.player_id = None;
+                network.session_token = None;
+                network.room_id = None;
+                network.last_seq = None;
+                game.reset();
+                conn_handle.connection.disconnect();
+                game_state.set(GameState::Menu);
             }
             _ => {
                 tracing::debug!("Unhandled server message: {:?}", msg);
@@ -366,6 +716,36 @@ fn handle_server_messages(
     }
 }
 
+/// 服务端通知以系统消息的形式显示在聊天栏
+fn system_message(text: &str) -> protocol::ChatMessage {
+    protocol::ChatMessage {
+        channel: protocol::ChatChannel::Lobby,
+        from_id: 0,
+        from: "系统".to_string(),
+        text: text.to_string(),
+        sent_at: 0,
+    }
+}
+
+/// 查询一页历史对局（不筛选）
+fn list_my_games(page: u32) -> ClientMessage {
+    ClientMessage::ListMyGames {
+        page,
+        page_size: protocol::MAX_HISTORY_PAGE_SIZE,
+        filter: protocol::GameHistoryFilter::default(),
+    }
+}
+
+/// 根据当前房间类型确定在线游戏模式
+fn online_game_mode(network: &NetworkState, room_id: protocol::RoomId) -> crate::game::GameMode {
+    match network.current_room_type.clone().unwrap_or(protocol::RoomType::PvP) {
+        protocol::RoomType::PvE(difficulty) => {
+            crate::game::GameMode::OnlinePvE { room_id, difficulty }
+        }
+        protocol::RoomType::PvP => crate::game::GameMode::OnlinePvP { room_id },
+    }
+}
+
 /// 轮询网络消息
 fn poll_network(
     conn_handle: Res<NetworkConnectionHandle>,
@@ -376,12 +756,34 @@ fn poll_network(
     let messages = conn_handle.connection.drain_received();
     
     for msg in messages {
-        // 检查是否是登录成功消息，更新连接状态
-        if matches!(msg, ServerMessage::LoginSuccess { .. }) {
+        // 检查是否是登录或重连成功消息，更新连接状态
+        if matches!(msg, ServerMessage::LoginSuccess { .. } | ServerMessage::ReconnectSuccess { .. }) {
             network.status = ConnectionStatus::Connected;
         }
-        
-        server_events.write(ServerMessageEvent(msg));
+
+        match msg {
+            // 房间事件：记录序列号后按普通消息处理
+            ServerMessage::Sequenced(event) => {
+                network.last_seq = Some(event.seq);
+                server_events.write(ServerMessageEvent(event.message));
+            }
+            // 重连成功：先恢复快照，再按顺序补发错过的事件
+            ServerMessage::ReconnectSuccess { last_seq, ref missed_events, replay_complete, .. } => {
+                network.last_seq = Some(last_seq);
+                let missed: Vec<ServerMessage> = if replay_complete {
+                    missed_events.iter().map(|e| e.message.clone()).collect()
+                } else {
+                    Vec::new()
+                };
+                server_events.write(ServerMessageEvent(msg));
+                for missed_msg in missed {
+                    server_events.write(ServerMessageEvent(missed_msg));
+                }
+            }
+            msg => {
+                server_events.write(ServerMessageEvent(msg));
+            }
+        }
     }
 }
 
@@ -403,8 +805,10 @@ fn check_quick_match_timeout(
             network.is_quick_matching = false;
             network.quick_match_start = None;
             
-            // 断开连接并返回主菜单
+            // 退出匹配队列，断开连接并返回主菜单
+            conn_handle.connection.queue_send(ClientMessage::LeaveQueue);
             conn_handle.connection.disconnect();
+            network.status = ConnectionStatus::Disconnected;
             game_state.set(GameState::Menu);
         }
     }
@@ -457,3 +861,67 @@ fn check_lobby_connect_timeout(
         }
     }
 }
+
+/// 检查意外断线：对局中断线时凭会话令牌自动重连，其余情况返回主菜单
+fn check_connection_lost(
+    mut network: ResMut<NetworkState>,
+    mut game: ResMut<crate::game::ClientGame>,
+    conn_handle: Res<NetworkConnectionHandle>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let connection = &conn_handle.connection;
+    if !connection.is_dropped() {
+        return;
+    }
+
+    let reconnect = network.reconnect;
+    let attempts = match reconnect {
+        // 已登录的连接意外中断
+        None if network.status == ConnectionStatus::Connected => {
+            if network.room_id.is_none() || network.session_token.is_none() {
+                tracing::warn!("Connection lost");
+                connection_lost(&mut network, &mut game, &mut next_state, "与服务器的连接已断开");
+                return;
+            }
+            network.push_chat(system_message("与服务器的连接已断开，正在重连…"));
+            network.status = ConnectionStatus::Connecting;
+            1
+        }
+        Some(attempt) if attempt.last_attempt.elapsed().as_secs() >= RECONNECT_INTERVAL_SECS => {
+            if attempt.attempts >= MAX_RECONNECT_ATTEMPTS {
+                tracing::warn!("Reconnect failed after {} attempts", attempt.attempts);
+                connection_lost(&mut network, &mut game, &mut next_state, "无法重新连接到服务器");
+                return;
+            }
+            attempt.attempts + 1
+        }
+        _ => return,
+    };
+
+    let (Some(session_token), Some(room_id)) = (network.session_token.clone(), network.room_id) else {
+        return;
+    };
+    tracing::info!("Reconnecting to room {:?} (attempt {})", room_id, attempts);
+    network.reconnect = Some(ReconnectAttempt { attempts, last_attempt: Instant::now() });
+    connection.reconnect(network.server_addr.clone(), session_token, room_id, network.last_seq);
+}
+
+/// 连接无法恢复：放弃会话并返回主菜单
+fn connection_lost(
+    network: &mut NetworkState,
+    game: &mut crate::game::ClientGame,
+    next_state: &mut NextState<GameState>,
+    reason: &str,
+) {
+    network.status = ConnectionStatus::Error;
+    network.connection_error = Some(reason.to_string());
+    network.reconnect = None;
+    network.player_id = None;
+    network.session_token = None;
+    network.room_id = None;
+    network.last_seq = None;
+    network.is_quick_matching = false;
+    network.quick_match_start = None;
+    game.reset();
+    next_state.set(GameState::Menu);
+}
@@ -74,6 +74,66 @@ impl TimeLimit {
     }
 }
 
+/// 在线开房的时间控制预设
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum OnlineTimeControl {
+    /// 每方 10 分钟
+    #[default]
+    TenMinutes,
+    /// 5 分钟，每步加 3 秒
+    FivePlusThree,
+    /// 15 分钟，每步加 10 秒
+    FifteenPlusTen,
+    /// 10 分钟，3 次 30 秒读秒
+    Byoyomi,
+    /// 20 分钟，每步限时 60 秒
+    MoveLimit,
+}
+
+impl OnlineTimeControl {
+    /// 转换为协议中的时间控制
+    pub fn to_time_control(self) -> protocol::TimeControl {
+        use protocol::TimeControl;
+        match self {
+            OnlineTimeControl::TenMinutes => TimeControl::minutes(10),
+            OnlineTimeControl::FivePlusThree => TimeControl::minutes(5).with_increment(3),
+            OnlineTimeControl::FifteenPlusTen => TimeControl::minutes(15).with_increment(10),
+            OnlineTimeControl::Byoyomi => TimeControl::minutes(10).with_byoyomi(3, 30),
+            OnlineTimeControl::MoveLimit => TimeControl::minutes(20).with_move_limit(60),
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            OnlineTimeControl::TenMinutes => "10 分钟",
+            OnlineTimeControl::FivePlusThree => "5 分钟 + 3 秒",
+            OnlineTimeControl::FifteenPlusTen => "15 分钟 + 10 秒",
+            OnlineTimeControl::Byoyomi => "10 分钟 + 3 次 30 秒读秒",
+            OnlineTimeControl::MoveLimit => "20 分钟，每步 60 秒",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            OnlineTimeControl::TenMinutes => OnlineTimeControl::FivePlusThree,
+            OnlineTimeControl::FivePlusThree => OnlineTimeControl::FifteenPlusTen,
+            OnlineTimeControl::FifteenPlusTen => OnlineTimeControl::Byoyomi,
+            OnlineTimeControl::Byoyomi => OnlineTimeControl::MoveLimit,
+            OnlineTimeControl::MoveLimit => OnlineTimeControl::TenMinutes,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        match self {
+            OnlineTimeControl::TenMinutes => OnlineTimeControl::MoveLimit,
+            OnlineTimeControl::FivePlusThree => OnlineTimeControl::TenMinutes,
+            OnlineTimeControl::FifteenPlusTen => OnlineTimeControl::FivePlusThree,
+            OnlineTimeControl::Byoyomi => OnlineTimeControl::FifteenPlusTen,
+            OnlineTimeControl::MoveLimit => OnlineTimeControl::Byoyomi,
+        }
+    }
+}
+
 /// 翻转棋盘视角
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum BoardFlip {
@@ -350,6 +410,9 @@ pub struct GameSettings {
     // === 游戏设置 ===
     /// 本地对局时间限制
     pub time_limit: TimeLimit,
+    /// 在线开房的时间控制
+    #[serde(default)]
+    pub online_time_control: OnlineTimeControl,
     /// AI 思考时间上限（秒）
     pub ai_timeout_secs: u32,
     /// 默认 AI 难度
@@ -393,6 +456,9 @@ pub struct GameSettings {
     pub server_address: String,
     /// 默认昵称
     pub nickname: String,
+    /// 账号密码（未设置时以游客身份登录）
+    #[serde(default)]
+    pub password: Option<String>,
 
     // === LLM 设置 ===
     /// Ollama 服务地址
@@ -422,6 +488,7 @@ impl Default for GameSettings {
         Self {
             // 游戏设置
             time_limit: TimeLimit::default(),
+            online_time_control: OnlineTimeControl::default(),
             ai_timeout_secs: 10,
             default_difficulty: Difficulty::Medium,
             custom_ai_depth: default_custom_ai_depth(),
@@ -445,6 +512,7 @@ impl Default for GameSettings {
             // 网络设置
             server_address: "127.0.0.1:9527".to_string(),
             nickname: "玩家".to_string(),
+            password: None,
 
             // LLM 设置
             llm_base_url: "http://localhost:11434".to_string(),
@@ -151,6 +151,31 @@ impl StorageManager {
         Ok(())
     }
 
+    /// 服务端归档对局是否已同步到本地
+    pub fn has_archived_game(&self, game_id: u64) -> bool {
+        self.saves_dir.join(archived_filename(game_id)).exists()
+    }
+
+    /// 导入服务端归档的对局（记录 JSON 来自 `GameFetched`），返回文件名
+    pub fn import_archived_game(&self, game_id: u64, record_json: &str, player_side: Side) -> Result<String> {
+        let mut record = GameRecord::from_json(record_json).context("解析归档棋谱失败")?;
+        if let Some(save_info) = &mut record.save_info {
+            save_info.player_side = match player_side {
+                Side::Red => "red".to_string(),
+                Side::Black => "black".to_string(),
+            };
+        }
+
+        let filename = archived_filename(game_id);
+        let filepath = self.saves_dir.join(&filename);
+        let json_content = record.to_json().context("序列化棋谱失败")?;
+        fs::write(&filepath, json_content)
+            .with_context(|| format!("写入文件失败: {:?}", filepath))?;
+
+        tracing::info!("归档对局已导入: {}", filename);
+        Ok(filename)
+    }
+
     /// 获取存储目录路径
     pub fn saves_directory(&self) -> &Path {
         &self.saves_dir
@@ -197,6 +222,11 @@ fn get_saves_directory() -> Result<PathBuf> {
     Ok(app_data_dir.join("chinese-chess").join("saves"))
 }
 
+/// 服务端归档对局的本地文件名
+fn archived_filename(game_id: u64) -> String {
+    format!("archive_{}.json", game_id)
+}
+
 /// 生成文件名
 fn generate_filename(timestamp: &DateTime<Utc>, red_player: &str, black_player: &str) -> String {
     let timestamp_str = timestamp.format("%Y%m%d_%H%M%S").to_string();
@@ -0,0 +1,260 @@
+//! 聊天 UI - 大厅与对局中的聊天面板
+//!
+//! 回车开始输入，再次回车发送，Esc 取消。输入 `/mute` 屏蔽最近发言的其他玩家，
+//! `/unmute` 取消全部屏蔽
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+use bevy::window::{Ime, PrimaryWindow};
+use protocol::{ChatChannel, MAX_CHAT_LEN};
+
+use crate::game::ClientGame;
+use crate::network::{NetworkEvent, NetworkState};
+use crate::GameState;
+
+/// 聊天面板显示的消息条数
+const VISIBLE_CHAT_LINES: usize = 8;
+
+/// 聊天输入状态
+#[derive(Resource, Default)]
+pub struct ChatInput {
+    /// 正在输入的内容
+    pub text: String,
+    /// 是否处于输入状态
+    pub active: bool,
+}
+
+/// 聊天记录显示区标记
+#[derive(Component)]
+pub struct ChatLogDisplay;
+
+/// 聊天输入行标记
+#[derive(Component)]
+pub struct ChatInputDisplay;
+
+/// 生成聊天面板
+pub fn spawn_chat_panel(parent: &mut ChildSpawnerCommands, asset_server: &AssetServer, width: Val) {
+    parent
+        .spawn((
+            Node {
+                width,
+                flex_direction: FlexDirection::Column,
+                flex_shrink: 0.0,
+                padding: UiRect::all(Val::Px(8.0)),
+                margin: UiRect::vertical(Val::Px(5.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.3)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    min_height: Val::Px(120.0),
+                    ..default()
+                },
+                ChatLogDisplay,
+            ));
+
+            parent.spawn((
+                Text::new(input_hint(false, "")),
+                TextFont {
+                    font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.6, 0.6, 0.6)),
+                Node {
+                    margin: UiRect::top(Val::Px(6.0)),
+                    ..default()
+                },
+                ChatInputDisplay,
+            ));
+        });
+}
+
+/// 输入行文字
+fn input_hint(active: bool, text: &str) -> String {
+    if active {
+        format!("> {}_", text)
+    } else {
+        "按回车发言".to_string()
+    }
+}
+
+/// 更新聊天记录显示
+pub fn update_chat_log(
+    mut commands: Commands,
+    network: Res<NetworkState>,
+    asset_server: Res<AssetServer>,
+    query: Query<(Entity, Option<&Children>), With<ChatLogDisplay>>,
+) {
+    if !network.is_changed() {
+        return;
+    }
+
+    for (entity, children) in query.iter() {
+        if let Some(children) = children {
+            for child in children.iter() {
+                commands.entity(child).despawn();
+            }
+        }
+
+        let start = network.chat_messages.len().saturating_sub(VISIBLE_CHAT_LINES);
+        commands.entity(entity).with_children(|parent| {
+            for message in &network.chat_messages[start..] {
+                let (prefix, color) = match message.channel {
+                    ChatChannel::Spectators => ("[观战] ", Color::srgb(0.6, 0.7, 0.9)),
+                    ChatChannel::Lobby | ChatChannel::Room => ("", Color::srgb(0.85, 0.85, 0.85)),
+                };
+                parent.spawn((
+                    Text::new(format!("{}{}: {}", prefix, message.from, message.text)),
+                    TextFont {
+                        font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
+                        font_size: 14.0,
+                        ..default()
+                    },
+                    TextColor(color),
+                ));
+            }
+        });
+    }
+}
+
+/// 处理聊天键盘输入
+pub fn handle_chat_input(
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut ime_events: MessageReader<Ime>,
+    mut chat_input: ResMut<ChatInput>,
+    mut network_events: MessageWriter<NetworkEvent>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    network: Res<NetworkState>,
+    game: Res<ClientGame>,
+    state: Res<State<GameState>>,
+) {
+    // 本地对局没有聊天
+    if game.is_local() && *state.get() != GameState::Lobby {
+        keyboard_events.clear();
+        ime_events.clear();
+        return;
+    }
+
+    for event in keyboard_events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Enter if chat_input.active => {
+                let text = std::mem::take(&mut chat_input.text);
+                chat_input.active = false;
+                send_chat(&text, &mut network_events, &network, &game, state.get());
+            }
+            Key::Enter => {
+                chat_input.active = true;
+            }
+            Key::Escape if chat_input.active => {
+                chat_input.text.clear();
+                chat_input.active = false;
+            }
+            Key::Backspace if chat_input.active => {
+                chat_input.text.pop();
+            }
+            Key::Space if chat_input.active => {
+                push_text(&mut chat_input.text, " ");
+            }
+            Key::Character(s) if chat_input.active => {
+                push_text(&mut chat_input.text, s);
+            }
+            _ => {}
+        }
+    }
+
+    // 中文输入法提交的文字
+    for event in ime_events.read() {
+        if let Ime::Commit { value, .. } = event {
+            if chat_input.active {
+                push_text(&mut chat_input.text, value);
+            }
+        }
+    }
+
+    if let Ok(mut window) = windows.single_mut() {
+        if window.ime_enabled != chat_input.active {
+            window.ime_enabled = chat_input.active;
+        }
+    }
+}
+
+/// 追加输入（不超过长度上限）
+fn push_text(text: &mut String, s: &str) {
+    for c in s.chars() {
+        if text.chars().count() >= MAX_CHAT_LEN {
+            break;
+        }
+        text.push(c);
+    }
+}
+
+/// 发送聊天消息或执行屏蔽命令
+fn send_chat(
+    text: &str,
+    network_events: &mut MessageWriter<NetworkEvent>,
+    network: &NetworkState,
+    game: &ClientGame,
+    state: &GameState,
+) {
+    let text = text.trim();
+    match text {
+        "" => {}
+        "/mute" => {
+            // 屏蔽最近发言的其他玩家
+            let target = network
+                .chat_messages
+                .iter()
+                .rev()
+                .map(|m| m.from_id)
+                .find(|&id| Some(id) != network.player_id);
+            if let Some(player_id) = target {
+                network_events.write(NetworkEvent::SetMuted { player_id, muted: true });
+            }
+        }
+        "/unmute" => {
+            for &player_id in &network.muted_players {
+                network_events.write(NetworkEvent::SetMuted { player_id, muted: false });
+            }
+        }
+        _ => {
+            let channel = if *state == GameState::Lobby {
+                ChatChannel::Lobby
+            } else if game.is_spectating() {
+                ChatChannel::Spectators
+            } else {
+                ChatChannel::Room
+            };
+            network_events.write(NetworkEvent::SendChat {
+                channel,
+                text: text.to_string(),
+            });
+        }
+    }
+}
+
+/// 更新输入行显示
+pub fn update_chat_input_display(
+    chat_input: Res<ChatInput>,
+    mut query: Query<(&mut Text, &mut TextColor), With<ChatInputDisplay>>,
+) {
+    if !chat_input.is_changed() {
+        return;
+    }
+
+    for (mut text, mut color) in &mut query {
+        **text = input_hint(chat_input.active, &chat_input.text);
+        *color = if chat_input.active {
+            TextColor(Color::WHITE)
+        } else {
+            TextColor(Color::srgb(0.6, 0.6, 0.6))
+        };
+    }
+}
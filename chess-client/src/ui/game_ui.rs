@@ -32,8 +32,20 @@ pub struct PauseButtonText;
 #[derive(Component)]
 pub struct AiThinkingIndicator;
 
+/// 提和提示区标记
+#[derive(Component)]
+pub struct DrawOfferPrompt;
+
+/// 提和提示文字标记
+#[derive(Component)]
+pub struct DrawOfferPromptText;
+
+/// 答复提和的按钮区标记
+#[derive(Component)]
+pub struct DrawOfferResponseButtons;
+
 /// 设置游戏 UI
-pub fn setup_game_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_game_ui(mut commands: Commands, asset_server: Res<AssetServer>, game: Res<ClientGame>) {
     // 右侧面板
     commands
         .spawn((
@@ -58,11 +70,23 @@ pub fn setup_game_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
             // 棋谱区域
             spawn_move_history(parent, &asset_server);
 
+            // 聊天区（仅在线对局）
+            if !game.is_local() {
+                super::spawn_chat_panel(parent, &asset_server, Val::Percent(100.0));
+            }
+
             // 玩家信息区
             spawn_player_info(parent, &asset_server, "玩家", true);
 
-            // 按钮区
-            spawn_game_buttons(parent, &asset_server);
+            // 按钮区（观战时只能退出）
+            if game.is_spectating() {
+                spawn_game_button(parent, &asset_server, "退出观战", ButtonAction::StopSpectating);
+            } else {
+                if game.is_online_pvp() {
+                    spawn_draw_offer_prompt(parent, &asset_server);
+                }
+                spawn_game_buttons(parent, &asset_server, game.is_online_pvp());
+            }
         });
 
     // AI 思考指示器（棋盘中央）
@@ -194,8 +218,50 @@ fn spawn_move_history(parent: &mut ChildSpawnerCommands, asset_server: &AssetSer
         });
 }
 
+/// 生成提和提示区（有提和时显示）
+fn spawn_draw_offer_prompt(parent: &mut ChildSpawnerCommands, asset_server: &AssetServer) {
+    parent
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                flex_shrink: 0.0,
+                padding: UiRect::all(Val::Px(8.0)),
+                margin: UiRect::vertical(Val::Px(5.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.3, 0.25, 0.1, 0.8)),
+            DrawOfferPrompt,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(1.0, 0.9, 0.5)),
+                DrawOfferPromptText,
+            ));
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    DrawOfferResponseButtons,
+                ))
+                .with_children(|parent| {
+                    spawn_game_button(parent, asset_server, "同意", ButtonAction::AcceptDraw);
+                    spawn_game_button(parent, asset_server, "拒绝", ButtonAction::DeclineDraw);
+                });
+        });
+}
+
 /// 生成游戏按钮
-fn spawn_game_buttons(parent: &mut ChildSpawnerCommands, asset_server: &AssetServer) {
+fn spawn_game_buttons(parent: &mut ChildSpawnerCommands, asset_server: &AssetServer, online_pvp: bool) {
     parent
         .spawn(Node {
             flex_direction: FlexDirection::Column,
@@ -208,6 +274,10 @@ fn spawn_game_buttons(parent: &mut ChildSpawnerCommands, asset_server: &AssetSer
             spawn_game_button(parent, asset_server, "悔棋", ButtonAction::Undo);
             // 认输按钮
             spawn_game_button(parent, asset_server, "认输", ButtonAction::Resign);
+            // 提和按钮（仅在线玩家对战）
+            if online_pvp {
+                spawn_game_button(parent, asset_server, "提和", ButtonAction::OfferDraw);
+            }
             // 暂停按钮（带标记以便动态更新文字）
             spawn_pause_button(parent, asset_server);
             // 保存按钮
@@ -296,10 +366,10 @@ pub fn update_timer_display(
     }
 
     for (mut text, timer) in query.iter_mut() {
-        let time_ms = if timer.is_red {
-            game.red_time_ms
+        let (time_ms, byoyomi) = if timer.is_red {
+            (game.red_time_ms, game.red_byoyomi)
         } else {
-            game.black_time_ms
+            (game.black_time_ms, game.black_byoyomi)
         };
 
         // 无限时间模式（本地 PvE）显示 "--:--"
@@ -309,11 +379,43 @@ pub fn update_timer_display(
         } else {
             let minutes = time_ms / 60000;
             let seconds = (time_ms % 60000) / 1000;
-            **text = format!("{:02}:{:02}", minutes, seconds);
+            **text = if byoyomi.in_byoyomi {
+                // 读秒中显示剩余次数
+                format!("{:02}:{:02} 读秒×{}", minutes, seconds, byoyomi.periods_left)
+            } else {
+                format!("{:02}:{:02}", minutes, seconds)
+            };
         }
     }
 }
 
+/// 更新提和提示区
+pub fn update_draw_offer_prompt(
+    game: Res<ClientGame>,
+    mut prompt_query: Query<&mut Node, (With<DrawOfferPrompt>, Without<DrawOfferResponseButtons>)>,
+    mut buttons_query: Query<&mut Node, (With<DrawOfferResponseButtons>, Without<DrawOfferPrompt>)>,
+    mut text_query: Query<&mut Text, With<DrawOfferPromptText>>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+
+    let visible = game.incoming_draw_offer || game.draw_offer_sent;
+    for mut node in &mut prompt_query {
+        node.display = if visible { Display::Flex } else { Display::None };
+    }
+    for mut node in &mut buttons_query {
+        node.display = if game.incoming_draw_offer { Display::Flex } else { Display::None };
+    }
+    for mut text in &mut text_query {
+        **text = if game.incoming_draw_offer {
+            "对方提和，是否同意？".to_string()
+        } else {
+            "已提和，等待对方答复".to_string()
+        };
+    }
+}
+
 /// 更新暂停按钮文字
 pub fn update_pause_button_text(
     game: Res<ClientGame>,
@@ -392,7 +494,8 @@ pub fn handle_game_buttons(
         (Changed<Interaction>, With<Button>),
     >,
     mut game_events: MessageWriter<GameEvent>,
-    game: Res<ClientGame>,
+    mut network_events: MessageWriter<NetworkEvent>,
+    mut game: ResMut<ClientGame>,
     settings: Res<crate::settings::GameSettings>,
 ) {
     for (interaction, mut color, action) in &mut interaction_query {
@@ -400,12 +503,26 @@ pub fn handle_game_buttons(
             Interaction::Pressed => {
                 *color = PRESSED_BUTTON.into();
                 match action {
+                    ButtonAction::StopSpectating => {
+                        network_events.write(NetworkEvent::StopSpectating);
+                    }
                     ButtonAction::Undo => {
                         game_events.write(GameEvent::RequestUndo);
                     }
                     ButtonAction::Resign => {
                         game_events.write(GameEvent::Resign);
                     }
+                    ButtonAction::OfferDraw => {
+                        if !game.draw_offer_sent && !game.incoming_draw_offer {
+                            network_events.write(NetworkEvent::OfferDraw);
+                            game.draw_offer_sent = true;
+                        }
+                    }
+                    ButtonAction::AcceptDraw | ButtonAction::DeclineDraw => {
+                        let accept = matches!(action, ButtonAction::AcceptDraw);
+                        network_events.write(NetworkEvent::RespondDraw { accept });
+                        game.incoming_draw_offer = false;
+                    }
                     ButtonAction::Pause => {
                         if game.is_paused {
                             game_events.write(GameEvent::ResumeGame);
@@ -656,11 +773,17 @@ pub fn setup_game_over_ui(
     asset_server: Res<AssetServer>,
     game: Res<ClientGame>,
 ) {
-    // 根据游戏结果确定显示内容
-    let (title, title_color, subtitle) = match game.is_player_win() {
-        Some(true) => ("恭喜获胜！", Color::srgb(1.0, 0.84, 0.0), get_win_reason(&game)),
-        Some(false) => ("很遗憾，您输了", Color::srgb(0.8, 0.3, 0.3), get_win_reason(&game)),
-        None => {
+    // 根据游戏结果确定显示内容（观战时按红黑方描述）
+    let spectator_title = match &game.game_result {
+        Some(protocol::GameResult::RedWin(_)) if game.is_spectating() => Some("红方胜"),
+        Some(protocol::GameResult::BlackWin(_)) if game.is_spectating() => Some("黑方胜"),
+        _ => None,
+    };
+    let (title, title_color, subtitle) = match (spectator_title, game.is_player_win()) {
+        (Some(title), _) => (title, Color::srgb(1.0, 0.84, 0.0), get_win_reason(&game)),
+        (None, Some(true)) => ("恭喜获胜！", Color::srgb(1.0, 0.84, 0.0), get_win_reason(&game)),
+        (None, Some(false)) => ("很遗憾，您输了", Color::srgb(0.8, 0.3, 0.3), get_win_reason(&game)),
+        (None, None) => {
             if game.game_result.is_some() {
                 ("握手言和", Color::srgb(0.7, 0.7, 0.7), get_draw_reason(&game))
             } else {
@@ -670,11 +793,21 @@ pub fn setup_game_over_ui(
     };
 
     // 统计信息
-    let stats = format!(
+    let mut stats = format!(
         "总回合数：{}    总步数：{}",
         game.total_rounds(),
         game.total_moves()
     );
+    if let Some(change) = &game.rating_change {
+        let diff = change.after.rating - change.before.rating;
+        stats.push_str(&format!(
+            "\n等级分：{} → {}{} ({:+})",
+            change.before.rating,
+            change.after.rating,
+            if change.after.provisional { "?" } else { "" },
+            diff
+        ));
+    }
 
     commands
         .spawn((
@@ -986,6 +1119,7 @@ pub fn handle_game_over_buttons(
                                 network_events.write(NetworkEvent::Connect {
                                     addr: settings.server_address.clone(),
                                     nickname: settings.nickname.clone(),
+                                    password: settings.password.clone(),
                                 });
                             }
                             _ => {
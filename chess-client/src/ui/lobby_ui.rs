@@ -20,7 +20,7 @@ pub struct RoomListContainer;
 pub struct RoomEntry(pub protocol::RoomId);
 
 /// 设置大厅 UI
-pub fn setup_lobby(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_lobby(mut commands: Commands, asset_server: Res<AssetServer>, network: Res<NetworkState>) {
     commands
         .spawn((
             Node {
@@ -84,6 +84,9 @@ pub fn setup_lobby(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ));
                 });
 
+            // 大厅聊天
+            super::spawn_chat_panel(parent, &asset_server, Val::Px(600.0));
+
             // 底部按钮栏
             parent
                 .spawn(Node {
@@ -97,6 +100,11 @@ pub fn setup_lobby(mut commands: Commands, asset_server: Res<AssetServer>) {
                 .with_children(|parent| {
                     // 刷新按钮
                     spawn_lobby_button(parent, &asset_server, "刷新", ButtonAction::RefreshRooms);
+
+                    // 注册账号可将服务端归档的对局同步到本地棋谱
+                    if network.registered {
+                        spawn_lobby_button(parent, &asset_server, "同步棋谱", ButtonAction::SyncGameHistory);
+                    }
                     
                     // 返回主菜单
                     spawn_lobby_button(parent, &asset_server, "返回", ButtonAction::BackToMenuFromLobby);
@@ -277,7 +285,31 @@ fn spawn_room_entry(parent: &mut ChildSpawnerCommands, asset_server: &AssetServe
 
     // 计算玩家数量
     let player_count = room.red_player.is_some() as u8 + room.black_player.is_some() as u8;
+
+    // 房主及其等级分（定级中的分数带问号）
+    let host = room
+        .red_player
+        .as_ref()
+        .map(|name| (name, room.red_rating))
+        .or(room.black_player.as_ref().map(|name| (name, room.black_rating)));
+    let host_text = match host {
+        Some((name, Some(rating))) => format!(
+            " | {} ({}{})",
+            name,
+            rating.rating,
+            if rating.provisional { "?" } else { "" }
+        ),
+        Some((name, None)) => format!(" | {}", name),
+        None => String::new(),
+    };
+    let rated_text = if room.rated { "计分" } else { "休闲" };
     let can_join = room.state == RoomState::Waiting && matches!(room.room_type, RoomType::PvP);
+    let can_watch = matches!(room.state, RoomState::Playing | RoomState::Paused);
+    let spectators_text = if room.spectators > 0 {
+        format!(" | 观战: {}", room.spectators)
+    } else {
+        String::new()
+    };
 
     parent
         .spawn((
@@ -315,8 +347,14 @@ fn spawn_room_entry(parent: &mut ChildSpawnerCommands, asset_server: &AssetServe
                     // 房间详情
                     parent.spawn((
                         Text::new(format!(
-                            "{} | {} | 玩家: {}/2",
-                            room_type_text, status_text, player_count
+                            "{} {} {} | {} | 玩家: {}/2{}{}",
+                            room_type_text,
+                            rated_text,
+                            room.time_control,
+                            status_text,
+                            player_count,
+                            spectators_text,
+                            host_text
                         )),
                         TextFont {
                             font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
@@ -327,8 +365,15 @@ fn spawn_room_entry(parent: &mut ChildSpawnerCommands, asset_server: &AssetServe
                     ));
                 });
 
-            // 右侧：加入按钮
-            if can_join {
+            // 右侧：加入或观战按钮
+            let action = if can_join {
+                Some(("加入", ButtonAction::JoinRoomById(room.id)))
+            } else if can_watch {
+                Some(("观战", ButtonAction::SpectateRoomById(room.id)))
+            } else {
+                None
+            };
+            if let Some((label, action)) = action {
                 parent
                     .spawn((
                         Button,
@@ -340,11 +385,11 @@ fn spawn_room_entry(parent: &mut ChildSpawnerCommands, asset_server: &AssetServe
                             ..default()
                         },
                         BackgroundColor(Color::srgb(0.2, 0.5, 0.3)),
-                        ButtonAction::JoinRoomById(room.id),
+                        action,
                     ))
                     .with_children(|parent| {
                         parent.spawn((
-                            Text::new("加入"),
+                            Text::new(label),
                             TextFont {
                                 font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
                                 font_size: 16.0,
@@ -356,11 +401,7 @@ fn spawn_room_entry(parent: &mut ChildSpawnerCommands, asset_server: &AssetServe
             } else {
                 // 不可加入时显示灰色文字
                 parent.spawn((
-                    Text::new(if room.state == RoomState::Playing {
-                        "进行中"
-                    } else {
-                        "不可加入"
-                    }),
+                    Text::new("不可加入"),
                     TextFont {
                         font: asset_server.load("fonts/SourceHanSansSC-Regular.otf"),
                         font_size: 14.0,
@@ -391,6 +432,9 @@ pub fn handle_lobby_buttons(
                         network_events.write(NetworkEvent::ListRooms);
                         tracing::info!("Refreshing room list");
                     }
+                    ButtonAction::SyncGameHistory => {
+                        network_events.write(NetworkEvent::SyncGameHistory);
+                    }
                     ButtonAction::BackToMenuFromLobby => {
                         network_events.write(NetworkEvent::Disconnect);
                         game_state.set(GameState::Menu);
@@ -399,19 +443,22 @@ pub fn handle_lobby_buttons(
                         network_events.write(NetworkEvent::JoinRoom { room_id: *room_id });
                         tracing::info!("Joining room: {}", room_id);
                     }
+                    ButtonAction::SpectateRoomById(room_id) => {
+                        network_events.write(NetworkEvent::SpectateRoom { room_id: *room_id });
+                    }
                     _ => {}
                 }
             }
             Interaction::Hovered => {
                 // 加入按钮使用不同的悬停颜色
-                if matches!(action, ButtonAction::JoinRoomById(_)) {
+                if matches!(action, ButtonAction::JoinRoomById(_) | ButtonAction::SpectateRoomById(_)) {
                     *color = Color::srgb(0.25, 0.6, 0.35).into();
                 } else {
                     *color = HOVERED_BUTTON.into();
                 }
             }
             Interaction::None => {
-                if matches!(action, ButtonAction::JoinRoomById(_)) {
+                if matches!(action, ButtonAction::JoinRoomById(_) | ButtonAction::SpectateRoomById(_)) {
                     *color = Color::srgb(0.2, 0.5, 0.3).into();
                 } else {
                     *color = NORMAL_BUTTON.into();
@@ -250,12 +250,13 @@ fn handle_button_action(
             tracing::info!("Starting local PvE game with difficulty: {:?}", difficulty);
         }
         ButtonAction::QuickMatch => {
-            // 快速匹配：登录后自动加入或创建房间
+            // 快速匹配：登录后进入匹配队列
             network_state.pending_action = crate::network::PendingAction::QuickMatch;
             
             network_events.write(NetworkEvent::Connect {
                 addr: settings.server_address.clone(),
                 nickname: settings.nickname.clone(),
+                password: settings.password.clone(),
             });
             
             tracing::info!("Quick match requested");
@@ -265,12 +266,14 @@ fn handle_button_action(
             network_state.pending_action = crate::network::PendingAction::CreateRoom {
                 room_type: protocol::RoomType::PvP,
                 preferred_side: None,
+                time_control: settings.online_time_control.to_time_control(),
             };
             
             // 使用设置中的服务器地址和昵称
             network_events.write(NetworkEvent::Connect {
                 addr: settings.server_address.clone(),
                 nickname: settings.nickname.clone(),
+                password: settings.password.clone(),
             });
         }
         ButtonAction::JoinRoom => {
@@ -281,6 +284,7 @@ fn handle_button_action(
             network_events.write(NetworkEvent::Connect {
                 addr: settings.server_address.clone(),
                 nickname: settings.nickname.clone(),
+                password: settings.password.clone(),
             });
             game_state.set(GameState::Lobby);
         }
@@ -8,6 +8,7 @@ mod settings_ui;
 mod lobby_ui;
 mod saved_games_ui;
 mod analysis_ui;
+mod chat_ui;
 mod board_editor;
 
 pub use menu::*;
@@ -16,6 +17,7 @@ pub use settings_ui::*;
 pub use lobby_ui::*;
 pub use saved_games_ui::*;
 pub use analysis_ui::*;
+pub use chat_ui::*;
 pub use board_editor::*;
 
 use bevy::prelude::*;
@@ -88,6 +90,13 @@ impl Plugin for UiPlugin {
                 Update,
                 (handle_lobby_buttons, update_room_list).run_if(in_state(GameState::Lobby)),
             )
+            // 聊天（大厅与对局中）
+            .init_resource::<ChatInput>()
+            .add_systems(
+                Update,
+                (handle_chat_input, update_chat_log, update_chat_input_display)
+                    .run_if(in_state(GameState::Lobby).or(in_state(GameState::Playing))),
+            )
             // 设置页面
             .add_systems(OnEnter(GameState::Settings), setup_settings)
             .add_systems(OnExit(GameState::Settings), cleanup_settings)
@@ -101,7 +110,7 @@ impl Plugin for UiPlugin {
             .add_systems(OnExit(GameState::Playing), cleanup_game_ui)
             .add_systems(
                 Update,
-                (update_timer_display, update_move_history, update_pause_button_text, handle_game_buttons, update_ai_thinking_indicator, handle_move_history_scroll)
+                (update_timer_display, update_move_history, update_pause_button_text, update_draw_offer_prompt, handle_game_buttons, update_ai_thinking_indicator, handle_move_history_scroll)
                     .run_if(in_state(GameState::Playing)),
             )
             // 游戏结束
@@ -206,6 +215,9 @@ pub enum ButtonAction {
     // 游戏中
     Undo,
     Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
     Pause,
     Resume,
     SaveGame,
@@ -219,8 +231,12 @@ pub enum ButtonAction {
     SaveAnalysisReport,
     // 大厅
     RefreshRooms,
+    SyncGameHistory,
     BackToMenuFromLobby,
     JoinRoomById(protocol::RoomId),
+    SpectateRoomById(protocol::RoomId),
+    // 观战
+    StopSpectating,
 }
 
 /// 通用按钮样式
@@ -66,6 +66,8 @@ pub enum SettingsAction {
     // 游戏设置
     TimeLimitPrev,
     TimeLimitNext,
+    OnlineTimeControlPrev,
+    OnlineTimeControlNext,
     AiTimeoutDecrease,
     AiTimeoutIncrease,
     DifficultyPrev,
@@ -263,6 +265,17 @@ fn spawn_game_settings(
         SettingsAction::TimeLimitNext,
     );
 
+    // 在线开房时间控制
+    spawn_setting_row(
+        parent,
+        asset_server,
+        "在线开房时间控制",
+        settings.online_time_control.display_name(),
+        "online_time_control",
+        SettingsAction::OnlineTimeControlPrev,
+        SettingsAction::OnlineTimeControlNext,
+    );
+
     // AI 思考时间上限
     spawn_setting_row(
         parent,
@@ -904,6 +917,12 @@ fn handle_settings_action(
         SettingsAction::TimeLimitNext => {
             temp_settings.0.time_limit = temp_settings.0.time_limit.next();
         }
+        SettingsAction::OnlineTimeControlPrev => {
+            temp_settings.0.online_time_control = temp_settings.0.online_time_control.prev();
+        }
+        SettingsAction::OnlineTimeControlNext => {
+            temp_settings.0.online_time_control = temp_settings.0.online_time_control.next();
+        }
         SettingsAction::AiTimeoutDecrease => {
             if temp_settings.0.ai_timeout_secs > 5 {
                 temp_settings.0.ai_timeout_secs -= 1;
@@ -1090,6 +1109,7 @@ pub fn update_settings_display(
     for (mut text, display) in &mut query {
         let new_value = match display.0 {
             "time_limit" => temp_settings.0.time_limit.display_name().to_string(),
+            "online_time_control" => temp_settings.0.online_time_control.display_name().to_string(),
             "ai_timeout" => format!("{} 秒", temp_settings.0.ai_timeout_secs),
             "difficulty" => match temp_settings.0.default_difficulty {
                 protocol::Difficulty::Easy => "简单".to_string(),
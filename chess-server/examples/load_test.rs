@@ -0,0 +1,155 @@
+//! 房间任务吞吐量测试
+//!
+//! 同时进行多局人人对战，双方来回跳马，统计不同工作线程数下每秒处理的走棋数。
+//! 各房间在独立任务中走棋，吞吐量应随核数增长
+//!
+//! 运行方式:
+//! ```bash
+//! cargo run --release -p chess-server --example load_test -- --rooms 200 --plies 100
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use tokio::sync::{mpsc, RwLock};
+
+use chess_server::{AccountStore, MessageHandler, ServerConfig, ServerState};
+use protocol::{
+    ClientMessage, PlayerId, Position, RoomAccess, RoomType, RuleSet, ServerMessage, Side, TimeControl,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "房间任务吞吐量测试")]
+struct Args {
+    /// 同时进行的对局数
+    #[arg(long, default_value_t = 200)]
+    rooms: usize,
+    /// 每局走棋步数
+    #[arg(long, default_value_t = 100)]
+    plies: usize,
+}
+
+/// 双方来回跳马（红方 (7,0)↔(6,2)，黑方 (7,9)↔(6,7)），不吃子也不将军
+fn shuffle_move(ply: usize) -> ClientMessage {
+    let (home, out) = if ply.is_multiple_of(2) {
+        (Position::new_unchecked(7, 0), Position::new_unchecked(6, 2))
+    } else {
+        (Position::new_unchecked(7, 9), Position::new_unchecked(6, 7))
+    };
+    let (from, to) = if (ply / 2).is_multiple_of(2) { (home, out) } else { (out, home) };
+    ClientMessage::MakeMove { from, to }
+}
+
+/// 登录游客并接上一个只管丢弃消息的连接
+async fn login(state: &RwLock<ServerState>, nickname: String) -> PlayerId {
+    let login = ClientMessage::Login { nickname, password: None };
+    let player_id = match MessageHandler::dispatch(state, 0, login).await {
+        Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+        other => panic!("登录失败: {:?}", other),
+    };
+
+    let (tx, mut rx) = mpsc::channel(32);
+    state.read().await.players.attach(player_id, tx);
+    tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    player_id
+}
+
+/// 开好所有对局，返回每局的 (红方, 黑方)
+async fn setup(state: &RwLock<ServerState>, rooms: usize) -> Vec<(PlayerId, PlayerId)> {
+    let mut games = Vec::with_capacity(rooms);
+    for i in 0..rooms {
+        let red = login(state, format!("红方{}", i)).await;
+        let black = login(state, format!("黑方{}", i)).await;
+
+        let create = ClientMessage::CreateRoom {
+            room_type: RoomType::PvP,
+            preferred_side: Some(Side::Red),
+            rated: false,
+            time_control: TimeControl::default(),
+            rules: RuleSet::default(),
+            initial_fen: None,
+            access: RoomAccess::Public,
+        };
+        let room_id = match MessageHandler::dispatch(state, red, create).await {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            other => panic!("创建房间失败: {:?}", other),
+        };
+        let join = ClientMessage::JoinRoom { room_id, password: None };
+        match MessageHandler::dispatch(state, black, join).await {
+            Some(ServerMessage::RoomJoined { .. }) => {}
+            other => panic!("加入房间失败: {:?}", other),
+        }
+        games.push((red, black));
+    }
+    games
+}
+
+/// 用指定的工作线程数跑一轮，返回 (总走棋数, 耗时)
+fn run(worker_threads: usize, args: &Args) -> anyhow::Result<(usize, Duration)> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        let storage_dir = tempfile::tempdir()?;
+        let config = ServerConfig {
+            storage_dir: Some(storage_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let state = ServerState::with_accounts(config, AccountStore::open_in_memory()?)?;
+        let state = Arc::new(RwLock::new(state));
+        let games = setup(&state, args.rooms).await;
+
+        let plies = args.plies;
+        let start = Instant::now();
+        let tasks: Vec<_> = games
+            .into_iter()
+            .map(|(red, black)| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    for ply in 0..plies {
+                        let player_id = if ply.is_multiple_of(2) { red } else { black };
+                        let response = MessageHandler::dispatch(&state, player_id, shuffle_move(ply)).await;
+                        if let Some(ServerMessage::Error { message, .. }) = response {
+                            panic!("第 {} 步走棋失败: {}", ply + 1, message);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await?;
+        }
+
+        Ok((args.rooms * plies, start.elapsed()))
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut thread_counts = Vec::new();
+    let mut threads = 1;
+    while threads < cores {
+        thread_counts.push(threads);
+        threads *= 2;
+    }
+    thread_counts.push(cores);
+
+    println!("=== 房间任务吞吐量测试 ===");
+    println!("{} 局同时进行，每局 {} 步，CPU 核数 {}\n", args.rooms, args.plies, cores);
+    println!("{:>6} {:>10} {:>12} {:>8}", "线程数", "耗时", "步/秒", "加速比");
+
+    let mut baseline = None;
+    for threads in thread_counts {
+        let (moves, elapsed) = run(threads, &args)?;
+        let rate = moves as f64 / elapsed.as_secs_f64();
+        let speedup = rate / *baseline.get_or_insert(rate);
+        println!("{:>6} {:>10.2?} {:>12.0} {:>8.2}x", threads, elapsed, rate, speedup);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,471 @@
+//! 玩家账号
+//!
+//! 使用本地 SQLite 数据库持久化注册账号，密码以加盐 PBKDF2-SHA256 哈希保存
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::Utc;
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::Sha256;
+use thiserror::Error;
+
+/// 账号 ID
+pub type AccountId = i64;
+
+/// PBKDF2 迭代次数（迭代次数随哈希一同保存，调整后旧哈希仍可验证）
+#[cfg(not(test))]
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// 测试时降低迭代次数，避免调试构建下过慢
+#[cfg(test)]
+const PBKDF2_ROUNDS: u32 = 1_000;
+
+/// 盐长度（字节）
+const SALT_LEN: usize = 16;
+
+/// 哈希长度（字节）
+const HASH_LEN: usize = 32;
+
+/// 密码最小长度
+pub const MIN_PASSWORD_LEN: usize = 6;
+
+/// 密码最大长度
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+/// 账号错误
+#[derive(Error, Debug)]
+pub enum AccountError {
+    /// 昵称已被注册
+    #[error("昵称已被注册")]
+    NicknameTaken,
+
+    /// 昵称或密码错误
+    #[error("昵称或密码错误")]
+    InvalidCredentials,
+
+    /// 密码不符合要求
+    #[error("密码长度需为 {MIN_PASSWORD_LEN}-{MAX_PASSWORD_LEN} 个字符")]
+    InvalidPassword,
+
+    /// 数据库错误
+    #[error("数据库错误: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    /// 对局数据序列化错误
+    #[error("对局数据序列化失败: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// 注册账号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub id: AccountId,
+    pub nickname: String,
+}
+
+/// 账号存储
+pub struct AccountStore {
+    /// SQLite 连接不是 Sync 的，需加锁才能放入共享的服务器状态
+    conn: Mutex<Connection>,
+}
+
+impl AccountStore {
+    /// 打开（或创建）数据库文件
+    pub fn open(path: &Path) -> Result<Self, AccountError> {
+        if let Some(parent) = path.parent() {
+            // 目录创建失败时交给 SQLite 报告具体错误
+            let _ = std::fs::create_dir_all(parent);
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// 打开内存数据库（用于测试）
+    pub fn open_in_memory() -> Result<Self, AccountError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// 初始化表结构
+    fn init(conn: Connection) -> Result<Self, AccountError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS accounts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                nickname TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                last_login_at TEXT
+            );
+            CREATE TABLE IF NOT EXISTS ratings (
+                account_id INTEGER PRIMARY KEY REFERENCES accounts(id),
+                rating REAL NOT NULL,
+                deviation REAL NOT NULL,
+                volatility REAL NOT NULL,
+                games INTEGER NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS games (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                red_account INTEGER REFERENCES accounts(id),
+                black_account INTEGER REFERENCES accounts(id),
+                red_player TEXT NOT NULL,
+                black_player TEXT NOT NULL,
+                rated INTEGER NOT NULL,
+                winner TEXT NOT NULL,
+                result TEXT NOT NULL,
+                time_control TEXT NOT NULL,
+                move_count INTEGER NOT NULL,
+                finished_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS games_red_account ON games(red_account, finished_at);
+            CREATE INDEX IF NOT EXISTS games_black_account ON games(black_account, finished_at);
+            CREATE TABLE IF NOT EXISTS bots (
+                account_id INTEGER PRIMARY KEY REFERENCES accounts(id),
+                token_hash TEXT NOT NULL,
+                max_games INTEGER NOT NULL,
+                accept_challenges INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tournaments (
+                id INTEGER PRIMARY KEY,
+                data TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS friends (
+                account_id INTEGER NOT NULL REFERENCES accounts(id),
+                friend_id INTEGER NOT NULL REFERENCES accounts(id),
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (account_id, friend_id)
+            );
+            CREATE TABLE IF NOT EXISTS friend_requests (
+                from_account INTEGER NOT NULL REFERENCES accounts(id),
+                to_account INTEGER NOT NULL REFERENCES accounts(id),
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (from_account, to_account)
+            );
+            CREATE INDEX IF NOT EXISTS friend_requests_to ON friend_requests(to_account);
+            CREATE TABLE IF NOT EXISTS correspondence_games (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                red_account INTEGER NOT NULL REFERENCES accounts(id),
+                black_account INTEGER NOT NULL REFERENCES accounts(id),
+                deadline INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS correspondence_red ON correspondence_games(red_account);
+            CREATE INDEX IF NOT EXISTS correspondence_black ON correspondence_games(black_account);
+            CREATE INDEX IF NOT EXISTS correspondence_deadline ON correspondence_games(deadline);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// 底层数据库连接（供同库的其他存储使用）
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        // 持锁期间不会 panic，锁中毒时数据仍然可用
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 在阻塞线程池中执行账号库操作
+    ///
+    /// 密码哈希和 SQLite 读写都会阻塞线程，异步任务通过它调用，不占用运行时的工作线程
+    pub async fn blocking<T, F>(self: &Arc<Self>, f: F) -> T
+    where
+        F: FnOnce(&AccountStore) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let store = self.clone();
+        match tokio::task::spawn_blocking(move || f(&store)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// 校验密码
+    pub fn validate_password(password: &str) -> Result<(), AccountError> {
+        let len = password.chars().count();
+        if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&len) {
+            return Err(AccountError::InvalidPassword);
+        }
+        Ok(())
+    }
+
+    /// 注册新账号
+    pub fn register(&self, nickname: &str, password: &str) -> Result<Account, AccountError> {
+        Self::validate_password(password)?;
+
+        if self.is_registered(nickname)? {
+            return Err(AccountError::NicknameTaken);
+        }
+
+        // 哈希在取连接前完成，避免长时间占用数据库锁
+        let password_hash = hash_password(password);
+        let conn = self.connection();
+        conn.execute(
+            "INSERT INTO accounts (nickname, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![nickname, password_hash, Utc::now().to_rfc3339()],
+        )
+        .map_err(nickname_conflict)?;
+
+        Ok(Account {
+            id: conn.last_insert_rowid(),
+            nickname: nickname.to_string(),
+        })
+    }
+
+    /// 验证昵称和密码
+    pub fn authenticate(&self, nickname: &str, password: &str) -> Result<Account, AccountError> {
+        let row: Option<(AccountId, String)> = self
+            .connection()
+            .query_row(
+                "SELECT id, password_hash FROM accounts WHERE nickname = ?1",
+                params![nickname],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        let (id, stored_hash) = row.ok_or(AccountError::InvalidCredentials)?;
+        if !verify_password(password, &stored_hash) {
+            return Err(AccountError::InvalidCredentials);
+        }
+
+        self.connection().execute(
+            "UPDATE accounts SET last_login_at = ?1 WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), id],
+        )?;
+
+        Ok(Account {
+            id,
+            nickname: nickname.to_string(),
+        })
+    }
+
+    /// 修改密码（需验证旧密码）
+    pub fn change_password(
+        &self,
+        account_id: AccountId,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), AccountError> {
+        Self::validate_password(new_password)?;
+
+        let stored_hash: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT password_hash FROM accounts WHERE id = ?1",
+                params![account_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match stored_hash {
+            Some(hash) if verify_password(old_password, &hash) => {
+                self.connection().execute(
+                    "UPDATE accounts SET password_hash = ?1 WHERE id = ?2",
+                    params![hash_password(new_password), account_id],
+                )?;
+                Ok(())
+            }
+            _ => Err(AccountError::InvalidCredentials),
+        }
+    }
+
+    /// 昵称是否已被注册
+    pub fn is_registered(&self, nickname: &str) -> Result<bool, AccountError> {
+        let count: i64 = self.connection().query_row(
+            "SELECT COUNT(*) FROM accounts WHERE nickname = ?1",
+            params![nickname],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// 按 ID 获取账号
+    pub fn get(&self, account_id: AccountId) -> Result<Option<Account>, AccountError> {
+        let account = self
+            .connection()
+            .query_row(
+                "SELECT id, nickname FROM accounts WHERE id = ?1",
+                params![account_id],
+                |row| {
+                    Ok(Account {
+                        id: row.get(0)?,
+                        nickname: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(account)
+    }
+
+    /// 按昵称查找账号
+    pub fn find(&self, nickname: &str) -> Result<Option<Account>, AccountError> {
+        let account = self
+            .connection()
+            .query_row(
+                "SELECT id, nickname FROM accounts WHERE nickname = ?1",
+                params![nickname],
+                |row| {
+                    Ok(Account {
+                        id: row.get(0)?,
+                        nickname: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(account)
+    }
+}
+
+/// 生成密码哈希，格式：`pbkdf2-sha256$<迭代次数>$<盐>$<哈希>`
+fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let hash = derive_key(password, &salt, PBKDF2_ROUNDS);
+    format!("pbkdf2-sha256${}${}${}", PBKDF2_ROUNDS, to_hex(&salt), to_hex(&hash))
+}
+
+/// 昵称唯一约束冲突视为昵称已被注册（并发注册同一昵称时，检查与写入之间可能被抢先）
+pub(crate) fn nickname_conflict(error: rusqlite::Error) -> AccountError {
+    match &error {
+        rusqlite::Error::SqliteFailure(e, _) if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE => {
+            AccountError::NicknameTaken
+        }
+        _ => error.into(),
+    }
+}
+
+/// 验证密码是否与保存的哈希匹配
+fn verify_password(password: &str, stored: &str) -> bool {
+    let parts: Vec<&str> = stored.split('$').collect();
+    let [scheme, rounds, salt, hash] = parts.as_slice() else {
+        return false;
+    };
+    if *scheme != "pbkdf2-sha256" {
+        return false;
+    }
+    let (Ok(rounds), Some(salt), Some(expected)) = (rounds.parse(), from_hex(salt), from_hex(hash))
+    else {
+        return false;
+    };
+
+    let actual = derive_key(password, &salt, rounds);
+    constant_time_eq(&actual, &expected)
+}
+
+/// PBKDF2-HMAC-SHA256 派生
+fn derive_key(password: &str, salt: &[u8], rounds: u32) -> [u8; HASH_LEN] {
+    let mut out = [0u8; HASH_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, &mut out);
+    out
+}
+
+/// 常量时间比较，避免时序攻击
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 字节转十六进制
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 十六进制转字节
+pub(crate) fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_authenticate() {
+        let store = AccountStore::open_in_memory().unwrap();
+
+        let account = store.register("棋手", "secret123").unwrap();
+        assert_eq!(account.nickname, "棋手");
+        assert!(store.is_registered("棋手").unwrap());
+
+        let logged_in = store.authenticate("棋手", "secret123").unwrap();
+        assert_eq!(logged_in.id, account.id);
+
+        assert!(matches!(
+            store.authenticate("棋手", "wrong-password"),
+            Err(AccountError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            store.authenticate("无此人", "secret123"),
+            Err(AccountError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_and_weak_password() {
+        let store = AccountStore::open_in_memory().unwrap();
+        store.register("棋手", "secret123").unwrap();
+
+        assert!(matches!(
+            store.register("棋手", "another123"),
+            Err(AccountError::NicknameTaken)
+        ));
+        assert!(matches!(
+            store.register("新人", "123"),
+            Err(AccountError::InvalidPassword)
+        ));
+
+        // 检查之后被并发注册抢先时，唯一约束冲突同样报告昵称已被注册
+        let error = store
+            .connection()
+            .execute(
+                "INSERT INTO accounts (nickname, password_hash, created_at) VALUES ('棋手', '', '')",
+                [],
+            )
+            .map_err(nickname_conflict)
+            .unwrap_err();
+        assert!(matches!(error, AccountError::NicknameTaken));
+    }
+
+    #[test]
+    fn test_change_password() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let account = store.register("棋手", "secret123").unwrap();
+
+        assert!(store.change_password(account.id, "wrong", "newsecret").is_err());
+        store.change_password(account.id, "secret123", "newsecret").unwrap();
+
+        assert!(store.authenticate("棋手", "secret123").is_err());
+        assert!(store.authenticate("棋手", "newsecret").is_ok());
+    }
+
+    #[test]
+    fn test_password_hash_is_salted() {
+        let first = hash_password("secret123");
+        let second = hash_password("secret123");
+
+        assert_ne!(first, second);
+        assert!(verify_password("secret123", &first));
+        assert!(verify_password("secret123", &second));
+        assert!(!verify_password("secret123", "garbage"));
+    }
+
+    #[test]
+    fn test_persisted_across_reopen() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("server.db");
+
+        {
+            let store = AccountStore::open(&path).unwrap();
+            store.register("棋手", "secret123").unwrap();
+        }
+
+        let store = AccountStore::open(&path).unwrap();
+        assert!(store.authenticate("棋手", "secret123").is_ok());
+    }
+}
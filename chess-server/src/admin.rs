@@ -0,0 +1,477 @@
+//! 管理接口
+//!
+//! 供运维使用的本机 HTTP 接口，所有请求需携带 `Authorization: Bearer <令牌>`：
+//! - `GET /players`：在线玩家及状态
+//! - `GET /rooms`：房间列表及状态
+//! - `GET /rooms/{id}/fen`：房间当前局面（FEN）
+//! - `POST /players/{id}/kick?reason=...`：请出玩家
+//! - `POST /rooms/{id}/close?reason=...`：关闭房间（对局作废）
+//! - `POST /announce`：向所有连接发送公告，请求体 `{"text": "..."}`
+//! - `GET /bots`：机器人账号及当前连接数、对局数
+//! - `POST /bots`：创建机器人账号并签发 API 令牌，
+//!   请求体 `{"nickname": "...", "max_games": 4, "accept_challenges": true}`（后两项可省略）
+//! - `POST /bots/{id}/token`：重新签发机器人的 API 令牌（旧令牌失效）
+//! - `POST /shutdown`：停服
+
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+
+use protocol::{Fen, PlayerId, RoomId, RoomState, RoomType, ServerMessage};
+
+use crate::accounts::{AccountError, AccountId};
+use crate::bots::{BotSettings, DEFAULT_BOT_MAX_GAMES};
+use crate::player::{PlayerManager, PlayerStatus};
+use crate::server::{MessageHandler, ServerState};
+
+/// 未填写原因时发给玩家的说明
+const DEFAULT_KICK_REASON: &str = "已被管理员请出服务器";
+const DEFAULT_CLOSE_REASON: &str = "房间已被管理员关闭";
+
+/// 接口共享状态
+#[derive(Clone)]
+struct AdminState {
+    server: Arc<RwLock<ServerState>>,
+    token: Arc<str>,
+}
+
+/// 玩家信息
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlayerView {
+    pub id: PlayerId,
+    pub nickname: String,
+    /// online / in_room / spectating / disconnected
+    pub status: String,
+    pub room_id: Option<RoomId>,
+    pub guest: bool,
+    pub bot: bool,
+    /// 是否有连接
+    pub connected: bool,
+}
+
+/// 房间信息
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomView {
+    pub id: RoomId,
+    pub room_type: RoomType,
+    pub state: RoomState,
+    pub rated: bool,
+    /// 是否为私人房间
+    pub private: bool,
+    pub red_player: Option<String>,
+    pub black_player: Option<String>,
+    pub spectators: u32,
+}
+
+/// 房间局面
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomFen {
+    pub room_id: RoomId,
+    pub fen: String,
+}
+
+/// 公告请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Announcement {
+    pub text: String,
+}
+
+/// 机器人信息
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BotView {
+    pub account_id: AccountId,
+    pub nickname: String,
+    pub max_games: u32,
+    pub accept_challenges: bool,
+    /// 当前连接数
+    pub sessions: u32,
+    /// 进行中的对局数
+    pub games: u32,
+}
+
+/// 创建机器人请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewBot {
+    pub nickname: String,
+    pub max_games: Option<u32>,
+    pub accept_challenges: Option<bool>,
+}
+
+/// 签发的机器人令牌
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BotToken {
+    pub account_id: AccountId,
+    pub token: String,
+}
+
+/// 请出玩家、关闭房间的参数
+#[derive(Debug, Default, Deserialize)]
+struct ReasonParams {
+    reason: Option<String>,
+}
+
+/// 接口错误
+struct AdminError(StatusCode, &'static str);
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// 创建管理接口路由
+pub fn router(server: Arc<RwLock<ServerState>>, token: &str) -> Router {
+    let state = AdminState {
+        server,
+        token: Arc::from(token),
+    };
+    Router::new()
+        .route("/players", get(list_players))
+        .route("/players/{id}/kick", post(kick_player))
+        .route("/rooms", get(list_rooms))
+        .route("/rooms/{id}/fen", get(room_fen))
+        .route("/rooms/{id}/close", post(close_room))
+        .route("/announce", post(announce))
+        .route("/bots", get(list_bots).post(create_bot))
+        .route("/bots/{id}/token", post(rotate_bot_token))
+        .route("/shutdown", post(shutdown))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// 在监听器上运行管理接口，停服时退出
+pub async fn serve(listener: TcpListener, server: Arc<RwLock<ServerState>>, token: &str) -> std::io::Result<()> {
+    let shutdown = server.read().await.shutdown.clone();
+    axum::serve(listener, router(server, token))
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await
+}
+
+/// 校验令牌
+async fn authorize(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(given) if token_matches(&state.token, given) => next.run(request).await,
+        _ => AdminError(StatusCode::UNAUTHORIZED, "令牌无效").into_response(),
+    }
+}
+
+/// 比较令牌（耗时与不匹配的位置无关）
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn list_players(State(state): State<AdminState>) -> Json<Vec<PlayerView>> {
+    let server = state.server.read().await;
+    let registry = &server.players;
+    let mut players: Vec<PlayerView> = registry
+        .read()
+        .all()
+        .map(|player| {
+            let (status, room_id) = match player.status {
+                PlayerStatus::Online => ("online", None),
+                PlayerStatus::InRoom(room_id) => ("in_room", Some(room_id)),
+                PlayerStatus::Spectating(room_id) => ("spectating", Some(room_id)),
+                PlayerStatus::Disconnected(room_id) => ("disconnected", Some(room_id)),
+            };
+            PlayerView {
+                id: player.id,
+                nickname: player.nickname.clone(),
+                status: status.to_string(),
+                room_id,
+                guest: player.is_guest(),
+                bot: player.is_bot(),
+                connected: registry.is_connected(player.id),
+            }
+        })
+        .collect();
+    players.sort_by_key(|player| player.id);
+    Json(players)
+}
+
+async fn list_rooms(State(state): State<AdminState>) -> Json<Vec<RoomView>> {
+    let server = state.server.read().await;
+    let name_of = |id: Option<PlayerId>| match id {
+        Some(protocol::AI_PLAYER_ID) => Some("AI".to_string()),
+        id => id.and_then(|id| server.players.nickname(id)),
+    };
+    let mut rooms: Vec<RoomView> = server
+        .rooms
+        .list_all()
+        .into_iter()
+        .map(|summary| RoomView {
+            id: summary.id,
+            room_type: summary.room_type,
+            state: summary.state,
+            rated: summary.rated,
+            private: summary.private,
+            red_player: name_of(summary.red_player),
+            black_player: name_of(summary.black_player),
+            spectators: summary.spectators,
+        })
+        .collect();
+    rooms.sort_by_key(|room| room.id);
+    Json(rooms)
+}
+
+async fn room_fen(State(state): State<AdminState>, Path(room_id): Path<RoomId>) -> Result<Json<RoomFen>, AdminError> {
+    let room = state.server.read().await.rooms.get(room_id);
+    let fen = match room {
+        Some(room) => {
+            room.with_room(|room| match &room.game_state {
+                Some(game_state) => Fen::to_string(game_state),
+                None => room.initial_fen.clone(),
+            })
+            .await
+        }
+        None => None,
+    };
+    let fen = fen.ok_or(AdminError(StatusCode::NOT_FOUND, "房间不存在"))?;
+    Ok(Json(RoomFen { room_id, fen }))
+}
+
+async fn kick_player(
+    State(state): State<AdminState>,
+    Path(player_id): Path<PlayerId>,
+    Query(params): Query<ReasonParams>,
+) -> Result<StatusCode, AdminError> {
+    let reason = params.reason.unwrap_or_else(|| DEFAULT_KICK_REASON.to_string());
+    let mut lobby = &*state.server;
+    if !MessageHandler::kick_player(&mut lobby, player_id, reason).await {
+        return Err(AdminError(StatusCode::NOT_FOUND, "玩家不存在"));
+    }
+    tracing::info!("管理员请出玩家 {}", player_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn close_room(
+    State(state): State<AdminState>,
+    Path(room_id): Path<RoomId>,
+    Query(params): Query<ReasonParams>,
+) -> Result<StatusCode, AdminError> {
+    let reason = params.reason.unwrap_or_else(|| DEFAULT_CLOSE_REASON.to_string());
+    let mut lobby = &*state.server;
+    if !MessageHandler::close_room(&mut lobby, room_id, reason).await {
+        return Err(AdminError(StatusCode::NOT_FOUND, "房间不存在"));
+    }
+    tracing::info!("管理员关闭房间 {}", room_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn announce(
+    State(state): State<AdminState>,
+    Json(announcement): Json<Announcement>,
+) -> Result<Json<serde_json::Value>, AdminError> {
+    let text = announcement.text.trim().to_string();
+    if text.is_empty() {
+        return Err(AdminError(StatusCode::BAD_REQUEST, "公告内容为空"));
+    }
+    let players = state.server.read().await.players.clone();
+    let delivered = players.broadcast(ServerMessage::Announcement { text });
+    tracing::info!("管理员发布公告，送达 {} 个连接", delivered);
+    Ok(Json(serde_json::json!({ "delivered": delivered })))
+}
+
+/// 账号库错误
+fn database_error(e: AccountError) -> AdminError {
+    tracing::error!("管理接口访问账号库失败: {}", e);
+    AdminError(StatusCode::INTERNAL_SERVER_ERROR, "账号库错误")
+}
+
+async fn list_bots(State(state): State<AdminState>) -> Result<Json<Vec<BotView>>, AdminError> {
+    let server = state.server.read().await;
+    let bots = server.accounts.list_bots().map_err(database_error)?;
+    let players = server.players.read();
+    let views = bots
+        .into_iter()
+        .map(|bot| {
+            let sessions: Vec<_> = players.sessions_of(bot.account.id).collect();
+            BotView {
+                account_id: bot.account.id,
+                nickname: bot.account.nickname,
+                max_games: bot.settings.max_games,
+                accept_challenges: bot.settings.accept_challenges,
+                sessions: sessions.len() as u32,
+                games: sessions.iter().filter(|p| p.in_game()).count() as u32,
+            }
+        })
+        .collect();
+    Ok(Json(views))
+}
+
+async fn create_bot(
+    State(state): State<AdminState>,
+    Json(new_bot): Json<NewBot>,
+) -> Result<(StatusCode, Json<BotToken>), AdminError> {
+    let nickname = new_bot.nickname.trim();
+    if PlayerManager::validate_nickname(nickname).is_err() {
+        return Err(AdminError(StatusCode::BAD_REQUEST, "昵称无效"));
+    }
+    let settings = BotSettings {
+        max_games: new_bot.max_games.unwrap_or(DEFAULT_BOT_MAX_GAMES),
+        accept_challenges: new_bot.accept_challenges.unwrap_or(true),
+    };
+    if settings.max_games == 0 {
+        return Err(AdminError(StatusCode::BAD_REQUEST, "对局数上限必须大于 0"));
+    }
+
+    let accounts = state.server.read().await.accounts.clone();
+    let (account, token) = match accounts.create_bot(nickname, settings) {
+        Ok(created) => created,
+        Err(AccountError::NicknameTaken) => return Err(AdminError(StatusCode::CONFLICT, "昵称已被注册")),
+        Err(e) => return Err(database_error(e)),
+    };
+    tracing::info!("管理员创建机器人 {} (账号 {})", account.nickname, account.id);
+    Ok((StatusCode::CREATED, Json(BotToken { account_id: account.id, token })))
+}
+
+async fn rotate_bot_token(
+    State(state): State<AdminState>,
+    Path(account_id): Path<AccountId>,
+) -> Result<Json<BotToken>, AdminError> {
+    let accounts = state.server.read().await.accounts.clone();
+    let token = accounts
+        .rotate_bot_token(account_id)
+        .map_err(database_error)?
+        .ok_or(AdminError(StatusCode::NOT_FOUND, "机器人不存在"))?;
+    tracing::info!("管理员重新签发机器人 {} 的令牌", account_id);
+    Ok(Json(BotToken { account_id, token }))
+}
+
+async fn shutdown(State(state): State<AdminState>) -> StatusCode {
+    tracing::warn!("管理员触发停服");
+    state.server.read().await.shutdown.trigger();
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+
+    use crate::accounts::AccountStore;
+    use crate::config::ServerConfig;
+    use protocol::ClientMessage;
+
+    const TOKEN: &str = "secret";
+
+    /// 启动管理接口，返回监听地址
+    async fn start(server: Arc<RwLock<ServerState>>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, server, TOKEN).await });
+        addr
+    }
+
+    /// 发送一个 HTTP 请求，返回状态码和响应体
+    async fn request(addr: SocketAddr, method: &str, path: &str, token: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {token}\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let server = ServerState::with_accounts(ServerConfig::default(), AccountStore::open_in_memory().unwrap());
+        let server = Arc::new(RwLock::new(server.unwrap()));
+        let addr = start(server.clone()).await;
+
+        assert_eq!(request(addr, "GET", "/players", "wrong").await.0, 401);
+        assert_eq!(request(addr, "POST", "/shutdown", "").await.0, 401);
+        assert!(!server.read().await.shutdown.is_triggered());
+
+        assert_eq!(request(addr, "POST", "/shutdown", TOKEN).await.0, 202);
+        assert!(server.read().await.shutdown.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_and_kick() {
+        let server = ServerState::with_accounts(ServerConfig::default(), AccountStore::open_in_memory().unwrap());
+        let server = Arc::new(RwLock::new(server.unwrap()));
+        let addr = start(server.clone()).await;
+
+        // 玩家登录并创建人机私人房间（不出现在大厅列表中）
+        let login = ClientMessage::Login { nickname: "玩家".to_string(), password: None };
+        let player_id = match MessageHandler::dispatch(&server, 0, login).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            other => panic!("登录失败: {:?}", other),
+        };
+        let (tx, mut rx) = mpsc::channel(16);
+        server.read().await.players.attach(player_id, tx);
+        let create = ClientMessage::CreateRoom {
+            room_type: RoomType::PvE(protocol::Difficulty::Easy),
+            preferred_side: Some(protocol::Side::Red),
+            rated: false,
+            time_control: protocol::TimeControl::default(),
+            rules: protocol::RuleSet::default(),
+            initial_fen: None,
+            access: protocol::RoomAccess::Invite,
+        };
+        MessageHandler::dispatch(&server, player_id, create).await;
+        let room_id = match server.read().await.players.status(player_id) {
+            Some(PlayerStatus::InRoom(room_id)) => room_id,
+            other => panic!("创建房间失败: {:?}", other),
+        };
+
+        let (status, body) = request(addr, "GET", "/players", TOKEN).await;
+        assert_eq!(status, 200);
+        let players: Vec<PlayerView> = serde_json::from_str(&body).unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!((players[0].status.as_str(), players[0].room_id), ("in_room", Some(room_id)));
+        assert!(players[0].connected);
+
+        let (status, body) = request(addr, "GET", "/rooms", TOKEN).await;
+        assert_eq!(status, 200);
+        let rooms: Vec<RoomView> = serde_json::from_str(&body).unwrap();
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].black_player.as_deref(), Some("AI"));
+
+        let (status, body) = request(addr, "GET", &format!("/rooms/{}/fen", room_id), TOKEN).await;
+        assert_eq!(status, 200);
+        let fen: RoomFen = serde_json::from_str(&body).unwrap();
+        assert_eq!(fen.fen, protocol::INITIAL_FEN);
+        assert_eq!(request(addr, "GET", "/rooms/999/fen", TOKEN).await.0, 404);
+
+        // 请出玩家：收到通知，房间随之关闭
+        let path = format!("/players/{}/kick?reason=test", player_id);
+        assert_eq!(request(addr, "POST", &path, TOKEN).await.0, 204);
+        let kicked = std::iter::from_fn(|| rx.try_recv().ok()).any(|msg| matches!(msg, ServerMessage::Kicked { .. }));
+        assert!(kicked);
+        {
+            let server = server.read().await;
+            assert!(!server.players.read().exists(player_id));
+            assert!(server.rooms.get(room_id).is_none());
+        }
+        assert_eq!(request(addr, "POST", &path, TOKEN).await.0, 404);
+    }
+}
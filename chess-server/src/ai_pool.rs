@@ -0,0 +1,207 @@
+//! AI 计算池
+//!
+//! 人机对局的 AI 搜索在阻塞线程上进行，同时进行的搜索数不超过配置的 AI 计算线程数，
+//! 超出的请求排队等待。房间任务提交请求后继续处理其他消息，结果通过通道取回。
+//! 每个房间持有一个 [`AiSession`]，随请求交给计算线程、随结果交还，跨回合保留置换表
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{oneshot, Semaphore};
+
+use chess_ai::AiSession;
+use protocol::{BoardState, Difficulty, Move};
+
+use crate::metrics::ServerMetrics;
+
+/// AI 计算池（克隆后共享同一组计算线程）
+#[derive(Clone)]
+pub struct AiPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    /// 搜索许可，数量即同时进行的搜索上限
+    permits: Arc<Semaphore>,
+    workers: usize,
+    /// 每个会话的置换表上限（MB）
+    session_tt_mb: usize,
+    /// 搜索耗时与节点数记入服务器指标
+    metrics: Arc<ServerMetrics>,
+    /// 等待许可的请求数
+    queued: AtomicUsize,
+    /// 正在搜索的请求数
+    running: AtomicUsize,
+    /// 已完成的搜索数
+    completed: AtomicU64,
+    /// 累计排队时间（微秒）
+    total_wait_us: AtomicU64,
+    /// 最长排队时间（微秒）
+    max_wait_us: AtomicU64,
+}
+
+/// 计算池运行指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiPoolMetrics {
+    /// 同时进行的搜索上限
+    pub workers: usize,
+    /// 排队中的请求数
+    pub queued: usize,
+    /// 正在搜索的请求数
+    pub running: usize,
+    /// 已完成的搜索数
+    pub completed: u64,
+    /// 平均排队时间
+    pub average_wait: Duration,
+    /// 最长排队时间
+    pub max_wait: Duration,
+}
+
+impl AiPool {
+    /// 创建计算池，`workers` 为同时进行的搜索上限，`session_tt_mb` 为每个会话的置换表上限（MB）
+    pub fn new(workers: usize, session_tt_mb: usize, metrics: Arc<ServerMetrics>) -> Self {
+        let workers = workers.max(1);
+        Self {
+            inner: Arc::new(PoolInner {
+                permits: Arc::new(Semaphore::new(workers)),
+                workers,
+                session_tt_mb,
+                metrics,
+                queued: AtomicUsize::new(0),
+                running: AtomicUsize::new(0),
+                completed: AtomicU64::new(0),
+                total_wait_us: AtomicU64::new(0),
+                max_wait_us: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// 为一局棋创建会话（置换表不超过配置的上限）
+    pub fn new_session(&self, difficulty: Difficulty) -> AiSession {
+        AiSession::with_memory_limit(difficulty, self.inner.session_tt_mb)
+    }
+
+    /// 提交一次搜索，返回接收结果的通道，会话随结果交还（无合法走法时走法为 None）
+    ///
+    /// `history` 为当前局面之前的对局局面
+    pub fn submit(
+        &self,
+        mut session: AiSession,
+        state: BoardState,
+        history: Vec<BoardState>,
+    ) -> oneshot::Receiver<(AiSession, Option<Move>)> {
+        let (tx, rx) = oneshot::channel();
+        let inner = self.inner.clone();
+        inner.queued.fetch_add(1, Ordering::Relaxed);
+        let submitted_at = Instant::now();
+
+        tokio::spawn(async move {
+            let permit = inner.permits.clone().acquire_owned().await;
+            inner.queued.fetch_sub(1, Ordering::Relaxed);
+            let Ok(permit) = permit else {
+                return;
+            };
+            inner.record_wait(submitted_at.elapsed());
+            // 房间已关闭或局面已变化，结果不会被采用，不再占用计算线程
+            if tx.is_closed() {
+                inner.metrics.ai_searches_abandoned.inc();
+                return;
+            }
+
+            inner.running.fetch_add(1, Ordering::Relaxed);
+            let metrics = inner.metrics.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let started_at = Instant::now();
+                let best = session.search(&state, &history);
+                metrics.ai_search(started_at.elapsed(), session.engine().nodes_searched());
+                (session, best)
+            })
+            .await;
+            inner.running.fetch_sub(1, Ordering::Relaxed);
+            inner.completed.fetch_add(1, Ordering::Relaxed);
+            drop(permit);
+
+            match result {
+                Ok(result) => {
+                    let _ = tx.send(result);
+                }
+                Err(e) => tracing::error!("AI 搜索任务失败: {}", e),
+            }
+        });
+
+        rx
+    }
+
+    /// 当前运行指标
+    pub fn metrics(&self) -> AiPoolMetrics {
+        let inner = &self.inner;
+        let completed = inner.completed.load(Ordering::Relaxed);
+        let total_wait = inner.total_wait_us.load(Ordering::Relaxed);
+        AiPoolMetrics {
+            workers: inner.workers,
+            queued: inner.queued.load(Ordering::Relaxed),
+            running: inner.running.load(Ordering::Relaxed),
+            completed,
+            average_wait: Duration::from_micros(total_wait.checked_div(completed).unwrap_or(0)),
+            max_wait: Duration::from_micros(inner.max_wait_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl PoolInner {
+    fn record_wait(&self, wait: Duration) {
+        let wait_us = wait.as_micros() as u64;
+        self.total_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+        self.max_wait_us.fetch_max(wait_us, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_queues_beyond_limit() {
+        let server_metrics = Arc::new(ServerMetrics::new());
+        let pool = AiPool::new(1, 1, server_metrics.clone());
+        let state = BoardState::initial();
+
+        let first = pool.submit(pool.new_session(Difficulty::Easy), state.clone(), Vec::new());
+        let second = pool.submit(pool.new_session(Difficulty::Easy), state, Vec::new());
+        assert_eq!(pool.metrics().queued, 2);
+
+        let (session, best) = first.await.unwrap();
+        assert!(best.is_some());
+        assert!(session.memory_bytes() <= 1024 * 1024);
+        assert!(second.await.unwrap().1.is_some());
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.queued, metrics.running, metrics.completed), (0, 0, 2));
+        // 第二个请求要等第一个搜索完成
+        assert!(metrics.max_wait > Duration::ZERO);
+        // 搜索耗时记入服务器指标
+        assert!(server_metrics.render(&[], metrics).contains("chess_ai_search_seconds_count 2"));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_search_skipped() {
+        let server_metrics = Arc::new(ServerMetrics::new());
+        let pool = AiPool::new(1, 1, server_metrics.clone());
+        let state = BoardState::initial();
+
+        // 排队中的请求在轮到之前已无人等待结果
+        let first = pool.submit(pool.new_session(Difficulty::Easy), state.clone(), Vec::new());
+        drop(pool.submit(pool.new_session(Difficulty::Easy), state, Vec::new()));
+        assert!(first.await.unwrap().1.is_some());
+        while server_metrics.ai_searches_abandoned.get() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let metrics = pool.metrics();
+        assert_eq!((metrics.running, metrics.completed), (0, 1));
+        let text = server_metrics.render(&[], metrics);
+        assert!(text.contains("chess_ai_searches_abandoned_total 1"));
+        assert!(text.contains("chess_ai_search_seconds_count 1"));
+    }
+}
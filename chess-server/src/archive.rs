@@ -0,0 +1,240 @@
+//! 对局归档
+//!
+//! 每局结束的对局（含结果、每步用时和双方信息）自动写入 `games` 表，
+//! 注册账号的玩家可分页查询自己的历史对局并取回完整棋谱
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use protocol::{GameHistoryFilter, GameOutcome, GameRecord, GameResult, GameSummary, Side};
+
+use crate::accounts::{AccountError, AccountId, AccountStore};
+
+/// 查询条件：对局属于 ?1 且满足各项筛选（参数为 NULL 表示不限）
+const HISTORY_CONDITION: &str = "(red_account = ?1 OR black_account = ?1)
+    AND (?2 IS NULL OR rated = ?2)
+    AND (?3 IS NULL
+         OR (red_account IS ?1 AND black_player = ?3)
+         OR (black_account IS ?1 AND red_player = ?3))
+    AND (?4 IS NULL OR (CASE
+            WHEN winner = 'draw' THEN 'draw'
+            WHEN (winner = 'red') = (red_account IS ?1) THEN 'win'
+            ELSE 'loss'
+         END) = ?4)";
+
+/// 胜方在数据库中的表示
+fn winner_str(result: &GameResult) -> &'static str {
+    match result {
+        GameResult::RedWin(_) => "red",
+        GameResult::BlackWin(_) => "black",
+        GameResult::Draw(_) => "draw",
+    }
+}
+
+/// 胜负在数据库中的表示
+fn outcome_str(outcome: GameOutcome) -> &'static str {
+    match outcome {
+        GameOutcome::Win => "win",
+        GameOutcome::Loss => "loss",
+        GameOutcome::Draw => "draw",
+    }
+}
+
+/// 在给定连接（或事务）上写入一局归档，返回对局 ID
+pub(crate) fn insert_game(
+    conn: &Connection,
+    red_account: Option<AccountId>,
+    black_account: Option<AccountId>,
+    rated: bool,
+    result: &GameResult,
+    record: &GameRecord,
+    finished_at: u64,
+) -> Result<u64, AccountError> {
+    conn.execute(
+        "INSERT INTO games (red_account, black_account, red_player, black_player, rated,
+                            winner, result, time_control, move_count, finished_at, record)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            red_account,
+            black_account,
+            record.metadata.red_player,
+            record.metadata.black_player,
+            rated,
+            winner_str(result),
+            serde_json::to_string(result)?,
+            record.metadata.time_control.clone().unwrap_or_default(),
+            record.moves.len() as u32,
+            finished_at as i64,
+            serde_json::to_string(record)?,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+impl AccountStore {
+    /// 归档一局已结束的对局，返回对局 ID
+    ///
+    /// 游客和 AI 一方的账号为 None；`finished_at` 为 Unix 时间戳（毫秒）
+    pub fn archive_game(
+        &self,
+        red_account: Option<AccountId>,
+        black_account: Option<AccountId>,
+        rated: bool,
+        result: &GameResult,
+        record: &GameRecord,
+        finished_at: u64,
+    ) -> Result<u64, AccountError> {
+        insert_game(&self.connection(), red_account, black_account, rated, result, record, finished_at)
+    }
+
+    /// 分页查询账号的历史对局（按结束时间倒序），返回 (本页对局, 总局数)
+    pub fn list_games(
+        &self,
+        account_id: AccountId,
+        filter: &GameHistoryFilter,
+        page: u32,
+        page_size: u32,
+    ) -> Result<(Vec<GameSummary>, u32), AccountError> {
+        let conn = self.connection();
+        let rated = filter.rated;
+        let opponent = filter.opponent.as_deref();
+        let outcome = filter.outcome.map(outcome_str);
+
+        let total: u32 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM games WHERE {}", HISTORY_CONDITION),
+            params![account_id, rated, opponent, outcome],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, red_player, black_player, red_account IS ?1, result, rated,
+                    time_control, move_count, finished_at
+             FROM games WHERE {}
+             ORDER BY finished_at DESC, id DESC
+             LIMIT ?5 OFFSET ?6",
+            HISTORY_CONDITION
+        ))?;
+        let rows = stmt.query_map(
+            params![
+                account_id,
+                rated,
+                opponent,
+                outcome,
+                page_size,
+                page as i64 * page_size as i64
+            ],
+            |row| {
+                let is_red: bool = row.get(3)?;
+                let result: String = row.get(4)?;
+                let result = serde_json::from_str(&result).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e))
+                })?;
+                let finished_at: i64 = row.get(8)?;
+                Ok(GameSummary {
+                    game_id: row.get::<_, i64>(0)? as u64,
+                    red_player: row.get(1)?,
+                    black_player: row.get(2)?,
+                    your_side: if is_red { Side::Red } else { Side::Black },
+                    result,
+                    rated: row.get(5)?,
+                    time_control: row.get(6)?,
+                    move_count: row.get(7)?,
+                    finished_at: finished_at.max(0) as u64,
+                })
+            },
+        )?;
+
+        let games = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok((games, total))
+    }
+
+    /// 获取账号参与过的一局历史对局的棋谱 JSON（对局不存在或不属于该账号时返回 None）
+    pub fn fetch_game(&self, account_id: AccountId, game_id: u64) -> Result<Option<String>, AccountError> {
+        let record = self
+            .connection()
+            .query_row(
+                "SELECT record FROM games WHERE id = ?1 AND (red_account = ?2 OR black_account = ?2)",
+                params![game_id as i64, account_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{DrawReason, WinReason};
+
+    fn archive(
+        store: &AccountStore,
+        red: (Option<AccountId>, &str),
+        black: (Option<AccountId>, &str),
+        result: GameResult,
+        finished_at: u64,
+    ) -> u64 {
+        let mut record = GameRecord::new(red.1.to_string(), black.1.to_string());
+        record.set_result(result.clone());
+        store
+            .archive_game(red.0, black.0, red.0.is_some() && black.0.is_some(), &result, &record, finished_at)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_archive_and_list() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let alice = store.register("alice", "secret1").unwrap().id;
+        let bob = store.register("bob", "secret2").unwrap().id;
+
+        let first = archive(&store, (Some(alice), "alice"), (Some(bob), "bob"), GameResult::RedWin(WinReason::Checkmate), 1);
+        archive(&store, (Some(bob), "bob"), (Some(alice), "alice"), GameResult::RedWin(WinReason::Resign), 2);
+        archive(&store, (None, "游客"), (Some(alice), "alice"), GameResult::Draw(DrawReason::Agreement), 3);
+
+        let (games, total) = store.list_games(alice, &GameHistoryFilter::default(), 0, 2).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(games.len(), 2);
+        // 最近结束的在前
+        assert_eq!(games[0].finished_at, 3);
+        assert_eq!(games[0].your_side, Side::Black);
+        assert!(!games[0].rated);
+
+        let (games, _) = store.list_games(alice, &GameHistoryFilter::default(), 1, 2).unwrap();
+        assert_eq!(games[0].game_id, first);
+        assert_eq!(games[0].result, GameResult::RedWin(WinReason::Checkmate));
+
+        let wins = GameHistoryFilter { outcome: Some(GameOutcome::Win), ..Default::default() };
+        let (games, total) = store.list_games(alice, &wins, 0, 10).unwrap();
+        assert_eq!((total, games[0].game_id), (1, first));
+
+        let losses = GameHistoryFilter { outcome: Some(GameOutcome::Loss), ..Default::default() };
+        assert_eq!(store.list_games(alice, &losses, 0, 10).unwrap().1, 1);
+        assert_eq!(store.list_games(bob, &losses, 0, 10).unwrap().1, 1);
+
+        let draws = GameHistoryFilter { outcome: Some(GameOutcome::Draw), ..Default::default() };
+        assert_eq!(store.list_games(alice, &draws, 0, 10).unwrap().1, 1);
+
+        let vs_bob = GameHistoryFilter { opponent: Some("bob".to_string()), ..Default::default() };
+        assert_eq!(store.list_games(alice, &vs_bob, 0, 10).unwrap().1, 2);
+
+        let rated = GameHistoryFilter { rated: Some(true), ..Default::default() };
+        assert_eq!(store.list_games(alice, &rated, 0, 10).unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_fetch_game() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let alice = store.register("alice", "secret1").unwrap().id;
+        let bob = store.register("bob", "secret2").unwrap().id;
+        let game_id = archive(&store, (Some(alice), "alice"), (None, "AI"), GameResult::BlackWin(WinReason::Timeout), 1);
+
+        let json = store.fetch_game(alice, game_id).unwrap().unwrap();
+        let record = GameRecord::from_json(&json).unwrap();
+        assert_eq!(record.metadata.black_player, "AI");
+        assert_eq!(record.metadata.result, Some(GameResult::BlackWin(WinReason::Timeout)));
+
+        // 只能获取自己参与的对局
+        assert!(store.fetch_game(bob, game_id).unwrap().is_none());
+        assert!(store.fetch_game(alice, game_id + 1).unwrap().is_none());
+    }
+}
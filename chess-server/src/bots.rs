@@ -0,0 +1,193 @@
+//! 机器人账号
+//!
+//! 机器人是一类特殊的注册账号：没有密码，由管理员创建并签发 API 令牌，
+//! 外部程序凭令牌登录。令牌格式 `<账号 ID>.<随机数>`，只保存随机数的 SHA-256 摘要。
+//! 同一机器人可同时建立多个连接，每个连接各下一局，总对局数受账号的并发上限约束
+
+use chrono::Utc;
+use rand::RngCore;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{
+    constant_time_eq, from_hex, nickname_conflict, to_hex, Account, AccountError, AccountId, AccountStore,
+};
+
+/// 令牌随机数长度（字节）
+const TOKEN_SECRET_LEN: usize = 24;
+
+/// 机器人账号的密码哈希占位（不是合法哈希，密码登录永远失败）
+const NO_PASSWORD: &str = "!bot";
+
+/// 默认的同时对局数上限
+pub const DEFAULT_BOT_MAX_GAMES: u32 = 4;
+
+/// 机器人设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BotSettings {
+    /// 同时进行的对局数上限（含匹配队列中的连接）
+    pub max_games: u32,
+    /// 是否自动接受挑战
+    pub accept_challenges: bool,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            max_games: DEFAULT_BOT_MAX_GAMES,
+            accept_challenges: true,
+        }
+    }
+}
+
+/// 机器人账号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotAccount {
+    pub account: Account,
+    pub settings: BotSettings,
+}
+
+/// 令牌随机数的摘要（十六进制）
+fn token_digest(secret: &[u8]) -> String {
+    to_hex(&Sha256::digest(secret))
+}
+
+/// 生成新令牌，返回 (令牌, 摘要)
+fn new_token(account_id: AccountId) -> (String, String) {
+    let mut secret = [0u8; TOKEN_SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    (format!("{}.{}", account_id, to_hex(&secret)), token_digest(&secret))
+}
+
+impl AccountStore {
+    /// 创建机器人账号，返回账号和 API 令牌（令牌只在此时可见）
+    pub fn create_bot(&self, nickname: &str, settings: BotSettings) -> Result<(Account, String), AccountError> {
+        if self.is_registered(nickname)? {
+            return Err(AccountError::NicknameTaken);
+        }
+
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO accounts (nickname, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![nickname, NO_PASSWORD, Utc::now().to_rfc3339()],
+        )
+        .map_err(nickname_conflict)?;
+        let id = tx.last_insert_rowid();
+        let (token, digest) = new_token(id);
+        tx.execute(
+            "INSERT INTO bots (account_id, token_hash, max_games, accept_challenges) VALUES (?1, ?2, ?3, ?4)",
+            params![id, digest, settings.max_games, settings.accept_challenges],
+        )?;
+        tx.commit()?;
+
+        let account = Account {
+            id,
+            nickname: nickname.to_string(),
+        };
+        Ok((account, token))
+    }
+
+    /// 凭 API 令牌验证机器人
+    pub fn authenticate_bot(&self, token: &str) -> Result<BotAccount, AccountError> {
+        let parsed = token
+            .split_once('.')
+            .and_then(|(id, secret)| Some((id.parse::<AccountId>().ok()?, from_hex(secret)?)));
+        let (id, secret) = parsed.ok_or(AccountError::InvalidCredentials)?;
+
+        let row: Option<(String, String, u32, bool)> = self
+            .connection()
+            .query_row(
+                "SELECT a.nickname, b.token_hash, b.max_games, b.accept_challenges
+                 FROM bots b JOIN accounts a ON a.id = b.account_id WHERE b.account_id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        let (nickname, digest, max_games, accept_challenges) = row.ok_or(AccountError::InvalidCredentials)?;
+        if !constant_time_eq(token_digest(&secret).as_bytes(), digest.as_bytes()) {
+            return Err(AccountError::InvalidCredentials);
+        }
+
+        self.connection().execute(
+            "UPDATE accounts SET last_login_at = ?1 WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), id],
+        )?;
+
+        Ok(BotAccount {
+            account: Account { id, nickname },
+            settings: BotSettings {
+                max_games,
+                accept_challenges,
+            },
+        })
+    }
+
+    /// 重新签发机器人的 API 令牌（旧令牌立即失效），机器人不存在时返回 None
+    pub fn rotate_bot_token(&self, account_id: AccountId) -> Result<Option<String>, AccountError> {
+        let (token, digest) = new_token(account_id);
+        let updated = self.connection().execute(
+            "UPDATE bots SET token_hash = ?1 WHERE account_id = ?2",
+            params![digest, account_id],
+        )?;
+        Ok((updated > 0).then_some(token))
+    }
+
+    /// 所有机器人账号（按创建顺序）
+    pub fn list_bots(&self) -> Result<Vec<BotAccount>, AccountError> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(
+            "SELECT a.id, a.nickname, b.max_games, b.accept_challenges
+             FROM bots b JOIN accounts a ON a.id = b.account_id ORDER BY a.id",
+        )?;
+        let bots = stmt
+            .query_map([], |row| {
+                Ok(BotAccount {
+                    account: Account {
+                        id: row.get(0)?,
+                        nickname: row.get(1)?,
+                    },
+                    settings: BotSettings {
+                        max_games: row.get(2)?,
+                        accept_challenges: row.get(3)?,
+                    },
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(bots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bot_token() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let settings = BotSettings {
+            max_games: 2,
+            accept_challenges: false,
+        };
+        let (account, token) = store.create_bot("象棋机器人", settings).unwrap();
+
+        let bot = store.authenticate_bot(&token).unwrap();
+        assert_eq!(bot.account, account);
+        assert_eq!(bot.settings, settings);
+        assert_eq!(store.list_bots().unwrap(), vec![bot]);
+
+        // 机器人不能用密码登录，昵称也不能再注册
+        assert!(store.authenticate("象棋机器人", NO_PASSWORD).is_err());
+        assert!(matches!(store.register("象棋机器人", "secret123"), Err(AccountError::NicknameTaken)));
+
+        // 篡改或换发后旧令牌失效
+        let forged = format!("{}.{}", account.id, "00".repeat(TOKEN_SECRET_LEN));
+        assert!(matches!(store.authenticate_bot(&forged), Err(AccountError::InvalidCredentials)));
+        assert!(store.authenticate_bot("not-a-token").is_err());
+        let rotated = store.rotate_bot_token(account.id).unwrap().unwrap();
+        assert!(store.authenticate_bot(&token).is_err());
+        assert!(store.authenticate_bot(&rotated).is_ok());
+        assert_eq!(store.rotate_bot_token(account.id + 1).unwrap(), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! 挑战
+//!
+//! 向指定玩家发起的对局邀请。机器人自动接受（不经过这里），
+//! 其他玩家的挑战在此登记，等待对方接受或拒绝，超过有效时间自动撤回
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use protocol::{ChallengeId, ChallengeInfo, PlayerId, RuleSet, TimeControl, CHALLENGE_TIMEOUT_SECS};
+
+/// 每位玩家同时发出的挑战数上限
+pub const MAX_OUTGOING_CHALLENGES: usize = 5;
+
+/// 挑战的有效时间
+pub const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(CHALLENGE_TIMEOUT_SECS);
+
+/// 发起挑战失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeError {
+    /// 已向对方发出挑战
+    Duplicate,
+    /// 发出的挑战过多
+    TooMany,
+}
+
+/// 待回应的挑战
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub id: ChallengeId,
+    /// 发起方
+    pub from: PlayerId,
+    /// 被挑战方
+    pub to: PlayerId,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    pub rated: bool,
+    pub expires_at: Instant,
+}
+
+impl Challenge {
+    /// 发给客户端的挑战信息
+    pub fn info(&self, challenger: String, challenged: String, now: Instant) -> ChallengeInfo {
+        ChallengeInfo {
+            id: self.id,
+            challenger,
+            challenged,
+            time_control: self.time_control,
+            rules: self.rules,
+            rated: self.rated,
+            expires_in_secs: self.expires_at.saturating_duration_since(now).as_secs() as u32,
+        }
+    }
+
+    /// 是否涉及某玩家
+    pub fn involves(&self, player_id: PlayerId) -> bool {
+        self.from == player_id || self.to == player_id
+    }
+}
+
+/// 挑战登记簿
+#[derive(Debug)]
+pub struct ChallengeBook {
+    pending: HashMap<ChallengeId, Challenge>,
+    next_id: ChallengeId,
+}
+
+impl ChallengeBook {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// 登记新挑战
+    pub fn issue(
+        &mut self,
+        from: PlayerId,
+        to: PlayerId,
+        time_control: TimeControl,
+        rules: RuleSet,
+        rated: bool,
+        now: Instant,
+    ) -> Result<&Challenge, ChallengeError> {
+        if self.pending.values().any(|c| c.from == from && c.to == to) {
+            return Err(ChallengeError::Duplicate);
+        }
+        if self.pending.values().filter(|c| c.from == from).count() >= MAX_OUTGOING_CHALLENGES {
+            return Err(ChallengeError::TooMany);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let challenge = Challenge {
+            id,
+            from,
+            to,
+            time_control,
+            rules,
+            rated,
+            expires_at: now + CHALLENGE_TIMEOUT,
+        };
+        Ok(self.pending.entry(id).or_insert(challenge))
+    }
+
+    /// 查看挑战
+    pub fn get(&self, id: ChallengeId) -> Option<&Challenge> {
+        self.pending.get(&id)
+    }
+
+    /// 取出挑战（接受、拒绝或撤回）
+    pub fn take(&mut self, id: ChallengeId) -> Option<Challenge> {
+        self.pending.remove(&id)
+    }
+
+    /// 取出涉及某玩家的所有挑战
+    pub fn take_involving(&mut self, player_id: PlayerId) -> Vec<Challenge> {
+        self.take_where(|c| c.involves(player_id))
+    }
+
+    /// 取出已过期的挑战
+    pub fn take_expired(&mut self, now: Instant) -> Vec<Challenge> {
+        self.take_where(|c| c.expires_at <= now)
+    }
+
+    fn take_where(&mut self, pred: impl Fn(&Challenge) -> bool) -> Vec<Challenge> {
+        let ids: Vec<ChallengeId> = self.pending.values().filter(|c| pred(c)).map(|c| c.id).collect();
+        let mut taken: Vec<Challenge> = ids.into_iter().filter_map(|id| self.pending.remove(&id)).collect();
+        taken.sort_by_key(|c| c.id);
+        taken
+    }
+
+    /// 待回应的挑战数
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 是否没有待回应的挑战
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Default for ChallengeBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_and_expire() {
+        let mut book = ChallengeBook::new();
+        let now = Instant::now();
+        let issue = |book: &mut ChallengeBook, from, to, now| {
+            book.issue(from, to, TimeControl::default(), RuleSet::default(), false, now)
+                .map(|c| c.id)
+        };
+
+        let first = issue(&mut book, 1, 2, now).unwrap();
+        assert_eq!(issue(&mut book, 1, 2, now), Err(ChallengeError::Duplicate));
+        for to in 3..3 + MAX_OUTGOING_CHALLENGES as PlayerId - 1 {
+            issue(&mut book, 1, to, now).unwrap();
+        }
+        assert_eq!(issue(&mut book, 1, 99, now), Err(ChallengeError::TooMany));
+
+        let info = book.get(first).unwrap().info("甲".into(), "乙".into(), now + Duration::from_secs(10));
+        assert_eq!(info.expires_in_secs, CHALLENGE_TIMEOUT_SECS as u32 - 10);
+
+        // 其他玩家发起的挑战不受影响
+        let later = now + Duration::from_secs(30);
+        let other = issue(&mut book, 2, 1, later).unwrap();
+        assert_eq!(book.take_involving(3).len(), 1);
+
+        let expired = book.take_expired(now + CHALLENGE_TIMEOUT);
+        assert_eq!(expired.len(), MAX_OUTGOING_CHALLENGES - 1);
+        assert_eq!(expired[0].id, first);
+        assert!(book.take(other).is_some());
+        assert!(book.is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+//! 聊天
+//!
+//! 大厅与房间聊天的公共部分：消息校验、敏感词过滤、发言频率限制和房间聊天记录
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use protocol::{ChatChannel, ChatMessage, PlayerId, MAX_CHAT_LEN};
+
+/// 每个房间保留的聊天记录条数（重连和开始观战时同步）
+pub const CHAT_HISTORY_LEN: usize = 50;
+
+/// 频率限制窗口内最多发送的消息数
+pub const CHAT_RATE_LIMIT: usize = 5;
+
+/// 频率限制窗口
+pub const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// 校验消息内容，返回去除首尾空白后的文本
+pub fn validate_text(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let len = text.chars().count();
+    (len > 0 && len <= MAX_CHAT_LEN).then_some(text)
+}
+
+/// 当前 Unix 时间戳（毫秒）
+pub fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// 敏感词过滤器
+///
+/// 不区分大小写匹配，命中的字符逐个替换为 `*`
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    /// 小写化后的敏感词
+    words: Vec<Vec<char>>,
+}
+
+impl ChatFilter {
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let words = words
+            .iter()
+            .map(|w| w.as_ref().trim().chars().map(fold_case).collect::<Vec<_>>())
+            .filter(|w| !w.is_empty())
+            .collect();
+        Self { words }
+    }
+
+    /// 过滤消息
+    pub fn apply(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let folded: Vec<char> = chars.iter().copied().map(fold_case).collect();
+        let mut masked = vec![false; chars.len()];
+
+        for word in &self.words {
+            if word.len() > folded.len() {
+                continue;
+            }
+            for start in 0..=folded.len() - word.len() {
+                if folded[start..start + word.len()] == word[..] {
+                    masked[start..start + word.len()].fill(true);
+                }
+            }
+        }
+
+        chars
+            .iter()
+            .zip(masked)
+            .map(|(&c, masked)| if masked { '*' } else { c })
+            .collect()
+    }
+}
+
+/// 逐字符小写化（保持字符位置一一对应）
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// 发言频率限制（滑动窗口）
+#[derive(Debug, Default)]
+pub struct ChatLimiter {
+    sent: HashMap<PlayerId, VecDeque<Instant>>,
+}
+
+impl ChatLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 检查玩家此刻能否发言，允许时记录本次发言
+    pub fn check(&mut self, player_id: PlayerId, now: Instant) -> bool {
+        let sent = self.sent.entry(player_id).or_default();
+        while sent
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= CHAT_RATE_WINDOW)
+        {
+            sent.pop_front();
+        }
+
+        if sent.len() >= CHAT_RATE_LIMIT {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    /// 玩家离线时清理记录
+    pub fn forget(&mut self, player_id: PlayerId) {
+        self.sent.remove(&player_id);
+    }
+}
+
+/// 房间聊天记录
+#[derive(Debug, Clone, Default)]
+pub struct ChatHistory {
+    messages: VecDeque<ChatMessage>,
+}
+
+impl ChatHistory {
+    /// 记录一条消息（超出容量时丢弃最旧的）
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.messages.len() >= CHAT_HISTORY_LEN {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    /// 最近的聊天记录
+    ///
+    /// 观战区消息只对观战者可见；`muted` 中的发送者被排除
+    pub fn recent(&self, include_spectators: bool, muted: impl Fn(PlayerId) -> bool) -> Vec<ChatMessage> {
+        self.messages
+            .iter()
+            .filter(|m| include_spectators || m.channel != ChatChannel::Spectators)
+            .filter(|m| !muted(m.from_id))
+            .cloned()
+            .collect()
+    }
+
+    /// 记录条数
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// 是否没有记录
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(channel: ChatChannel, from_id: PlayerId, text: &str) -> ChatMessage {
+        ChatMessage {
+            channel,
+            from_id,
+            from: format!("玩家{}", from_id),
+            text: text.to_string(),
+            sent_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_validate_text() {
+        assert_eq!(validate_text("  你好  "), Some("你好"));
+        assert_eq!(validate_text("   "), None);
+        assert!(validate_text(&"棋".repeat(MAX_CHAT_LEN)).is_some());
+        assert!(validate_text(&"棋".repeat(MAX_CHAT_LEN + 1)).is_none());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = ChatFilter::new(&["笨蛋", "noob", " "]);
+        assert_eq!(filter.apply("你这个笨蛋"), "你这个**");
+        assert_eq!(filter.apply("NoOb move"), "**** move");
+        assert_eq!(filter.apply("好棋"), "好棋");
+        assert_eq!(ChatFilter::default().apply("笨蛋"), "笨蛋");
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = ChatLimiter::new();
+        let now = Instant::now();
+
+        for _ in 0..CHAT_RATE_LIMIT {
+            assert!(limiter.check(1, now));
+        }
+        assert!(!limiter.check(1, now));
+        // 其他玩家不受影响
+        assert!(limiter.check(2, now));
+        // 窗口过后恢复
+        assert!(limiter.check(1, now + CHAT_RATE_WINDOW));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = ChatHistory::default();
+        for i in 0..CHAT_HISTORY_LEN + 5 {
+            history.push(message(ChatChannel::Room, 1, &i.to_string()));
+        }
+        history.push(message(ChatChannel::Spectators, 2, "观众"));
+
+        assert_eq!(history.len(), CHAT_HISTORY_LEN);
+        let for_players = history.recent(false, |_| false);
+        assert_eq!(for_players.len(), CHAT_HISTORY_LEN - 1);
+        assert_eq!(for_players[0].text, "6");
+
+        let for_spectators = history.recent(true, |id| id == 1);
+        assert_eq!(for_spectators.len(), 1);
+    }
+}
@@ -0,0 +1,453 @@
+//! 服务器配置
+//!
+//! 配置来源（优先级从低到高）：默认值 < JSON 配置文件 < 命令行参数
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use protocol::{TransportType, HEARTBEAT_TIMEOUT_SECS, MAX_CONNECTIONS, RECONNECT_TIMEOUT_SECS};
+
+use crate::rate_limit::RateLimitConfig;
+
+/// 默认服务端口
+pub const DEFAULT_PORT: u16 = 9527;
+
+/// 默认的重启宽限期（秒）
+pub const DEFAULT_RESTORE_GRACE_SECS: u64 = 180;
+
+/// 默认的每连接发送队列容量（条）
+pub const DEFAULT_SEND_QUEUE_CAPACITY: usize = 256;
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 默认格式
+    #[default]
+    Full,
+    /// 紧凑格式
+    Compact,
+    /// 多行易读格式
+    Pretty,
+    /// JSON（便于日志采集）
+    Json,
+}
+
+/// 服务器配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// 监听地址
+    pub listen_addr: String,
+    /// 传输协议（目前仅支持 TCP）
+    pub transport: TransportType,
+    /// 最大连接数
+    pub max_connections: usize,
+    /// 心跳超时（秒），超过此时间未收到客户端消息则断开；0 表示不检查
+    pub heartbeat_timeout_secs: u64,
+    /// 断线重连超时（秒），超时后断线方判负
+    pub reconnect_timeout_secs: u64,
+    /// 每个连接待发送消息的队列容量（条）
+    ///
+    /// 发送消息时不等待：队列满说明客户端读得太慢，服务器断开该连接并按断线处理，
+    /// 对局中的玩家可在重连超时内重连。容量需容纳聊天、好友状态等短时突发的消息
+    pub send_queue_capacity: usize,
+    /// 棋局存储目录（未设置时使用系统数据目录）
+    pub storage_dir: Option<PathBuf>,
+    /// 账号数据库路径（未设置时使用系统数据目录下的 server.db）
+    pub database_path: Option<PathBuf>,
+    /// 停服快照路径（未设置时使用系统数据目录下的 snapshot.json）
+    pub snapshot_path: Option<PathBuf>,
+    /// 重启后等待玩家重连的宽限期（秒），超时未回来的一方判负
+    pub restore_grace_secs: u64,
+    /// 是否允许游客登录
+    pub allow_guests: bool,
+    /// 机器人是否参与等级分（关闭时机器人不能下计分对局）
+    pub rate_bot_games: bool,
+    /// AI 计算线程数（同时进行的 AI 搜索上限）
+    pub ai_workers: usize,
+    /// 每局 AI 会话的置换表上限（MB）
+    pub ai_session_tt_mb: usize,
+    /// 管理接口监听地址（只允许本机地址，未设置时不启用）
+    pub admin_addr: Option<String>,
+    /// 管理接口令牌（请求需携带 `Authorization: Bearer <令牌>`）
+    pub admin_token: Option<String>,
+    /// 指标接口监听地址（Prometheus 文本格式，只允许本机地址，未设置时不启用）
+    pub metrics_addr: Option<String>,
+    /// 聊天敏感词（命中部分替换为 *）
+    pub chat_filter_words: Vec<String>,
+    /// 限流与防滥用
+    pub rate_limit: RateLimitConfig,
+    /// 日志输出格式
+    pub log_format: LogFormat,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: format!("0.0.0.0:{}", DEFAULT_PORT),
+            transport: TransportType::Tcp,
+            max_connections: MAX_CONNECTIONS,
+            heartbeat_timeout_secs: HEARTBEAT_TIMEOUT_SECS,
+            reconnect_timeout_secs: RECONNECT_TIMEOUT_SECS,
+            send_queue_capacity: DEFAULT_SEND_QUEUE_CAPACITY,
+            storage_dir: None,
+            database_path: None,
+            snapshot_path: None,
+            restore_grace_secs: DEFAULT_RESTORE_GRACE_SECS,
+            allow_guests: true,
+            rate_bot_games: false,
+            ai_workers: 2,
+            ai_session_tt_mb: 16,
+            admin_addr: None,
+            admin_token: None,
+            metrics_addr: None,
+            chat_filter_words: Vec::new(),
+            rate_limit: RateLimitConfig::default(),
+            log_format: LogFormat::default(),
+        }
+    }
+}
+
+/// 命令行参数
+#[derive(Debug, Default, Parser)]
+#[command(name = "chess-server", version, about = "中国象棋服务器")]
+pub struct Cli {
+    /// JSON 配置文件路径
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// 监听地址，如 0.0.0.0:9527
+    #[arg(short, long)]
+    pub listen: Option<String>,
+    /// 传输协议（tcp / quic）
+    #[arg(long, value_parser = parse_transport)]
+    pub transport: Option<TransportType>,
+    /// 最大连接数
+    #[arg(long)]
+    pub max_connections: Option<usize>,
+    /// 心跳超时（秒），0 表示不检查
+    #[arg(long)]
+    pub heartbeat_timeout: Option<u64>,
+    /// 断线重连超时（秒）
+    #[arg(long)]
+    pub reconnect_timeout: Option<u64>,
+    /// 每个连接的发送队列容量（条），积压超过时断开连接
+    #[arg(long)]
+    pub send_queue_capacity: Option<usize>,
+    /// 棋局存储目录
+    #[arg(long)]
+    pub storage_dir: Option<PathBuf>,
+    /// 账号数据库路径
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+    /// 停服快照路径
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+    /// 重启后等待玩家重连的宽限期（秒）
+    #[arg(long)]
+    pub restore_grace: Option<u64>,
+    /// 禁止游客登录
+    #[arg(long)]
+    pub no_guests: bool,
+    /// 机器人对局计入等级分
+    #[arg(long)]
+    pub rate_bot_games: bool,
+    /// AI 计算线程数
+    #[arg(long)]
+    pub ai_workers: Option<usize>,
+    /// 每局 AI 会话的置换表上限（MB）
+    #[arg(long)]
+    pub ai_session_tt_mb: Option<usize>,
+    /// 管理接口监听地址，如 127.0.0.1:9528
+    #[arg(long)]
+    pub admin_addr: Option<String>,
+    /// 管理接口令牌
+    #[arg(long)]
+    pub admin_token: Option<String>,
+    /// 指标接口监听地址，如 127.0.0.1:9529
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+    /// 关闭按消息类别的限流
+    #[arg(long)]
+    pub no_rate_limit: bool,
+    /// 单个 IP 的最大并发连接数，0 表示不限制
+    #[arg(long)]
+    pub max_connections_per_ip: Option<usize>,
+    /// 登录失败次数上限，0 表示不限制
+    #[arg(long)]
+    pub max_login_failures: Option<u32>,
+    /// 日志输出格式
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+}
+
+/// 解析传输协议名称
+fn parse_transport(s: &str) -> Result<TransportType, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "tcp" => Ok(TransportType::Tcp),
+        "quic" => Ok(TransportType::Quic),
+        _ => Err(format!("未知的传输协议: {}", s)),
+    }
+}
+
+impl ServerConfig {
+    /// 从 JSON 文件加载配置（缺省字段使用默认值）
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("读取配置文件失败: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("解析配置文件失败: {:?}", path))
+    }
+
+    /// 根据命令行参数构建配置
+    pub fn from_cli(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 用命令行参数覆盖配置
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(listen) = &cli.listen {
+            self.listen_addr = listen.clone();
+        }
+        if let Some(transport) = cli.transport {
+            self.transport = transport;
+        }
+        if let Some(max_connections) = cli.max_connections {
+            self.max_connections = max_connections;
+        }
+        if let Some(secs) = cli.heartbeat_timeout {
+            self.heartbeat_timeout_secs = secs;
+        }
+        if let Some(secs) = cli.reconnect_timeout {
+            self.reconnect_timeout_secs = secs;
+        }
+        if let Some(capacity) = cli.send_queue_capacity {
+            self.send_queue_capacity = capacity;
+        }
+        if let Some(dir) = &cli.storage_dir {
+            self.storage_dir = Some(dir.clone());
+        }
+        if let Some(path) = &cli.database {
+            self.database_path = Some(path.clone());
+        }
+        if let Some(path) = &cli.snapshot {
+            self.snapshot_path = Some(path.clone());
+        }
+        if let Some(secs) = cli.restore_grace {
+            self.restore_grace_secs = secs;
+        }
+        if cli.no_guests {
+            self.allow_guests = false;
+        }
+        if cli.rate_bot_games {
+            self.rate_bot_games = true;
+        }
+        if let Some(workers) = cli.ai_workers {
+            self.ai_workers = workers;
+        }
+        if let Some(mb) = cli.ai_session_tt_mb {
+            self.ai_session_tt_mb = mb;
+        }
+        if let Some(addr) = &cli.admin_addr {
+            self.admin_addr = Some(addr.clone());
+        }
+        if let Some(token) = &cli.admin_token {
+            self.admin_token = Some(token.clone());
+        }
+        if let Some(addr) = &cli.metrics_addr {
+            self.metrics_addr = Some(addr.clone());
+        }
+        if cli.no_rate_limit {
+            self.rate_limit.enabled = false;
+        }
+        if let Some(limit) = cli.max_connections_per_ip {
+            self.rate_limit.max_connections_per_ip = limit;
+        }
+        if let Some(limit) = cli.max_login_failures {
+            self.rate_limit.max_login_failures = limit;
+        }
+        if let Some(format) = cli.log_format {
+            self.log_format = format;
+        }
+    }
+
+    /// 校验配置
+    pub fn validate(&self) -> Result<()> {
+        self.listen_addr
+            .parse::<SocketAddr>()
+            .with_context(|| format!("无效的监听地址: {}", self.listen_addr))?;
+        if self.transport != TransportType::Tcp {
+            anyhow::bail!("暂不支持的传输协议: {:?}", self.transport);
+        }
+        if self.max_connections == 0 {
+            anyhow::bail!("最大连接数必须大于 0");
+        }
+        if self.send_queue_capacity == 0 {
+            anyhow::bail!("发送队列容量必须大于 0");
+        }
+        if self.ai_workers == 0 {
+            anyhow::bail!("AI 计算线程数必须大于 0");
+        }
+        if self.ai_session_tt_mb == 0 {
+            anyhow::bail!("AI 会话置换表上限必须大于 0");
+        }
+        if let Some(addr) = &self.admin_addr {
+            let addr: SocketAddr = addr
+                .parse()
+                .with_context(|| format!("无效的管理接口地址: {}", addr))?;
+            if !addr.ip().is_loopback() {
+                anyhow::bail!("管理接口只能监听本机地址: {}", addr);
+            }
+            if self.admin_token.as_deref().is_none_or(str::is_empty) {
+                anyhow::bail!("启用管理接口需要设置管理令牌");
+            }
+        }
+        if let Some(addr) = &self.metrics_addr {
+            let addr: SocketAddr = addr
+                .parse()
+                .with_context(|| format!("无效的指标接口地址: {}", addr))?;
+            if !addr.ip().is_loopback() {
+                anyhow::bail!("指标接口只能监听本机地址: {}", addr);
+            }
+        }
+        self.rate_limit.validate()?;
+        Ok(())
+    }
+
+    /// 账号数据库路径
+    pub fn database_path(&self) -> Result<PathBuf> {
+        match &self.database_path {
+            Some(path) => Ok(path.clone()),
+            None => {
+                let app_data_dir = dirs::data_dir().context("无法获取应用数据目录")?;
+                Ok(app_data_dir.join("chinese-chess").join("server.db"))
+            }
+        }
+    }
+
+    /// 停服快照路径
+    pub fn snapshot_path(&self) -> Result<PathBuf> {
+        match &self.snapshot_path {
+            Some(path) => Ok(path.clone()),
+            None => {
+                let app_data_dir = dirs::data_dir().context("无法获取应用数据目录")?;
+                Ok(app_data_dir.join("chinese-chess").join("snapshot.json"))
+            }
+        }
+    }
+
+    /// 重启后等待玩家重连的宽限期
+    pub fn restore_grace(&self) -> Duration {
+        Duration::from_secs(self.restore_grace_secs)
+    }
+
+    /// 心跳超时（None 表示不检查）
+    pub fn heartbeat_timeout(&self) -> Option<Duration> {
+        (self.heartbeat_timeout_secs > 0).then(|| Duration::from_secs(self.heartbeat_timeout_secs))
+    }
+
+    /// 断线重连超时
+    pub fn reconnect_timeout(&self) -> Duration {
+        Duration::from_secs(self.reconnect_timeout_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        let config = ServerConfig::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.listen_addr, "0.0.0.0:9527");
+        assert_eq!(config.max_connections, MAX_CONNECTIONS);
+    }
+
+    #[test]
+    fn test_partial_config_file() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{ "max_connections": 8, "log_format": "json", "chat_filter_words": ["笨蛋"] }"#,
+        )
+        .unwrap();
+        assert_eq!(config.max_connections, 8);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.chat_filter_words, ["笨蛋"]);
+        assert_eq!(config.rate_limit.max_connections_per_ip, 16);
+        // 未指定的字段使用默认值
+        assert_eq!(config.reconnect_timeout_secs, RECONNECT_TIMEOUT_SECS);
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("server.json");
+        fs::write(&path, r#"{ "listen_addr": "127.0.0.1:1000", "max_connections": 8 }"#).unwrap();
+
+        let cli = Cli::parse_from([
+            "chess-server",
+            "--config",
+            path.to_str().unwrap(),
+            "--listen",
+            "127.0.0.1:2000",
+            "--heartbeat-timeout",
+            "0",
+        ]);
+        let config = ServerConfig::from_cli(&cli).unwrap();
+
+        assert_eq!(config.listen_addr, "127.0.0.1:2000");
+        assert_eq!(config.max_connections, 8);
+        assert_eq!(config.heartbeat_timeout(), None);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = ServerConfig {
+            listen_addr: "not an address".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = ServerConfig {
+            transport: TransportType::Quic,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let cli = Cli::parse_from(["chess-server", "--max-connections", "0"]);
+        assert!(ServerConfig::from_cli(&cli).is_err());
+        let cli = Cli::parse_from(["chess-server", "--send-queue-capacity", "0"]);
+        assert!(ServerConfig::from_cli(&cli).is_err());
+
+        // 管理接口只能监听本机地址，且必须设置令牌
+        let cli = Cli::parse_from(["chess-server", "--admin-addr", "0.0.0.0:9528", "--admin-token", "t"]);
+        assert!(ServerConfig::from_cli(&cli).is_err());
+        let cli = Cli::parse_from(["chess-server", "--admin-addr", "127.0.0.1:9528"]);
+        assert!(ServerConfig::from_cli(&cli).is_err());
+        let cli = Cli::parse_from(["chess-server", "--admin-addr", "127.0.0.1:9528", "--admin-token", "t"]);
+        assert!(ServerConfig::from_cli(&cli).is_ok());
+
+        // 指标接口同样只能监听本机地址
+        let cli = Cli::parse_from(["chess-server", "--metrics-addr", "0.0.0.0:9529"]);
+        assert!(ServerConfig::from_cli(&cli).is_err());
+        let cli = Cli::parse_from(["chess-server", "--metrics-addr", "[::1]:9529"]);
+        assert!(ServerConfig::from_cli(&cli).is_ok());
+
+        // 限流参数必须为正
+        let config: ServerConfig =
+            serde_json::from_str(r#"{ "rate_limit": { "game": { "burst": 0, "per_sec": 1.0 } } }"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! 通信对局
+//!
+//! 每步限若干天的慢棋。对局以 JSON 存入 `correspondence_games` 表，与连接无关：
+//! 注册账号的玩家可同时进行多局，登录后列出对局、走一步即可离开。
+//! 走棋时用 [`Room`] 从棋谱重放局面并判定胜负，结束后与房间对局一样结算等级分并归档；
+//! 服务器定期检查期限，超时未走的一方判负，超时未接受的邀请自动撤回
+
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use protocol::{
+    BoardState, CorrespondenceGameInfo, CorrespondenceId, ErrorCode, GameRecord, GameResult, Move,
+    RatingChange, RoomType, RuleSet, Side, WinReason,
+};
+
+use crate::accounts::{AccountError, AccountId, AccountStore};
+use crate::{archive, ratings};
+use crate::room::Room;
+
+/// 一天的毫秒数
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// 通信对局不支持悔棋和提和
+const CORRESPONDENCE_RULES: RuleSet = RuleSet {
+    allow_undo: false,
+    allow_draw_offers: false,
+};
+
+/// 通信对局操作被拒的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorrespondenceError {
+    /// 对方尚未接受邀请
+    NotAccepted,
+    /// 对局已结束
+    Finished,
+    /// 不是该玩家的回合
+    NotYourTurn,
+    /// 走法不合法
+    InvalidMove(&'static str),
+    /// 保存的棋谱无法重放
+    Corrupted,
+}
+
+impl CorrespondenceError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CorrespondenceError::NotAccepted => ErrorCode::GameNotStarted,
+            CorrespondenceError::Finished => ErrorCode::GameAlreadyOver,
+            CorrespondenceError::NotYourTurn => ErrorCode::NotYourTurn,
+            CorrespondenceError::InvalidMove(_) => ErrorCode::InvalidMove,
+            CorrespondenceError::Corrupted => ErrorCode::InternalError,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            CorrespondenceError::NotAccepted => "对方尚未接受邀请",
+            CorrespondenceError::Finished => "对局已结束",
+            CorrespondenceError::NotYourTurn => "不是你的回合",
+            CorrespondenceError::InvalidMove(message) => message,
+            CorrespondenceError::Corrupted => "对局数据损坏",
+        }
+    }
+}
+
+/// 通信对局
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrespondenceGame {
+    /// 对局 ID（写入数据库时分配）
+    pub id: CorrespondenceId,
+    pub red_account: AccountId,
+    pub black_account: AccountId,
+    pub rated: bool,
+    pub days_per_move: u32,
+    /// 发出邀请的一方（对方接受前为 Some）
+    pub invited_by: Option<Side>,
+    /// 期限（Unix 时间戳，毫秒）：邀请的有效期，或走子方的走棋期限
+    pub deadline: i64,
+    /// 棋谱（双方昵称、走法和结果）
+    pub record: GameRecord,
+}
+
+impl CorrespondenceGame {
+    /// 发出邀请，`now` 为 Unix 时间戳（毫秒）
+    pub fn invite(
+        red: (AccountId, String),
+        black: (AccountId, String),
+        invited_by: Side,
+        days_per_move: u32,
+        rated: bool,
+        now: i64,
+    ) -> Self {
+        let mut record = GameRecord::new(red.1, black.1);
+        record.metadata.time_control = Some(format!("{}d", days_per_move));
+        record.metadata.rated = Some(rated);
+        record.metadata.rules = Some(CORRESPONDENCE_RULES);
+        Self {
+            id: 0,
+            red_account: red.0,
+            black_account: black.0,
+            rated,
+            days_per_move,
+            invited_by: Some(invited_by),
+            deadline: now + days_per_move as i64 * DAY_MS,
+            record,
+        }
+    }
+
+    /// 账号在该局中的执子方
+    pub fn side_of(&self, account_id: AccountId) -> Option<Side> {
+        if self.red_account == account_id {
+            Some(Side::Red)
+        } else if self.black_account == account_id {
+            Some(Side::Black)
+        } else {
+            None
+        }
+    }
+
+    /// 执子方的账号
+    pub fn account(&self, side: Side) -> AccountId {
+        match side {
+            Side::Red => self.red_account,
+            Side::Black => self.black_account,
+        }
+    }
+
+    /// 是否已结束
+    pub fn is_finished(&self) -> bool {
+        self.record.metadata.result.is_some()
+    }
+
+    /// 从棋谱重放出房间（不计时）
+    fn room(&self) -> Result<Room, CorrespondenceError> {
+        let mut room = Room::new(self.id, RoomType::PvP);
+        room.rated = self.rated;
+        room.rules = CORRESPONDENCE_RULES;
+        room.start_game_from_fen(&self.record.initial_fen)
+            .map_err(|_| CorrespondenceError::Corrupted)?;
+        room.timer = None;
+
+        for record in &self.record.moves {
+            let (Some(from), Some(to)) = (record.from_position(), record.to_position()) else {
+                return Err(CorrespondenceError::Corrupted);
+            };
+            room.make_move(Move::new(from, to)).map_err(|_| CorrespondenceError::Corrupted)?;
+        }
+        Ok(room)
+    }
+
+    /// 当前局面
+    pub fn position(&self) -> Result<BoardState, CorrespondenceError> {
+        self.room()?.game_state.ok_or(CorrespondenceError::Corrupted)
+    }
+
+    /// 走子方（按初始局面和步数推算）
+    pub fn current_turn(&self) -> Result<Side, CorrespondenceError> {
+        Ok(self.position()?.current_turn)
+    }
+
+    /// 接受邀请，走棋期限从现在开始计算
+    pub fn accept(&mut self, now: i64) {
+        self.invited_by = None;
+        self.deadline = now + self.days_per_move as i64 * DAY_MS;
+    }
+
+    /// `side` 走一步，对局结束时返回结果；走完后对方的期限从现在开始计算
+    pub fn play(&mut self, side: Side, mv: Move, now: i64) -> Result<Option<GameResult>, CorrespondenceError> {
+        if self.is_finished() {
+            return Err(CorrespondenceError::Finished);
+        }
+        if self.invited_by.is_some() {
+            return Err(CorrespondenceError::NotAccepted);
+        }
+
+        let mut room = self.room()?;
+        if room.game_state.as_ref().map(|state| state.current_turn) != Some(side) {
+            return Err(CorrespondenceError::NotYourTurn);
+        }
+        room.make_move(mv).map_err(CorrespondenceError::InvalidMove)?;
+        if let Some(record) = room.move_records.pop() {
+            self.record.add_move(record);
+        }
+        self.deadline = now + self.days_per_move as i64 * DAY_MS;
+
+        let result = room.check_game_over();
+        if let Some(result) = &result {
+            self.record.set_result(result.clone());
+        }
+        Ok(result)
+    }
+
+    /// `side` 认输
+    pub fn resign(&mut self, side: Side) -> Result<GameResult, CorrespondenceError> {
+        if self.is_finished() {
+            return Err(CorrespondenceError::Finished);
+        }
+        if self.invited_by.is_some() {
+            return Err(CorrespondenceError::NotAccepted);
+        }
+        let result = match side {
+            Side::Red => GameResult::BlackWin(WinReason::Resign),
+            Side::Black => GameResult::RedWin(WinReason::Resign),
+        };
+        self.record.set_result(result.clone());
+        Ok(result)
+    }
+
+    /// 走子方超过期限未走，判负
+    pub fn forfeit(&mut self) -> Result<GameResult, CorrespondenceError> {
+        let result = match self.current_turn()? {
+            Side::Red => GameResult::BlackWin(WinReason::Timeout),
+            Side::Black => GameResult::RedWin(WinReason::Timeout),
+        };
+        self.record.set_result(result.clone());
+        Ok(result)
+    }
+
+    /// 对局信息（`side` 一方视角）
+    pub fn info(&self, side: Side) -> Result<CorrespondenceGameInfo, CorrespondenceError> {
+        Ok(CorrespondenceGameInfo {
+            id: self.id,
+            red_player: self.record.metadata.red_player.clone(),
+            black_player: self.record.metadata.black_player.clone(),
+            your_side: side,
+            rated: self.rated,
+            days_per_move: self.days_per_move,
+            invited_by: self.invited_by,
+            game_state: self.position()?,
+            move_count: self.record.moves.len() as u32,
+            last_move: self.record.moves.last().map(|record| record.notation.clone()),
+            deadline: self.deadline.max(0) as u64,
+            result: self.record.metadata.result.clone(),
+        })
+    }
+}
+
+/// 解析 `data` 列中的对局
+fn parse_game(data: rusqlite::Result<String>) -> Result<CorrespondenceGame, AccountError> {
+    Ok(serde_json::from_str(&data?)?)
+}
+
+impl AccountStore {
+    /// 保存新的通信对局并分配 ID
+    pub fn create_correspondence(&self, game: &mut CorrespondenceGame) -> Result<(), AccountError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO correspondence_games (red_account, black_account, deadline, data) VALUES (?1, ?2, ?3, '')",
+            params![game.red_account, game.black_account, game.deadline],
+        )?;
+        game.id = tx.last_insert_rowid() as CorrespondenceId;
+        tx.execute(
+            "UPDATE correspondence_games SET data = ?2 WHERE id = ?1",
+            params![game.id as i64, serde_json::to_string(game)?],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 保存通信对局的最新状态
+    pub fn save_correspondence(&self, game: &CorrespondenceGame) -> Result<(), AccountError> {
+        self.connection().execute(
+            "UPDATE correspondence_games SET deadline = ?2, data = ?3 WHERE id = ?1",
+            params![game.id as i64, game.deadline, serde_json::to_string(game)?],
+        )?;
+        Ok(())
+    }
+
+    /// 删除通信对局（邀请被拒绝或对局结束后）
+    pub fn delete_correspondence(&self, id: CorrespondenceId) -> Result<(), AccountError> {
+        self.connection()
+            .execute("DELETE FROM correspondence_games WHERE id = ?1", params![id as i64])?;
+        Ok(())
+    }
+
+    /// 获取一局通信对局
+    pub fn correspondence_game(&self, id: CorrespondenceId) -> Result<Option<CorrespondenceGame>, AccountError> {
+        let data = self
+            .connection()
+            .query_row(
+                "SELECT data FROM correspondence_games WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        data.map(|data| parse_game(Ok(data))).transpose()
+    }
+
+    /// 账号参与的通信对局（含待接受的邀请，按期限排列）
+    pub fn correspondence_games(&self, account_id: AccountId) -> Result<Vec<CorrespondenceGame>, AccountError> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(
+            "SELECT data FROM correspondence_games
+             WHERE red_account = ?1 OR black_account = ?1
+             ORDER BY deadline, id",
+        )?;
+        let rows = stmt.query_map(params![account_id], |row| row.get(0))?;
+        rows.map(parse_game).collect()
+    }
+
+    /// 账号参与的通信对局数
+    pub fn count_correspondence(&self, account_id: AccountId) -> Result<usize, AccountError> {
+        let count: i64 = self.connection().query_row(
+            "SELECT COUNT(*) FROM correspondence_games WHERE red_account = ?1 OR black_account = ?1",
+            params![account_id],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// 期限早于 `now` 的通信对局
+    pub fn overdue_correspondence(&self, now: i64) -> Result<Vec<CorrespondenceGame>, AccountError> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare("SELECT data FROM correspondence_games WHERE deadline < ?1 ORDER BY deadline")?;
+        let rows = stmt.query_map(params![now], |row| row.get(0))?;
+        rows.map(parse_game).collect()
+    }
+
+    /// 结束通信对局：计分对局结算等级分，然后归档并从进行中的对局里删除
+    ///
+    /// 三步在同一事务中完成，不会出现已结算却仍在进行、或重复结算的对局
+    pub fn finish_correspondence(
+        &self,
+        game: &CorrespondenceGame,
+        result: &GameResult,
+        now: i64,
+    ) -> Result<Vec<RatingChange>, AccountError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        let rating_changes = if game.rated {
+            ratings::settle(&tx, game.red_account, game.black_account, result)?
+        } else {
+            Vec::new()
+        };
+        archive::insert_game(
+            &tx,
+            Some(game.red_account),
+            Some(game.black_account),
+            game.rated,
+            result,
+            &game.record,
+            now.max(0) as u64,
+        )?;
+        tx.execute("DELETE FROM correspondence_games WHERE id = ?1", params![game.id as i64])?;
+        tx.commit()?;
+        Ok(rating_changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{GameHistoryFilter, Position};
+
+    /// 已接受的对局（甲执红）
+    fn started(store: &AccountStore, rated: bool) -> CorrespondenceGame {
+        let red = store.register("甲", "secret1").unwrap().id;
+        let black = store.register("乙", "secret2").unwrap().id;
+        let mut game = CorrespondenceGame::invite((red, "甲".to_string()), (black, "乙".to_string()), Side::Red, 3, rated, 0);
+        store.create_correspondence(&mut game).unwrap();
+        game.accept(1_000);
+        store.save_correspondence(&game).unwrap();
+        game
+    }
+
+    fn mv(from: (u8, u8), to: (u8, u8)) -> Move {
+        Move::new(Position::new_unchecked(from.0, from.1), Position::new_unchecked(to.0, to.1))
+    }
+
+    #[test]
+    fn test_play_and_persist() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let mut game = started(&store, false);
+        assert_eq!(game.deadline, 1_000 + 3 * DAY_MS);
+
+        assert_eq!(game.play(Side::Black, mv((1, 9), (2, 7)), 2_000), Err(CorrespondenceError::NotYourTurn));
+        assert_eq!(game.play(Side::Red, mv((7, 2), (4, 2)), 2_000), Ok(None));
+        assert!(matches!(game.play(Side::Black, mv((0, 0), (0, 5)), 3_000), Err(CorrespondenceError::InvalidMove(_))));
+        assert_eq!(game.deadline, 2_000 + 3 * DAY_MS);
+        store.save_correspondence(&game).unwrap();
+
+        // 重新读取后从棋谱重放局面
+        let loaded = store.correspondence_game(game.id).unwrap().unwrap();
+        assert_eq!(loaded.current_turn(), Ok(Side::Black));
+        let info = loaded.info(Side::Black).unwrap();
+        assert_eq!((info.move_count, info.last_move.as_deref()), (1, Some("炮二平五")));
+        assert_eq!(store.correspondence_games(game.black_account).unwrap().len(), 1);
+        assert_eq!(store.count_correspondence(game.red_account).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_overdue_game_is_forfeited_and_archived() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let mut game = started(&store, true);
+        game.play(Side::Red, mv((7, 2), (4, 2)), 2_000).unwrap();
+        store.save_correspondence(&game).unwrap();
+
+        assert!(store.overdue_correspondence(game.deadline).unwrap().is_empty());
+        let mut overdue = store.overdue_correspondence(game.deadline + 1).unwrap();
+        assert_eq!(overdue.len(), 1);
+
+        // 黑方未在期限内走棋
+        let game = &mut overdue[0];
+        let result = game.forfeit().unwrap();
+        assert_eq!(result, GameResult::RedWin(WinReason::Timeout));
+        let changes = store.finish_correspondence(game, &result, game.deadline + 1).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].after.rating > changes[0].before.rating);
+
+        assert!(store.correspondence_game(game.id).unwrap().is_none());
+        let (archived, _) = store.list_games(game.red_account, &GameHistoryFilter::default(), 0, 10).unwrap();
+        assert_eq!((archived[0].time_control.as_str(), archived[0].move_count), ("3d", 1));
+        assert_eq!(game.resign(Side::Black), Err(CorrespondenceError::Finished));
+    }
+}
@@ -0,0 +1,279 @@
+//! 好友
+//!
+//! 好友关系和好友请求保存在账号数据库中，仅注册账号可用。
+//! 好友的在线状态由玩家状态推导，服务器定期对比并推送变化给在线的好友
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension};
+
+use protocol::{Presence, MAX_FRIENDS};
+
+use crate::accounts::{Account, AccountError, AccountId, AccountStore};
+use crate::player::{PlayerManager, PlayerStatus};
+
+/// 发送好友请求的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FriendRequestOutcome {
+    /// 请求已发出
+    Sent,
+    /// 对方已向自己发出请求，直接成为好友
+    Accepted,
+    /// 已经是好友
+    AlreadyFriends,
+    /// 已发出过请求
+    AlreadyRequested,
+    /// 任一方好友数已达上限
+    LimitReached,
+}
+
+impl AccountStore {
+    /// 两个账号是否为好友
+    pub fn are_friends(&self, a: AccountId, b: AccountId) -> Result<bool, AccountError> {
+        let found = self
+            .connection()
+            .query_row(
+                "SELECT 1 FROM friends WHERE account_id = ?1 AND friend_id = ?2",
+                params![a, b],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// 任一账号的好友数是否已达上限
+    pub fn friend_limit_reached(&self, a: AccountId, b: AccountId) -> Result<bool, AccountError> {
+        let most: Option<i64> = self.connection().query_row(
+            "SELECT MAX(n) FROM (SELECT COUNT(*) AS n FROM friends WHERE account_id IN (?1, ?2) GROUP BY account_id)",
+            params![a, b],
+            |row| row.get(0),
+        )?;
+        Ok(most.unwrap_or(0) as usize >= MAX_FRIENDS)
+    }
+
+    /// 发送好友请求（对方已向自己发出请求时直接成为好友）
+    pub fn request_friend(&self, from: AccountId, to: AccountId) -> Result<FriendRequestOutcome, AccountError> {
+        if self.are_friends(from, to)? {
+            return Ok(FriendRequestOutcome::AlreadyFriends);
+        }
+        if self.friend_limit_reached(from, to)? {
+            return Ok(FriendRequestOutcome::LimitReached);
+        }
+        if self.respond_friend_request(from, to, true)? {
+            return Ok(FriendRequestOutcome::Accepted);
+        }
+        let inserted = self.connection().execute(
+            "INSERT OR IGNORE INTO friend_requests (from_account, to_account, created_at) VALUES (?1, ?2, ?3)",
+            params![from, to, Utc::now().to_rfc3339()],
+        )?;
+        Ok(if inserted > 0 {
+            FriendRequestOutcome::Sent
+        } else {
+            FriendRequestOutcome::AlreadyRequested
+        })
+    }
+
+    /// 回应 `from` 发给 `to` 的好友请求，没有该请求时返回 false
+    ///
+    /// 不检查好友数上限，接受前由调用方用 [`Self::friend_limit_reached`] 检查
+    pub fn respond_friend_request(&self, to: AccountId, from: AccountId, accept: bool) -> Result<bool, AccountError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        let removed = tx.execute(
+            "DELETE FROM friend_requests WHERE from_account = ?1 AND to_account = ?2",
+            params![from, to],
+        )?;
+        if removed == 0 {
+            return Ok(false);
+        }
+        if accept {
+            let now = Utc::now().to_rfc3339();
+            for (a, b) in [(from, to), (to, from)] {
+                tx.execute(
+                    "INSERT OR IGNORE INTO friends (account_id, friend_id, created_at) VALUES (?1, ?2, ?3)",
+                    params![a, b, now],
+                )?;
+            }
+            // 自己发给对方的请求一并清除
+            tx.execute(
+                "DELETE FROM friend_requests WHERE from_account = ?1 AND to_account = ?2",
+                params![to, from],
+            )?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// 删除好友（双向），不是好友时返回 false
+    pub fn remove_friend(&self, a: AccountId, b: AccountId) -> Result<bool, AccountError> {
+        let removed = self.connection().execute(
+            "DELETE FROM friends WHERE (account_id = ?1 AND friend_id = ?2) OR (account_id = ?2 AND friend_id = ?1)",
+            params![a, b],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// 好友列表（按昵称排序）
+    pub fn friends(&self, account_id: AccountId) -> Result<Vec<Account>, AccountError> {
+        self.query_accounts(
+            "SELECT a.id, a.nickname FROM friends f JOIN accounts a ON a.id = f.friend_id
+             WHERE f.account_id = ?1 ORDER BY a.nickname",
+            account_id,
+        )
+    }
+
+    /// 待处理的好友请求：(收到的, 发出的)
+    pub fn friend_requests(&self, account_id: AccountId) -> Result<(Vec<Account>, Vec<Account>), AccountError> {
+        let incoming = self.query_accounts(
+            "SELECT a.id, a.nickname FROM friend_requests r JOIN accounts a ON a.id = r.from_account
+             WHERE r.to_account = ?1 ORDER BY r.created_at",
+            account_id,
+        )?;
+        let outgoing = self.query_accounts(
+            "SELECT a.id, a.nickname FROM friend_requests r JOIN accounts a ON a.id = r.to_account
+             WHERE r.from_account = ?1 ORDER BY r.created_at",
+            account_id,
+        )?;
+        Ok((incoming, outgoing))
+    }
+
+    fn query_accounts(&self, sql: &str, account_id: AccountId) -> Result<Vec<Account>, AccountError> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(sql)?;
+        let accounts = stmt
+            .query_map(params![account_id], |row| {
+                Ok(Account {
+                    id: row.get(0)?,
+                    nickname: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(accounts)
+    }
+}
+
+/// 玩家状态对应的在线状态
+pub fn presence(status: PlayerStatus) -> Presence {
+    match status {
+        PlayerStatus::Online => Presence::Online,
+        PlayerStatus::InRoom(room_id) | PlayerStatus::Spectating(room_id) => Presence::InRoom(room_id),
+        PlayerStatus::Disconnected(_) => Presence::Disconnected,
+    }
+}
+
+/// 同一账号有多个连接（机器人）时取最空闲的状态
+fn availability(presence: Presence) -> u8 {
+    match presence {
+        Presence::Offline => 0,
+        Presence::Disconnected => 1,
+        Presence::InRoom(_) => 2,
+        Presence::Online => 3,
+    }
+}
+
+/// 所有已登录注册账号的昵称和在线状态
+pub fn account_presences(players: &PlayerManager) -> HashMap<AccountId, (String, Presence)> {
+    let mut presences: HashMap<AccountId, (String, Presence)> = HashMap::new();
+    for player in players.all() {
+        let Some(account_id) = player.account_id else {
+            continue;
+        };
+        let current = presence(player.status);
+        presences
+            .entry(account_id)
+            .and_modify(|(_, best)| {
+                if availability(current) > availability(*best) {
+                    *best = current;
+                }
+            })
+            .or_insert_with(|| (player.nickname.clone(), current));
+    }
+    presences
+}
+
+/// 在线状态变化检测
+#[derive(Debug, Default)]
+pub struct PresenceTracker {
+    last: HashMap<AccountId, (String, Presence)>,
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 与上次对比，返回状态变化的账号（下线的账号为 `Offline`）
+    pub fn update(&mut self, current: HashMap<AccountId, (String, Presence)>) -> Vec<(AccountId, String, Presence)> {
+        let mut changes: Vec<(AccountId, String, Presence)> = current
+            .iter()
+            .filter(|(id, (_, presence))| self.last.get(id).is_none_or(|(_, last)| last != presence))
+            .map(|(&id, (nickname, presence))| (id, nickname.clone(), *presence))
+            .collect();
+        changes.extend(
+            self.last
+                .iter()
+                .filter(|(id, _)| !current.contains_key(id))
+                .map(|(&id, (nickname, _))| (id, nickname.clone(), Presence::Offline)),
+        );
+        self.last = current;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_friend_requests() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let a = store.register("甲", "secret123").unwrap().id;
+        let b = store.register("乙", "secret123").unwrap().id;
+        let c = store.register("丙", "secret123").unwrap().id;
+
+        assert_eq!(store.request_friend(a, b).unwrap(), FriendRequestOutcome::Sent);
+        assert_eq!(store.request_friend(a, b).unwrap(), FriendRequestOutcome::AlreadyRequested);
+        let (incoming, outgoing) = store.friend_requests(b).unwrap();
+        assert_eq!(incoming[0].nickname, "甲");
+        assert!(outgoing.is_empty());
+
+        // 对方也发出请求时直接成为好友
+        assert_eq!(store.request_friend(b, a).unwrap(), FriendRequestOutcome::Accepted);
+        assert!(store.are_friends(a, b).unwrap() && store.are_friends(b, a).unwrap());
+        assert_eq!(store.request_friend(a, b).unwrap(), FriendRequestOutcome::AlreadyFriends);
+        assert_eq!(store.friend_requests(a).unwrap(), (Vec::new(), Vec::new()));
+
+        // 拒绝后请求消失
+        store.request_friend(c, a).unwrap();
+        assert!(store.respond_friend_request(a, c, false).unwrap());
+        assert!(!store.respond_friend_request(a, c, true).unwrap());
+        assert!(!store.are_friends(a, c).unwrap());
+
+        let names: Vec<_> = store.friends(a).unwrap().into_iter().map(|f| f.nickname).collect();
+        assert_eq!(names, ["乙"]);
+        assert!(store.remove_friend(b, a).unwrap());
+        assert!(!store.remove_friend(a, b).unwrap());
+        assert!(store.friends(a).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_presence_tracker() {
+        let mut players = PlayerManager::new();
+        let a = players.login_account("甲".to_string(), 1).unwrap();
+        players.login("游客".to_string()).unwrap();
+
+        let mut tracker = PresenceTracker::new();
+        let changes = tracker.update(account_presences(&players));
+        assert_eq!(changes, [(1, "甲".to_string(), Presence::Online)]);
+        assert!(tracker.update(account_presences(&players)).is_empty());
+
+        players.set_status(a, PlayerStatus::InRoom(7));
+        let changes = tracker.update(account_presences(&players));
+        assert_eq!(changes, [(1, "甲".to_string(), Presence::InRoom(7))]);
+
+        players.remove(a);
+        let changes = tracker.update(account_presences(&players));
+        assert_eq!(changes, [(1, "甲".to_string(), Presence::Offline)]);
+    }
+}
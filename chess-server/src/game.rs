@@ -2,21 +2,101 @@
 //!
 //! 包含计时器系统
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use protocol::{Side, INITIAL_TIME_MS};
+use serde::{Deserialize, Serialize};
+
+use protocol::{ByoyomiState, Side, TimeControl};
+
+/// 单方时钟
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct SideClock {
+    /// 剩余基本用时（毫秒）
+    main_ms: u64,
+    /// 剩余读秒次数（含当前这一次）
+    periods_left: u32,
+    /// 当前这次读秒的剩余时间（毫秒）
+    period_ms: u64,
+    /// 是否超过了每步限时
+    flagged: bool,
+}
+
+impl SideClock {
+    fn new(control: &TimeControl) -> Self {
+        let periods_left = if control.has_byoyomi() { control.byoyomi_periods } else { 0 };
+        Self {
+            main_ms: control.main_time_ms,
+            periods_left,
+            period_ms: control.byoyomi_ms,
+            flagged: false,
+        }
+    }
+
+    /// 扣除用时：先用基本用时，再逐次消耗读秒
+    fn spend(&mut self, elapsed: u64, byoyomi_ms: u64) {
+        let from_main = elapsed.min(self.main_ms);
+        self.main_ms -= from_main;
+        let mut elapsed = elapsed - from_main;
+
+        while elapsed > 0 && self.periods_left > 0 {
+            if elapsed < self.period_ms {
+                self.period_ms -= elapsed;
+                return;
+            }
+            elapsed -= self.period_ms;
+            self.periods_left -= 1;
+            self.period_ms = byoyomi_ms;
+        }
+    }
+
+    /// 显示的剩余时间（读秒中为当前这次读秒的剩余时间）
+    fn time_ms(&self) -> u64 {
+        if self.flagged {
+            0
+        } else if self.main_ms > 0 {
+            self.main_ms
+        } else if self.periods_left > 0 {
+            self.period_ms
+        } else {
+            0
+        }
+    }
+
+    fn byoyomi(&self) -> ByoyomiState {
+        ByoyomiState {
+            periods_left: self.periods_left,
+            in_byoyomi: self.main_ms == 0 && self.periods_left > 0,
+        }
+    }
+}
+
+/// 计时器快照（停服时保存，恢复后处于暂停状态）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSnapshot {
+    control: TimeControl,
+    red: SideClock,
+    black: SideClock,
+    current_turn: Side,
+    move_spent_ms: u64,
+}
 
 /// 游戏计时器
+///
+/// 支持每步加秒、读秒和每步限时，规则见 [`TimeControl`]
 #[derive(Debug)]
 pub struct GameTimer {
-    /// 红方剩余时间（毫秒）
-    red_time_ms: u64,
-    /// 黑方剩余时间（毫秒）
-    black_time_ms: u64,
+    /// 时间控制
+    control: TimeControl,
+    /// 红方时钟
+    red: SideClock,
+    /// 黑方时钟
+    black: SideClock,
     /// 当前走子方
     current_turn: Side,
     /// 当前回合开始时间
     turn_start: Option<Instant>,
+    /// 本步暂停前已用的时间（用于每步限时）
+    move_spent_ms: u64,
     /// 是否暂停
     paused: bool,
 }
@@ -24,83 +104,134 @@ pub struct GameTimer {
 impl GameTimer {
     /// 创建新计时器（每方默认 10 分钟）
     pub fn new() -> Self {
-        Self {
-            red_time_ms: INITIAL_TIME_MS,
-            black_time_ms: INITIAL_TIME_MS,
-            current_turn: Side::Red,
-            turn_start: Some(Instant::now()),
-            paused: false,
-        }
+        Self::with_control(TimeControl::default())
     }
 
     /// 创建自定义时间的计时器
     pub fn with_time(time_ms: u64) -> Self {
+        Self::with_control(TimeControl {
+            main_time_ms: time_ms,
+            ..TimeControl::default()
+        })
+    }
+
+    /// 按时间控制创建计时器
+    pub fn with_control(control: TimeControl) -> Self {
         Self {
-            red_time_ms: time_ms,
-            black_time_ms: time_ms,
+            control,
+            red: SideClock::new(&control),
+            black: SideClock::new(&control),
             current_turn: Side::Red,
             turn_start: Some(Instant::now()),
+            move_spent_ms: 0,
             paused: false,
         }
     }
 
+    /// 时间控制
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
     /// 获取红方剩余时间（毫秒）
     pub fn red_time_ms(&self) -> u64 {
-        if self.current_turn == Side::Red && !self.paused {
-            self.calculate_remaining(self.red_time_ms)
-        } else {
-            self.red_time_ms
-        }
+        self.time_ms(Side::Red)
     }
 
     /// 获取黑方剩余时间（毫秒）
     pub fn black_time_ms(&self) -> u64 {
-        if self.current_turn == Side::Black && !self.paused {
-            self.calculate_remaining(self.black_time_ms)
-        } else {
-            self.black_time_ms
+        self.time_ms(Side::Black)
+    }
+
+    /// 获取读秒状态
+    pub fn byoyomi(&self, side: Side) -> ByoyomiState {
+        self.clock(side).byoyomi()
+    }
+
+    /// 某方此刻的剩余时间，有每步限时时不超过本步剩余的限时
+    fn time_ms(&self, side: Side) -> u64 {
+        let time = self.clock(side).time_ms();
+        match self.control.move_limit_ms {
+            Some(limit) if side == self.current_turn => {
+                time.min(limit.saturating_sub(self.move_spent_ms + self.elapsed_ms()))
+            }
+            _ => time,
         }
     }
 
-    /// 计算剩余时间
-    fn calculate_remaining(&self, base_time: u64) -> u64 {
-        if let Some(start) = self.turn_start {
-            let elapsed = start.elapsed().as_millis() as u64;
-            base_time.saturating_sub(elapsed)
-        } else {
-            base_time
+    fn stored_mut(&mut self, side: Side) -> &mut SideClock {
+        match side {
+            Side::Red => &mut self.red,
+            Side::Black => &mut self.black,
+        }
+    }
+
+    /// 本回合计时中已用的时间
+    fn elapsed_ms(&self) -> u64 {
+        match self.turn_start {
+            Some(start) if !self.paused => start.elapsed().as_millis() as u64,
+            _ => 0,
         }
     }
 
-    /// 切换走子方
+    /// 计算某方此刻的时钟
+    fn clock(&self, side: Side) -> SideClock {
+        let mut clock = match side {
+            Side::Red => self.red,
+            Side::Black => self.black,
+        };
+        if side == self.current_turn {
+            clock.spend(self.elapsed_ms(), self.control.byoyomi_ms);
+        }
+        clock
+    }
+
+    /// 结算当前方已用的时间
+    fn commit(&mut self) {
+        let elapsed = self.elapsed_ms();
+        let spent = self.move_spent_ms + elapsed;
+        let over_limit = self.control.move_limit_ms.is_some_and(|limit| spent >= limit);
+        let byoyomi_ms = self.control.byoyomi_ms;
+
+        let clock = self.stored_mut(self.current_turn);
+        clock.spend(elapsed, byoyomi_ms);
+        clock.flagged |= over_limit;
+        self.move_spent_ms = spent;
+    }
+
+    /// 走完一步后切换走子方（基本用时内加秒，读秒中重置本次读秒）
     pub fn switch_turn(&mut self) {
-        // 更新当前方的剩余时间
-        match self.current_turn {
-            Side::Red => {
-                self.red_time_ms = self.red_time_ms();
-            }
-            Side::Black => {
-                self.black_time_ms = self.black_time_ms();
+        self.commit();
+        let increment = self.control.increment_ms;
+        let byoyomi_ms = self.control.byoyomi_ms;
+        let clock = self.stored_mut(self.current_turn);
+        if clock.time_ms() > 0 {
+            if clock.main_ms > 0 {
+                clock.main_ms += increment;
+            } else {
+                clock.period_ms = byoyomi_ms;
             }
         }
+        self.hand_over();
+    }
+
+    /// 切换走子方但不加秒（悔棋、从局面开局）
+    pub fn pass_turn(&mut self) {
+        self.commit();
+        self.hand_over();
+    }
 
-        // 切换到对方
+    fn hand_over(&mut self) {
         self.current_turn = self.current_turn.opponent();
-        self.turn_start = Some(Instant::now());
+        self.move_spent_ms = 0;
+        self.turn_start = if self.paused { None } else { Some(Instant::now()) };
     }
 
     /// 暂停计时器
     pub fn pause(&mut self) {
         if !self.paused {
             // 保存当前剩余时间
-            match self.current_turn {
-                Side::Red => {
-                    self.red_time_ms = self.red_time_ms();
-                }
-                Side::Black => {
-                    self.black_time_ms = self.black_time_ms();
-                }
-            }
+            self.commit();
             self.turn_start = None;
             self.paused = true;
         }
@@ -119,12 +250,14 @@ impl GameTimer {
         self.pause();
     }
 
+    /// 当前走子方距超时还有多久（暂停时为 None）
+    pub fn time_to_flag(&self) -> Option<Duration> {
+        (!self.paused).then(|| Duration::from_millis(self.time_ms(self.current_turn)))
+    }
+
     /// 检查是否超时
     pub fn is_timeout(&self, side: Side) -> bool {
-        match side {
-            Side::Red => self.red_time_ms() == 0,
-            Side::Black => self.black_time_ms() == 0,
-        }
+        self.time_ms(side) == 0
     }
 
     /// 获取当前走子方
@@ -138,9 +271,35 @@ impl GameTimer {
     }
 
     /// 设置剩余时间（用于重连恢复）
+    ///
+    /// 剩余时间为零时视为已进入读秒，读秒次数保持不变
     pub fn set_times(&mut self, red_time_ms: u64, black_time_ms: u64) {
-        self.red_time_ms = red_time_ms;
-        self.black_time_ms = black_time_ms;
+        self.red.main_ms = red_time_ms;
+        self.black.main_ms = black_time_ms;
+    }
+
+    /// 此刻的计时器快照（已计入本回合用时）
+    pub fn snapshot(&self) -> ClockSnapshot {
+        ClockSnapshot {
+            control: self.control,
+            red: self.clock(Side::Red),
+            black: self.clock(Side::Black),
+            current_turn: self.current_turn,
+            move_spent_ms: self.move_spent_ms + self.elapsed_ms(),
+        }
+    }
+
+    /// 从快照恢复计时器（暂停状态，调用 resume 后继续计时）
+    pub fn from_snapshot(snapshot: ClockSnapshot) -> Self {
+        Self {
+            control: snapshot.control,
+            red: snapshot.red,
+            black: snapshot.black,
+            current_turn: snapshot.current_turn,
+            turn_start: None,
+            move_spent_ms: snapshot.move_spent_ms,
+            paused: true,
+        }
     }
 
     /// 重置当前回合开始时间（用于断线重连）
@@ -160,8 +319,20 @@ impl Default for GameTimer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use protocol::INITIAL_TIME_MS;
     use std::thread;
-    use std::time::Duration;
+
+    /// 把当前回合的开始时间往前拨，模拟已经思考了 `ms` 毫秒
+    fn think(timer: &mut GameTimer, ms: u64) {
+        timer.turn_start = Some(Instant::now() - Duration::from_millis(ms));
+    }
+
+    fn assert_about(actual: u64, expected: u64) {
+        assert!(
+            actual <= expected && actual + 500 > expected,
+            "expected about {expected}, got {actual}"
+        );
+    }
 
     #[test]
     fn test_timer_initial() {
@@ -211,4 +382,92 @@ mod tests {
         // 恢复后时间继续减少
         assert!(timer.red_time_ms() < time_at_pause);
     }
+
+    #[test]
+    fn test_timer_increment() {
+        let mut timer = GameTimer::with_control(TimeControl::minutes(1).with_increment(5));
+
+        think(&mut timer, 10_000);
+        timer.switch_turn();
+        assert_about(timer.red_time_ms(), 55_000);
+
+        // 悔棋切换不加秒
+        think(&mut timer, 10_000);
+        timer.pass_turn();
+        assert_about(timer.black_time_ms(), 50_000);
+    }
+
+    #[test]
+    fn test_timer_byoyomi() {
+        let control = TimeControl {
+            main_time_ms: 10_000,
+            ..TimeControl::minutes(0).with_byoyomi(3, 5)
+        };
+        let mut timer = GameTimer::with_control(control);
+
+        // 基本用时耗尽后进入读秒
+        think(&mut timer, 12_000);
+        assert_about(timer.red_time_ms(), 3_000);
+        assert_eq!(timer.byoyomi(Side::Red), ByoyomiState { periods_left: 3, in_byoyomi: true });
+
+        // 读秒内走完，本次读秒重置
+        timer.switch_turn();
+        assert_eq!(timer.red_time_ms(), 5_000);
+        assert_eq!(timer.byoyomi(Side::Red).periods_left, 3);
+        assert_eq!(timer.byoyomi(Side::Black), ByoyomiState { periods_left: 3, in_byoyomi: false });
+        timer.switch_turn();
+
+        // 超过两次读秒
+        think(&mut timer, 11_000);
+        assert_about(timer.red_time_ms(), 4_000);
+        assert_eq!(timer.byoyomi(Side::Red).periods_left, 1);
+
+        // 读秒用完判负
+        think(&mut timer, 16_000);
+        assert!(timer.is_timeout(Side::Red));
+        timer.switch_turn();
+        assert_eq!(timer.red_time_ms(), 0);
+    }
+
+    #[test]
+    fn test_timer_move_limit() {
+        let mut timer = GameTimer::with_control(TimeControl::minutes(10).with_move_limit(30));
+
+        think(&mut timer, 20_000);
+        assert_about(timer.red_time_ms(), 10_000);
+
+        // 暂停前后累计计算本步用时
+        timer.pause();
+        assert_about(timer.red_time_ms(), 10_000);
+        timer.resume();
+        think(&mut timer, 11_000);
+        assert!(timer.is_timeout(Side::Red));
+
+        timer.switch_turn();
+        assert_eq!(timer.red_time_ms(), 0);
+        assert!(!timer.is_timeout(Side::Black));
+    }
+
+    #[test]
+    fn test_timer_snapshot() {
+        let mut timer = GameTimer::with_control(TimeControl::minutes(5).with_byoyomi(3, 30));
+        timer.switch_turn();
+        think(&mut timer, 301_000);
+
+        // 快照计入本回合用时，恢复后暂停，继续计时后接着扣
+        let restored = GameTimer::from_snapshot(timer.snapshot());
+        assert!(restored.is_paused());
+        assert_eq!(restored.current_turn(), Side::Black);
+        assert_about(restored.black_time_ms(), 29_000);
+        assert_eq!(restored.byoyomi(Side::Black).periods_left, 3);
+        assert_eq!(restored.red_time_ms(), timer.red_time_ms());
+        let paused_at = restored.black_time_ms();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(restored.black_time_ms(), paused_at);
+
+        let mut restored = restored;
+        restored.resume();
+        think(&mut restored, 10_000);
+        assert_about(restored.black_time_ms(), 19_000);
+    }
 }
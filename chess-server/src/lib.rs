@@ -1,20 +1,78 @@
 //! 中国象棋服务端
 //!
 //! 包含:
+//! - 服务器配置
+//! - 管理接口
+//! - 玩家账号
+//! - 机器人账号
+//! - 对局归档
+//! - 会话令牌
+//! - 等级分
+//! - 匹配队列
+//! - 挑战与好友
+//! - 通信对局
+//! - 运行指标
+//! - 聊天
+//! - 限流与防滥用
 //! - 房间系统
+//! - 房间任务
+//! - 玩家登记
 //! - 对局控制
 //! - 玩家管理
-//! - AI 集成
+//! - AI 集成与计算池
 //! - 棋局存储
+//! - 断线事件重放
+//! - 停服信号
+//! - 停服快照
+//! - 比赛
 
+pub mod accounts;
+pub mod admin;
+pub mod ai_pool;
+pub mod archive;
+pub mod bots;
+pub mod challenge;
+pub mod chat;
+pub mod config;
+pub mod correspondence;
+pub mod friends;
 pub mod game;
+pub mod matchmaking;
+pub mod metrics;
 pub mod player;
+pub mod rate_limit;
+pub mod ratings;
+pub mod registry;
+pub mod replay;
 pub mod room;
+pub mod room_actor;
 pub mod server;
+pub mod session;
+pub mod snapshot;
+pub mod shutdown;
 pub mod storage;
+pub mod tournament;
 
+pub use accounts::{Account, AccountError, AccountId, AccountStore};
+pub use ai_pool::{AiPool, AiPoolMetrics};
+pub use bots::{BotAccount, BotSettings};
+pub use chat::{ChatFilter, ChatHistory, ChatLimiter};
+pub use challenge::{Challenge, ChallengeBook};
+pub use config::{Cli, LogFormat, ServerConfig};
+pub use correspondence::CorrespondenceGame;
+pub use friends::PresenceTracker;
 pub use game::GameTimer;
+pub use matchmaking::Matchmaker;
+pub use metrics::ServerMetrics;
 pub use player::{Player, PlayerManager, PlayerStatus};
-pub use room::{Room, RoomManager};
+pub use rate_limit::{ConnectionLimiter, IpGuard, RateLimitConfig};
+pub use ratings::Rating;
+pub use replay::ReplayBuffer;
+pub use registry::PlayerRegistry;
+pub use room::{PlayerLabel, Room, RoomKey, RoomSettings, RoomSummary};
+pub use room_actor::{RoomActor, RoomHandle, RoomManager, RoomServices};
 pub use server::{MessageHandler, ServerState};
+pub use session::SessionManager;
+pub use shutdown::Shutdown;
 pub use storage::{StorageManager, SavedGameInfo};
+pub use tournament::{Tournament, TournamentManager};
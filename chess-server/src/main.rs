@@ -1,105 +1,524 @@
 //! 中国象棋服务端入口
 
-use std::net::SocketAddr;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::net::TcpListener;
-use tokio::sync::{mpsc, RwLock};
+use clap::Parser;
+use tokio::io::AsyncRead;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, RwLock, Semaphore};
 use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-use chess_server::{MessageHandler, ServerState};
+use chess_server::rate_limit::{MessageCategory, Refusal, Verdict};
+use chess_server::snapshot::ServerSnapshot;
+use chess_server::{
+    admin, metrics, Cli, ConnectionLimiter, IpGuard, LogFormat, MessageHandler, PresenceTracker, RateLimitConfig,
+    ServerConfig, ServerMetrics, ServerState,
+};
 use protocol::{
-    ClientMessage, FrameReader, FrameWriter, PlayerId, ProtocolError, ServerMessage,
+    ClientMessage, ErrorCode, FrameReader, FrameWriter, PlayerId, ProtocolError, ServerMessage,
 };
 
-/// 默认服务端口
-const DEFAULT_PORT: u16 = 9527;
+/// 停服时留给发送任务的时间
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = ServerConfig::from_cli(&cli)?;
+
     // 初始化日志
-    tracing_subscriber::fmt::init();
+    init_logging(config.log_format);
+
+    let listener = TcpListener::bind(&config.listen_addr).await?;
+
+    info!("中国象棋服务器启动，监听 {}", config.listen_addr);
+    info!(
+        "最大连接数 {}，心跳超时 {}s，重连超时 {}s",
+        config.max_connections, config.heartbeat_timeout_secs, config.reconnect_timeout_secs
+    );
+
+    let rate_limit = Arc::new(config.rate_limit.clone());
+    info!(
+        "消息限流{}，单 IP 连接上限 {}，登录失败上限 {}",
+        if rate_limit.enabled { "开启" } else { "关闭" },
+        rate_limit.max_connections_per_ip,
+        rate_limit.max_login_failures
+    );
+
+    let heartbeat_timeout = config.heartbeat_timeout();
+    let send_queue_capacity = config.send_queue_capacity;
+    let connection_limit = Arc::new(Semaphore::new(config.max_connections));
+    let ip_guard = Arc::new(IpGuard::new(&rate_limit));
+    let admin = config.admin_addr.clone().zip(config.admin_token.clone());
+    let metrics_addr = config.metrics_addr.clone();
+    let snapshot_path = config.snapshot_path()?;
+    let mut server_state = ServerState::with_config(config)?;
+
+    // 恢复上次停服时保存的对局
+    match ServerSnapshot::take(&snapshot_path) {
+        Ok(Some(snapshot)) => {
+            let restored = server_state.restore_games(snapshot);
+            info!("已从停服快照恢复 {} 个对局", restored);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("读取停服快照失败: {:#}", e),
+    }
+
+    let state = Arc::new(RwLock::new(server_state));
+    let shutdown = state.read().await.shutdown.clone();
+
+    // SIGTERM 或 Ctrl+C 触发停服
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            wait_for_signal().await;
+            info!("收到停服信号");
+            shutdown.trigger();
+        }
+    });
+
+    // 启动管理接口
+    if let Some((admin_addr, admin_token)) = admin {
+        let admin_listener = TcpListener::bind(&admin_addr).await?;
+        info!("管理接口监听 {}", admin_addr);
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(admin_listener, state, &admin_token).await {
+                error!("管理接口错误: {}", e);
+            }
+        });
+    }
+
+    // 启动定时任务：各自只在处理时短暂持有大厅锁，互不等待
+    // 断线超时检查与挑战过期
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut lobby = &*state;
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                MessageHandler::check_disconnect_timeouts(&mut lobby).await;
+                MessageHandler::expire_challenges(&mut lobby).await;
+            }
+        }
+    });
+
+    // 匹配
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut lobby = &*state;
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                MessageHandler::run_matchmaking(&mut lobby).await;
+            }
+        }
+    });
+
+    // 比赛推进
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut lobby = &*state;
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                MessageHandler::run_tournaments(&mut lobby).await;
+            }
+        }
+    });
 
-    let addr: SocketAddr = format!("0.0.0.0:{}", DEFAULT_PORT).parse()?;
-    let listener = TcpListener::bind(&addr).await?;
-    
-    info!("中国象棋服务器启动，监听 {}", addr);
+    // 好友状态推送与通信对局期限检查只用玩家登记和账号库，不需要大厅锁
+    let (players, accounts) = {
+        let state = state.read().await;
+        (state.players.clone(), state.accounts.clone())
+    };
+    tokio::spawn({
+        let (players, accounts) = (players.clone(), accounts.clone());
+        async move {
+            let mut presence = PresenceTracker::new();
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                MessageHandler::push_presence(&players, &accounts, &mut presence).await;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            MessageHandler::check_correspondence_deadlines(&players, &accounts).await;
+        }
+    });
+
+    // 启动指标接口
+    if let Some(metrics_addr) = metrics_addr {
+        let metrics_listener = TcpListener::bind(&metrics_addr).await?;
+        info!("指标接口监听 {}", metrics_addr);
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_listener, state).await {
+                error!("指标接口错误: {}", e);
+            }
+        });
+    }
+    let server_metrics = state.read().await.metrics.clone();
 
-    let state = Arc::new(RwLock::new(ServerState::new()?));
+    // 定期清理过期的 IP 记录
+    tokio::spawn({
+        let ip_guard = ip_guard.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                ip_guard.prune(Instant::now());
+            }
+        }
+    });
 
-    // 启动断线超时检查任务
-    let state_clone = state.clone();
+    // 定期记录 AI 计算池的排队情况
+    let ai_pool = state.read().await.ai_pool.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        let mut last_completed = 0;
         loop {
             interval.tick().await;
-            let mut state = state_clone.write().await;
-            MessageHandler::check_disconnect_timeouts(&mut state).await;
+            let metrics = ai_pool.metrics();
+            if metrics.completed != last_completed || metrics.queued > 0 {
+                last_completed = metrics.completed;
+                info!(
+                    "AI 计算池: 上限 {}，计算中 {}，排队 {}，已完成 {}，平均排队 {:?}，最长排队 {:?}",
+                    metrics.workers, metrics.running, metrics.queued, metrics.completed,
+                    metrics.average_wait, metrics.max_wait
+                );
+            }
         }
     });
 
     loop {
-        let (socket, addr) = listener.accept().await?;
+        let (socket, addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.wait() => break,
+        };
+
+        // 来源 IP 的连接数上限与封禁
+        let ip = addr.ip();
+        let ip_permit = match ip_guard.admit(ip, Instant::now()) {
+            Ok(permit) => permit,
+            Err(refusal) => {
+                let message = match refusal {
+                    Refusal::TooManyConnections => {
+                        warn!("来源 IP 连接数已达上限，拒绝连接: {}", addr);
+                        "同一地址的连接数过多，请稍后再试".to_string()
+                    }
+                    Refusal::Banned(remaining) => {
+                        info!("来源 IP 已被临时封禁，拒绝连接: {}", addr);
+                        format!("操作过于频繁，请 {} 秒后再试", remaining.as_secs().max(1))
+                    }
+                };
+                server_metrics.connection_refused(refusal);
+                tokio::spawn(reject_connection(socket, ErrorCode::RateLimited, message));
+                continue;
+            }
+        };
+
+        // 连接数上限：许可随连接任务一起释放
+        let permit = match connection_limit.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("连接数已达上限，拒绝连接: {}", addr);
+                server_metrics.connections_rejected.inc();
+                let message = "服务器连接数已满，请稍后再试".to_string();
+                tokio::spawn(reject_connection(socket, ErrorCode::ServerFull, message));
+                continue;
+            }
+        };
+
         info!("新连接: {}", addr);
+        server_metrics.connections_accepted.inc();
+        server_metrics.connections.inc();
 
         let state = state.clone();
-        
+        let server_metrics = server_metrics.clone();
+        let rate_limit = rate_limit.clone();
+        let ip_guard = ip_guard.clone();
+
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(socket, state).await {
+            let guard = Guard {
+                ip,
+                rate_limit: &rate_limit,
+                ip_guard: &ip_guard,
+                metrics: &server_metrics,
+            };
+            if let Err(e) = handle_connection(socket, state, heartbeat_timeout, send_queue_capacity, guard).await {
                 error!("连接处理错误: {}", e);
             }
+            server_metrics.connections.dec();
+            drop(permit);
+            drop(ip_permit);
         });
     }
+
+    // 停服：不再接受新连接，暂停并保存进行中的对局，通知在线玩家后留出时间发送
+    let mut state = state.write().await;
+    let snapshot = state.suspend_games().await;
+    let saved = !snapshot.rooms.is_empty()
+        && match snapshot.save(&snapshot_path) {
+            Ok(()) => {
+                info!("已保存 {} 个进行中的对局到 {:?}", snapshot.rooms.len(), snapshot_path);
+                true
+            }
+            Err(e) => {
+                error!("保存停服快照失败: {:#}", e);
+                false
+            }
+        };
+    let notice = if saved {
+        ServerMessage::ServerRestarting { grace_secs: state.config.restore_grace_secs as u32 }
+    } else {
+        ServerMessage::Announcement { text: "服务器即将关闭".to_string() }
+    };
+    let notified = state.players.broadcast(notice);
+    info!("服务器停止，已通知 {} 个连接", notified);
+    drop(state);
+    tokio::time::sleep(SHUTDOWN_GRACE).await;
+    Ok(())
+}
+
+/// 等待 SIGTERM 或 Ctrl+C
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("无法监听 SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("无法监听 Ctrl+C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// 按配置的格式初始化日志
+fn init_logging(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+    match format {
+        LogFormat::Full => builder.init(),
+        LogFormat::Compact => builder.compact().init(),
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// 拒绝超出上限的连接
+async fn reject_connection(socket: TcpStream, code: ErrorCode, message: String) {
+    let (_read_half, write_half) = socket.into_split();
+    let mut writer = FrameWriter::new(write_half);
+    let response = ServerMessage::Error { code, message };
+    let _ = writer.write_frame(&response).await;
+}
+
+/// 连接任务的限流与防滥用上下文
+struct Guard<'a> {
+    ip: IpAddr,
+    rate_limit: &'a RateLimitConfig,
+    ip_guard: &'a IpGuard,
+    metrics: &'a ServerMetrics,
+}
+
+impl Guard<'_> {
+    /// 按消息类别限流；违规过多时记录日志并封禁来源 IP
+    fn check(&self, limiter: &mut ConnectionLimiter, msg: &ClientMessage) -> Verdict {
+        let category = MessageCategory::of(msg);
+        let now = Instant::now();
+        let verdict = limiter.check(category, now);
+        match verdict {
+            Verdict::Allow => {}
+            Verdict::Reject { notify } => {
+                self.metrics.rate_limited(category);
+                if notify {
+                    info!("{} 的 {} 类消息超出频率限制", self.ip, category.as_str());
+                }
+            }
+            Verdict::Disconnect => {
+                self.metrics.rate_limited(category);
+                self.metrics.penalty("disconnect");
+                warn!("{} 消息过于频繁（违规 {} 次），断开连接", self.ip, limiter.strikes());
+                if let Some(duration) = self.ip_guard.ban(self.ip, now) {
+                    self.metrics.penalty("ip_ban");
+                    warn!("临时封禁 {} {}s", self.ip, duration.as_secs());
+                }
+            }
+        }
+        verdict
+    }
+
+    /// 登录被暂停时返回错误消息
+    ///
+    /// 来源 IP 失败过多时暂停所有登录；昵称失败过多时只暂停该账号的密码登录
+    fn login_locked(&self, account: Option<&str>) -> Option<ServerMessage> {
+        let now = Instant::now();
+        let remaining = self
+            .ip_guard
+            .login_locked(self.ip, now)
+            .or_else(|| self.ip_guard.account_locked(account?, now))?;
+        Some(ServerMessage::Error {
+            code: ErrorCode::RateLimited,
+            message: format!("登录失败次数过多，请 {} 秒后再试", remaining.as_secs().max(1)),
+        })
+    }
+
+    /// 根据登录结果更新失败记录（`account` 为密码登录使用的昵称）
+    fn login_result(&self, account: Option<&str>, response: &ServerMessage) {
+        match response {
+            ServerMessage::LoginSuccess { .. } | ServerMessage::ReconnectSuccess { .. } => {
+                self.ip_guard.login_succeeded(self.ip);
+                if let Some(nickname) = account {
+                    self.ip_guard.account_login_succeeded(nickname);
+                }
+            }
+            ServerMessage::Error {
+                code: ErrorCode::InvalidCredentials | ErrorCode::InvalidSession,
+                ..
+            } => {
+                self.metrics.login_failures.inc();
+                let now = Instant::now();
+                if self.ip_guard.login_failed(self.ip, now) {
+                    self.metrics.penalty("login_lockout");
+                    warn!(
+                        "{} 登录失败次数过多，暂停登录 {}s",
+                        self.ip, self.rate_limit.login_lockout_secs
+                    );
+                }
+                if let Some(nickname) = account.filter(|nickname| self.ip_guard.account_login_failed(nickname, now)) {
+                    self.metrics.penalty("account_lockout");
+                    warn!(
+                        "账号 {} 密码错误次数过多，暂停密码登录 {}s",
+                        nickname, self.rate_limit.login_lockout_secs
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 限流提示
+fn rate_limited() -> ServerMessage {
+    ServerMessage::Error {
+        code: ErrorCode::RateLimited,
+        message: "操作过于频繁，请稍后再试".to_string(),
+    }
+}
+
+/// 读取一条客户端消息（超过心跳超时未收到任何消息视为连接失效）
+async fn read_message<R: AsyncRead + Unpin + Send>(
+    reader: &mut FrameReader<R>,
+    heartbeat_timeout: Option<Duration>,
+    metrics: &ServerMetrics,
+) -> Result<ClientMessage, ProtocolError> {
+    let result = match heartbeat_timeout {
+        Some(limit) => tokio::time::timeout(limit, reader.read_frame())
+            .await
+            .unwrap_or(Err(ProtocolError::ConnectionTimeout)),
+        None => reader.read_frame().await,
+    };
+    if let Err(e) = &result {
+        metrics.frame_error(e);
+    }
+    result
 }
 
 async fn handle_connection(
-    socket: tokio::net::TcpStream,
+    socket: TcpStream,
     state: Arc<RwLock<ServerState>>,
+    heartbeat_timeout: Option<Duration>,
+    send_queue_capacity: usize,
+    guard: Guard<'_>,
 ) -> anyhow::Result<()> {
+    let metrics = guard.metrics;
+    let mut limiter = ConnectionLimiter::new(guard.rate_limit, Instant::now());
     let (read_half, write_half) = socket.into_split();
     let mut reader = FrameReader::new(read_half);
     let mut writer = FrameWriter::new(write_half);
 
-    // 创建消息通道
-    let (tx, mut rx) = mpsc::channel::<ServerMessage>(32);
+    // 创建消息通道（积压超过容量时断开，见 `ServerConfig::send_queue_capacity`）
+    let (tx, mut rx) = mpsc::channel::<ServerMessage>(send_queue_capacity);
 
     // 等待登录消息
     let player_id: PlayerId;
     loop {
-        match reader.read_frame::<ClientMessage>().await {
+        match read_message(&mut reader, heartbeat_timeout, metrics).await {
             Ok(msg) => {
+                match guard.check(&mut limiter, &msg) {
+                    Verdict::Allow => {}
+                    Verdict::Reject { notify } => {
+                        if notify {
+                            writer.write_frame(&rate_limited()).await?;
+                        }
+                        continue;
+                    }
+                    Verdict::Disconnect => return Ok(()),
+                }
+                let is_login = matches!(
+                    msg,
+                    ClientMessage::Login { .. }
+                        | ClientMessage::Register { .. }
+                        | ClientMessage::BotLogin { .. }
+                        | ClientMessage::Reconnect { .. }
+                );
+                // 密码登录按昵称另计失败次数
+                let account = match &msg {
+                    ClientMessage::Login { nickname, password: Some(_) } => Some(nickname.clone()),
+                    _ => None,
+                };
+                if let Some(response) = is_login.then(|| guard.login_locked(account.as_deref())).flatten() {
+                    writer.write_frame(&response).await?;
+                    continue;
+                }
                 match msg {
-                    ClientMessage::Login { nickname } => {
-                        let mut state = state.write().await;
-                        match state.players.login(nickname) {
-                            Ok(id) => {
+                    ClientMessage::Login { .. } | ClientMessage::Register { .. } | ClientMessage::BotLogin { .. } => {
+                        // 登记玩家时同时挂上发送通道；回复在释放大厅锁后写出
+                        if let Some(response) = MessageHandler::login(&state, msg, tx.clone()).await {
+                            guard.login_result(account.as_deref(), &response);
+                            if let ServerMessage::LoginSuccess { player_id: id, .. } = response {
                                 player_id = id;
-                                state.connections.insert(id, tx.clone());
-                                
-                                // 发送登录成功
-                                let response = ServerMessage::LoginSuccess { player_id: id };
                                 writer.write_frame(&response).await?;
                                 break;
-                            }
-                            Err(msg) => {
-                                let response = ServerMessage::Error {
-                                    code: protocol::ErrorCode::InvalidNickname,
-                                    message: msg.to_string(),
-                                };
+                            } else {
                                 writer.write_frame(&response).await?;
                             }
                         }
                     }
-                    ClientMessage::Reconnect { player_id: pid, room_id } => {
-                        let mut state = state.write().await;
-                        if let Some(response) = MessageHandler::handle(
-                            &mut state,
-                            pid,
-                            ClientMessage::Reconnect { player_id: pid, room_id },
-                        ).await {
-                            if matches!(response, ServerMessage::ReconnectSuccess { .. }) {
+                    ClientMessage::Reconnect { session_token, room_id, last_seq } => {
+                        let pid = state.read().await.sessions.validate(&session_token);
+                        // 房间任务在生成快照的同时登记新连接，之后的事件不会遗漏或重复
+                        let response = MessageHandler::handle_reconnect(
+                            &mut &*state,
+                            &session_token,
+                            room_id,
+                            last_seq,
+                            Some(tx.clone()),
+                        )
+                        .await;
+                        if let Some(response) = response {
+                            guard.login_result(account.as_deref(), &response);
+                            if let (ServerMessage::ReconnectSuccess { .. }, Some(pid)) = (&response, pid) {
                                 player_id = pid;
-                                state.connections.insert(pid, tx.clone());
                                 writer.write_frame(&response).await?;
                                 break;
                             } else {
@@ -109,7 +528,7 @@ async fn handle_connection(
                     }
                     _ => {
                         let response = ServerMessage::Error {
-                            code: protocol::ErrorCode::InvalidNickname,
+                            code: ErrorCode::AccountRequired,
                             message: "请先登录".to_string(),
                         };
                         writer.write_frame(&response).await?;
@@ -120,6 +539,10 @@ async fn handle_connection(
                 info!("客户端断开连接（登录前）");
                 return Ok(());
             }
+            Err(ProtocolError::ConnectionTimeout) => {
+                info!("客户端登录超时");
+                return Ok(());
+            }
             Err(e) => {
                 error!("读取帧错误: {}", e);
                 return Err(e.into());
@@ -129,10 +552,17 @@ async fn handle_connection(
 
     info!("玩家 {} 登录成功", player_id);
 
+    // 登记持有唯一的强发送端：队列积压被移除时，发送任务取完剩余消息即结束
+    let players = state.read().await.players.clone();
+    let connection = tx.downgrade();
+    drop(tx);
+
     // 启动发送任务
     let mut write_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            if writer.write_frame(&msg).await.is_err() {
+            // 被请出时发完通知即关闭连接
+            let kicked = matches!(msg, ServerMessage::Kicked { .. });
+            if writer.write_frame(&msg).await.is_err() || kicked {
                 break;
             }
         }
@@ -141,19 +571,36 @@ async fn handle_connection(
     // 主循环：读取客户端消息
     loop {
         tokio::select! {
-            result = reader.read_frame::<ClientMessage>() => {
+            result = read_message(&mut reader, heartbeat_timeout, metrics) => {
                 match result {
                     Ok(msg) => {
-                        let mut state = state.write().await;
-                        
-                        if let Some(response) = MessageHandler::handle(&mut state, player_id, msg).await {
-                            let _ = tx.send(response).await;
+                        match guard.check(&mut limiter, &msg) {
+                            Verdict::Allow => {}
+                            Verdict::Reject { notify } => {
+                                if notify {
+                                    players.send(player_id, rate_limited());
+                                }
+                                continue;
+                            }
+                            Verdict::Disconnect => break,
+                        }
+                        let logout = matches!(msg, ClientMessage::Logout);
+                        if let Some(response) = MessageHandler::dispatch(&state, player_id, msg).await {
+                            players.send(player_id, response);
+                        }
+                        if logout {
+                            info!("玩家 {} 注销", player_id);
+                            break;
                         }
                     }
                     Err(ProtocolError::ConnectionClosed) => {
                         info!("玩家 {} 断开连接", player_id);
                         break;
                     }
+                    Err(ProtocolError::ConnectionTimeout) => {
+                        warn!("玩家 {} 心跳超时，断开连接", player_id);
+                        break;
+                    }
                     Err(e) => {
                         error!("读取帧错误: {}", e);
                         break;
@@ -167,10 +614,10 @@ async fn handle_connection(
         }
     }
 
-    // 处理断线
-    {
-        let mut state = state.write().await;
-        MessageHandler::handle_disconnect(&mut state, player_id).await;
+    // 处理断线（重连的新连接已经接替时不再处理）
+    let attached = connection.upgrade().is_some_and(|tx| players.is_attached(player_id, &tx));
+    if attached || !players.is_connected(player_id) {
+        MessageHandler::handle_disconnect(&mut &*state, player_id).await;
     }
 
     Ok(())
@@ -0,0 +1,325 @@
+//! 匹配队列
+//!
+//! 按等级分和时间控制为排队的玩家配对。等级分窗口随等待时间逐渐放宽，
+//! 执红执黑根据双方近期的先后手记录分配
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use protocol::{PlayerId, ServerMessage, Side, TimeControl};
+
+use crate::accounts::AccountId;
+
+/// 初始等级分窗口
+pub const INITIAL_RATING_WINDOW: u32 = 100;
+
+/// 每秒放宽的等级分窗口
+pub const RATING_WINDOW_GROWTH_PER_SEC: u32 = 10;
+
+/// 等级分窗口上限
+pub const MAX_RATING_WINDOW: u32 = 600;
+
+/// 队列状态推送间隔
+pub const QUEUE_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 排队中的玩家
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    pub player_id: PlayerId,
+    /// 玩家的账号（游客为 None），同一账号的连接不会互相配对
+    pub account_id: Option<AccountId>,
+    pub rating: i32,
+    pub time_control: TimeControl,
+    pub rated: bool,
+    pub joined_at: Instant,
+    /// 上次推送队列状态的时间
+    last_status_at: Option<Instant>,
+}
+
+impl QueueEntry {
+    pub fn new(
+        player_id: PlayerId,
+        account_id: Option<AccountId>,
+        rating: i32,
+        time_control: TimeControl,
+        rated: bool,
+    ) -> Self {
+        Self {
+            player_id,
+            account_id,
+            rating,
+            time_control,
+            rated,
+            joined_at: Instant::now(),
+            last_status_at: None,
+        }
+    }
+
+    /// 当前可接受的等级分差
+    pub fn rating_window(&self, now: Instant) -> u32 {
+        let waited = now.saturating_duration_since(self.joined_at).as_secs() as u32;
+        INITIAL_RATING_WINDOW
+            .saturating_add(waited.saturating_mul(RATING_WINDOW_GROWTH_PER_SEC))
+            .min(MAX_RATING_WINDOW)
+    }
+
+    /// 能否与另一名玩家配对
+    fn accepts(&self, other: &QueueEntry, now: Instant) -> bool {
+        let diff = self.rating.abs_diff(other.rating);
+        self.time_control == other.time_control
+            && self.rated == other.rated
+            && (self.account_id.is_none() || self.account_id != other.account_id)
+            && diff <= self.rating_window(now)
+            && diff <= other.rating_window(now)
+    }
+}
+
+/// 配对结果
+#[derive(Debug, Clone)]
+pub struct Pairing {
+    pub red: PlayerId,
+    pub black: PlayerId,
+    pub time_control: TimeControl,
+    pub rated: bool,
+}
+
+/// 匹配器
+#[derive(Default)]
+pub struct Matchmaker {
+    /// 按进入队列的先后排列
+    queue: Vec<QueueEntry>,
+    /// 先后手平衡（执红 +1，执黑 -1）
+    colour_balance: HashMap<PlayerId, i32>,
+}
+
+impl Matchmaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 加入队列（已在队列中则更新偏好并重新计时）
+    pub fn enqueue(&mut self, entry: QueueEntry) {
+        self.remove(entry.player_id);
+        self.queue.push(entry);
+    }
+
+    /// 退出队列，返回之前是否在队列中
+    pub fn remove(&mut self, player_id: PlayerId) -> bool {
+        let before = self.queue.len();
+        self.queue.retain(|e| e.player_id != player_id);
+        self.queue.len() != before
+    }
+
+    /// 玩家彻底离线时清理记录
+    pub fn forget(&mut self, player_id: PlayerId) {
+        self.remove(player_id);
+        self.colour_balance.remove(&player_id);
+    }
+
+    /// 移出已不能开局的玩家（如已进入房间），其余玩家保留原来的排队时间
+    pub fn retain_available(&mut self, available: impl Fn(PlayerId) -> bool) {
+        self.queue.retain(|e| available(e.player_id));
+    }
+
+    /// 是否在队列中
+    pub fn contains(&self, player_id: PlayerId) -> bool {
+        self.queue.iter().any(|e| e.player_id == player_id)
+    }
+
+    /// 队列人数
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// 队列是否为空
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// 为队列中的玩家配对，已配对的玩家移出队列
+    ///
+    /// 先到先得：按排队顺序为每名玩家挑选等级分最接近的可接受对手
+    pub fn find_matches(&mut self, now: Instant) -> Vec<Pairing> {
+        let mut pairings = Vec::new();
+        let mut i = 0;
+
+        while i < self.queue.len() {
+            let entry = &self.queue[i];
+            let best = self
+                .queue
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .filter(|(_, other)| entry.accepts(other, now))
+                .min_by_key(|(_, other)| entry.rating.abs_diff(other.rating))
+                .map(|(j, _)| j);
+
+            match best {
+                Some(j) => {
+                    let second = self.queue.remove(j);
+                    let first = self.queue.remove(i);
+                    pairings.push(self.assign_colours(first, second));
+                }
+                None => i += 1,
+            }
+        }
+
+        pairings
+    }
+
+    /// 需要推送的队列状态
+    pub fn due_statuses(&mut self, now: Instant) -> Vec<(PlayerId, ServerMessage)> {
+        let players_waiting = self.queue.len() as u32;
+        self.queue
+            .iter_mut()
+            .filter(|e| {
+                e.last_status_at
+                    .is_none_or(|at| now.saturating_duration_since(at) >= QUEUE_STATUS_INTERVAL)
+            })
+            .map(|e| {
+                e.last_status_at = Some(now);
+                (e.player_id, Self::status_of(e, players_waiting, now))
+            })
+            .collect()
+    }
+
+    /// 指定玩家的队列状态
+    pub fn status(&self, player_id: PlayerId, now: Instant) -> Option<ServerMessage> {
+        let entry = self.queue.iter().find(|e| e.player_id == player_id)?;
+        Some(Self::status_of(entry, self.queue.len() as u32, now))
+    }
+
+    fn status_of(entry: &QueueEntry, players_waiting: u32, now: Instant) -> ServerMessage {
+        ServerMessage::QueueStatus {
+            players_waiting,
+            waited_secs: now.saturating_duration_since(entry.joined_at).as_secs() as u32,
+            rating_window: entry.rating_window(now),
+        }
+    }
+
+    /// 分配先后手：近期执红较少的一方执红，相同时随机
+    fn assign_colours(&mut self, first: QueueEntry, second: QueueEntry) -> Pairing {
+        let first_balance = self.colour_balance.get(&first.player_id).copied().unwrap_or(0);
+        let second_balance = self.colour_balance.get(&second.player_id).copied().unwrap_or(0);
+
+        let first_side = match first_balance.cmp(&second_balance) {
+            std::cmp::Ordering::Less => Side::Red,
+            std::cmp::Ordering::Greater => Side::Black,
+            std::cmp::Ordering::Equal if rand::random::<bool>() => Side::Red,
+            std::cmp::Ordering::Equal => Side::Black,
+        };
+        let (red, black) = match first_side {
+            Side::Red => (first, second),
+            Side::Black => (second, first),
+        };
+
+        *self.colour_balance.entry(red.player_id).or_insert(0) += 1;
+        *self.colour_balance.entry(black.player_id).or_insert(0) -= 1;
+
+        Pairing {
+            red: red.player_id,
+            black: black.player_id,
+            time_control: red.time_control,
+            rated: red.rated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(player_id: PlayerId, rating: i32) -> QueueEntry {
+        QueueEntry::new(player_id, None, rating, TimeControl::default(), false)
+    }
+
+    #[test]
+    fn test_pairs_closest_rating() {
+        let mut matchmaker = Matchmaker::new();
+        matchmaker.enqueue(entry(1, 1500));
+        matchmaker.enqueue(entry(2, 1590));
+        matchmaker.enqueue(entry(3, 1520));
+
+        let pairings = matchmaker.find_matches(Instant::now());
+        assert_eq!(pairings.len(), 1);
+
+        let mut paired = [pairings[0].red, pairings[0].black];
+        paired.sort();
+        assert_eq!(paired, [1, 3]);
+        assert!(matchmaker.contains(2));
+    }
+
+    #[test]
+    fn test_window_widens_over_time() {
+        let mut matchmaker = Matchmaker::new();
+        matchmaker.enqueue(entry(1, 1500));
+        matchmaker.enqueue(entry(2, 1800));
+
+        let now = Instant::now();
+        assert!(matchmaker.find_matches(now).is_empty());
+
+        // 等待 30 秒后窗口放宽到 400
+        let later = now + Duration::from_secs(30);
+        assert_eq!(matchmaker.queue[0].rating_window(later), 400);
+        assert_eq!(matchmaker.find_matches(later).len(), 1);
+        assert!(matchmaker.is_empty());
+    }
+
+    #[test]
+    fn test_requires_same_preferences() {
+        let mut matchmaker = Matchmaker::new();
+        matchmaker.enqueue(entry(1, 1500));
+        matchmaker.enqueue(QueueEntry::new(2, None, 1500, TimeControl::minutes(5), false));
+        matchmaker.enqueue(QueueEntry::new(3, None, 1500, TimeControl::default(), true));
+
+        assert!(matchmaker.find_matches(Instant::now()).is_empty());
+        assert_eq!(matchmaker.len(), 3);
+    }
+
+    #[test]
+    fn test_same_account_not_paired() {
+        let mut matchmaker = Matchmaker::new();
+        // 同一机器人账号的两个连接
+        matchmaker.enqueue(QueueEntry::new(1, Some(7), 1500, TimeControl::default(), false));
+        matchmaker.enqueue(QueueEntry::new(2, Some(7), 1500, TimeControl::default(), false));
+        assert!(matchmaker.find_matches(Instant::now()).is_empty());
+
+        matchmaker.enqueue(entry(3, 1500));
+        let pairings = matchmaker.find_matches(Instant::now());
+        assert_eq!(pairings.len(), 1);
+        assert!([pairings[0].red, pairings[0].black].contains(&3));
+        assert_eq!(matchmaker.len(), 1);
+    }
+
+    #[test]
+    fn test_unavailable_player_not_paired() {
+        let mut matchmaker = Matchmaker::new();
+        matchmaker.enqueue(entry(1, 1500));
+        matchmaker.enqueue(entry(2, 1500));
+        let joined_at = matchmaker.queue[0].joined_at;
+
+        // 2 号已不在大厅，1 号留在队列中继续等待
+        matchmaker.retain_available(|id| id != 2);
+        assert!(matchmaker.find_matches(Instant::now()).is_empty());
+        assert!(matchmaker.contains(1) && !matchmaker.contains(2));
+        assert_eq!(matchmaker.queue[0].joined_at, joined_at);
+    }
+
+    #[test]
+    fn test_colour_balance() {
+        let mut matchmaker = Matchmaker::new();
+        let mut red_count = 0;
+
+        for _ in 0..4 {
+            matchmaker.enqueue(entry(1, 1500));
+            matchmaker.enqueue(entry(2, 1500));
+            let pairing = matchmaker.find_matches(Instant::now()).remove(0);
+            if pairing.red == 1 {
+                red_count += 1;
+            }
+        }
+
+        // 双方轮流执红
+        assert_eq!(red_count, 2);
+    }
+}
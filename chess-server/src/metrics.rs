@@ -0,0 +1,294 @@
+//! 运行指标
+//!
+//! 以 Prometheus 文本格式导出服务器运行指标。计数器在各处事件发生时累加，
+//! 房间数、AI 计算池排队数等瞬时值在抓取时按当前状态生成。
+//! 启用后在配置的地址上提供 `GET /metrics`
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use prometheus::{
+    exponential_buckets, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+
+use protocol::{ProtocolError, RoomState, RoomType};
+
+use crate::ai_pool::AiPoolMetrics;
+use crate::rate_limit::{MessageCategory, Refusal};
+use crate::room::RoomSummary;
+use crate::server::ServerState;
+
+/// 服务器运行指标（各服务共享同一份）
+pub struct ServerMetrics {
+    registry: Registry,
+    /// 当前连接数
+    pub connections: IntGauge,
+    /// 累计接受的连接数
+    pub connections_accepted: IntCounter,
+    /// 因连接数已满被拒绝的连接数
+    pub connections_rejected: IntCounter,
+    /// 登录成功次数（按游客/注册账号/机器人）
+    logins: IntCounterVec,
+    /// 房间数（按类型和状态，抓取时生成）
+    rooms: IntGaugeVec,
+    /// 累计走棋步数（含 AI）
+    pub moves: IntCounter,
+    /// AI 搜索耗时（秒）
+    ai_search_seconds: Histogram,
+    /// AI 搜索节点数
+    ai_search_nodes: Histogram,
+    /// 开始前已无人等待结果而放弃的 AI 搜索数（房间关闭或局面已变化）
+    pub ai_searches_abandoned: IntCounter,
+    /// AI 计算池排队与计算中的请求数（抓取时生成）
+    ai_pool: IntGaugeVec,
+    /// 读帧错误（按错误类型）
+    frame_errors: IntCounterVec,
+    /// 重连成功次数
+    pub reconnects: IntCounter,
+    /// 断线超时次数
+    pub disconnect_timeouts: IntCounter,
+    /// 因限流丢弃的消息数（按消息类别）
+    rate_limited: IntCounterVec,
+    /// 按来源 IP 拒绝的连接数（按原因）
+    connections_refused: IntCounterVec,
+    /// 登录失败次数
+    pub login_failures: IntCounter,
+    /// 防滥用处罚次数（刷屏断开、封禁 IP、拒绝登录）
+    penalties: IntCounterVec,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("chess".to_string()), None)
+            .expect("指标前缀合法");
+        let metrics = Self {
+            connections: IntGauge::new("connections", "当前连接数").expect("指标定义合法"),
+            connections_accepted: IntCounter::new("connections_accepted_total", "累计接受的连接数")
+                .expect("指标定义合法"),
+            connections_rejected: IntCounter::new("connections_rejected_total", "因连接数已满被拒绝的连接数")
+                .expect("指标定义合法"),
+            logins: IntCounterVec::new(Opts::new("logins_total", "登录成功次数"), &["kind"])
+                .expect("指标定义合法"),
+            rooms: IntGaugeVec::new(Opts::new("rooms", "房间数"), &["room_type", "state"])
+                .expect("指标定义合法"),
+            moves: IntCounter::new("moves_total", "累计走棋步数").expect("指标定义合法"),
+            ai_search_seconds: Histogram::with_opts(
+                HistogramOpts::new("ai_search_seconds", "AI 搜索耗时（秒）")
+                    .buckets(exponential_buckets(0.01, 2.0, 12).expect("分桶参数合法")),
+            )
+            .expect("指标定义合法"),
+            ai_search_nodes: Histogram::with_opts(
+                HistogramOpts::new("ai_search_nodes", "AI 搜索节点数")
+                    .buckets(exponential_buckets(1000.0, 4.0, 10).expect("分桶参数合法")),
+            )
+            .expect("指标定义合法"),
+            ai_searches_abandoned: IntCounter::new("ai_searches_abandoned_total", "放弃的 AI 搜索数")
+                .expect("指标定义合法"),
+            ai_pool: IntGaugeVec::new(Opts::new("ai_pool_requests", "AI 计算池请求数"), &["state"])
+                .expect("指标定义合法"),
+            frame_errors: IntCounterVec::new(Opts::new("frame_errors_total", "读帧错误次数"), &["kind"])
+                .expect("指标定义合法"),
+            reconnects: IntCounter::new("reconnects_total", "重连成功次数").expect("指标定义合法"),
+            disconnect_timeouts: IntCounter::new("disconnect_timeouts_total", "断线超时次数")
+                .expect("指标定义合法"),
+            rate_limited: IntCounterVec::new(Opts::new("rate_limited_total", "因限流丢弃的消息数"), &["category"])
+                .expect("指标定义合法"),
+            connections_refused: IntCounterVec::new(
+                Opts::new("connections_refused_total", "按来源 IP 拒绝的连接数"),
+                &["reason"],
+            )
+            .expect("指标定义合法"),
+            login_failures: IntCounter::new("login_failures_total", "登录失败次数").expect("指标定义合法"),
+            penalties: IntCounterVec::new(Opts::new("abuse_penalties_total", "防滥用处罚次数"), &["penalty"])
+                .expect("指标定义合法"),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 17] = [
+            Box::new(metrics.connections.clone()),
+            Box::new(metrics.connections_accepted.clone()),
+            Box::new(metrics.connections_rejected.clone()),
+            Box::new(metrics.logins.clone()),
+            Box::new(metrics.rooms.clone()),
+            Box::new(metrics.moves.clone()),
+            Box::new(metrics.ai_search_seconds.clone()),
+            Box::new(metrics.ai_search_nodes.clone()),
+            Box::new(metrics.ai_searches_abandoned.clone()),
+            Box::new(metrics.ai_pool.clone()),
+            Box::new(metrics.frame_errors.clone()),
+            Box::new(metrics.reconnects.clone()),
+            Box::new(metrics.disconnect_timeouts.clone()),
+            Box::new(metrics.rate_limited.clone()),
+            Box::new(metrics.connections_refused.clone()),
+            Box::new(metrics.login_failures.clone()),
+            Box::new(metrics.penalties.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).expect("指标名不重复");
+        }
+        metrics
+    }
+
+    /// 记录一次登录
+    pub fn login(&self, guest: bool) {
+        let kind = if guest { "guest" } else { "account" };
+        self.logins.with_label_values(&[kind]).inc();
+    }
+
+    /// 记录一次机器人登录
+    pub fn bot_login(&self) {
+        self.logins.with_label_values(&["bot"]).inc();
+    }
+
+    /// 记录一次 AI 搜索
+    pub fn ai_search(&self, elapsed: Duration, nodes: u64) {
+        self.ai_search_seconds.observe(elapsed.as_secs_f64());
+        self.ai_search_nodes.observe(nodes as f64);
+    }
+
+    /// 记录一次读帧错误（连接正常关闭不计）
+    pub fn frame_error(&self, error: &ProtocolError) {
+        if !matches!(error, ProtocolError::ConnectionClosed) {
+            self.frame_errors.with_label_values(&[error.kind()]).inc();
+        }
+    }
+
+    /// 记录一条因限流丢弃的消息
+    pub fn rate_limited(&self, category: MessageCategory) {
+        self.rate_limited.with_label_values(&[category.as_str()]).inc();
+    }
+
+    /// 记录一次按来源 IP 拒绝的连接
+    pub fn connection_refused(&self, refusal: Refusal) {
+        self.connections_refused.with_label_values(&[refusal.as_str()]).inc();
+    }
+
+    /// 记录一次防滥用处罚（`disconnect`、`ip_ban`、`login_lockout`、`account_lockout`）
+    pub fn penalty(&self, penalty: &str) {
+        self.penalties.with_label_values(&[penalty]).inc();
+    }
+
+    /// 按当前房间和计算池状态生成文本格式的指标
+    pub fn render(&self, rooms: &[RoomSummary], ai_pool: AiPoolMetrics) -> String {
+        self.rooms.reset();
+        for room in rooms {
+            let room_type = match room.room_type {
+                RoomType::PvP => "pvp",
+                RoomType::PvE(_) => "pve",
+            };
+            let state = match room.state {
+                RoomState::Waiting => "waiting",
+                RoomState::Playing => "playing",
+                RoomState::Paused => "paused",
+                RoomState::Finished => "finished",
+            };
+            self.rooms.with_label_values(&[room_type, state]).inc();
+        }
+        self.ai_pool.with_label_values(&["queued"]).set(ai_pool.queued as i64);
+        self.ai_pool.with_label_values(&["running"]).set(ai_pool.running as i64);
+
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_else(|e| {
+                tracing::error!("指标编码失败: {}", e);
+                String::new()
+            })
+    }
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 在监听器上提供 `GET /metrics`，停服时退出
+pub async fn serve(listener: TcpListener, server: Arc<RwLock<ServerState>>) -> std::io::Result<()> {
+    let shutdown = server.read().await.shutdown.clone();
+    let router = Router::new().route("/metrics", get(scrape)).with_state(server);
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await
+}
+
+async fn scrape(State(server): State<Arc<RwLock<ServerState>>>) -> impl IntoResponse {
+    let server = server.read().await;
+    let body = server.metrics.render(&server.rooms.list_all(), server.ai_pool.metrics());
+    (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{Difficulty, RuleSet, TimeControl};
+
+    fn summary(id: u64, room_type: RoomType, state: RoomState) -> RoomSummary {
+        RoomSummary {
+            id,
+            room_type,
+            state,
+            rated: false,
+            time_control: TimeControl::default(),
+            rules: RuleSet::default(),
+            private: false,
+            invite_code: None,
+            custom_fen: None,
+            red_player: None,
+            black_player: None,
+            spectators: 0,
+        }
+    }
+
+    #[test]
+    fn test_render_text_format() {
+        let metrics = ServerMetrics::new();
+        metrics.login(true);
+        metrics.moves.inc_by(3);
+        metrics.ai_search(Duration::from_millis(50), 12_000);
+        metrics.frame_error(&ProtocolError::ConnectionTimeout);
+        metrics.frame_error(&ProtocolError::ConnectionClosed);
+        metrics.rate_limited(MessageCategory::Ping);
+        metrics.connection_refused(Refusal::TooManyConnections);
+        metrics.penalty("disconnect");
+
+        let rooms = [
+            summary(1, RoomType::PvP, RoomState::Playing),
+            summary(2, RoomType::PvP, RoomState::Playing),
+            summary(3, RoomType::PvE(Difficulty::Easy), RoomState::Waiting),
+        ];
+        let pool = AiPoolMetrics {
+            workers: 2,
+            queued: 1,
+            running: 2,
+            completed: 5,
+            average_wait: Duration::ZERO,
+            max_wait: Duration::ZERO,
+        };
+        let text = metrics.render(&rooms, pool);
+
+        assert!(text.contains("chess_logins_total{kind=\"guest\"} 1"));
+        assert!(text.contains("chess_moves_total 3"));
+        assert!(text.contains("chess_rooms{room_type=\"pvp\",state=\"playing\"} 2"));
+        assert!(text.contains("chess_rooms{room_type=\"pve\",state=\"waiting\"} 1"));
+        assert!(text.contains("chess_ai_pool_requests{state=\"queued\"} 1"));
+        assert!(text.contains("chess_ai_search_seconds_count 1"));
+        assert!(text.contains("chess_ai_search_nodes_sum 12000"));
+        assert!(text.contains("chess_frame_errors_total{kind=\"timeout\"} 1"));
+        assert!(!text.contains("kind=\"closed\""));
+        assert!(text.contains("chess_rate_limited_total{category=\"ping\"} 1"));
+        assert!(text.contains("chess_connections_refused_total{reason=\"per_ip\"} 1"));
+        assert!(text.contains("chess_abuse_penalties_total{penalty=\"disconnect\"} 1"));
+
+        // 房间结束后不再出现在指标中
+        let text = metrics.render(&rooms[2..], pool);
+        assert!(!text.contains("state=\"playing\""));
+    }
+}
@@ -1,10 +1,13 @@
 //! 玩家管理
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use protocol::{PlayerId, RoomId};
 
+use crate::accounts::AccountId;
+use crate::bots::BotSettings;
+
 /// 玩家状态
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlayerStatus {
@@ -12,6 +15,8 @@ pub enum PlayerStatus {
     Online,
     /// 在线，在房间中
     InRoom(RoomId),
+    /// 在线，观战中
+    Spectating(RoomId),
     /// 断线中（保留房间）
     Disconnected(RoomId),
 }
@@ -22,6 +27,12 @@ pub struct Player {
     pub id: PlayerId,
     pub nickname: String,
     pub status: PlayerStatus,
+    /// 注册账号 ID（游客为 None）
+    pub account_id: Option<AccountId>,
+    /// 已屏蔽聊天的玩家
+    pub muted: HashSet<PlayerId>,
+    /// 机器人设置（非机器人为 None）
+    pub bot: Option<BotSettings>,
 }
 
 impl Player {
@@ -30,8 +41,31 @@ impl Player {
             id,
             nickname,
             status: PlayerStatus::Online,
+            account_id: None,
+            muted: HashSet::new(),
+            bot: None,
         }
     }
+
+    /// 是否为游客
+    pub fn is_guest(&self) -> bool {
+        self.account_id.is_none()
+    }
+
+    /// 是否为机器人
+    pub fn is_bot(&self) -> bool {
+        self.bot.is_some()
+    }
+
+    /// 是否占用着一局对局（在房间中或断线保留房间）
+    pub fn in_game(&self) -> bool {
+        matches!(self.status, PlayerStatus::InRoom(_) | PlayerStatus::Disconnected(_))
+    }
+
+    /// 是否屏蔽了某玩家的聊天
+    pub fn has_muted(&self, player_id: PlayerId) -> bool {
+        self.muted.contains(&player_id)
+    }
 }
 
 /// 玩家管理器
@@ -69,8 +103,35 @@ impl PlayerManager {
         Ok(())
     }
 
-    /// 登录玩家
+    /// 登录玩家（游客）
     pub fn login(&mut self, nickname: String) -> Result<PlayerId, &'static str> {
+        self.login_as(nickname, None)
+    }
+
+    /// 以注册账号登录
+    pub fn login_account(
+        &mut self,
+        nickname: String,
+        account_id: AccountId,
+    ) -> Result<PlayerId, &'static str> {
+        self.login_as(nickname, Some(account_id))
+    }
+
+    /// 机器人登录（同一机器人的多个连接共用昵称，不占用昵称表）
+    pub fn login_bot(&mut self, nickname: String, account_id: AccountId, settings: BotSettings) -> PlayerId {
+        let id = self.generate_id();
+        let mut player = Player::new(id, nickname);
+        player.account_id = Some(account_id);
+        player.bot = Some(settings);
+        self.players.insert(id, player);
+        id
+    }
+
+    fn login_as(
+        &mut self,
+        nickname: String,
+        account_id: Option<AccountId>,
+    ) -> Result<PlayerId, &'static str> {
         Self::validate_nickname(&nickname)?;
 
         // 检查昵称是否已被占用
@@ -79,7 +140,8 @@ impl PlayerManager {
         }
 
         let id = self.generate_id();
-        let player = Player::new(id, nickname.clone());
+        let mut player = Player::new(id, nickname.clone());
+        player.account_id = account_id;
         
         self.players.insert(id, player);
         self.nickname_to_id.insert(nickname, id);
@@ -87,6 +149,24 @@ impl PlayerManager {
         Ok(id)
     }
 
+    /// 恢复停服前的玩家（沿用原 ID，之后生成的 ID 不会与之重复）
+    ///
+    /// ID 或昵称已被占用时返回 false
+    pub fn restore(&mut self, player: Player) -> bool {
+        let takes_nickname = !player.is_bot();
+        if self.players.contains_key(&player.id)
+            || (takes_nickname && self.nickname_to_id.contains_key(&player.nickname))
+        {
+            return false;
+        }
+        self.next_id.fetch_max(player.id + 1, Ordering::SeqCst);
+        if takes_nickname {
+            self.nickname_to_id.insert(player.nickname.clone(), player.id);
+        }
+        self.players.insert(player.id, player);
+        true
+    }
+
     /// 玩家断线
     pub fn disconnect(&mut self, player_id: PlayerId) -> Option<RoomId> {
         if let Some(player) = self.players.get_mut(&player_id) {
@@ -120,7 +200,9 @@ impl PlayerManager {
     /// 移除玩家（彻底离线）
     pub fn remove(&mut self, player_id: PlayerId) -> Option<Player> {
         if let Some(player) = self.players.remove(&player_id) {
-            self.nickname_to_id.remove(&player.nickname);
+            if self.nickname_to_id.get(&player.nickname) == Some(&player_id) {
+                self.nickname_to_id.remove(&player.nickname);
+            }
             Some(player)
         } else {
             None
@@ -154,6 +236,44 @@ impl PlayerManager {
         self.players.contains_key(&player_id)
     }
 
+    /// 昵称是否被在线玩家占用
+    pub fn is_nickname_taken(&self, nickname: &str) -> bool {
+        self.nickname_to_id.contains_key(nickname)
+    }
+
+    /// 以该账号登录的所有连接（机器人可有多个）
+    pub fn sessions_of(&self, account_id: AccountId) -> impl Iterator<Item = &Player> {
+        self.players.values().filter(move |p| p.account_id == Some(account_id))
+    }
+
+    /// 以该注册账号登录的玩家（机器人有多个连接时优先返回大厅中的连接）
+    pub fn find_by_account(&self, account_id: AccountId) -> Option<PlayerId> {
+        self.sessions_of(account_id)
+            .min_by_key(|p| p.status != PlayerStatus::Online)
+            .map(|p| p.id)
+    }
+
+    /// 检查玩家是否在观战
+    pub fn is_spectating(&self, player_id: PlayerId) -> bool {
+        self.players
+            .get(&player_id)
+            .is_some_and(|p| matches!(p.status, PlayerStatus::Spectating(_)))
+    }
+
+    /// 大厅中的玩家（在线且不在房间或观战中）
+    pub fn lobby_players(&self) -> Vec<PlayerId> {
+        self.players
+            .values()
+            .filter(|p| p.status == PlayerStatus::Online)
+            .map(|p| p.id)
+            .collect()
+    }
+
+    /// 所有玩家（含断线中保留的玩家）
+    pub fn all(&self) -> impl Iterator<Item = &Player> {
+        self.players.values()
+    }
+
     /// 获取在线玩家数量
     pub fn online_count(&self) -> usize {
         self.players.len()
@@ -181,6 +301,17 @@ mod tests {
         assert_ne!(id1, id2);
     }
 
+    #[test]
+    fn test_login_account() {
+        let mut manager = PlayerManager::new();
+
+        let guest = manager.login("游客".to_string()).unwrap();
+        let member = manager.login_account("会员".to_string(), 7).unwrap();
+
+        assert!(manager.get(guest).unwrap().is_guest());
+        assert_eq!(manager.get(member).unwrap().account_id, Some(7));
+    }
+
     #[test]
     fn test_duplicate_nickname() {
         let mut manager = PlayerManager::new();
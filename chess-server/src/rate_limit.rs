@@ -0,0 +1,656 @@
+//! 限流与防滥用
+//!
+//! - 每个连接按消息类别各有一个令牌桶，超出的消息直接丢弃（每轮只回复一次错误）
+//! - 被丢弃的消息累计为违规次数，随时间逐步消退；达到上限断开连接并临时封禁来源 IP
+//! - 限制单个 IP 的并发连接数
+//! - 同一 IP 登录失败次数过多时暂时拒绝登录
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use protocol::ClientMessage;
+
+/// 消息类别（各自独立限流）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageCategory {
+    /// 登录、注册、重连、修改密码
+    Auth,
+    /// 对局操作
+    Game,
+    /// 房间列表、棋局查询等只读请求
+    Query,
+    /// 心跳
+    Ping,
+    /// 其他
+    General,
+}
+
+impl MessageCategory {
+    const ALL: [MessageCategory; 5] = [Self::Auth, Self::Game, Self::Query, Self::Ping, Self::General];
+
+    /// 消息所属类别
+    pub fn of(msg: &ClientMessage) -> Self {
+        match msg {
+            ClientMessage::Login { .. }
+            | ClientMessage::Register { .. }
+            | ClientMessage::BotLogin { .. }
+            | ClientMessage::Reconnect { .. }
+            | ClientMessage::ChangePassword { .. } => Self::Auth,
+            ClientMessage::MakeMove { .. }
+            | ClientMessage::RequestUndo
+            | ClientMessage::RespondUndo { .. }
+            | ClientMessage::Resign
+            | ClientMessage::OfferDraw
+            | ClientMessage::RespondDraw { .. }
+            | ClientMessage::PauseGame
+            | ClientMessage::ResumeGame
+            | ClientMessage::SaveGame
+            | ClientMessage::CorrespondenceMove { .. }
+            | ClientMessage::ResignCorrespondence { .. } => Self::Game,
+            ClientMessage::ListRooms
+            | ClientMessage::ListMyGames { .. }
+            | ClientMessage::FetchGame { .. }
+            | ClientMessage::LoadGame { .. }
+            | ClientMessage::ListTournaments
+            | ClientMessage::GetTournament { .. }
+            | ClientMessage::ListFriends
+            | ClientMessage::ListCorrespondence
+            | ClientMessage::GetCorrespondence { .. } => Self::Query,
+            ClientMessage::Ping => Self::Ping,
+            _ => Self::General,
+        }
+    }
+
+    /// 指标和日志中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Game => "game",
+            Self::Query => "query",
+            Self::Ping => "ping",
+            Self::General => "general",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// 令牌桶参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketLimit {
+    /// 桶容量（允许的突发消息数）
+    pub burst: u32,
+    /// 每秒补充的令牌数
+    pub per_sec: f64,
+}
+
+impl BucketLimit {
+    pub const fn new(burst: u32, per_sec: f64) -> Self {
+        Self { burst, per_sec }
+    }
+}
+
+/// 限流配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// 是否按消息类别限流
+    pub enabled: bool,
+    /// 登录、注册、重连、修改密码
+    pub auth: BucketLimit,
+    /// 对局操作
+    pub game: BucketLimit,
+    /// 只读查询
+    pub query: BucketLimit,
+    /// 心跳
+    pub ping: BucketLimit,
+    /// 其他消息
+    pub general: BucketLimit,
+    /// 每隔多少秒消退一次违规
+    pub strike_decay_secs: u64,
+    /// 违规累计达到此数断开连接
+    pub disconnect_strikes: u32,
+    /// 因刷屏断开后封禁来源 IP 的时长（秒），0 表示不封禁
+    pub ban_secs: u64,
+    /// 单个 IP 的最大并发连接数，0 表示不限制
+    pub max_connections_per_ip: usize,
+    /// 统计窗口内允许的登录失败次数（按来源 IP 和按昵称分别统计），0 表示不限制
+    pub max_login_failures: u32,
+    /// 登录失败统计窗口（秒）
+    pub login_failure_window_secs: u64,
+    /// 登录失败过多后拒绝登录的时长（秒）
+    pub login_lockout_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auth: BucketLimit::new(5, 0.5),
+            game: BucketLimit::new(10, 2.0),
+            query: BucketLimit::new(10, 1.0),
+            ping: BucketLimit::new(5, 1.0),
+            general: BucketLimit::new(20, 5.0),
+            strike_decay_secs: 5,
+            disconnect_strikes: 50,
+            ban_secs: 60,
+            max_connections_per_ip: 16,
+            max_login_failures: 5,
+            login_failure_window_secs: 300,
+            login_lockout_secs: 300,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// 校验配置
+    pub fn validate(&self) -> Result<()> {
+        for category in MessageCategory::ALL {
+            let limit = self.limit(category);
+            if limit.burst == 0 || !limit.per_sec.is_finite() || limit.per_sec <= 0.0 {
+                anyhow::bail!("限流参数无效 ({}): 容量和补充速率必须大于 0", category.as_str());
+            }
+        }
+        if self.disconnect_strikes == 0 {
+            anyhow::bail!("断开连接的违规次数必须大于 0");
+        }
+        if self.strike_decay_secs == 0 {
+            anyhow::bail!("违规消退间隔必须大于 0");
+        }
+        Ok(())
+    }
+
+    /// 某类消息的令牌桶参数
+    pub fn limit(&self, category: MessageCategory) -> BucketLimit {
+        match category {
+            MessageCategory::Auth => self.auth,
+            MessageCategory::Game => self.game,
+            MessageCategory::Query => self.query,
+            MessageCategory::Ping => self.ping,
+            MessageCategory::General => self.general,
+        }
+    }
+}
+
+/// 令牌桶
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: BucketLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    /// 补充令牌后尝试取一个
+    fn take(&mut self, limit: BucketLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_sec).min(limit.burst as f64);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 限流判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// 正常处理
+    Allow,
+    /// 丢弃消息；`notify` 为真时回复一次限流错误
+    Reject { notify: bool },
+    /// 违规过多，断开连接
+    Disconnect,
+}
+
+/// 单个连接的消息限流
+#[derive(Debug, Clone)]
+pub struct ConnectionLimiter {
+    config: RateLimitConfig,
+    buckets: [TokenBucket; 5],
+    /// 累计违规次数
+    strikes: u32,
+    /// 上次消退违规的时间
+    last_decay: Instant,
+    /// 上次放行之后是否已回复过限流错误
+    notified: bool,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        Self {
+            buckets: MessageCategory::ALL.map(|c| TokenBucket::full(config.limit(c), now)),
+            config: config.clone(),
+            strikes: 0,
+            last_decay: now,
+            notified: false,
+        }
+    }
+
+    /// 判定一条消息能否处理
+    pub fn check(&mut self, category: MessageCategory, now: Instant) -> Verdict {
+        if !self.config.enabled {
+            return Verdict::Allow;
+        }
+        self.decay(now);
+
+        let limit = self.config.limit(category);
+        if self.buckets[category.index()].take(limit, now) {
+            self.notified = false;
+            return Verdict::Allow;
+        }
+
+        self.strikes += 1;
+        if self.strikes >= self.config.disconnect_strikes {
+            return Verdict::Disconnect;
+        }
+        let notify = !self.notified;
+        self.notified = true;
+        Verdict::Reject { notify }
+    }
+
+    /// 当前累计违规次数
+    pub fn strikes(&self) -> u32 {
+        self.strikes
+    }
+
+    /// 每经过一个消退间隔减少一次违规
+    fn decay(&mut self, now: Instant) {
+        let interval = Duration::from_secs(self.config.strike_decay_secs.max(1));
+        let elapsed = now.saturating_duration_since(self.last_decay);
+        let steps = (elapsed.as_secs_f64() / interval.as_secs_f64()) as u32;
+        if steps > 0 {
+            self.strikes = self.strikes.saturating_sub(steps);
+            self.last_decay += interval * steps;
+        }
+    }
+}
+
+/// 拒绝连接的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// 该 IP 的连接数已达上限
+    TooManyConnections,
+    /// 该 IP 被临时封禁（剩余时长）
+    Banned(Duration),
+}
+
+impl Refusal {
+    /// 指标中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TooManyConnections => "per_ip",
+            Self::Banned(_) => "banned",
+        }
+    }
+}
+
+/// 登录失败记录：统计窗口内失败达到上限后拒绝登录一段时间
+#[derive(Debug, Default)]
+struct LoginFailures {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl LoginFailures {
+    /// 拒绝登录的剩余时长
+    fn locked(&self, now: Instant) -> Option<Duration> {
+        let until = self.locked_until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// 记录一次失败，达到上限时开始拒绝登录并返回 true
+    fn failed(&mut self, config: &RateLimitConfig, now: Instant) -> bool {
+        let window = Duration::from_secs(config.login_failure_window_secs);
+        self.expire(window, now);
+        self.failures.push_back(now);
+        if self.failures.len() < config.max_login_failures as usize {
+            return false;
+        }
+        self.failures.clear();
+        self.locked_until = Some(now + Duration::from_secs(config.login_lockout_secs));
+        true
+    }
+
+    /// 丢弃窗口外的失败
+    fn expire(&mut self, window: Duration, now: Instant) {
+        while self
+            .failures
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= window)
+        {
+            self.failures.pop_front();
+        }
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        self.failures.is_empty() && self.locked_until.is_none_or(|t| t <= now)
+    }
+}
+
+/// 单个 IP 的记录
+#[derive(Debug, Default)]
+struct IpRecord {
+    connections: usize,
+    banned_until: Option<Instant>,
+    login: LoginFailures,
+}
+
+impl IpRecord {
+    fn is_idle(&self, now: Instant) -> bool {
+        self.connections == 0 && self.banned_until.is_none_or(|t| t <= now) && self.login.is_idle(now)
+    }
+}
+
+/// 按来源 IP 的连接数上限、封禁与登录失败限制，以及按昵称的登录失败限制（所有连接共享）
+///
+/// 换 IP 猜同一账号的密码时，按昵称的计数同样会锁定该账号的密码登录
+#[derive(Debug)]
+pub struct IpGuard {
+    config: RateLimitConfig,
+    records: Mutex<HashMap<IpAddr, IpRecord>>,
+    accounts: Mutex<HashMap<String, LoginFailures>>,
+}
+
+impl IpGuard {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            records: Mutex::new(HashMap::new()),
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 登记一个新连接，许可释放时计数减一
+    pub fn admit(self: &Arc<Self>, ip: IpAddr, now: Instant) -> Result<IpPermit, Refusal> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let record = records.entry(ip).or_default();
+        if let Some(until) = record.banned_until.filter(|&t| t > now) {
+            return Err(Refusal::Banned(until - now));
+        }
+        let limit = self.config.max_connections_per_ip;
+        if limit > 0 && record.connections >= limit {
+            return Err(Refusal::TooManyConnections);
+        }
+        record.connections += 1;
+        Ok(IpPermit {
+            guard: self.clone(),
+            ip,
+        })
+    }
+
+    /// 封禁 IP，返回封禁时长（未配置封禁时返回 None）
+    pub fn ban(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        if self.config.ban_secs == 0 {
+            return None;
+        }
+        let duration = Duration::from_secs(self.config.ban_secs);
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.entry(ip).or_default().banned_until = Some(now + duration);
+        Some(duration)
+    }
+
+    /// 该 IP 是否因登录失败过多被拒绝登录（返回剩余时长）
+    pub fn login_locked(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.get(&ip)?.login.locked(now)
+    }
+
+    /// 记录一次登录失败，达到上限时开始拒绝登录并返回 true
+    pub fn login_failed(&self, ip: IpAddr, now: Instant) -> bool {
+        if self.config.max_login_failures == 0 {
+            return false;
+        }
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.entry(ip).or_default().login.failed(&self.config, now)
+    }
+
+    /// 登录成功后清除失败记录
+    pub fn login_succeeded(&self, ip: IpAddr) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(record) = records.get_mut(&ip) {
+            record.login.failures.clear();
+        }
+    }
+
+    /// 该昵称的密码登录是否因失败过多被暂停（返回剩余时长）
+    pub fn account_locked(&self, nickname: &str, now: Instant) -> Option<Duration> {
+        let accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts.get(nickname)?.locked(now)
+    }
+
+    /// 记录一次该昵称的密码错误，达到上限时暂停其密码登录并返回 true
+    pub fn account_login_failed(&self, nickname: &str, now: Instant) -> bool {
+        if self.config.max_login_failures == 0 {
+            return false;
+        }
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts.entry(nickname.to_string()).or_default().failed(&self.config, now)
+    }
+
+    /// 该昵称登录成功后清除失败记录
+    pub fn account_login_succeeded(&self, nickname: &str) {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts.remove(nickname);
+    }
+
+    /// 清理没有连接、封禁和失败记录的 IP 与昵称
+    pub fn prune(&self, now: Instant) {
+        let window = Duration::from_secs(self.config.login_failure_window_secs);
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        for record in records.values_mut() {
+            record.login.expire(window, now);
+        }
+        records.retain(|_, record| !record.is_idle(now));
+        drop(records);
+
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        for failures in accounts.values_mut() {
+            failures.expire(window, now);
+        }
+        accounts.retain(|_, failures| !failures.is_idle(now));
+    }
+
+    /// 某个 IP 当前的连接数
+    pub fn connections(&self, ip: IpAddr) -> usize {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        records.get(&ip).map_or(0, |r| r.connections)
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(record) = records.get_mut(&ip) {
+            record.connections = record.connections.saturating_sub(1);
+        }
+    }
+}
+
+/// 连接许可（随连接任务一起释放）
+#[derive(Debug)]
+pub struct IpPermit {
+    guard: Arc<IpGuard>,
+    ip: IpAddr,
+}
+
+impl Drop for IpPermit {
+    fn drop(&mut self) {
+        self.guard.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::Position;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    #[test]
+    fn test_message_categories() {
+        let mv = ClientMessage::MakeMove {
+            from: Position::new_unchecked(0, 0),
+            to: Position::new_unchecked(0, 1),
+        };
+        assert_eq!(MessageCategory::of(&mv), MessageCategory::Game);
+        assert_eq!(MessageCategory::of(&ClientMessage::ListRooms), MessageCategory::Query);
+        assert_eq!(MessageCategory::of(&ClientMessage::Ping), MessageCategory::Ping);
+        assert_eq!(MessageCategory::of(&ClientMessage::LeaveQueue), MessageCategory::General);
+        let login = ClientMessage::BotLogin { token: String::new() };
+        assert_eq!(MessageCategory::of(&login), MessageCategory::Auth);
+    }
+
+    #[test]
+    fn test_token_bucket_and_escalation() {
+        let config = RateLimitConfig {
+            ping: BucketLimit::new(3, 1.0),
+            disconnect_strikes: 4,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut limiter = ConnectionLimiter::new(&config, now);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(MessageCategory::Ping, now), Verdict::Allow);
+        }
+        // 超出后只回复一次错误，其余静默丢弃
+        assert_eq!(limiter.check(MessageCategory::Ping, now), Verdict::Reject { notify: true });
+        assert_eq!(limiter.check(MessageCategory::Ping, now), Verdict::Reject { notify: false });
+        // 其他类别互不影响
+        assert_eq!(limiter.check(MessageCategory::Game, now), Verdict::Allow);
+
+        // 一秒补充一个令牌，放行后重新提醒
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.check(MessageCategory::Ping, later), Verdict::Allow);
+        assert_eq!(limiter.check(MessageCategory::Ping, later), Verdict::Reject { notify: true });
+        assert_eq!(limiter.strikes(), 3);
+        assert_eq!(limiter.check(MessageCategory::Ping, later), Verdict::Disconnect);
+
+        // 违规随时间消退
+        let mut limiter = ConnectionLimiter::new(&config, now);
+        for _ in 0..5 {
+            limiter.check(MessageCategory::Ping, now);
+        }
+        assert_eq!(limiter.strikes(), 2);
+        let decayed = now + Duration::from_secs(config.strike_decay_secs * 2);
+        limiter.check(MessageCategory::Ping, decayed);
+        assert_eq!(limiter.strikes(), 0);
+
+        // 关闭限流时全部放行
+        let config = RateLimitConfig { enabled: false, ..config };
+        let mut limiter = ConnectionLimiter::new(&config, now);
+        for _ in 0..100 {
+            assert_eq!(limiter.check(MessageCategory::Ping, now), Verdict::Allow);
+        }
+    }
+
+    #[test]
+    fn test_ip_connection_limit_and_ban() {
+        let config = RateLimitConfig {
+            max_connections_per_ip: 2,
+            ban_secs: 30,
+            ..Default::default()
+        };
+        let guard = Arc::new(IpGuard::new(&config));
+        let now = Instant::now();
+
+        let first = guard.admit(ip(1), now).unwrap();
+        let _second = guard.admit(ip(1), now).unwrap();
+        assert_eq!(guard.admit(ip(1), now).unwrap_err(), Refusal::TooManyConnections);
+        assert!(guard.admit(ip(2), now).is_ok());
+
+        // 连接结束后释放名额
+        drop(first);
+        assert_eq!(guard.connections(ip(1)), 1);
+        let _third = guard.admit(ip(1), now).unwrap();
+
+        assert_eq!(guard.ban(ip(3), now), Some(Duration::from_secs(30)));
+        assert_eq!(
+            guard.admit(ip(3), now + Duration::from_secs(10)).unwrap_err(),
+            Refusal::Banned(Duration::from_secs(20))
+        );
+        assert!(guard.admit(ip(3), now + Duration::from_secs(30)).is_ok());
+
+        // 空闲记录被清理
+        guard.prune(now + Duration::from_secs(60));
+        assert_eq!(guard.records.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_login_failure_lockout() {
+        let config = RateLimitConfig {
+            max_login_failures: 3,
+            login_failure_window_secs: 60,
+            login_lockout_secs: 120,
+            ..Default::default()
+        };
+        let guard = IpGuard::new(&config);
+        let now = Instant::now();
+
+        assert!(!guard.login_failed(ip(1), now));
+        // 窗口外的失败不计
+        assert!(!guard.login_failed(ip(1), now + Duration::from_secs(61)));
+        assert!(!guard.login_failed(ip(1), now + Duration::from_secs(62)));
+        assert_eq!(guard.login_locked(ip(1), now + Duration::from_secs(62)), None);
+        assert!(guard.login_failed(ip(1), now + Duration::from_secs(63)));
+        assert_eq!(
+            guard.login_locked(ip(1), now + Duration::from_secs(73)),
+            Some(Duration::from_secs(110))
+        );
+        assert_eq!(guard.login_locked(ip(2), now), None);
+        assert_eq!(guard.login_locked(ip(1), now + Duration::from_secs(183)), None);
+
+        // 登录成功清除失败记录
+        guard.login_failed(ip(2), now);
+        guard.login_failed(ip(2), now);
+        guard.login_succeeded(ip(2));
+        assert!(!guard.login_failed(ip(2), now));
+    }
+
+    #[test]
+    fn test_account_login_lockout() {
+        let config = RateLimitConfig {
+            max_login_failures: 2,
+            login_failure_window_secs: 60,
+            login_lockout_secs: 120,
+            ..Default::default()
+        };
+        let guard = IpGuard::new(&config);
+        let now = Instant::now();
+
+        // 按昵称计数，与来源 IP 无关
+        assert!(!guard.account_login_failed("会员", now));
+        assert!(guard.account_login_failed("会员", now + Duration::from_secs(1)));
+        assert_eq!(
+            guard.account_locked("会员", now + Duration::from_secs(21)),
+            Some(Duration::from_secs(100))
+        );
+        assert_eq!(guard.account_locked("其他", now), None);
+        assert_eq!(guard.login_locked(ip(1), now), None);
+        assert_eq!(guard.account_locked("会员", now + Duration::from_secs(121)), None);
+
+        // 登录成功清除记录，过期的记录被清理
+        guard.account_login_failed("棋手", now);
+        guard.account_login_succeeded("棋手");
+        assert!(!guard.account_login_failed("棋手", now));
+        guard.prune(now + Duration::from_secs(200));
+        assert!(guard.accounts.lock().unwrap().is_empty());
+    }
+}
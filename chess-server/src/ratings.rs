@@ -0,0 +1,340 @@
+//! 等级分（Glicko-2）
+//!
+//! 每局计分对局视为一个评级周期，按 Glickman 的 Glicko-2 算法更新双方的
+//! 等级分、评级偏差（RD）和波动率。新玩家 RD 较大，对局结果对分数影响更明显
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use protocol::{GameResult, RatingChange, RatingInfo, Side};
+
+use crate::accounts::{AccountError, AccountId, AccountStore};
+
+/// 初始等级分
+pub const DEFAULT_RATING: f64 = 1500.0;
+
+/// 初始评级偏差（同时也是上限）
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+
+/// 初始波动率
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+
+/// 系统常数 τ，约束波动率随时间的变化
+const TAU: f64 = 0.5;
+
+/// 波动率迭代的收敛阈值
+const CONVERGENCE_TOLERANCE: f64 = 1e-6;
+
+/// Glicko-2 内部标度换算系数
+const SCALE: f64 = 173.7178;
+
+/// 对局数少于此值时视为定级中
+const PROVISIONAL_GAMES: u32 = 10;
+
+/// RD 高于此值时视为定级中
+const PROVISIONAL_DEVIATION: f64 = 110.0;
+
+/// 玩家等级分
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    /// 已完成的计分对局数
+    pub games: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+            games: 0,
+        }
+    }
+}
+
+impl Rating {
+    /// 是否仍在定级中
+    pub fn is_provisional(&self) -> bool {
+        self.games < PROVISIONAL_GAMES || self.deviation > PROVISIONAL_DEVIATION
+    }
+
+    /// 转换为协议中展示用的等级分
+    pub fn info(&self) -> RatingInfo {
+        RatingInfo {
+            rating: self.rating.round() as i32,
+            deviation: self.deviation.round() as i32,
+            provisional: self.is_provisional(),
+        }
+    }
+
+    /// 根据一个评级周期内的对局结果计算新等级分
+    ///
+    /// `results` 为 (对手等级分, 得分) 列表，得分 1.0 胜、0.5 和、0.0 负
+    pub fn update(&self, results: &[(Rating, f64)]) -> Rating {
+        let mu = (self.rating - DEFAULT_RATING) / SCALE;
+        let phi = self.deviation / SCALE;
+
+        if results.is_empty() {
+            // 没有对局时只增加不确定性
+            let phi_star = (phi * phi + self.volatility * self.volatility).sqrt();
+            return Rating {
+                deviation: (phi_star * SCALE).min(DEFAULT_DEVIATION),
+                ..*self
+            };
+        }
+
+        // 估计方差 v 与改进量 Δ
+        let mut v_inv = 0.0;
+        let mut delta_sum = 0.0;
+        for (opponent, score) in results {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+            let phi_j = opponent.deviation / SCALE;
+            let g = g(phi_j);
+            let e = expected(mu, mu_j, phi_j);
+            v_inv += g * g * e * (1.0 - e);
+            delta_sum += g * (score - e);
+        }
+        let v = 1.0 / v_inv;
+        let delta = v * delta_sum;
+
+        let sigma = new_volatility(phi, self.volatility, v, delta);
+
+        let phi_star = (phi * phi + sigma * sigma).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * delta_sum;
+
+        Rating {
+            rating: new_mu * SCALE + DEFAULT_RATING,
+            deviation: (new_phi * SCALE).min(DEFAULT_DEVIATION),
+            volatility: sigma,
+            games: self.games + results.len() as u32,
+        }
+    }
+}
+
+/// 结算一局对局，返回 (红方新等级分, 黑方新等级分)
+pub fn rate_game(red: &Rating, black: &Rating, result: &GameResult) -> (Rating, Rating) {
+    let red_score = match result {
+        GameResult::RedWin(_) => 1.0,
+        GameResult::BlackWin(_) => 0.0,
+        GameResult::Draw(_) => 0.5,
+    };
+    (
+        red.update(&[(*black, red_score)]),
+        black.update(&[(*red, 1.0 - red_score)]),
+    )
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (std::f64::consts::PI * std::f64::consts::PI)).sqrt()
+}
+
+fn expected(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+/// 用 Illinois 算法求解新的波动率
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+/// 在给定连接（或事务）上读取账号等级分
+fn load_rating(conn: &Connection, account_id: AccountId) -> Result<Rating, AccountError> {
+    let rating = conn
+        .query_row(
+            "SELECT rating, deviation, volatility, games FROM ratings WHERE account_id = ?1",
+            params![account_id],
+            |row| {
+                Ok(Rating {
+                    rating: row.get(0)?,
+                    deviation: row.get(1)?,
+                    volatility: row.get(2)?,
+                    games: row.get(3)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(rating.unwrap_or_default())
+}
+
+/// 在给定连接（或事务）上保存账号等级分
+fn store_rating(conn: &Connection, account_id: AccountId, rating: &Rating) -> Result<(), AccountError> {
+    conn.execute(
+        "INSERT INTO ratings (account_id, rating, deviation, volatility, games, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(account_id) DO UPDATE SET
+            rating = excluded.rating,
+            deviation = excluded.deviation,
+            volatility = excluded.volatility,
+            games = excluded.games,
+            updated_at = excluded.updated_at",
+        params![
+            account_id,
+            rating.rating,
+            rating.deviation,
+            rating.volatility,
+            rating.games,
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+    Ok(())
+}
+
+/// 在事务中结算一局计分对局：读取双方等级分并写回新值
+pub(crate) fn settle(
+    tx: &Transaction,
+    red_account: AccountId,
+    black_account: AccountId,
+    result: &GameResult,
+) -> Result<Vec<RatingChange>, AccountError> {
+    let red = load_rating(tx, red_account)?;
+    let black = load_rating(tx, black_account)?;
+    let (new_red, new_black) = rate_game(&red, &black, result);
+    store_rating(tx, red_account, &new_red)?;
+    store_rating(tx, black_account, &new_black)?;
+
+    Ok(vec![
+        RatingChange { side: Side::Red, before: red.info(), after: new_red.info() },
+        RatingChange { side: Side::Black, before: black.info(), after: new_black.info() },
+    ])
+}
+
+impl AccountStore {
+    /// 获取账号等级分（尚未参加计分对局时返回初始值）
+    pub fn rating(&self, account_id: AccountId) -> Result<Rating, AccountError> {
+        load_rating(&self.connection(), account_id)
+    }
+
+    /// 保存账号等级分
+    pub fn save_rating(&self, account_id: AccountId, rating: &Rating) -> Result<(), AccountError> {
+        store_rating(&self.connection(), account_id, rating)
+    }
+
+    /// 结算一局计分对局并保存双方的新等级分
+    ///
+    /// 读取和写回在同一事务中完成，并发结算同一账号的对局不会互相覆盖
+    pub fn settle_ratings(
+        &self,
+        red_account: AccountId,
+        black_account: AccountId,
+        result: &GameResult,
+    ) -> Result<Vec<RatingChange>, AccountError> {
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        let rating_changes = settle(&tx, red_account, black_account, result)?;
+        tx.commit()?;
+        Ok(rating_changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{DrawReason, WinReason};
+
+    /// Glickman 论文中的示例
+    #[test]
+    fn test_glicko2_reference_example() {
+        let player = Rating {
+            rating: 1500.0,
+            deviation: 200.0,
+            volatility: 0.06,
+            games: 0,
+        };
+        let opponent = |rating, deviation| Rating {
+            rating,
+            deviation,
+            ..Rating::default()
+        };
+
+        let updated = player.update(&[
+            (opponent(1400.0, 30.0), 1.0),
+            (opponent(1550.0, 100.0), 0.0),
+            (opponent(1700.0, 300.0), 0.0),
+        ]);
+
+        assert!((updated.rating - 1464.06).abs() < 0.01, "{}", updated.rating);
+        assert!((updated.deviation - 151.52).abs() < 0.01, "{}", updated.deviation);
+        assert!((updated.volatility - 0.05999).abs() < 0.0001);
+        assert_eq!(updated.games, 3);
+    }
+
+    #[test]
+    fn test_rate_game() {
+        let red = Rating::default();
+        let black = Rating::default();
+
+        let (new_red, new_black) = rate_game(&red, &black, &GameResult::RedWin(WinReason::Checkmate));
+        assert!(new_red.rating > red.rating);
+        assert!(new_black.rating < black.rating);
+        assert!(new_red.deviation < red.deviation);
+
+        // 同分和棋不改变等级分
+        let (new_red, new_black) = rate_game(&red, &black, &GameResult::Draw(DrawReason::Agreement));
+        assert!((new_red.rating - red.rating).abs() < 1e-6);
+        assert!((new_black.rating - black.rating).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_provisional() {
+        let mut rating = Rating::default();
+        assert!(rating.info().provisional);
+
+        rating.games = PROVISIONAL_GAMES;
+        rating.deviation = 80.0;
+        assert!(!rating.info().provisional);
+    }
+
+    #[test]
+    fn test_rating_persistence() {
+        let store = AccountStore::open_in_memory().unwrap();
+        let account = store.register("棋手", "secret123").unwrap();
+
+        assert_eq!(store.rating(account.id).unwrap(), Rating::default());
+
+        let updated = Rating {
+            rating: 1620.5,
+            deviation: 120.0,
+            volatility: 0.059,
+            games: 4,
+        };
+        store.save_rating(account.id, &updated).unwrap();
+        assert_eq!(store.rating(account.id).unwrap(), updated);
+    }
+}
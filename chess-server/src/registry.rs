@@ -0,0 +1,189 @@
+//! 玩家登记服务
+//!
+//! 在线玩家信息与各连接的消息通道。大厅和各房间任务共享同一份登记，
+//! 内部用同步锁保护，持锁时间只限于读写几个字段，不会跨越 await。
+//!
+//! 登记持有连接唯一的强发送端。发送不等待：队列满说明客户端读得太慢，
+//! 直接移除通道，连接的发送任务随之结束并按断线处理，不会拖住大厅和房间任务
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+use protocol::{PlayerId, ServerMessage};
+
+use crate::accounts::AccountId;
+use crate::player::{PlayerManager, PlayerStatus};
+
+/// 玩家登记（克隆后共享同一份数据）
+#[derive(Clone, Default)]
+pub struct PlayerRegistry {
+    players: Arc<RwLock<PlayerManager>>,
+    /// 玩家 ID -> 消息发送通道
+    connections: Arc<RwLock<HashMap<PlayerId, mpsc::Sender<ServerMessage>>>>,
+}
+
+impl PlayerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取在线玩家
+    pub fn read(&self) -> RwLockReadGuard<'_, PlayerManager> {
+        // 持锁期间不会 panic，锁中毒时数据仍然可用
+        self.players.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 修改在线玩家
+    pub fn write(&self) -> RwLockWriteGuard<'_, PlayerManager> {
+        self.players.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 玩家状态
+    pub fn status(&self, player_id: PlayerId) -> Option<PlayerStatus> {
+        self.read().get(player_id).map(|p| p.status)
+    }
+
+    /// 玩家昵称
+    pub fn nickname(&self, player_id: PlayerId) -> Option<String> {
+        self.read().get_nickname(player_id).map(str::to_string)
+    }
+
+    /// 玩家的账号（游客为 None）
+    pub fn account_id(&self, player_id: PlayerId) -> Option<AccountId> {
+        self.read().get(player_id).and_then(|p| p.account_id)
+    }
+
+    /// 登记玩家的消息通道
+    pub fn attach(&self, player_id: PlayerId, tx: mpsc::Sender<ServerMessage>) {
+        self.connections
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(player_id, tx);
+    }
+
+    /// 移除玩家的消息通道
+    pub fn detach(&self, player_id: PlayerId) {
+        self.connections
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&player_id);
+    }
+
+    /// 玩家是否有连接
+    pub fn is_connected(&self, player_id: PlayerId) -> bool {
+        self.connections
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&player_id)
+    }
+
+    /// 玩家登记的是否为该通道（重连的新连接接替后不再是）
+    pub fn is_attached(&self, player_id: PlayerId, tx: &mpsc::Sender<ServerMessage>) -> bool {
+        self.connections
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&player_id)
+            .is_some_and(|attached| attached.same_channel(tx))
+    }
+
+    /// 发送消息给所有连接，返回送达的连接数
+    pub fn broadcast(&self, msg: ServerMessage) -> usize {
+        let player_ids: Vec<_> = self
+            .connections
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .copied()
+            .collect();
+        player_ids
+            .into_iter()
+            .filter(|&player_id| self.send(player_id, msg.clone()))
+            .count()
+    }
+
+    /// 推送给账号的所有在线连接
+    pub fn send_to_account(&self, account_id: AccountId, msg: ServerMessage) {
+        let sessions: Vec<PlayerId> = self.read().sessions_of(account_id).map(|p| p.id).collect();
+        for player_id in sessions {
+            self.send(player_id, msg.clone());
+        }
+    }
+
+    /// 发送消息给玩家，返回是否送达（未连接时丢弃）
+    ///
+    /// 队列已满时移除该玩家的通道，连接随之关闭
+    pub fn send(&self, player_id: PlayerId, msg: ServerMessage) -> bool {
+        let tx = self
+            .connections
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&player_id)
+            .cloned();
+        let Some(tx) = tx else {
+            return false;
+        };
+        match tx.try_send(msg) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("玩家 {} 的消息队列已满，断开连接", player_id);
+                let mut connections = self.connections.write().unwrap_or_else(|e| e.into_inner());
+                // 期间可能已被重连的新连接接替
+                if connections.get(&player_id).is_some_and(|attached| attached.same_channel(&tx)) {
+                    connections.remove(&player_id);
+                }
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_to_attached_player() {
+        let registry = PlayerRegistry::new();
+        let player_id = registry.write().login("玩家".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(4);
+
+        // 未连接时消息被丢弃
+        assert!(!registry.send(player_id, ServerMessage::Pong));
+        registry.attach(player_id, tx.clone());
+        assert!(registry.is_attached(player_id, &tx));
+        assert!(registry.send(player_id, ServerMessage::Pong));
+        assert!(matches!(rx.try_recv(), Ok(ServerMessage::Pong)));
+        assert!(rx.try_recv().is_err());
+
+        // 克隆共享同一份登记
+        let shared = registry.clone();
+        assert_eq!(shared.nickname(player_id).as_deref(), Some("玩家"));
+        assert_eq!(shared.status(player_id), Some(PlayerStatus::Online));
+        shared.detach(player_id);
+        registry.send(player_id, ServerMessage::Pong);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_is_detached() {
+        let registry = PlayerRegistry::new();
+        let player_id = registry.write().login("玩家".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(2);
+        registry.attach(player_id, tx);
+
+        // 队列满时不等待，直接移除通道
+        assert_eq!(registry.broadcast(ServerMessage::Pong), 1);
+        assert!(registry.send(player_id, ServerMessage::Pong));
+        assert!(!registry.send(player_id, ServerMessage::Pong));
+        assert!(!registry.is_connected(player_id));
+
+        // 登记持有唯一的发送端，移除后发送任务取完积压的消息即结束
+        assert!(rx.recv().await.is_some());
+        assert!(rx.recv().await.is_some());
+        assert!(rx.recv().await.is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! 房间事件重放缓冲
+//!
+//! 为房间内的每条事件分配递增序列号，并保留最近的若干条，
+//! 供断线重连的客户端补齐错过的事件
+
+use std::collections::VecDeque;
+
+use protocol::{PlayerId, SequencedEvent, ServerMessage};
+
+/// 每个房间默认保留的事件数量
+pub const REPLAY_BUFFER_CAPACITY: usize = 256;
+
+/// 缓冲中的事件
+#[derive(Debug, Clone)]
+struct BufferedEvent {
+    seq: u64,
+    /// 接收者（None 表示房间内所有玩家）
+    recipient: Option<PlayerId>,
+    message: ServerMessage,
+}
+
+/// 有界的事件重放缓冲
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    /// 下一个要分配的序列号（从 1 开始）
+    next_seq: u64,
+    events: VecDeque<BufferedEvent>,
+}
+
+/// 重放结果
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// 错过的事件（按序列号升序）
+    pub events: Vec<SequencedEvent>,
+    /// 是否完整（为 false 表示部分事件已被淘汰）
+    pub complete: bool,
+}
+
+impl ReplayBuffer {
+    /// 创建指定容量的缓冲
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            next_seq: 1,
+            events: VecDeque::with_capacity(capacity.min(REPLAY_BUFFER_CAPACITY)),
+        }
+    }
+
+    /// 记录一条事件，返回带序列号的事件
+    pub fn push(&mut self, recipient: Option<PlayerId>, message: ServerMessage) -> SequencedEvent {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(BufferedEvent {
+            seq,
+            recipient,
+            message: message.clone(),
+        });
+
+        SequencedEvent { seq, message }
+    }
+
+    /// 最近分配的序列号（尚无事件时为 0）
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    /// 获取某玩家在 `after_seq` 之后错过的事件
+    pub fn since(&self, player_id: PlayerId, after_seq: u64) -> Replay {
+        // 缓冲中最早的序列号，若比 after_seq + 1 还大则说明有事件已被淘汰
+        let oldest = self.events.front().map_or(self.next_seq, |e| e.seq);
+        let complete = after_seq + 1 >= oldest;
+
+        let events = self
+            .events
+            .iter()
+            .filter(|e| e.seq > after_seq)
+            .filter(|e| e.recipient.is_none_or(|id| id == player_id))
+            .map(|e| SequencedEvent {
+                seq: e.seq,
+                message: e.message.clone(),
+            })
+            .collect();
+
+        Replay { events, complete }
+    }
+}
+
+impl Default for ReplayBuffer {
+    fn default() -> Self {
+        Self::new(REPLAY_BUFFER_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_numbers() {
+        let mut buffer = ReplayBuffer::new(8);
+        assert_eq!(buffer.last_seq(), 0);
+
+        let first = buffer.push(None, ServerMessage::GamePaused);
+        let second = buffer.push(None, ServerMessage::GameResumed);
+
+        assert_eq!(first.seq, 1);
+        assert_eq!(second.seq, 2);
+        assert_eq!(buffer.last_seq(), 2);
+    }
+
+    #[test]
+    fn test_since_filters_recipient() {
+        let mut buffer = ReplayBuffer::new(8);
+        buffer.push(None, ServerMessage::GamePaused);
+        buffer.push(Some(1), ServerMessage::UndoRejected);
+        buffer.push(Some(2), ServerMessage::OpponentReconnected);
+
+        let replay = buffer.since(1, 0);
+        assert!(replay.complete);
+        assert_eq!(replay.events.len(), 2);
+        assert_eq!(replay.events[0].seq, 1);
+        assert_eq!(replay.events[1].seq, 2);
+
+        let replay = buffer.since(2, 1);
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].seq, 3);
+    }
+
+    #[test]
+    fn test_bounded_capacity() {
+        let mut buffer = ReplayBuffer::new(3);
+        for _ in 0..5 {
+            buffer.push(None, ServerMessage::Pong);
+        }
+
+        // 序列号 1、2 已被淘汰
+        let replay = buffer.since(1, 0);
+        assert!(!replay.complete);
+        assert_eq!(replay.events.len(), 3);
+        assert_eq!(replay.events[0].seq, 3);
+
+        // 从 2 之后开始仍然完整
+        let replay = buffer.since(1, 2);
+        assert!(replay.complete);
+        assert_eq!(replay.events.len(), 3);
+
+        // 已是最新
+        let replay = buffer.since(1, 5);
+        assert!(replay.complete);
+        assert!(replay.events.is_empty());
+    }
+}
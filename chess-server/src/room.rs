@@ -1,15 +1,146 @@
 //! 房间系统
 
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
+use serde::{Deserialize, Serialize};
+
 use protocol::{
-    BoardState, GameResult, Move, MoveGenerator, PlayerId, RoomId, RoomInfo,
-    RoomState, RoomType, Side, WinReason,
+    BoardState, ChessError, Fen, GameResult, Move, MoveGenerator, MoveRecord, Notation,
+    PlayerId, RatingInfo, RoomAccess, RoomId, RoomInfo, RoomState, RoomType, RuleSet, ServerMessage,
+    Side, TimeControl, TournamentId, WinReason, INITIAL_FEN, MAX_ROOM_PASSWORD_LEN,
 };
 
+use crate::chat::ChatHistory;
 use crate::game::GameTimer;
+use crate::replay::ReplayBuffer;
+
+/// 每个房间的观战人数上限
+pub const MAX_SPECTATORS: usize = 100;
+
+/// 每方每局最多提和次数
+pub const MAX_DRAW_OFFERS: u32 = 3;
+
+/// 同一方两次提和之间至少间隔的步数（双方合计）
+pub const DRAW_OFFER_INTERVAL_PLIES: usize = 10;
+
+/// 提和被拒的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawOfferError {
+    /// 已有未答复的提和
+    Pending,
+    /// 距上次提和太近
+    TooSoon,
+    /// 本局提和次数已用完
+    LimitReached,
+}
+
+impl DrawOfferError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            DrawOfferError::Pending => "已有未答复的提和",
+            DrawOfferError::TooSoon => "提和过于频繁，请多走几步再提",
+            DrawOfferError::LimitReached => "本局提和次数已用完",
+        }
+    }
+}
+
+/// 对局中的提和状态
+#[derive(Debug, Clone, Default)]
+pub struct DrawOffers {
+    /// 未答复的提和方
+    pub pending: Option<Side>,
+    /// 各方已提和次数（红、黑）
+    counts: [u32; 2],
+    /// 各方上次提和时的步数（红、黑）
+    last_ply: [Option<usize>; 2],
+}
+
+impl DrawOffers {
+    fn index(side: Side) -> usize {
+        match side {
+            Side::Red => 0,
+            Side::Black => 1,
+        }
+    }
+
+    /// 在第 `ply` 步时提和
+    pub fn offer(&mut self, side: Side, ply: usize) -> Result<(), DrawOfferError> {
+        let i = Self::index(side);
+        if self.pending.is_some() {
+            return Err(DrawOfferError::Pending);
+        }
+        if self.counts[i] >= MAX_DRAW_OFFERS {
+            return Err(DrawOfferError::LimitReached);
+        }
+        if self.last_ply[i].is_some_and(|last| ply < last + DRAW_OFFER_INTERVAL_PLIES) {
+            return Err(DrawOfferError::TooSoon);
+        }
+
+        self.pending = Some(side);
+        self.counts[i] += 1;
+        self.last_ply[i] = Some(ply);
+        Ok(())
+    }
+
+    /// 取出对 `responder` 有效的提和（提和方为对方时）
+    pub fn take_for(&mut self, responder: Side) -> Option<Side> {
+        self.pending.take_if(|by| *by == responder.opponent())
+    }
+}
+
+/// 私人房间的进入凭据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomKey {
+    /// 服务器生成的邀请码
+    Invite(String),
+    /// 房主设置的密码
+    Password(String),
+}
+
+impl RoomKey {
+    /// 检查玩家提供的密码或邀请码（邀请码不区分大小写）
+    pub fn accepts(&self, given: Option<&str>) -> bool {
+        match (self, given) {
+            (RoomKey::Invite(code), Some(given)) => code.eq_ignore_ascii_case(given),
+            (RoomKey::Password(password), Some(given)) => password == given,
+            (_, None) => false,
+        }
+    }
+}
+
+/// 创建房间时的自定义设置
+#[derive(Debug, Clone, Default)]
+pub struct RoomSettings {
+    pub rated: bool,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    /// 自定义初始局面（None 表示标准开局）
+    pub custom_fen: Option<String>,
+    pub access: RoomAccess,
+}
+
+impl RoomSettings {
+    /// 校验初始局面与密码（时间控制另行校验）
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let Some(fen) = &self.custom_fen {
+            if Fen::parse(fen).is_err() {
+                return Err("初始局面 FEN 无效");
+            }
+            if self.rated {
+                return Err("计分对局只能从标准开局开始");
+            }
+        }
+        if let RoomAccess::Password(password) = &self.access {
+            let len = password.chars().count();
+            if len == 0 || len > MAX_ROOM_PASSWORD_LEN {
+                return Err("房间密码长度无效");
+            }
+        }
+        Ok(())
+    }
+}
 
 /// 房间状态版本号（用于检测 race condition）
 static ROOM_VERSION_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -19,24 +150,52 @@ pub struct Room {
     pub id: RoomId,
     pub room_type: RoomType,
     pub state: RoomState,
+    /// 是否为计分对局
+    pub rated: bool,
+    /// 时间控制
+    pub time_control: TimeControl,
+    /// 对局规则
+    pub rules: RuleSet,
+    /// 所属比赛（比赛对局结束后向大厅报告结果）
+    pub tournament: Option<TournamentId>,
+    /// 私人房间的进入凭据（公开房间为 None）
+    pub key: Option<RoomKey>,
+    /// 自定义初始局面（创建时已校验，None 表示标准开局）
+    pub custom_fen: Option<String>,
     /// 红方玩家 ID
     pub red_player: Option<PlayerId>,
     /// 黑方玩家 ID
     pub black_player: Option<PlayerId>,
+    /// 观战者
+    pub spectators: HashSet<PlayerId>,
     /// 棋盘状态
     pub game_state: Option<BoardState>,
     /// 计时器
     pub timer: Option<GameTimer>,
+    /// 初始局面 FEN
+    pub initial_fen: String,
     /// 走法历史
     pub move_history: Vec<Move>,
+    /// 走法记录（记谱与每步的时间数据，与 move_history 一一对应）
+    pub move_records: Vec<MoveRecord>,
     /// 无吃子计数历史（用于悔棋恢复）
     pub no_capture_history: Vec<u32>,
     /// 创建时间
     pub created_at: Instant,
     /// 悔棋请求方（如果有）
     pub undo_requested_by: Option<Side>,
+    /// 提和状态
+    pub draw_offers: DrawOffers,
+    /// 对局结果（结束后才有）
+    pub result: Option<GameResult>,
     /// 状态版本号（每次状态变更时递增）
     pub version: u64,
+    /// 房间事件重放缓冲（用于断线重连补发）
+    pub events: ReplayBuffer,
+    /// 房间聊天记录
+    pub chat: ChatHistory,
+    /// 上次定时推送时间的时刻
+    pub clock_synced_at: Option<Instant>,
 }
 
 impl Room {
@@ -46,15 +205,29 @@ impl Room {
             id,
             room_type,
             state: RoomState::Waiting,
+            rated: false,
+            time_control: TimeControl::default(),
+            rules: RuleSet::default(),
+            tournament: None,
+            key: None,
+            custom_fen: None,
             red_player: None,
             black_player: None,
+            spectators: HashSet::new(),
             game_state: None,
             timer: None,
+            initial_fen: INITIAL_FEN.to_string(),
             move_history: Vec::new(),
+            move_records: Vec::new(),
             no_capture_history: Vec::new(),
             created_at: Instant::now(),
             undo_requested_by: None,
+            draw_offers: DrawOffers::default(),
+            result: None,
             version: ROOM_VERSION_COUNTER.fetch_add(1, Ordering::SeqCst),
+            events: ReplayBuffer::default(),
+            chat: ChatHistory::default(),
+            clock_synced_at: None,
         }
     }
 
@@ -63,14 +236,24 @@ impl Room {
         self.version = ROOM_VERSION_COUNTER.fetch_add(1, Ordering::SeqCst);
     }
 
-    /// 获取房间信息（用于列表展示）
-    pub fn info(&self, red_name: Option<String>, black_name: Option<String>) -> RoomInfo {
-        RoomInfo {
+    /// 房间概要（用于大厅列表与消息路由）
+    pub fn summary(&self) -> RoomSummary {
+        RoomSummary {
             id: self.id,
             room_type: self.room_type,
-            red_player: red_name,
-            black_player: black_name,
             state: self.state,
+            rated: self.rated,
+            time_control: self.time_control,
+            rules: self.rules,
+            private: self.key.is_some(),
+            invite_code: match &self.key {
+                Some(RoomKey::Invite(code)) => Some(code.clone()),
+                _ => None,
+            },
+            custom_fen: self.custom_fen.clone(),
+            red_player: self.red_player,
+            black_player: self.black_player,
+            spectators: self.spectators.len() as u32,
         }
     }
 
@@ -84,6 +267,25 @@ impl Room {
         self.red_player == Some(player_id) || self.black_player == Some(player_id)
     }
 
+    /// 是否可以观战（对局已开始且未结束）
+    pub fn is_watchable(&self) -> bool {
+        matches!(self.state, RoomState::Playing | RoomState::Paused)
+    }
+
+    /// 添加观战者，人数已满时返回 false
+    pub fn add_spectator(&mut self, player_id: PlayerId) -> bool {
+        if self.spectators.len() >= MAX_SPECTATORS {
+            return false;
+        }
+        self.spectators.insert(player_id);
+        true
+    }
+
+    /// 移除观战者，返回之前是否在观战
+    pub fn remove_spectator(&mut self, player_id: PlayerId) -> bool {
+        self.spectators.remove(&player_id)
+    }
+
     /// 获取玩家的颜色
     pub fn get_player_side(&self, player_id: PlayerId) -> Option<Side> {
         if self.red_player == Some(player_id) {
@@ -157,12 +359,41 @@ impl Room {
     }
 
     /// 开始游戏
+    ///
+    /// 设置了自定义初始局面时从该局面开始
     pub fn start_game(&mut self) {
-        self.game_state = Some(BoardState::initial());
-        self.timer = Some(GameTimer::new());
+        let custom = self
+            .custom_fen
+            .clone()
+            .and_then(|fen| Fen::parse(&fen).ok().map(|board_state| (fen, board_state)));
+        match custom {
+            Some((fen, board_state)) => self.begin(fen, board_state),
+            None => self.begin(INITIAL_FEN.to_string(), BoardState::initial()),
+        }
+    }
+
+    /// 从指定局面开始游戏
+    pub fn start_game_from_fen(&mut self, fen: &str) -> Result<(), ChessError> {
+        let board_state = Fen::parse(fen)?;
+        self.begin(fen.to_string(), board_state);
+        Ok(())
+    }
+
+    fn begin(&mut self, fen: String, board_state: BoardState) {
+        let mut timer = GameTimer::with_control(self.time_control);
+        // 计时器从局面的走子方开始
+        if timer.current_turn() != board_state.current_turn {
+            timer.pass_turn();
+        }
+        self.initial_fen = fen;
+        self.game_state = Some(board_state);
+        self.timer = Some(timer);
         self.state = RoomState::Playing;
         self.move_history.clear();
+        self.move_records.clear();
         self.no_capture_history.clear();
+        self.draw_offers = DrawOffers::default();
+        self.result = None;
     }
 
     /// 暂停游戏（仅 PvE）
@@ -192,8 +423,9 @@ impl Room {
     }
 
     /// 结束游戏
-    pub fn finish(&mut self, _result: GameResult) {
+    pub fn finish(&mut self, result: GameResult) {
         self.state = RoomState::Finished;
+        self.result = Some(result);
         if let Some(timer) = &mut self.timer {
             timer.stop();
         }
@@ -212,6 +444,10 @@ impl Room {
         // 记录当前无吃子计数（用于悔棋恢复）
         self.no_capture_history.push(game_state.no_capture_count);
 
+        // 走棋前生成中文记谱
+        let notation = Notation::to_chinese(&game_state.board, &mv).unwrap_or_default();
+        let mover = game_state.current_turn;
+
         // 执行走法
         let captured = game_state.board.move_piece(mv.from, mv.to);
         
@@ -235,8 +471,22 @@ impl Room {
         recorded_move.captured = captured;
         self.move_history.push(recorded_move);
 
-        // 清除悔棋请求
+        // 记录记谱与走棋方剩余时间
+        let mut record = MoveRecord::with_timestamp(
+            mv.from,
+            mv.to,
+            notation,
+            chrono::Utc::now().timestamp_millis() as u64,
+        );
+        record.time_left_ms = self.timer.as_ref().map(|timer| match mover {
+            Side::Red => timer.red_time_ms(),
+            Side::Black => timer.black_time_ms(),
+        });
+        self.move_records.push(record);
+
+        // 清除悔棋请求，未答复的提和随走棋撤回
         self.undo_requested_by = None;
+        self.draw_offers.pending = None;
 
         // 递增版本号
         self.bump_version();
@@ -247,6 +497,7 @@ impl Room {
     /// 悔棋
     pub fn undo_move(&mut self) -> Result<Move, &'static str> {
         let last_move = self.move_history.pop().ok_or("没有可悔的棋")?;
+        self.move_records.pop();
         let game_state = self.game_state.as_mut().ok_or("游戏未开始")?;
 
         // 恢复棋子位置
@@ -270,15 +521,37 @@ impl Room {
 
         // 同步更新计时器状态
         if let Some(timer) = &mut self.timer {
-            timer.switch_turn();
+            timer.pass_turn();
         }
 
-        // 清除悔棋请求
+        // 清除悔棋请求与未答复的提和
         self.undo_requested_by = None;
+        self.draw_offers.pending = None;
+
+        // 递增版本号（作废计算中的 AI 走法）
+        self.bump_version();
 
         Ok(last_move)
     }
 
+    /// 当前局面之前的对局局面（从初始局面重放走法，按时间顺序）
+    pub fn position_history(&self) -> Vec<BoardState> {
+        let Ok(mut state) = Fen::parse(&self.initial_fen) else {
+            return Vec::new();
+        };
+        let mut history = Vec::with_capacity(self.move_history.len());
+        for mv in &self.move_history {
+            let next = {
+                let mut next = state.clone();
+                next.board.move_piece(mv.from, mv.to);
+                next.switch_turn();
+                next
+            };
+            history.push(std::mem::replace(&mut state, next));
+        }
+        history
+    }
+
     /// 检查游戏是否结束
     pub fn check_game_over(&self) -> Option<GameResult> {
         let game_state = self.game_state.as_ref()?;
@@ -318,32 +591,48 @@ impl Room {
 
     /// 生成棋谱记录
     pub fn generate_game_record(&self, red_name: &str, black_name: &str) -> Option<protocol::GameRecord> {
-        use protocol::{Board, GameRecord, MoveRecord, Notation};
-        
+        use protocol::GameRecord;
+
         let _game_state = self.game_state.as_ref()?;
-        
-        let mut record = GameRecord::new(red_name.to_string(), black_name.to_string());
-        
-        // 从初始棋盘开始重放，每步走棋前生成记谱
-        let mut board = Board::initial();
-        for mv in &self.move_history {
-            // 在走棋前用当前棋盘状态生成记谱
-            let notation = Notation::to_chinese(&board, mv).unwrap_or("未知".to_string());
-            let move_record = MoveRecord::new(mv.from, mv.to, notation);
-            record.add_move(move_record);
-            
-            // 执行走法更新棋盘
-            board.move_piece(mv.from, mv.to);
+
+        let mut record = GameRecord::from_fen(
+            red_name.to_string(),
+            black_name.to_string(),
+            self.initial_fen.clone(),
+        );
+        record.metadata.time_control = Some(self.time_control.to_string());
+        if matches!(self.room_type, RoomType::PvP) {
+            record.metadata.rated = Some(self.rated);
+            record.metadata.rules = Some(self.rules);
         }
-        
-        // 如果游戏结束，设置结果
-        if let Some(result) = self.check_game_over() {
+        for move_record in &self.move_records {
+            record.add_move(move_record.clone());
+        }
+
+        // 如果游戏结束，设置结果（认输、超时等由 finish 记录，将死与和棋规则由局面判断）
+        if let Some(result) = self.result.clone().or_else(|| self.check_game_over()) {
             record.set_result(result);
         }
-        
+
         Some(record)
     }
 
+    /// 当前时间与读秒状态
+    pub fn time_update(&self) -> ServerMessage {
+        let (red_time_ms, black_time_ms) = self.get_time_state();
+        let (red_byoyomi, black_byoyomi) = self
+            .timer
+            .as_ref()
+            .map(|timer| (timer.byoyomi(Side::Red), timer.byoyomi(Side::Black)))
+            .unwrap_or_default();
+        ServerMessage::TimeUpdate {
+            red_time_ms,
+            black_time_ms,
+            red_byoyomi,
+            black_byoyomi,
+        }
+    }
+
     /// 获取当前时间状态
     pub fn get_time_state(&self) -> (u64, u64) {
         if let Some(timer) = &self.timer {
@@ -354,91 +643,79 @@ impl Room {
     }
 }
 
-/// 房间管理器
-pub struct RoomManager {
-    rooms: HashMap<RoomId, Room>,
-    next_id: AtomicU64,
+/// 房间概要
+///
+/// 由房间任务在每条命令处理完后发布，大厅无需询问房间任务即可列出房间
+#[derive(Debug, Clone)]
+pub struct RoomSummary {
+    pub id: RoomId,
+    pub room_type: RoomType,
+    pub state: RoomState,
+    pub rated: bool,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    /// 是否为私人房间
+    pub private: bool,
+    /// 邀请码（仅邀请制私人房间，用于按邀请码查找房间）
+    pub invite_code: Option<String>,
+    pub custom_fen: Option<String>,
+    pub red_player: Option<PlayerId>,
+    pub black_player: Option<PlayerId>,
+    /// 观战人数
+    pub spectators: u32,
 }
 
-impl RoomManager {
-    pub fn new() -> Self {
-        Self {
-            rooms: HashMap::new(),
-            next_id: AtomicU64::new(1),
-        }
-    }
-
-    /// 生成新的房间 ID
-    fn generate_id(&self) -> RoomId {
-        self.next_id.fetch_add(1, Ordering::SeqCst)
-    }
-
-    /// 创建房间
-    pub fn create(&mut self, room_type: RoomType) -> RoomId {
-        let id = self.generate_id();
-        let room = Room::new(id, room_type);
-        self.rooms.insert(id, room);
-        id
-    }
-
-    /// 获取房间
-    pub fn get(&self, room_id: RoomId) -> Option<&Room> {
-        self.rooms.get(&room_id)
-    }
-
-    /// 获取房间（可变）
-    pub fn get_mut(&mut self, room_id: RoomId) -> Option<&mut Room> {
-        self.rooms.get_mut(&room_id)
-    }
-
-    /// 移除房间
-    pub fn remove(&mut self, room_id: RoomId) -> Option<Room> {
-        self.rooms.remove(&room_id)
-    }
+/// 房间列表中展示的玩家信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerLabel {
+    pub nickname: String,
+    /// 等级分（游客为 None）
+    pub rating: Option<RatingInfo>,
+    pub bot: bool,
+}
 
-    /// 获取可加入的房间列表（Waiting 状态的 PvP 房间）
-    pub fn list_joinable(&self) -> Vec<&Room> {
-        self.rooms
-            .values()
-            .filter(|r| r.state == RoomState::Waiting && matches!(r.room_type, RoomType::PvP))
-            .collect()
+impl RoomSummary {
+    /// 获取房间信息（用于列表展示）
+    pub fn info(&self, red: Option<PlayerLabel>, black: Option<PlayerLabel>) -> RoomInfo {
+        RoomInfo {
+            id: self.id,
+            room_type: self.room_type,
+            red_bot: red.as_ref().is_some_and(|p| p.bot),
+            black_bot: black.as_ref().is_some_and(|p| p.bot),
+            red_rating: red.as_ref().and_then(|p| p.rating),
+            black_rating: black.as_ref().and_then(|p| p.rating),
+            red_player: red.map(|p| p.nickname),
+            black_player: black.map(|p| p.nickname),
+            state: self.state,
+            rated: self.rated,
+            spectators: self.spectators,
+            time_control: self.time_control,
+            rules: self.rules,
+            private: self.private,
+            custom_fen: self.custom_fen.clone(),
+        }
     }
 
-    /// 查找玩家所在的房间
-    pub fn find_player_room(&self, player_id: PlayerId) -> Option<RoomId> {
-        self.rooms
-            .values()
-            .find(|r| r.has_player(player_id))
-            .map(|r| r.id)
+    /// 是否可以加入（等待中的公开 PvP 房间）
+    pub fn is_joinable(&self) -> bool {
+        self.state == RoomState::Waiting && matches!(self.room_type, RoomType::PvP) && !self.private
     }
 
-    /// 获取房间数量
-    pub fn count(&self) -> usize {
-        self.rooms.len()
+    /// 是否在大厅展示（可加入的房间和可观战的公开对局）
+    pub fn is_visible(&self) -> bool {
+        self.is_joinable() || (!self.private && matches!(self.state, RoomState::Playing | RoomState::Paused))
     }
-}
 
-impl Default for RoomManager {
-    fn default() -> Self {
-        Self::new()
+    /// 检查玩家是否在房间中
+    pub fn has_player(&self, player_id: PlayerId) -> bool {
+        self.red_player == Some(player_id) || self.black_player == Some(player_id)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use protocol::Difficulty;
-
-    #[test]
-    fn test_create_room() {
-        let mut manager = RoomManager::new();
-        
-        let id1 = manager.create(RoomType::PvP);
-        let id2 = manager.create(RoomType::PvE(Difficulty::Medium));
-        
-        assert_ne!(id1, id2);
-        assert_eq!(manager.count(), 2);
-    }
+    use protocol::{Difficulty, Position};
 
     #[test]
     fn test_add_player() {
@@ -487,24 +764,130 @@ mod tests {
 
     #[test]
     fn test_list_joinable() {
-        let mut manager = RoomManager::new();
-        
-        // 创建 3 个 PvP 房间
-        let id1 = manager.create(RoomType::PvP);
-        let id2 = manager.create(RoomType::PvP);
-        let _id3 = manager.create(RoomType::PvE(Difficulty::Easy));
-        
-        // 让一个房间开始游戏
-        {
-            let room = manager.get_mut(id1).unwrap();
-            room.add_player(100, None);
-            room.add_player(200, None);
-            room.start_game();
-        }
-        
-        // 只有一个可加入的房间
-        let joinable = manager.list_joinable();
-        assert_eq!(joinable.len(), 1);
-        assert_eq!(joinable[0].id, id2);
+        let waiting = Room::new(1, RoomType::PvP);
+        let pve = Room::new(2, RoomType::PvE(Difficulty::Easy));
+        let mut playing = Room::new(3, RoomType::PvP);
+        playing.add_player(100, None);
+        playing.add_player(200, None);
+        playing.start_game();
+
+        // 只有等待中的 PvP 房间可加入
+        assert!(waiting.summary().is_joinable());
+        assert!(!pve.summary().is_joinable());
+        assert!(!playing.summary().is_joinable());
+    }
+
+    #[test]
+    fn test_spectators() {
+        let mut room = Room::new(1, RoomType::PvP);
+        room.add_player(100, None);
+        room.add_player(200, None);
+        assert!(!room.is_watchable());
+
+        room.start_game();
+        assert!(room.is_watchable());
+        assert!(room.add_spectator(300));
+        assert!(!room.has_player(300));
+        assert_eq!(room.summary().info(None, None).spectators, 1);
+
+        // 进行中的对局出现在大厅列表中
+        assert!(room.summary().is_visible());
+
+        assert!(room.remove_spectator(300));
+        assert!(!room.remove_spectator(300));
+    }
+
+    #[test]
+    fn test_move_records_follow_history() {
+        let mut room = Room::new(1, RoomType::PvP);
+        room.add_player(100, None);
+        room.add_player(200, None);
+        room.start_game();
+
+        room.make_move(Move::new(Position::new_unchecked(7, 2), Position::new_unchecked(4, 2)))
+            .unwrap();
+        room.make_move(Move::new(Position::new_unchecked(1, 9), Position::new_unchecked(2, 7)))
+            .unwrap();
+
+        assert_eq!(room.move_records.len(), 2);
+        assert_eq!(room.move_records[0].notation, "炮二平五");
+        assert!(room.move_records[0].time_left_ms.is_some());
+        assert!(room.move_records[0].timestamp.is_some());
+
+        // 历史局面不含当前局面
+        let history = room.position_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].current_turn, Side::Red);
+        assert!(history[1].board.get(Position::new_unchecked(4, 2)).is_some());
+
+        // 悔棋作废计算中的 AI 走法
+        let version = room.version;
+        room.undo_move().unwrap();
+        assert_ne!(room.version, version);
+        assert_eq!(room.move_records.len(), 1);
+        assert_eq!(room.position_history().len(), 1);
+
+        let record = room.generate_game_record("红", "黑").unwrap();
+        assert_eq!(record.initial_fen, INITIAL_FEN);
+        assert_eq!(record.moves.len(), 1);
+        assert_eq!(record.moves[0].notation, "炮二平五");
+    }
+
+    #[test]
+    fn test_start_game_from_fen() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b 0 1";
+        let mut room = Room::new(1, RoomType::PvP);
+        room.start_game_from_fen(fen).unwrap();
+
+        assert_eq!(room.initial_fen, fen);
+        assert_eq!(room.game_state.as_ref().unwrap().current_turn, Side::Black);
+        assert_eq!(room.timer.as_ref().unwrap().current_turn(), Side::Black);
+        assert!(room.start_game_from_fen("invalid").is_err());
+    }
+
+    #[test]
+    fn test_private_room_with_custom_fen() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b 0 1";
+        let mut room = Room::new(1, RoomType::PvP);
+        room.key = Some(RoomKey::Password("口令".to_string()));
+        room.custom_fen = Some(fen.to_string());
+
+        // 私人房间不可从大厅加入，只凭密码进入
+        assert!(!room.summary().is_joinable());
+        assert!(room.key.as_ref().unwrap().accepts(Some("口令")));
+        assert!(!room.key.as_ref().unwrap().accepts(Some("错误")));
+        assert!(!room.key.as_ref().unwrap().accepts(None));
+
+        room.add_player(100, None);
+        room.add_player(200, None);
+        room.start_game();
+        assert_eq!(room.initial_fen, fen);
+        assert_eq!(room.timer.as_ref().unwrap().current_turn(), Side::Black);
+        assert!(!room.summary().is_visible());
+    }
+
+    #[test]
+    fn test_draw_offer_limits() {
+        let mut offers = DrawOffers::default();
+
+        assert_eq!(offers.offer(Side::Red, 0), Ok(()));
+        assert_eq!(offers.offer(Side::Black, 0), Err(DrawOfferError::Pending));
+        // 只有对方能答复
+        assert_eq!(offers.take_for(Side::Red), None);
+        assert_eq!(offers.take_for(Side::Black), Some(Side::Red));
+
+        // 被拒后需间隔若干步
+        assert_eq!(offers.offer(Side::Red, 4), Err(DrawOfferError::TooSoon));
+        assert_eq!(offers.offer(Side::Black, 4), Ok(()));
+        offers.pending = None;
+
+        assert_eq!(offers.offer(Side::Red, DRAW_OFFER_INTERVAL_PLIES), Ok(()));
+        offers.pending = None;
+        assert_eq!(offers.offer(Side::Red, 2 * DRAW_OFFER_INTERVAL_PLIES), Ok(()));
+        offers.pending = None;
+        assert_eq!(
+            offers.offer(Side::Red, 3 * DRAW_OFFER_INTERVAL_PLIES),
+            Err(DrawOfferError::LimitReached)
+        );
     }
 }
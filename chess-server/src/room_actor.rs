@@ -0,0 +1,1534 @@
+//! 房间任务
+//!
+//! 每个房间运行在独立的任务中，独占房间状态并按顺序处理收件箱中的命令。
+//! 走棋、悔棋、提和、认输、保存和对局计时都在房间任务内完成，不同房间互不等待；
+//! 大厅只通过 [`RoomHandle`] 与房间通信
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::{mpsc, oneshot};
+
+use chess_ai::AiSession;
+
+use protocol::{
+    ChatChannel, ChatMessage, ClientMessage, DrawReason, ErrorCode, GameRecord, GameResult, Move,
+    PlayerId, Position, RatingChange, RoomId, RoomState, RoomType, ServerMessage, Side, WinReason,
+    INVITE_CODE_LEN,
+};
+
+use crate::accounts::{AccountId, AccountStore};
+use crate::ai_pool::AiPool;
+use crate::metrics::ServerMetrics;
+use crate::player::PlayerStatus;
+use crate::registry::PlayerRegistry;
+use crate::room::{DrawOfferError, Room, RoomSummary};
+use crate::snapshot::RoomSnapshot;
+use crate::storage::StorageManager;
+
+/// 定时推送权威时间的间隔
+pub const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 房间收件箱容量（满时发送方等待）
+pub const ROOM_INBOX_CAPACITY: usize = 64;
+
+/// 房间任务使用的共享服务
+#[derive(Clone)]
+pub struct RoomServices {
+    pub players: PlayerRegistry,
+    pub accounts: Arc<AccountStore>,
+    pub storage: Arc<StorageManager>,
+    pub ai: AiPool,
+    pub metrics: Arc<ServerMetrics>,
+    /// 比赛对局的结果（由大厅汇入比赛）
+    pub game_results: mpsc::UnboundedSender<FinishedGame>,
+}
+
+/// 结束的比赛对局
+#[derive(Debug, Clone)]
+pub struct FinishedGame {
+    pub room_id: RoomId,
+    pub result: GameResult,
+}
+
+/// 命令处理完（消息已发出）后执行的回复
+type Reply = Box<dyn FnOnce() + Send>;
+
+/// 在房间任务中执行的操作
+type Job = Box<dyn FnOnce(&mut RoomActor) -> Reply + Send>;
+
+/// 房间收件箱中的命令
+enum RoomCommand {
+    /// 对局双方的消息（走棋、悔棋、提和、认输等）
+    Client {
+        player_id: PlayerId,
+        msg: ClientMessage,
+        reply: oneshot::Sender<Option<ServerMessage>>,
+    },
+    /// 大厅发起的操作（加入、离开、观战、断线重连等）
+    Call(Job),
+}
+
+/// 待发送的单条消息
+enum Outgoing {
+    /// 发给玩家的普通消息
+    Player(PlayerId, ServerMessage),
+    /// 房间广播事件
+    Room(ServerMessage),
+    /// 发给房间内指定玩家的事件
+    RoomPlayer(PlayerId, ServerMessage),
+    /// 房间内的临时消息（不进入重放缓冲）
+    RoomTransient(ServerMessage),
+    /// 对局结束：结算写库后发出 GameOver
+    GameOver(Box<Settlement>, GameOverTo),
+}
+
+/// GameOver 的接收方
+enum GameOverTo {
+    /// 广播给房间
+    Room,
+    /// 只发给房间内指定玩家
+    Player(PlayerId),
+    /// 不发送
+    Nobody,
+}
+
+/// 结束对局时记下的结算内容
+///
+/// 等级分结算和归档要读写数据库，在发送消息时交给阻塞线程池执行
+struct Settlement {
+    room_id: RoomId,
+    result: GameResult,
+    red_account: Option<AccountId>,
+    black_account: Option<AccountId>,
+    rated: bool,
+    record: Option<GameRecord>,
+    finished_at: u64,
+}
+
+impl Settlement {
+    /// 结算计分对局的等级分并归档对局，返回等级分变化
+    fn apply(self, accounts: &AccountStore) -> Vec<RatingChange> {
+        let rating_changes = match (self.rated, self.red_account, self.black_account) {
+            (true, Some(red), Some(black)) => {
+                accounts.settle_ratings(red, black, &self.result).unwrap_or_else(|e| {
+                    tracing::error!("房间 {} 等级分结算失败: {}", self.room_id, e);
+                    Vec::new()
+                })
+            }
+            _ => Vec::new(),
+        };
+
+        if let Some(record) = &self.record {
+            if let Err(e) = accounts.archive_game(
+                self.red_account,
+                self.black_account,
+                self.rated,
+                &self.result,
+                record,
+                self.finished_at,
+            ) {
+                tracing::error!("房间 {} 对局归档失败: {}", self.room_id, e);
+            }
+        }
+        rating_changes
+    }
+}
+
+/// 待发送的消息（按加入顺序，在命令处理完后发送）
+#[derive(Default)]
+struct PendingMessages {
+    queue: Vec<Outgoing>,
+}
+
+impl PendingMessages {
+    fn send(&mut self, player_id: PlayerId, msg: ServerMessage) {
+        self.queue.push(Outgoing::Player(player_id, msg));
+    }
+
+    fn broadcast(&mut self, msg: ServerMessage) {
+        self.queue.push(Outgoing::Room(msg));
+    }
+
+    fn send_in_room(&mut self, player_id: PlayerId, msg: ServerMessage) {
+        self.queue.push(Outgoing::RoomPlayer(player_id, msg));
+    }
+
+    fn send_transient(&mut self, msg: ServerMessage) {
+        self.queue.push(Outgoing::RoomTransient(msg));
+    }
+
+    fn game_over(&mut self, settlement: Settlement, to: GameOverTo) {
+        self.queue.push(Outgoing::GameOver(Box::new(settlement), to));
+    }
+}
+
+/// 进行中的 AI 搜索
+struct AiSearch {
+    /// 提交搜索时的房间版本
+    version: u64,
+    result: oneshot::Receiver<(AiSession, Option<Move>)>,
+}
+
+/// 房间任务循环中发生的事件
+enum RoomEvent {
+    Command(RoomCommand),
+    AiResult(u64, Option<(Box<AiSession>, Option<Move>)>),
+    Clock,
+}
+
+/// 房间任务
+pub struct RoomActor {
+    pub room: Room,
+    services: RoomServices,
+    pending: PendingMessages,
+    /// 发布给大厅的房间概要
+    summary: Arc<RwLock<RoomSummary>>,
+    /// 等待中的 AI 搜索
+    ai_search: Option<AiSearch>,
+    /// 本局的 AI 会话（搜索期间交给计算池）
+    ai_session: Option<AiSession>,
+    /// 房间已空，处理完当前命令后结束任务
+    closed: bool,
+}
+
+/// 房间任务句柄
+#[derive(Clone)]
+pub struct RoomHandle {
+    id: RoomId,
+    inbox: mpsc::Sender<RoomCommand>,
+    summary: Arc<RwLock<RoomSummary>>,
+}
+
+impl RoomHandle {
+    /// 房间 ID
+    pub fn id(&self) -> RoomId {
+        self.id
+    }
+
+    /// 房间概要（最近一条命令处理完时的状态）
+    pub fn summary(&self) -> RoomSummary {
+        self.summary.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 交给房间任务处理对局消息并等待回复（房间已关闭时返回 None）
+    pub async fn request(&self, player_id: PlayerId, msg: ClientMessage) -> Option<ServerMessage> {
+        let (reply, response) = oneshot::channel();
+        let command = RoomCommand::Client { player_id, msg, reply };
+        self.inbox.send(command).await.ok()?;
+        response.await.ok().flatten()
+    }
+
+    /// 在房间任务中执行操作并取回结果（房间已关闭时返回 None）
+    ///
+    /// 结果在操作产生的消息发出之后才返回
+    pub async fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut RoomActor) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (tx, rx) = oneshot::channel();
+        self.inbox.send(RoomCommand::Call(Self::job(f, tx))).await.ok()?;
+        rx.await.ok()
+    }
+
+    /// 把操作立即放入收件箱，返回取结果的接收端
+    ///
+    /// 大厅持锁时用它保证操作排在之后的命令前面，释放锁后再等待结果；
+    /// 收件箱已满或房间已关闭时接收端直接返回错误
+    pub fn post<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut RoomActor) -> T + Send + 'static,
+    ) -> oneshot::Receiver<T> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.inbox.try_send(RoomCommand::Call(Self::job(f, tx)));
+        rx
+    }
+
+    fn job<T: Send + 'static>(f: impl FnOnce(&mut RoomActor) -> T + Send + 'static, tx: oneshot::Sender<T>) -> Job {
+        Box::new(move |actor| {
+            let value = f(actor);
+            Box::new(move || {
+                let _ = tx.send(value);
+            })
+        })
+    }
+
+    /// 在房间任务中读取或修改房间状态
+    pub async fn with_room<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Room) -> T + Send + 'static,
+    ) -> Option<T> {
+        self.call(move |actor| f(&mut actor.room)).await
+    }
+}
+
+impl RoomActor {
+    /// 为房间启动任务
+    pub fn spawn(room: Room, services: RoomServices) -> RoomHandle {
+        let (inbox, commands) = mpsc::channel(ROOM_INBOX_CAPACITY);
+        let summary = Arc::new(RwLock::new(room.summary()));
+        let handle = RoomHandle {
+            id: room.id,
+            inbox,
+            summary: summary.clone(),
+        };
+
+        let actor = Self {
+            room,
+            services,
+            pending: PendingMessages::default(),
+            summary,
+            ai_search: None,
+            ai_session: None,
+            closed: false,
+        };
+        tokio::spawn(actor.run(commands));
+        handle
+    }
+
+    /// 任务主循环：依次处理命令，空闲时等到下一个需要检查时钟的时刻
+    async fn run(mut self, mut commands: mpsc::Receiver<RoomCommand>) {
+        // 创建时已开局的房间，开局消息由大厅随后发出，首次时间同步推迟一个周期
+        if self.room.state == RoomState::Playing {
+            self.room.clock_synced_at.get_or_insert_with(Instant::now);
+        }
+        // 加载的棋局或玩家执黑时可能轮到 AI 先走
+        self.schedule_ai();
+        let mut next_check = self.run_clock(Instant::now());
+        self.flush().await;
+
+        while !self.closed {
+            let clock = async move {
+                match next_check {
+                    // 剩余时间按毫秒取整，多等 1 毫秒确保到点
+                    Some(wait) => tokio::time::sleep(wait + Duration::from_millis(1)).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let ai = async {
+                match &mut self.ai_search {
+                    Some(search) => (search.version, (&mut search.result).await),
+                    None => std::future::pending().await,
+                }
+            };
+            let event = tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => RoomEvent::Command(command),
+                    None => break,
+                },
+                (version, result) = ai => {
+                    RoomEvent::AiResult(version, result.ok().map(|(session, best)| (Box::new(session), best)))
+                }
+                _ = clock => RoomEvent::Clock,
+            };
+
+            let reply = match event {
+                RoomEvent::Command(command) => Some(self.process(command).await),
+                RoomEvent::AiResult(version, result) => {
+                    self.ai_search = None;
+                    let best = match result {
+                        Some((session, best)) => {
+                            self.ai_session = Some(*session);
+                            best
+                        }
+                        // 搜索任务异常结束，会话随之丢弃，按无合法走法处理
+                        None => None,
+                    };
+                    self.ai_search_finished(version, best);
+                    None
+                }
+                RoomEvent::Clock => None,
+            };
+
+            self.drop_stale_ai_search();
+            // 走棋、开局、暂停、断线等都会改变超时时刻，每次都重新检查
+            next_check = self.run_clock(Instant::now());
+            self.flush().await;
+            self.publish();
+            if let Some(reply) = reply {
+                reply();
+            }
+        }
+        tracing::debug!("房间 {} 任务结束", self.room.id);
+    }
+
+    async fn process(&mut self, command: RoomCommand) -> Reply {
+        match command {
+            RoomCommand::Client { player_id, msg, reply } => {
+                let response = self.handle_client(player_id, msg).await;
+                Box::new(move || {
+                    let _ = reply.send(response);
+                })
+            }
+            RoomCommand::Call(job) => job(self),
+        }
+    }
+
+    /// 房间内的玩家和观战者
+    fn members(&self) -> Vec<PlayerId> {
+        [self.room.red_player, self.room.black_player]
+            .into_iter()
+            .flatten()
+            .chain(self.room.spectators.iter().copied())
+            .collect()
+    }
+
+    /// 发送待发送的消息
+    ///
+    /// 广播事件在发送时分配序列号并记入重放缓冲，断线的玩家重连后可补齐；
+    /// 对局结束的结算在阻塞线程池中完成后再发 GameOver，保持消息顺序
+    async fn flush(&mut self) {
+        for outgoing in std::mem::take(&mut self.pending.queue) {
+            match outgoing {
+                Outgoing::Player(player_id, msg) => {
+                    self.services.players.send(player_id, msg);
+                }
+                Outgoing::Room(msg) => {
+                    let event = self.room.events.push(None, msg);
+                    let msg = ServerMessage::Sequenced(Box::new(event));
+                    for player_id in self.members() {
+                        self.services.players.send(player_id, msg.clone());
+                    }
+                }
+                Outgoing::RoomPlayer(player_id, msg) => {
+                    let event = self.room.events.push(Some(player_id), msg);
+                    let msg = ServerMessage::Sequenced(Box::new(event));
+                    self.services.players.send(player_id, msg);
+                }
+                Outgoing::RoomTransient(msg) => {
+                    for player_id in self.members() {
+                        self.services.players.send(player_id, msg.clone());
+                    }
+                }
+                Outgoing::GameOver(settlement, to) => {
+                    let result = settlement.result.clone();
+                    let accounts = &self.services.accounts;
+                    let rating_changes = accounts.blocking(move |accounts| settlement.apply(accounts)).await;
+                    let msg = ServerMessage::GameOver { result, rating_changes };
+                    let player_id = match to {
+                        GameOverTo::Room => None,
+                        GameOverTo::Player(player_id) => Some(player_id),
+                        GameOverTo::Nobody => continue,
+                    };
+                    let event = self.room.events.push(player_id, msg);
+                    let msg = ServerMessage::Sequenced(Box::new(event));
+                    match player_id {
+                        Some(player_id) => {
+                            self.services.players.send(player_id, msg);
+                        }
+                        None => {
+                            for player_id in self.members() {
+                                self.services.players.send(player_id, msg.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 发布房间概要
+    fn publish(&self) {
+        *self.summary.write().unwrap_or_else(|e| e.into_inner()) = self.room.summary();
+    }
+
+    /// 处理对局双方的消息
+    async fn handle_client(&mut self, player_id: PlayerId, msg: ClientMessage) -> Option<ServerMessage> {
+        match msg {
+            ClientMessage::MakeMove { from, to } => self.handle_make_move(player_id, from, to),
+            ClientMessage::RequestUndo => self.handle_request_undo(player_id),
+            ClientMessage::RespondUndo { accept } => self.handle_respond_undo(accept),
+            ClientMessage::Resign => self.handle_resign(player_id),
+            ClientMessage::OfferDraw => self.handle_offer_draw(player_id),
+            ClientMessage::RespondDraw { accept } => self.handle_respond_draw(player_id, accept),
+            ClientMessage::PauseGame => self.handle_pause(),
+            ClientMessage::ResumeGame => self.handle_resume(),
+            ClientMessage::SaveGame => self.handle_save_game().await,
+            _ => None,
+        }
+    }
+
+    /// 玩家加入房间，返回分配的颜色；房间满员后开局
+    pub fn join(&mut self, player_id: PlayerId, password: Option<&str>) -> Result<Side, Box<ServerMessage>> {
+        let room = &mut self.room;
+
+        // 私人房间需要密码或邀请码
+        if room.key.as_ref().is_some_and(|key| !key.accepts(password)) {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::RoomAccessDenied,
+                message: "密码或邀请码错误".to_string(),
+            }));
+        }
+
+        // 检查房间状态
+        if room.state != RoomState::Waiting {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::RoomClosed,
+                message: "房间不可加入".to_string(),
+            }));
+        }
+
+        // 检查房间是否已满
+        if room.is_full() {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::RoomFull,
+                message: "房间已满".to_string(),
+            }));
+        }
+
+        // 计分对局需要注册账号，同时取加入者昵称
+        let joiner_nickname = {
+            let players = self.services.players.read();
+            if room.rated && players.get(player_id).is_none_or(|p| p.is_guest()) {
+                return Err(Box::new(ServerMessage::Error {
+                    code: ErrorCode::AccountRequired,
+                    message: "计分对局需要注册账号".to_string(),
+                }));
+            }
+            players.get_nickname(player_id).unwrap_or("玩家").to_string()
+        };
+
+        // 获取房主 ID
+        let opponent_id = room.red_player.or(room.black_player);
+
+        // 加入房间
+        let Some(side) = room.add_player(player_id, None) else {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::RoomFull,
+                message: "房间已满".to_string(),
+            }));
+        };
+
+        // 通知房主有人加入
+        if let Some(opponent_id) = opponent_id {
+            self.pending.send_in_room(opponent_id, ServerMessage::OpponentJoined { nickname: joiner_nickname });
+        }
+
+        // 如果房间满了，开始游戏
+        if room.is_full() {
+            room.start_game();
+            self.announce_game_start();
+        }
+
+        Ok(side)
+    }
+
+    /// 通知房间内双方游戏开始
+    pub fn announce_game_start(&mut self) {
+        let room = &self.room;
+        let (Some(game_state), Some(red_id), Some(black_id)) =
+            (room.game_state.clone(), room.red_player, room.black_player)
+        else {
+            return;
+        };
+        let red_player = self.services.players.nickname(red_id).unwrap_or_else(|| "玩家".to_string());
+        let black_player = self.services.players.nickname(black_id).unwrap_or_else(|| "玩家".to_string());
+
+        for (player_id, your_side) in [(red_id, Side::Red), (black_id, Side::Black)] {
+            self.pending.send_in_room(
+                player_id,
+                ServerMessage::GameStarted {
+                    initial_state: game_state.clone(),
+                    your_side,
+                    red_player: red_player.clone(),
+                    black_player: black_player.clone(),
+                    initial_fen: room.initial_fen.clone(),
+                    moves: room.move_records.clone(),
+                },
+            );
+        }
+    }
+
+    /// 玩家离开房间，对局进行中时离开者判负
+    ///
+    /// 只剩 AI 或空无一人时房间关闭，返回需要送走的观战者
+    pub fn leave(&mut self, player_id: PlayerId) -> Option<Vec<PlayerId>> {
+        let side = self.room.get_player_side(player_id)?;
+        let opponent_id = self.room.get_opponent_id(player_id);
+
+        // 如果游戏进行中，离开者判负
+        if self.room.state == RoomState::Playing {
+            let result = match side {
+                Side::Red => GameResult::BlackWin(WinReason::Disconnect),
+                Side::Black => GameResult::RedWin(WinReason::Disconnect),
+            };
+            // 通知对手胜利
+            let to = opponent_id.map_or(GameOverTo::Nobody, GameOverTo::Player);
+            self.finish_game(result, to);
+        }
+
+        self.room.remove_player(player_id);
+
+        let empty = [self.room.red_player, self.room.black_player]
+            .into_iter()
+            .flatten()
+            .all(|id| id == protocol::AI_PLAYER_ID);
+        if !empty {
+            return None;
+        }
+        self.closed = true;
+        Some(self.room.spectators.drain().collect())
+    }
+
+    /// 关闭房间：进行中的对局作废（不计结果），返回需要送回大厅的玩家和观战者
+    pub fn close(&mut self) -> Vec<PlayerId> {
+        let members = self
+            .members()
+            .into_iter()
+            .filter(|&id| id != protocol::AI_PLAYER_ID)
+            .collect();
+        self.ai_search = None;
+        self.closed = true;
+        members
+    }
+
+    /// 停服：暂停时钟并生成对局快照，之后房间任务结束
+    ///
+    /// 人机对局按断线处理整局暂停，恢复后由玩家继续，AI 不会趁玩家未归时走棋。
+    /// 没有进行中的对局时返回 None
+    pub fn suspend(&mut self) -> Option<RoomSnapshot> {
+        if !matches!(self.room.state, RoomState::Playing | RoomState::Paused) {
+            return None;
+        }
+        self.room.pause();
+        if let Some(timer) = &mut self.room.timer {
+            timer.pause();
+        }
+        self.pending.broadcast(self.room.time_update());
+        self.ai_search = None;
+        self.closed = true;
+        Some(RoomSnapshot::capture(&self.room))
+    }
+
+    /// 开始观战，返回对局快照
+    pub fn spectate(&mut self, player_id: PlayerId) -> Result<ServerMessage, Box<ServerMessage>> {
+        if !self.room.is_watchable() {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "对局尚未开始".to_string(),
+            }));
+        }
+
+        let name_of = |id: Option<PlayerId>| match id {
+            Some(protocol::AI_PLAYER_ID) => "AI".to_string(),
+            id => id
+                .and_then(|id| self.services.players.nickname(id))
+                .unwrap_or_else(|| "玩家".to_string()),
+        };
+        let red_player = name_of(self.room.red_player);
+        let black_player = name_of(self.room.black_player);
+        let chat_history = self.chat_history_for(player_id, true);
+
+        let room = &mut self.room;
+        if !room.add_spectator(player_id) {
+            return Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::RoomFull,
+                message: "观战人数已满".to_string(),
+            }));
+        }
+
+        let (red_time_ms, black_time_ms) = room.get_time_state();
+        let game_state = room.game_state.clone().ok_or_else(|| Box::new(ServerMessage::Error {
+            code: ErrorCode::GameNotStarted,
+            message: "对局尚未开始".to_string(),
+        }))?;
+        Ok(ServerMessage::SpectateStarted {
+            room_id: room.id,
+            game_state,
+            red_player,
+            black_player,
+            red_time_ms,
+            black_time_ms,
+            initial_fen: room.initial_fen.clone(),
+            moves: room.move_records.clone(),
+            last_seq: room.events.last_seq(),
+            chat_history,
+        })
+    }
+
+    /// 停止观战
+    pub fn stop_spectating(&mut self, player_id: PlayerId) {
+        self.room.remove_spectator(player_id);
+    }
+
+    /// 玩家断线：通知对手，人机对局自动暂停
+    pub fn player_disconnected(&mut self, player_id: PlayerId, timeout_secs: u32) {
+        if let Some(opponent_id) = self.room.get_opponent_id(player_id) {
+            self.pending.send_in_room(opponent_id, ServerMessage::OpponentDisconnected { timeout_secs });
+        }
+
+        // PvE 模式自动暂停
+        if matches!(self.room.room_type, RoomType::PvE(_)) {
+            self.room.pause();
+        }
+    }
+
+    /// 玩家重连，返回对局快照和错过的事件
+    ///
+    /// 新连接在生成快照的同一条命令中登记，快照之后的事件都会送达新连接
+    pub fn reconnect(
+        &mut self,
+        player_id: PlayerId,
+        last_seq: Option<u64>,
+        tx: Option<mpsc::Sender<ServerMessage>>,
+    ) -> Option<ServerMessage> {
+        let room = &self.room;
+        if !room.has_player(player_id) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::NotInRoom,
+                message: "不在该房间中".to_string(),
+            });
+        }
+
+        // 获取房间信息
+        let your_side = room.get_player_side(player_id)?;
+        let game_state = room.game_state.clone()?;
+        let (red_time_ms, black_time_ms) = room.get_time_state();
+        let opponent_id = room.get_opponent_id(player_id);
+        let undo_requested_by = room.undo_requested_by;
+        let initial_fen = room.initial_fen.clone();
+        let moves = room.move_records.clone();
+        let chat_history = self.chat_history_for(player_id, false);
+
+        // 收集断线期间错过的事件（没有本地状态的客户端只需要快照）
+        let snapshot_seq = room.events.last_seq();
+        let (missed_events, replay_complete) = match last_seq {
+            Some(seq) => {
+                let replay = room.events.since(player_id, seq);
+                (replay.events, replay.complete)
+            }
+            None => (Vec::new(), false),
+        };
+
+        // 恢复玩家状态并登记新连接
+        self.services.players.write().reconnect(player_id);
+        if let Some(tx) = tx {
+            self.services.players.attach(player_id, tx);
+        }
+
+        // 停服恢复的对局暂停着计时，双方都回来后继续
+        let seated = [self.room.red_player, self.room.black_player];
+        let all_back = seated.into_iter().flatten().all(|id| {
+            !matches!(self.services.players.status(id), Some(PlayerStatus::Disconnected(_)))
+        });
+        if let Some(timer) = &mut self.room.timer {
+            if self.room.room_type == RoomType::PvP && self.room.state == RoomState::Playing && all_back {
+                timer.resume();
+            }
+        }
+
+        // 如果是当前走棋方重连，重置计时器开始时间
+        if let Some(timer) = &mut self.room.timer {
+            if timer.current_turn() == your_side && !timer.is_paused() {
+                // 重连时重置当前回合开始时间，避免断线期间时间被计入
+                timer.reset_turn_start();
+            }
+        }
+
+        // 通知对手
+        if let Some(opponent_id) = opponent_id {
+            self.pending.send_in_room(opponent_id, ServerMessage::OpponentReconnected);
+        }
+
+        Some(ServerMessage::ReconnectSuccess {
+            room_id: self.room.id,
+            game_state,
+            your_side,
+            red_time_ms,
+            black_time_ms,
+            initial_fen,
+            moves,
+            last_seq: snapshot_seq,
+            missed_events,
+            replay_complete,
+            undo_requested_by,
+            chat_history,
+        })
+    }
+
+    /// 发送房间聊天消息并记入聊天记录
+    ///
+    /// 房间频道发给双方和观战者；观战区只发给观战者（对局双方可以发言，但看不到观战者的消息）
+    pub fn deliver_chat(&mut self, message: ChatMessage) {
+        let recipients = match message.channel {
+            ChatChannel::Spectators => {
+                let mut recipients: Vec<PlayerId> = self.room.spectators.iter().copied().collect();
+                if !recipients.contains(&message.from_id) {
+                    recipients.push(message.from_id);
+                }
+                recipients
+            }
+            ChatChannel::Room | ChatChannel::Lobby => self.members(),
+        };
+        self.room.chat.push(message.clone());
+
+        let players = self.services.players.read();
+        for recipient in recipients {
+            let muted = players
+                .get(recipient)
+                .is_some_and(|p| p.has_muted(message.from_id));
+            if !muted {
+                self.pending.send(recipient, ServerMessage::Chat(message.clone()));
+            }
+        }
+    }
+
+    /// 玩家可见的房间聊天记录（排除已屏蔽的发送者）
+    pub fn chat_history_for(&self, player_id: PlayerId, include_spectators: bool) -> Vec<ChatMessage> {
+        let players = self.services.players.read();
+        let player = players.get(player_id);
+        self.room
+            .chat
+            .recent(include_spectators, |from| player.is_some_and(|p| p.has_muted(from)))
+    }
+
+    /// 处理走棋
+    fn handle_make_move(&mut self, player_id: PlayerId, from: Position, to: Position) -> Option<ServerMessage> {
+        let room = &mut self.room;
+
+        // 检查游戏状态
+        if room.state != RoomState::Playing {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "游戏未开始".to_string(),
+            });
+        }
+
+        // 检查是否轮到该玩家
+        let player_side = room.get_player_side(player_id)?;
+        let game_state = room.game_state.as_ref()?;
+        if game_state.current_turn != player_side {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::NotYourTurn,
+                message: "不是你的回合".to_string(),
+            });
+        }
+
+        // 执行走棋
+        let mv = Move::new(from, to);
+        if let Err(msg) = room.make_move(mv) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::InvalidMove,
+                message: msg.to_string(),
+            });
+        }
+        self.services.metrics.moves.inc();
+
+        // 走棋时已生成中文记谱
+        let new_state = room.game_state.clone()?;
+        let notation = room.move_records.last()?.notation.clone();
+
+        // 获取时间信息
+        let time_update = room.time_update();
+
+        // 检查游戏是否结束
+        let game_over = room.check_game_over();
+
+        // 广播走棋消息
+        self.pending.broadcast(ServerMessage::MoveMade {
+            from,
+            to,
+            new_state,
+            notation,
+        });
+
+        // 发送时间更新
+        self.pending.broadcast(time_update);
+
+        // 处理游戏结束
+        if let Some(result) = game_over {
+            self.finish_game(result, GameOverTo::Room);
+            return None;
+        }
+
+        // PvE 模式：交给 AI 计算池，结果回到房间任务后再走棋
+        self.schedule_ai();
+
+        None
+    }
+
+    /// 轮到 AI 走棋时提交搜索
+    ///
+    /// 会话同时只能用于一次搜索，已有搜索在进行时等它返回后再重新检查
+    fn schedule_ai(&mut self) {
+        let room = &self.room;
+        let RoomType::PvE(difficulty) = room.room_type else {
+            return;
+        };
+        if room.state != RoomState::Playing || self.ai_search.is_some() {
+            return;
+        }
+        let Some(game_state) = room.game_state.as_ref() else {
+            return;
+        };
+        if room.get_player_id(game_state.current_turn) != Some(protocol::AI_PLAYER_ID) {
+            return;
+        }
+
+        let session = match self.ai_session.take() {
+            Some(session) if session.difficulty() == difficulty => session,
+            _ => self.services.ai.new_session(difficulty),
+        };
+        self.ai_search = Some(AiSearch {
+            version: room.version,
+            result: self.services.ai.submit(session, game_state.clone(), room.position_history()),
+        });
+    }
+
+    /// 局面已变化（悔棋等）或对局已结束（认输等）时放弃进行中的搜索，计算池不再为它占用计算线程
+    ///
+    /// 会话随搜索一起丢弃，需要时重新提交
+    fn drop_stale_ai_search(&mut self) {
+        let room = &self.room;
+        let stale = |search: &AiSearch| search.version != room.version || room.state == RoomState::Finished;
+        if self.ai_search.as_ref().is_some_and(stale) {
+            self.ai_search = None;
+            self.schedule_ai();
+        }
+    }
+
+    /// AI 搜索完成
+    fn ai_search_finished(&mut self, version: u64, best: Option<Move>) {
+        match best {
+            Some(ai_move) => self.apply_ai_move(ai_move, version),
+            None if version == self.room.version => {
+                // AI 无法走棋，判定 AI 负
+                tracing::warn!("AI 无法找到合法走法，判定 AI 负");
+                self.ai_loses();
+            }
+            None => {}
+        }
+        // 计算期间暂停或悔棋后，局面可能仍轮到 AI
+        self.schedule_ai();
+    }
+
+    /// 应用 AI 走法（带版本检查）
+    fn apply_ai_move(&mut self, ai_move: Move, version_before: u64) {
+        let room = &mut self.room;
+
+        // 检查版本号，确保状态未改变
+        if room.version != version_before {
+            tracing::warn!("AI 计算期间游戏状态已改变，丢弃 AI 走法");
+            return;
+        }
+
+        // 检查游戏状态
+        if room.state != RoomState::Playing {
+            return;
+        }
+
+        // 执行 AI 走棋
+        if room.make_move(ai_move).is_err() {
+            tracing::error!("AI 走棋失败: {:?}，判定 AI 负", ai_move);
+            self.ai_loses();
+            return;
+        }
+        self.services.metrics.moves.inc();
+
+        // 重置计时器起点（AI 思考时间不应计入玩家时间）
+        if let Some(timer) = &mut room.timer {
+            timer.reset_turn_start();
+        }
+
+        // 走棋时已生成中文记谱
+        let new_state = match room.game_state.clone() {
+            Some(s) => s,
+            None => return,
+        };
+        let notation = room
+            .move_records
+            .last()
+            .map(|r| r.notation.clone())
+            .unwrap_or_default();
+
+        // 获取时间信息
+        let time_update = room.time_update();
+
+        // 检查游戏是否结束
+        let game_over = room.check_game_over();
+
+        // 广播 AI 走棋消息
+        self.pending.broadcast(ServerMessage::MoveMade {
+            from: ai_move.from,
+            to: ai_move.to,
+            new_state,
+            notation,
+        });
+
+        // 发送时间更新
+        self.pending.broadcast(time_update);
+
+        // 处理游戏结束
+        if let Some(result) = game_over {
+            self.finish_game(result, GameOverTo::Room);
+        }
+    }
+
+    /// AI 失败，判定 AI 负（玩家胜）
+    fn ai_loses(&mut self) {
+        if self.room.state != RoomState::Playing {
+            return;
+        }
+
+        let result = match self.room.get_player_side(protocol::AI_PLAYER_ID) {
+            Some(Side::Red) => GameResult::BlackWin(WinReason::Resign),
+            _ => GameResult::RedWin(WinReason::Resign),
+        };
+        self.finish_game(result, GameOverTo::Room);
+    }
+
+    /// 结束对局，结算完成后向 `to` 发送 GameOver
+    ///
+    /// 计分对局同时结算双方等级分，对局归档保存结果、每步剩余时间和终局时双方时间
+    fn finish_game(&mut self, result: GameResult, to: GameOverTo) {
+        self.room.finish(result.clone());
+        if self.room.tournament.is_some() {
+            // 大厅已停止时比赛结果无处可报，忽略
+            let _ = self.services.game_results.send(FinishedGame {
+                room_id: self.room.id,
+                result: result.clone(),
+            });
+        }
+
+        let finished_at = chrono::Utc::now();
+        let record = self.game_record().map(|mut record| {
+            let (red_time, black_time) = self.room.get_time_state();
+            record.save_info = Some(protocol::SaveInfo {
+                saved_at: finished_at,
+                game_state: "finished".to_string(),
+                player_side: "red".to_string(),
+                red_time_remaining_ms: red_time,
+                black_time_remaining_ms: black_time,
+            });
+            record
+        });
+        let settlement = Settlement {
+            room_id: self.room.id,
+            result,
+            red_account: self.account_of(self.room.red_player),
+            black_account: self.account_of(self.room.black_player),
+            rated: self.room.rated,
+            record,
+            finished_at: finished_at.timestamp_millis().max(0) as u64,
+        };
+        self.pending.game_over(settlement, to);
+    }
+
+    /// 玩家的注册账号
+    fn account_of(&self, player_id: Option<PlayerId>) -> Option<AccountId> {
+        let players = self.services.players.read();
+        player_id
+            .and_then(|id| players.get(id))
+            .and_then(|player| player.account_id)
+    }
+
+    /// 处理悔棋请求
+    fn handle_request_undo(&mut self, player_id: PlayerId) -> Option<ServerMessage> {
+        let room = &mut self.room;
+
+        if room.state != RoomState::Playing {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "游戏未开始".to_string(),
+            });
+        }
+
+        if !room.rules.allow_undo {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::UndoNotAllowed,
+                message: "本局不允许悔棋".to_string(),
+            });
+        }
+
+        if room.move_history.is_empty() {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::UndoNotAllowed,
+                message: "没有可悔的棋".to_string(),
+            });
+        }
+
+        let player_side = room.get_player_side(player_id)?;
+        let opponent_id = room.get_opponent_id(player_id);
+
+        // PvE 模式直接悔棋
+        if matches!(room.room_type, RoomType::PvE(_)) {
+            // PvE 模式撤回到轮到玩家：通常是 AI 一步 + 玩家一步，
+            // AI 思考中只撤回玩家一步
+            let mut undo_count = 0;
+            while undo_count < 2 && room.undo_move().is_ok() {
+                undo_count += 1;
+                if room.game_state.as_ref().is_some_and(|s| s.current_turn == player_side) {
+                    break;
+                }
+            }
+
+            if undo_count > 0 {
+                let new_state = room.game_state.clone()?;
+                // 撤回到开局仍轮到 AI 时重新计算
+                self.schedule_ai();
+                return Some(ServerMessage::UndoApproved { new_state });
+            } else {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::UndoNotAllowed,
+                    message: "悔棋失败".to_string(),
+                });
+            }
+        }
+
+        // PvP 模式需要对方同意
+        room.undo_requested_by = Some(player_side);
+
+        // 通知对手
+        if let Some(opponent_id) = opponent_id {
+            self.pending.send_in_room(opponent_id, ServerMessage::UndoRequested { by: player_side });
+        }
+
+        None
+    }
+
+    /// 处理悔棋响应
+    fn handle_respond_undo(&mut self, accept: bool) -> Option<ServerMessage> {
+        let room = &mut self.room;
+
+        // 检查是否有悔棋请求
+        let requester_side = room.undo_requested_by?;
+        let requester_id = room.get_player_id(requester_side)?;
+
+        if accept {
+            if room.undo_move().is_ok() {
+                let new_state = room.game_state.clone()?;
+                self.pending.broadcast(ServerMessage::UndoApproved { new_state });
+            }
+        } else {
+            // 通知请求方被拒绝
+            self.pending.send_in_room(requester_id, ServerMessage::UndoRejected);
+        }
+
+        self.room.undo_requested_by = None;
+        None
+    }
+
+    /// 处理提和
+    fn handle_offer_draw(&mut self, player_id: PlayerId) -> Option<ServerMessage> {
+        let room = &mut self.room;
+
+        if room.state != RoomState::Playing {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "游戏未开始".to_string(),
+            });
+        }
+        if matches!(room.room_type, RoomType::PvE(_)) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::DrawOfferNotAllowed,
+                message: "人机对战不能提和".to_string(),
+            });
+        }
+        if !room.rules.allow_draw_offers {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::DrawOfferNotAllowed,
+                message: "本局不允许提和".to_string(),
+            });
+        }
+
+        let player_side = room.get_player_side(player_id)?;
+        let ply = room.move_history.len();
+        if let Err(e) = room.draw_offers.offer(player_side, ply) {
+            let code = match e {
+                DrawOfferError::Pending => ErrorCode::DrawOfferNotAllowed,
+                DrawOfferError::TooSoon | DrawOfferError::LimitReached => ErrorCode::RateLimited,
+            };
+            return Some(ServerMessage::Error {
+                code,
+                message: e.message().to_string(),
+            });
+        }
+
+        // 通知对手
+        if let Some(opponent_id) = room.get_opponent_id(player_id) {
+            self.pending.send_in_room(opponent_id, ServerMessage::DrawOffered { by: player_side });
+        }
+
+        None
+    }
+
+    /// 处理提和响应
+    fn handle_respond_draw(&mut self, player_id: PlayerId, accept: bool) -> Option<ServerMessage> {
+        let room = &mut self.room;
+
+        // 提和可能已随走棋撤回
+        let player_side = room.get_player_side(player_id)?;
+        let Some(offered_by) = room.draw_offers.take_for(player_side) else {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::DrawOfferNotAllowed,
+                message: "没有待答复的提和".to_string(),
+            });
+        };
+
+        if accept && room.state == RoomState::Playing {
+            self.finish_game(GameResult::Draw(DrawReason::Agreement), GameOverTo::Room);
+        } else if let Some(offerer_id) = room.get_player_id(offered_by) {
+            // 通知提和方被拒绝
+            self.pending.send_in_room(offerer_id, ServerMessage::DrawDeclined);
+        }
+
+        None
+    }
+
+    /// 处理认输
+    fn handle_resign(&mut self, player_id: PlayerId) -> Option<ServerMessage> {
+        if self.room.state != RoomState::Playing {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "游戏未开始".to_string(),
+            });
+        }
+
+        let player_side = self.room.get_player_side(player_id)?;
+        let result = match player_side {
+            Side::Red => GameResult::BlackWin(WinReason::Resign),
+            Side::Black => GameResult::RedWin(WinReason::Resign),
+        };
+
+        self.finish_game(result, GameOverTo::Room);
+
+        None
+    }
+
+    /// 处理暂停
+    fn handle_pause(&mut self) -> Option<ServerMessage> {
+        if self.room.pause() {
+            Some(ServerMessage::GamePaused)
+        } else {
+            Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "无法暂停".to_string(),
+            })
+        }
+    }
+
+    /// 处理继续
+    fn handle_resume(&mut self) -> Option<ServerMessage> {
+        if self.room.resume() {
+            self.schedule_ai();
+            Some(ServerMessage::GameResumed)
+        } else {
+            Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "无法继续".to_string(),
+            })
+        }
+    }
+
+    /// 房间棋谱的玩家显示名
+    fn record_player_name(&self, player_id: Option<PlayerId>) -> String {
+        match player_id {
+            Some(protocol::AI_PLAYER_ID) => "AI".to_string(),
+            Some(id) => self.services.players.nickname(id).unwrap_or_else(|| "未知".to_string()),
+            None => "空".to_string(),
+        }
+    }
+
+    /// 生成房间当前的棋谱记录（含玩家名与 AI 难度）
+    pub fn game_record(&self) -> Option<GameRecord> {
+        let room = &self.room;
+        let red_name = self.record_player_name(room.red_player);
+        let black_name = self.record_player_name(room.black_player);
+        let mut record = room.generate_game_record(&red_name, &black_name)?;
+
+        // 保存 AI 难度
+        if let RoomType::PvE(difficulty) = room.room_type {
+            let difficulty = match difficulty {
+                protocol::Difficulty::Easy => "Easy".to_string(),
+                protocol::Difficulty::Medium => "Medium".to_string(),
+                protocol::Difficulty::Hard => "Hard".to_string(),
+                protocol::Difficulty::Custom { depth, time_limit_ms } => {
+                    format!("Custom(depth={},time={}ms)", depth, time_limit_ms)
+                }
+            };
+            record.set_ai_difficulty(&difficulty);
+        }
+        Some(record)
+    }
+
+    /// 处理保存棋局
+    ///
+    /// 写文件在阻塞线程池中执行
+    async fn handle_save_game(&mut self) -> Option<ServerMessage> {
+        let room = &self.room;
+
+        // 检查游戏状态
+        if room.state != RoomState::Playing && room.state != RoomState::Paused {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "只能保存进行中的棋局".to_string(),
+            });
+        }
+
+        // 生成棋谱记录
+        if let Some(mut record) = self.game_record() {
+            let (red_time, black_time) = room.get_time_state();
+            let red_name = record.metadata.red_player.clone();
+            let black_name = record.metadata.black_player.clone();
+
+            let game_state = room.game_state.clone()?;
+            let storage = self.services.storage.clone();
+
+            // 保存到文件
+            let saved = tokio::task::spawn_blocking(move || {
+                storage.save_game(&red_name, &black_name, &mut record, &game_state, red_time, black_time)
+            })
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+            match saved {
+                Ok(game_id) => Some(ServerMessage::GameSaved { game_id }),
+                Err(e) => Some(ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("保存失败: {}", e),
+                }),
+            }
+        } else {
+            Some(ServerMessage::Error {
+                code: ErrorCode::GameNotStarted,
+                message: "无法生成棋谱".to_string(),
+            })
+        }
+    }
+
+    /// 对局时钟：超时立即判负，否则定期推送权威时间
+    ///
+    /// 返回距下一次需要检查的时间（没有进行中的计时时为 None）。
+    /// 当前走子方断线时由断线超时处理，这里不判超时
+    pub fn run_clock(&mut self, now: Instant) -> Option<Duration> {
+        let room = &mut self.room;
+        if room.state != RoomState::Playing {
+            return None;
+        }
+        let timer = room.timer.as_ref().filter(|timer| !timer.is_paused())?;
+        let side = timer.current_turn();
+        let mover_disconnected = room
+            .get_player_id(side)
+            .and_then(|id| self.services.players.status(id))
+            .is_some_and(|status| matches!(status, PlayerStatus::Disconnected(_)));
+
+        let mut next_check = CLOCK_SYNC_INTERVAL;
+        if let Some(left) = timer.time_to_flag().filter(|_| !mover_disconnected) {
+            if left.is_zero() {
+                self.pending.broadcast(room.time_update());
+                let result = match side {
+                    Side::Red => GameResult::BlackWin(WinReason::Timeout),
+                    Side::Black => GameResult::RedWin(WinReason::Timeout),
+                };
+                tracing::info!("房间 {} {:?} 超时判负", room.id, side);
+                self.finish_game(result, GameOverTo::Room);
+                return None;
+            }
+            next_check = next_check.min(left);
+        }
+
+        let since_sync = room.clock_synced_at.map(|at| now.saturating_duration_since(at));
+        match since_sync {
+            Some(since) if since < CLOCK_SYNC_INTERVAL => {
+                next_check = next_check.min(CLOCK_SYNC_INTERVAL - since);
+            }
+            _ => {
+                room.clock_synced_at = Some(now);
+                self.pending.send_transient(room.time_update());
+            }
+        }
+        Some(next_check)
+    }
+}
+
+/// 房间目录：房间 ID -> 房间任务句柄
+pub struct RoomManager {
+    rooms: HashMap<RoomId, RoomHandle>,
+    next_id: AtomicU64,
+}
+
+impl RoomManager {
+    pub fn new() -> Self {
+        Self {
+            rooms: HashMap::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 生成新的房间 ID
+    pub fn generate_id(&self) -> RoomId {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// 为房间启动任务并登记
+    ///
+    /// 从停服快照恢复的房间沿用原 ID，之后生成的 ID 不会与之重复
+    pub fn spawn(&mut self, room: Room, services: RoomServices) -> RoomHandle {
+        self.next_id.fetch_max(room.id + 1, Ordering::SeqCst);
+        let handle = RoomActor::spawn(room, services);
+        self.rooms.insert(handle.id(), handle.clone());
+        handle
+    }
+
+    /// 获取房间句柄
+    pub fn get(&self, room_id: RoomId) -> Option<RoomHandle> {
+        self.rooms.get(&room_id).cloned()
+    }
+
+    /// 移除房间
+    pub fn remove(&mut self, room_id: RoomId) -> Option<RoomHandle> {
+        self.rooms.remove(&room_id)
+    }
+
+    /// 生成当前未被占用的邀请码（去掉了易混淆的字符）
+    pub fn generate_invite_code(&self) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+        let mut rng = rand::thread_rng();
+        loop {
+            let code: String = (0..INVITE_CODE_LEN)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            if self.find_by_invite(&code).is_none() {
+                return code;
+            }
+        }
+    }
+
+    /// 按邀请码查找私人房间（不区分大小写）
+    pub fn find_by_invite(&self, code: &str) -> Option<RoomId> {
+        self.summaries()
+            .find(|summary| summary.invite_code.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(code)))
+            .map(|summary| summary.id)
+    }
+
+    /// 获取可加入的房间列表（Waiting 状态的公开 PvP 房间）
+    pub fn list_joinable(&self) -> Vec<RoomSummary> {
+        self.summaries().filter(RoomSummary::is_joinable).collect()
+    }
+
+    /// 获取大厅展示的房间列表（可加入的房间和可观战的对局）
+    pub fn list_visible(&self) -> Vec<RoomSummary> {
+        self.summaries().filter(RoomSummary::is_visible).collect()
+    }
+
+    /// 获取所有房间
+    pub fn list_all(&self) -> Vec<RoomSummary> {
+        self.summaries().collect()
+    }
+
+    fn summaries(&self) -> impl Iterator<Item = RoomSummary> + '_ {
+        self.rooms.values().map(RoomHandle::summary)
+    }
+
+    /// 获取房间数量
+    pub fn count(&self) -> usize {
+        self.rooms.len()
+    }
+}
+
+impl Default for RoomManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::Difficulty;
+
+    fn services() -> RoomServices {
+        RoomServices {
+            players: PlayerRegistry::new(),
+            accounts: Arc::new(AccountStore::open_in_memory().unwrap()),
+            storage: Arc::new(StorageManager::new().unwrap()),
+            ai: AiPool::new(1, 1, Arc::default()),
+            metrics: Arc::default(),
+            game_results: mpsc::unbounded_channel().0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_room() {
+        let mut manager = RoomManager::new();
+        let services = services();
+
+        let id1 = manager.generate_id();
+        let id2 = manager.generate_id();
+        manager.spawn(Room::new(id1, RoomType::PvP), services.clone());
+        manager.spawn(Room::new(id2, RoomType::PvE(Difficulty::Medium)), services);
+
+        assert_ne!(id1, id2);
+        assert_eq!(manager.count(), 2);
+        assert_eq!(manager.list_joinable().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_summary_follows_room() {
+        let services = services();
+        let (red, black) = {
+            let mut players = services.players.write();
+            (players.login("红方".to_string()).unwrap(), players.login("黑方".to_string()).unwrap())
+        };
+        let mut room = Room::new(1, RoomType::PvP);
+        room.add_player(red, Some(Side::Red));
+        let handle = RoomActor::spawn(room, services);
+
+        assert!(matches!(handle.call(move |actor| actor.join(black, None)).await, Some(Ok(Side::Black))));
+        // 回复返回前概要已更新
+        let summary = handle.summary();
+        assert_eq!(summary.state, RoomState::Playing);
+        assert_eq!(summary.black_player, Some(black));
+
+        let response = handle.request(red, ClientMessage::Resign).await;
+        assert!(response.is_none());
+        assert_eq!(handle.summary().state, RoomState::Finished);
+    }
+
+    #[tokio::test]
+    async fn test_room_closes_when_empty() {
+        let services = services();
+        let player_id = services.players.write().login("玩家".to_string()).unwrap();
+        let mut room = Room::new(1, RoomType::PvE(Difficulty::Easy));
+        room.add_player(player_id, Some(Side::Red));
+        room.black_player = Some(protocol::AI_PLAYER_ID);
+        room.start_game();
+        let handle = RoomActor::spawn(room, services);
+
+        // 只剩 AI 时房间关闭，之后的命令不再处理
+        let closed = handle.call(move |actor| actor.leave(player_id)).await;
+        assert_eq!(closed, Some(Some(Vec::new())));
+        assert!(handle.with_room(|room| room.id).await.is_none());
+        assert!(handle.request(player_id, ClientMessage::Resign).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ai_replies_after_player_move() {
+        let services = services();
+        let player_id = services.players.write().login("玩家".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(16);
+        services.players.attach(player_id, tx);
+        let mut room = Room::new(1, RoomType::PvE(Difficulty::Easy));
+        room.add_player(player_id, Some(Side::Red));
+        room.black_player = Some(protocol::AI_PLAYER_ID);
+        room.start_game();
+        let handle = RoomActor::spawn(room, services);
+
+        // 提交搜索后立即回复，AI 走法稍后广播
+        let mv = ClientMessage::MakeMove {
+            from: Position::new_unchecked(7, 2),
+            to: Position::new_unchecked(4, 2),
+        };
+        assert!(handle.request(player_id, mv).await.is_none());
+        let mut moves = 0;
+        while moves < 2 {
+            if let Some(ServerMessage::Sequenced(event)) = rx.recv().await {
+                moves += matches!(event.message, ServerMessage::MoveMade { .. }) as usize;
+            }
+        }
+        let history = handle.with_room(|room| room.move_history.len()).await;
+        assert_eq!(history, Some(2));
+    }
+}
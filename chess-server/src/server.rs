@@ -1,62 +1,155 @@
 //! 服务器主逻辑
+//!
+//! 大厅服务：登录、会话、房间目录、匹配、观战与大厅聊天。
+//! 对局中的操作转交各房间任务处理（见 [`crate::room_actor`]）
 
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::DerefMut;
+use std::sync::Arc;
+use std::time::Instant;
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, RwLock};
 
-use chess_ai::AiEngine;
 use protocol::{
-    ClientMessage, ErrorCode, GameResult, Move, Notation, PlayerId,
-    Position, RoomId, RoomInfo, RoomState, RoomType, ServerMessage, Side, WinReason,
+    ChallengeId, ChallengeInfo, ChatChannel, ChatMessage, ClientMessage, CorrespondenceId, ErrorCode,
+    FriendInfo, GameHistoryFilter, GameResult, Move, PlayerId, Position, Presence, RatingChange,
+    RatingInfo, RoomAccess, RoomId, RoomInfo, RoomType, RuleSet, ServerMessage, Side, TimeControl,
+    TournamentFormat, TournamentGameResult, TournamentId, MAX_CORRESPONDENCE_DAYS,
+    MAX_CORRESPONDENCE_GAMES, MAX_FRIENDS, MAX_HISTORY_PAGE_SIZE, MAX_SWISS_ROUNDS, INITIAL_FEN,
+    MAX_TOURNAMENT_NAME_LEN,
 };
 
+use crate::accounts::{Account, AccountError, AccountId, AccountStore};
+use crate::ai_pool::AiPool;
+use crate::challenge::{Challenge, ChallengeBook, ChallengeError, MAX_OUTGOING_CHALLENGES};
+use crate::chat::{self, ChatFilter, ChatLimiter};
+use crate::config::ServerConfig;
+use crate::correspondence::{CorrespondenceError, CorrespondenceGame};
+use crate::friends::{self, FriendRequestOutcome, PresenceTracker};
+use crate::matchmaking::{Matchmaker, Pairing, QueueEntry};
+use crate::metrics::ServerMetrics;
 use crate::player::{PlayerManager, PlayerStatus};
-use crate::room::RoomManager;
+use crate::registry::PlayerRegistry;
+use crate::room::{PlayerLabel, Room, RoomKey, RoomSettings};
+use crate::room_actor::{FinishedGame, RoomHandle, RoomManager, RoomServices};
+use crate::session::SessionManager;
+use crate::shutdown::Shutdown;
 use crate::storage::StorageManager;
+use crate::tournament::{Entrant, Tournament, TournamentError, TournamentManager, NO_SHOW_TIMEOUT};
 
-/// 断线超时时间（秒）
-const DISCONNECT_TIMEOUT_SECS: u64 = 60;
-
-/// 服务器状态
+/// 服务器状态（大厅）
 pub struct ServerState {
-    pub players: PlayerManager,
+    pub config: ServerConfig,
+    /// 在线玩家与连接（与各房间任务共享）
+    pub players: PlayerRegistry,
+    /// 房间目录
     pub rooms: RoomManager,
-    pub storage: StorageManager,
-    /// 玩家 ID -> 消息发送通道
-    pub connections: HashMap<PlayerId, mpsc::Sender<ServerMessage>>,
+    pub storage: Arc<StorageManager>,
+    pub accounts: Arc<AccountStore>,
+    /// 人机对局的 AI 计算池
+    pub ai_pool: AiPool,
+    /// 运行指标
+    pub metrics: Arc<ServerMetrics>,
+    pub sessions: SessionManager,
+    pub matchmaker: Matchmaker,
+    /// 聊天敏感词过滤
+    pub chat_filter: ChatFilter,
+    /// 聊天频率限制
+    pub chat_limiter: ChatLimiter,
     /// 断线玩家的超时时间
     pub disconnect_timeouts: HashMap<PlayerId, Instant>,
+    /// 正在注册的昵称（写入账号期间游客不能以此昵称登录）
+    registering: HashSet<String>,
+    /// 停服信号
+    pub shutdown: Shutdown,
+    /// 比赛
+    pub tournaments: TournamentManager,
+    /// 比赛的保存顺序：修改比赛的操作持有它直到保存完成，先取它再取大厅锁
+    tournament_saves: Arc<tokio::sync::Mutex<()>>,
+    /// 待回应的挑战
+    pub challenges: ChallengeBook,
+    /// 房间任务报告的比赛对局结果
+    finished_games: mpsc::UnboundedReceiver<FinishedGame>,
+    finished_games_tx: mpsc::UnboundedSender<FinishedGame>,
 }
 
 impl ServerState {
     pub fn new() -> anyhow::Result<Self> {
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// 使用指定配置创建服务器状态
+    pub fn with_config(config: ServerConfig) -> anyhow::Result<Self> {
+        let accounts = AccountStore::open(&config.database_path()?)?;
+        Self::with_accounts(config, accounts)
+    }
+
+    /// 使用指定配置和账号存储创建服务器状态
+    pub fn with_accounts(config: ServerConfig, accounts: AccountStore) -> anyhow::Result<Self> {
+        let storage = match &config.storage_dir {
+            Some(dir) => StorageManager::with_dir(dir)?,
+            None => StorageManager::new()?,
+        };
+
+        let metrics = Arc::new(ServerMetrics::new());
+        let ai_pool = AiPool::new(config.ai_workers, config.ai_session_tt_mb, metrics.clone());
+        let tournaments = TournamentManager::load(&accounts, chrono::Utc::now().timestamp_millis())?;
+        let (finished_games_tx, finished_games) = mpsc::unbounded_channel();
+
         Ok(Self {
-            players: PlayerManager::new(),
+            chat_filter: ChatFilter::new(&config.chat_filter_words),
+            chat_limiter: ChatLimiter::new(),
+            config,
+            players: PlayerRegistry::new(),
             rooms: RoomManager::new(),
-            storage: StorageManager::new()?,
-            connections: HashMap::new(),
+            storage: Arc::new(storage),
+            accounts: Arc::new(accounts),
+            ai_pool,
+            metrics,
+            sessions: SessionManager::new(),
+            matchmaker: Matchmaker::new(),
             disconnect_timeouts: HashMap::new(),
+            registering: HashSet::new(),
+            shutdown: Shutdown::new(),
+            tournaments,
+            tournament_saves: Arc::default(),
+            challenges: ChallengeBook::new(),
+            finished_games,
+            finished_games_tx,
         })
     }
 
-    /// 发送消息给玩家
-    pub async fn send_to_player(&self, player_id: PlayerId, msg: ServerMessage) {
-        if let Some(tx) = self.connections.get(&player_id) {
-            let _ = tx.send(msg).await;
+    /// 房间任务使用的共享服务
+    pub fn room_services(&self) -> RoomServices {
+        RoomServices {
+            players: self.players.clone(),
+            accounts: self.accounts.clone(),
+            storage: self.storage.clone(),
+            ai: self.ai_pool.clone(),
+            metrics: self.metrics.clone(),
+            game_results: self.finished_games_tx.clone(),
         }
     }
 
-    /// 广播消息给房间内所有玩家
-    pub async fn broadcast_to_room(&self, room_id: RoomId, msg: ServerMessage) {
-        if let Some(room) = self.rooms.get(room_id) {
-            if let Some(red_id) = room.red_player {
-                self.send_to_player(red_id, msg.clone()).await;
-            }
-            if let Some(black_id) = room.black_player {
-                self.send_to_player(black_id, msg).await;
-            }
-        }
+    /// 玩家的等级分（游客为 None）
+    pub fn player_rating(&self, player_id: PlayerId) -> Option<RatingInfo> {
+        let account_id = self.players.read().get(player_id)?.account_id?;
+        self.accounts.rating(account_id).ok().map(|rating| rating.info())
+    }
+
+    /// 玩家的昵称、等级分和是否为机器人（用于房间列表）
+    pub fn player_label(&self, player_id: PlayerId) -> Option<PlayerLabel> {
+        let (nickname, bot) = {
+            let players = self.players.read();
+            let player = players.get(player_id)?;
+            (player.nickname.clone(), player.is_bot())
+        };
+        Some(PlayerLabel {
+            nickname,
+            rating: self.player_rating(player_id),
+            bot,
+        })
     }
 }
 
@@ -66,176 +159,638 @@ impl Default for ServerState {
     }
 }
 
-/// 待发送的消息
-struct PendingMessages {
-    messages: Vec<(PlayerId, ServerMessage)>,
-    broadcasts: Vec<(RoomId, ServerMessage)>,
+/// 大厅状态的访问方式
+///
+/// 需要等待房间任务的操作分段加锁：锁内检查并取出房间句柄，释放锁后等待房间任务，
+/// 再重新加锁收尾，繁忙的房间不会挡住整个大厅。
+/// 连接任务和定时任务通过 `&RwLock<ServerState>` 访问，测试中直接使用 [`ServerState`]
+pub trait Lobby: Send {
+    /// 取得大厅状态的独占访问
+    fn lock(&mut self) -> impl Future<Output = impl DerefMut<Target = ServerState> + Send + '_> + Send;
 }
 
-impl PendingMessages {
-    fn new() -> Self {
-        Self {
-            messages: Vec::new(),
-            broadcasts: Vec::new(),
-        }
-    }
-
-    fn send(&mut self, player_id: PlayerId, msg: ServerMessage) {
-        self.messages.push((player_id, msg));
+impl Lobby for ServerState {
+    fn lock(&mut self) -> impl Future<Output = impl DerefMut<Target = ServerState> + Send + '_> + Send {
+        std::future::ready(self)
     }
+}
 
-    fn broadcast(&mut self, room_id: RoomId, msg: ServerMessage) {
-        self.broadcasts.push((room_id, msg));
+impl Lobby for &RwLock<ServerState> {
+    fn lock(&mut self) -> impl Future<Output = impl DerefMut<Target = ServerState> + Send + '_> + Send {
+        self.write()
     }
+}
 
-    async fn flush(self, state: &ServerState) {
-        for (player_id, msg) in self.messages {
-            state.send_to_player(player_id, msg).await;
-        }
-        for (room_id, msg) in self.broadcasts {
-            state.broadcast_to_room(room_id, msg).await;
-        }
-    }
+/// 对局操作的去向
+enum GameRoute {
+    /// 玩家所在房间的任务
+    Room(RoomHandle),
+    /// 观战者不能操作棋局
+    Spectator,
+    /// 不在对局中，忽略
+    Ignored,
 }
 
 /// 消息处理器
 pub struct MessageHandler;
 
 impl MessageHandler {
+    /// 处理一条客户端消息（连接任务的入口）
+    ///
+    /// 对局操作只在查找房间时短暂持有读锁，由房间任务处理，不同房间的走棋互不阻塞；
+    /// 其余大厅操作持有写锁处理，等待房间任务和阻塞线程池时不持锁
+    pub async fn dispatch(
+        state: &RwLock<ServerState>,
+        player_id: PlayerId,
+        msg: ClientMessage,
+    ) -> Option<ServerMessage> {
+        if Self::is_game_message(&msg) {
+            let route = Self::game_route(&*state.read().await, player_id);
+            return Self::forward_to_room(route, player_id, msg).await;
+        }
+        // 修改密码要做两次密码哈希，不持有大厅锁
+        if let ClientMessage::ChangePassword { old_password, new_password } = msg {
+            let (accounts, account_id) = {
+                let state = state.read().await;
+                (state.accounts.clone(), Self::account_of(&state, player_id))
+            };
+            return Self::handle_change_password(&accounts, account_id, old_password, new_password).await;
+        }
+
+        let mut lobby = state;
+        Self::handle(&mut lobby, player_id, msg).await
+    }
+
+    /// 处理登录前的登录、注册和机器人登录消息（连接任务的入口）
+    ///
+    /// 密码哈希耗时较长，在阻塞线程池中完成且不持有大厅锁；验证通过后短暂持有写锁登记玩家，
+    /// 并在同一把锁内挂上连接的发送通道，登记之后推送的消息不会丢失
+    pub async fn login(
+        state: &RwLock<ServerState>,
+        msg: ClientMessage,
+        tx: mpsc::Sender<ServerMessage>,
+    ) -> Option<ServerMessage> {
+        let accounts = state.read().await.accounts.clone();
+        let verified = match msg {
+            ClientMessage::Login { nickname, password: Some(password) } => {
+                Self::authenticate(&accounts, nickname, password).await
+            }
+            ClientMessage::Register { nickname, password } => Self::register_account(&accounts, nickname, password).await,
+            // 游客和机器人登录不涉及密码哈希
+            msg => {
+                let mut state = state.write().await;
+                let response = Self::handle(&mut *state, 0, msg).await;
+                if let Some(ServerMessage::LoginSuccess { player_id, .. }) = &response {
+                    state.players.attach(*player_id, tx);
+                }
+                return response;
+            }
+        };
+
+        let mut state = state.write().await;
+        let response = match verified {
+            Ok(account) => Self::login_player(&mut state, account.nickname, Some(account.id)),
+            Err(error) => Some(error),
+        };
+        if let Some(ServerMessage::LoginSuccess { player_id, .. }) = &response {
+            state.players.attach(*player_id, tx);
+        }
+        response
+    }
+
     /// 处理客户端消息
+    ///
+    /// 只涉及大厅的操作在一次加锁内完成；需要等待房间任务的操作分段加锁（见 [`Lobby`]）
     pub async fn handle(
-        state: &mut ServerState,
+        lobby: &mut impl Lobby,
         player_id: PlayerId,
         msg: ClientMessage,
     ) -> Option<ServerMessage> {
-        let mut pending = PendingMessages::new();
-        
-        let result = match msg {
-            ClientMessage::Login { nickname } => {
-                Self::handle_login(state, nickname)
+        let login = matches!(
+            msg,
+            ClientMessage::Login { .. }
+                | ClientMessage::Register { .. }
+                | ClientMessage::BotLogin { .. }
+                | ClientMessage::Reconnect { .. }
+        );
+        if login && lobby.lock().await.players.read().exists(player_id) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AlreadyLoggedIn,
+                message: "已经登录".to_string(),
+            });
+        }
+
+        match msg {
+            msg @ (ClientMessage::MakeMove { .. }
+            | ClientMessage::RequestUndo
+            | ClientMessage::RespondUndo { .. }
+            | ClientMessage::Resign
+            | ClientMessage::OfferDraw
+            | ClientMessage::RespondDraw { .. }
+            | ClientMessage::PauseGame
+            | ClientMessage::ResumeGame
+            | ClientMessage::SaveGame) => {
+                let route = Self::game_route(&*lobby.lock().await, player_id);
+                Self::forward_to_room(route, player_id, msg).await
+            }
+            ClientMessage::Login { nickname, password } => {
+                Self::handle_login(lobby, nickname, password).await
+            }
+            ClientMessage::Register { nickname, password } => {
+                Self::handle_register(lobby, nickname, password).await
+            }
+            ClientMessage::BotLogin { token } => {
+                Self::handle_bot_login(&mut *lobby.lock().await, &token)
+            }
+            ClientMessage::ChangePassword { old_password, new_password } => {
+                let (accounts, account_id) = {
+                    let state = lobby.lock().await;
+                    (state.accounts.clone(), Self::account_of(&state, player_id))
+                };
+                Self::handle_change_password(&accounts, account_id, old_password, new_password).await
+            }
+            ClientMessage::Logout => {
+                Self::handle_logout(lobby, player_id).await
             }
-            ClientMessage::Reconnect { player_id: pid, room_id } => {
-                Self::handle_reconnect(state, &mut pending, pid, room_id)
+            ClientMessage::Reconnect { session_token, room_id, last_seq } => {
+                Self::handle_reconnect(lobby, &session_token, room_id, last_seq, None).await
             }
-            ClientMessage::CreateRoom { room_type, preferred_side } => {
-                Self::handle_create_room(state, player_id, room_type, preferred_side)
+            ClientMessage::CreateRoom { room_type, preferred_side, rated, time_control, rules, initial_fen, access } => {
+                let settings = RoomSettings {
+                    rated,
+                    time_control,
+                    rules,
+                    custom_fen: initial_fen,
+                    access,
+                };
+                Self::handle_create_room(&mut *lobby.lock().await, player_id, room_type, preferred_side, settings)
+            }
+            ClientMessage::JoinRoom { room_id, password } => {
+                Self::handle_join_room(lobby, player_id, room_id, password).await
             }
-            ClientMessage::JoinRoom { room_id } => {
-                Self::handle_join_room(state, &mut pending, player_id, room_id)
+            ClientMessage::JoinByInvite { code } => {
+                Self::handle_join_by_invite(lobby, player_id, &code).await
             }
             ClientMessage::LeaveRoom => {
-                Self::handle_leave_room(state, &mut pending, player_id)
+                Self::handle_leave_room(lobby, player_id).await
             }
             ClientMessage::ListRooms => {
-                Self::handle_list_rooms(state)
+                Self::handle_list_rooms(&*lobby.lock().await)
+            }
+            ClientMessage::EnterQueue { time_control, rated } => {
+                Self::handle_enter_queue(&mut *lobby.lock().await, player_id, time_control, rated)
+            }
+            ClientMessage::LeaveQueue => {
+                lobby.lock().await.matchmaker.remove(player_id);
+                Some(ServerMessage::QueueLeft)
+            }
+            ClientMessage::Challenge { nickname, time_control, rated, rules } => {
+                Self::handle_challenge(lobby, player_id, &nickname, time_control, rules, rated).await
+            }
+            ClientMessage::RespondChallenge { challenge_id, accept } => {
+                Self::handle_respond_challenge(lobby, player_id, challenge_id, accept).await
+            }
+            ClientMessage::CancelChallenge { challenge_id } => {
+                Self::handle_cancel_challenge(&mut *lobby.lock().await, player_id, challenge_id)
+            }
+            ClientMessage::ListFriends => {
+                Self::account_request(lobby, move |accounts, players| match players.account_id(player_id) {
+                    Some(account_id) => Some(Self::friend_list(accounts, players, account_id)),
+                    None => Some(Self::friends_need_account()),
+                })
+                .await
+            }
+            ClientMessage::AddFriend { nickname } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_add_friend(accounts, players, player_id, &nickname)
+                })
+                .await
+            }
+            ClientMessage::RespondFriendRequest { nickname, accept } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_respond_friend_request(accounts, players, player_id, &nickname, accept)
+                })
+                .await
+            }
+            ClientMessage::RemoveFriend { nickname } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_remove_friend(accounts, players, player_id, &nickname)
+                })
+                .await
+            }
+            ClientMessage::CreateCorrespondence { nickname, days_per_move, rated } => {
+                if let Some(error) = Self::check_bot_rated(&*lobby.lock().await, player_id, rated) {
+                    return Some(error);
+                }
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_create_correspondence(accounts, players, player_id, &nickname, days_per_move, rated)
+                })
+                .await
+            }
+            ClientMessage::RespondCorrespondence { game_id, accept } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_respond_correspondence(accounts, players, player_id, game_id, accept)
+                })
+                .await
             }
-            ClientMessage::MakeMove { from, to } => {
-                Self::handle_make_move(state, &mut pending, player_id, from, to)
+            ClientMessage::ListCorrespondence => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_list_correspondence(accounts, players, player_id)
+                })
+                .await
+            }
+            ClientMessage::GetCorrespondence { game_id } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_get_correspondence(accounts, players, player_id, game_id)
+                })
+                .await
             }
-            ClientMessage::RequestUndo => {
-                Self::handle_request_undo(state, &mut pending, player_id)
+            ClientMessage::CorrespondenceMove { game_id, from, to } => {
+                let metrics = lobby.lock().await.metrics.clone();
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_correspondence_move(accounts, players, &metrics, player_id, game_id, from, to)
+                })
+                .await
             }
-            ClientMessage::RespondUndo { accept } => {
-                Self::handle_respond_undo(state, &mut pending, player_id, accept)
+            ClientMessage::ResignCorrespondence { game_id } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_resign_correspondence(accounts, players, player_id, game_id)
+                })
+                .await
             }
-            ClientMessage::Resign => {
-                Self::handle_resign(state, &mut pending, player_id)
+            ClientMessage::SpectateRoom { room_id } => {
+                Self::handle_spectate_room(lobby, player_id, room_id).await
             }
-            ClientMessage::PauseGame => {
-                Self::handle_pause(state, player_id)
+            ClientMessage::StopSpectating => {
+                Self::handle_stop_spectating(lobby, player_id).await
             }
-            ClientMessage::ResumeGame => {
-                Self::handle_resume(state, player_id)
+            ClientMessage::SendChat { channel, text } => {
+                Self::handle_send_chat(lobby, player_id, channel, &text).await
             }
-            ClientMessage::SaveGame => {
-                Self::handle_save_game(state, player_id)
+            ClientMessage::SetMuted { player_id: target, muted } => {
+                Self::handle_set_muted(&mut *lobby.lock().await, player_id, target, muted)
             }
             ClientMessage::LoadGame { game_id } => {
-                Self::handle_load_game(state, &mut pending, player_id, game_id)
+                Self::handle_load_game(lobby, player_id, game_id).await
+            }
+            ClientMessage::ListMyGames { page, page_size, filter } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_list_my_games(accounts, players, player_id, page, page_size, filter)
+                })
+                .await
+            }
+            ClientMessage::FetchGame { game_id } => {
+                Self::account_request(lobby, move |accounts, players| {
+                    Self::handle_fetch_game(accounts, players, player_id, game_id)
+                })
+                .await
+            }
+            ClientMessage::CreateTournament { name, format, time_control, rules } => {
+                Self::handle_create_tournament(lobby, player_id, name, format, time_control, rules).await
+            }
+            ClientMessage::ListTournaments => {
+                Some(ServerMessage::TournamentList { tournaments: lobby.lock().await.tournaments.list() })
+            }
+            ClientMessage::JoinTournament { tournament_id } => {
+                // 比赛对局计分
+                if let Some(error) = Self::check_bot_rated(&*lobby.lock().await, player_id, true) {
+                    return Some(error);
+                }
+                Self::update_tournament(lobby, player_id, tournament_id, |t, entrant| t.join(entrant)).await
+            }
+            ClientMessage::LeaveTournament { tournament_id } => {
+                Self::update_tournament(lobby, player_id, tournament_id, |t, entrant| t.leave(entrant.account_id)).await
+            }
+            ClientMessage::StartTournament { tournament_id } => {
+                Self::handle_start_tournament(lobby, player_id, tournament_id).await
+            }
+            ClientMessage::GetTournament { tournament_id } => {
+                match lobby.lock().await.tournaments.get(tournament_id) {
+                    Some(tournament) => Some(tournament.details()),
+                    None => Some(Self::tournament_not_found()),
+                }
             }
             ClientMessage::Ping => Some(ServerMessage::Pong),
-        };
+        }
+    }
 
-        // 发送待发送的消息
-        pending.flush(state).await;
-        
-        result
+    /// 是否为交给房间任务处理的对局操作
+    fn is_game_message(msg: &ClientMessage) -> bool {
+        matches!(
+            msg,
+            ClientMessage::MakeMove { .. }
+                | ClientMessage::RequestUndo
+                | ClientMessage::RespondUndo { .. }
+                | ClientMessage::Resign
+                | ClientMessage::OfferDraw
+                | ClientMessage::RespondDraw { .. }
+                | ClientMessage::PauseGame
+                | ClientMessage::ResumeGame
+                | ClientMessage::SaveGame
+        )
     }
 
-    /// 处理登录
-    fn handle_login(state: &mut ServerState, nickname: String) -> Option<ServerMessage> {
-        match state.players.login(nickname) {
-            Ok(player_id) => Some(ServerMessage::LoginSuccess { player_id }),
-            Err(msg) => Some(ServerMessage::Error {
-                code: if msg.contains("占用") {
-                    ErrorCode::NicknameOccupied
-                } else {
-                    ErrorCode::InvalidNickname
-                },
-                message: msg.to_string(),
+    /// 查找处理对局操作的房间
+    fn game_route(state: &ServerState, player_id: PlayerId) -> GameRoute {
+        match state.players.status(player_id) {
+            Some(PlayerStatus::InRoom(room_id)) => {
+                state.rooms.get(room_id).map_or(GameRoute::Ignored, GameRoute::Room)
+            }
+            Some(PlayerStatus::Spectating(_)) => GameRoute::Spectator,
+            _ => GameRoute::Ignored,
+        }
+    }
+
+    /// 把对局操作转交房间任务
+    async fn forward_to_room(route: GameRoute, player_id: PlayerId, msg: ClientMessage) -> Option<ServerMessage> {
+        match route {
+            GameRoute::Room(room) => room.request(player_id, msg).await,
+            GameRoute::Spectator => Some(ServerMessage::Error {
+                code: ErrorCode::SpectatorReadOnly,
+                message: "观战中不能操作棋局".to_string(),
             }),
+            GameRoute::Ignored => None,
         }
     }
 
-    /// 处理重连
-    fn handle_reconnect(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
-        player_id: PlayerId,
-        room_id: RoomId,
+    /// 处理登录
+    ///
+    /// 提供密码时按注册账号验证（验证期间不持有大厅锁），否则以游客身份登录（不能使用已注册的昵称）
+    async fn handle_login(
+        lobby: &mut impl Lobby,
+        nickname: String,
+        password: Option<String>,
     ) -> Option<ServerMessage> {
-        // 检查玩家是否存在
-        if !state.players.exists(player_id) {
+        if let Some(password) = password {
+            let accounts = lobby.lock().await.accounts.clone();
+            return match Self::authenticate(&accounts, nickname, password).await {
+                Ok(account) => Self::login_player(&mut *lobby.lock().await, account.nickname, Some(account.id)),
+                Err(error) => Some(error),
+            };
+        }
+
+        // 游客登录
+        let mut state = lobby.lock().await;
+        if !state.config.allow_guests {
             return Some(ServerMessage::Error {
-                code: ErrorCode::PlayerNotFound,
-                message: "玩家不存在".to_string(),
+                code: ErrorCode::AccountRequired,
+                message: "服务器不允许游客登录，请注册账号".to_string(),
+            });
+        }
+        match state.accounts.is_registered(&nickname) {
+            Ok(false) if !state.registering.contains(&nickname) => Self::login_player(&mut state, nickname, None),
+            Ok(_) => Some(ServerMessage::Error {
+                code: ErrorCode::NicknameReserved,
+                message: "该昵称已被注册，请输入密码登录".to_string(),
+            }),
+            Err(e) => Some(Self::account_error(e)),
+        }
+    }
+
+    /// 处理注册（成功后直接登录）
+    async fn handle_register(
+        lobby: &mut impl Lobby,
+        nickname: String,
+        password: String,
+    ) -> Option<ServerMessage> {
+        // 昵称被在线玩家占用时不创建账号，否则账号建成后却无法登录
+        let accounts = {
+            let mut state = lobby.lock().await;
+            if state.players.read().is_nickname_taken(&nickname) || state.registering.contains(&nickname) {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::NicknameOccupied,
+                    message: "昵称已被占用".to_string(),
+                });
+            }
+            state.registering.insert(nickname.clone());
+            state.accounts.clone()
+        };
+
+        let registered = Self::register_account(&accounts, nickname.clone(), password).await;
+        let mut state = lobby.lock().await;
+        state.registering.remove(&nickname);
+        match registered {
+            Ok(account) => Self::login_player(&mut state, account.nickname, Some(account.id)),
+            Err(error) => Some(error),
+        }
+    }
+
+    /// 验证账号密码（密码哈希在阻塞线程池中进行）
+    async fn authenticate(
+        accounts: &Arc<AccountStore>,
+        nickname: String,
+        password: String,
+    ) -> Result<Account, ServerMessage> {
+        accounts
+            .blocking(move |accounts| accounts.authenticate(&nickname, &password))
+            .await
+            .map_err(Self::account_error)
+    }
+
+    /// 注册账号（密码哈希在阻塞线程池中进行）
+    async fn register_account(
+        accounts: &Arc<AccountStore>,
+        nickname: String,
+        password: String,
+    ) -> Result<Account, ServerMessage> {
+        if let Err(msg) = PlayerManager::validate_nickname(&nickname) {
+            return Err(ServerMessage::Error {
+                code: ErrorCode::InvalidNickname,
+                message: msg.to_string(),
             });
         }
 
-        // 检查房间是否存在
-        let room = state.rooms.get(room_id)?;
-        if !room.has_player(player_id) {
+        let account = accounts
+            .blocking(move |accounts| accounts.register(&nickname, &password))
+            .await
+            .map_err(Self::account_error)?;
+        tracing::info!("新账号注册: {}", account.nickname);
+        Ok(account)
+    }
+
+    /// 处理修改密码（密码哈希在阻塞线程池中进行）
+    async fn handle_change_password(
+        accounts: &Arc<AccountStore>,
+        account_id: Option<AccountId>,
+        old_password: String,
+        new_password: String,
+    ) -> Option<ServerMessage> {
+        let Some(account_id) = account_id else {
             return Some(ServerMessage::Error {
-                code: ErrorCode::NotInRoom,
-                message: "不在该房间中".to_string(),
+                code: ErrorCode::AccountRequired,
+                message: "游客无法修改密码".to_string(),
             });
+        };
+
+        let result = accounts
+            .blocking(move |accounts| accounts.change_password(account_id, &old_password, &new_password))
+            .await;
+        match result {
+            Ok(()) => Some(ServerMessage::PasswordChanged),
+            Err(e) => Some(Self::account_error(e)),
         }
+    }
 
-        // 获取房间信息
-        let your_side = room.get_player_side(player_id)?;
-        let game_state = room.game_state.clone()?;
-        let (red_time_ms, black_time_ms) = if let Some(timer) = &room.timer {
-            (timer.red_time_ms(), timer.black_time_ms())
-        } else {
-            (0, 0)
+    /// 处理机器人登录
+    fn handle_bot_login(state: &mut ServerState, token: &str) -> Option<ServerMessage> {
+        let bot = match state.accounts.authenticate_bot(token) {
+            Ok(bot) => bot,
+            Err(e) => return Some(Self::account_error(e)),
         };
-        let opponent_id = room.get_opponent_id(player_id);
 
-        // 恢复玩家状态
-        state.players.reconnect(player_id);
-        state.disconnect_timeouts.remove(&player_id);
+        let player_id = state
+            .players
+            .write()
+            .login_bot(bot.account.nickname.clone(), bot.account.id, bot.settings);
+        state.metrics.bot_login();
+        tracing::info!("机器人 {} 登录: 玩家 {}", bot.account.nickname, player_id);
+        Some(ServerMessage::LoginSuccess {
+            player_id,
+            guest: false,
+            session_token: state.sessions.issue(player_id),
+        })
+    }
 
-        // 如果是当前走棋方重连，重置计时器开始时间
-        let room = state.rooms.get_mut(room_id)?;
-        if let Some(timer) = &mut room.timer {
-            if timer.current_turn() == your_side && !timer.is_paused() {
-                // 重连时重置当前回合开始时间，避免断线期间时间被计入
-                timer.reset_turn_start();
+    /// 未开启机器人计分时，机器人不能下计分对局
+    fn check_bot_rated(state: &ServerState, player_id: PlayerId, rated: bool) -> Option<ServerMessage> {
+        let bot = state.players.read().get(player_id).is_some_and(|p| p.is_bot());
+        (bot && rated && !state.config.rate_bot_games).then(|| ServerMessage::Error {
+            code: ErrorCode::BotNotAllowed,
+            message: "机器人对局不计分".to_string(),
+        })
+    }
+
+    /// 机器人能否再开一局：同一机器人其他连接中进行和排队的对局数不超过上限
+    fn check_bot_capacity(state: &ServerState, player_id: PlayerId) -> Option<ServerMessage> {
+        let players = state.players.read();
+        let player = players.get(player_id)?;
+        let (Some(settings), Some(account_id)) = (player.bot, player.account_id) else {
+            return None;
+        };
+        let busy = players
+            .sessions_of(account_id)
+            .filter(|p| p.id != player_id && (p.in_game() || state.matchmaker.contains(p.id)))
+            .count();
+        (busy as u32 >= settings.max_games).then(|| ServerMessage::Error {
+            code: ErrorCode::BotBusy,
+            message: format!("机器人同时进行的对局数已达上限 ({})", settings.max_games),
+        })
+    }
+
+    /// 登记在线玩家
+    fn login_player(
+        state: &mut ServerState,
+        nickname: String,
+        account_id: Option<AccountId>,
+    ) -> Option<ServerMessage> {
+        let result = match account_id {
+            Some(account_id) => state.players.write().login_account(nickname, account_id),
+            None => state.players.write().login(nickname),
+        };
+
+        match result {
+            Ok(player_id) => {
+                state.metrics.login(account_id.is_none());
+                Some(ServerMessage::LoginSuccess {
+                    player_id,
+                    guest: account_id.is_none(),
+                    session_token: state.sessions.issue(player_id),
+                })
             }
+            Err(msg) => Some(ServerMessage::Error {
+                code: if msg.contains("占用") {
+                    ErrorCode::NicknameOccupied
+                } else {
+                    ErrorCode::InvalidNickname
+                },
+                message: msg.to_string(),
+            }),
         }
+    }
 
-        // 通知对手
-        if let Some(opponent_id) = opponent_id {
-            pending.send(opponent_id, ServerMessage::OpponentReconnected);
+    /// 账号错误转换为错误消息
+    fn account_error(error: AccountError) -> ServerMessage {
+        let code = match &error {
+            AccountError::NicknameTaken => ErrorCode::NicknameOccupied,
+            AccountError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            AccountError::InvalidPassword => ErrorCode::InvalidPassword,
+            AccountError::Database(e) => {
+                tracing::error!("账号数据库错误: {}", e);
+                ErrorCode::InternalError
+            }
+            AccountError::Serialization(e) => {
+                tracing::error!("对局数据序列化错误: {}", e);
+                ErrorCode::InternalError
+            }
+        };
+        ServerMessage::Error {
+            code,
+            message: error.to_string(),
         }
+    }
 
-        Some(ServerMessage::ReconnectSuccess {
-            room_id,
-            game_state,
-            your_side,
-            red_time_ms,
-            black_time_ms,
-        })
+    /// 处理注销：离开房间并移除玩家，会话令牌立即失效
+    async fn handle_logout(lobby: &mut impl Lobby, player_id: PlayerId) -> Option<ServerMessage> {
+        Self::handle_leave_room(lobby, player_id).await;
+        Self::remove_player(lobby, player_id).await;
+        Some(ServerMessage::LoggedOut)
+    }
+
+    /// 移除玩家并注销其会话
+    async fn remove_player(lobby: &mut impl Lobby, player_id: PlayerId) {
+        Self::handle_stop_spectating(lobby, player_id).await;
+        let mut state = lobby.lock().await;
+        Self::close_challenges_of(&mut state, player_id, "对方已离线");
+        state.players.write().remove(player_id);
+        state.matchmaker.forget(player_id);
+        state.chat_limiter.forget(player_id);
+        state.sessions.revoke(player_id);
+        state.disconnect_timeouts.remove(&player_id);
+    }
+
+    /// 处理重连
+    ///
+    /// 重连成功时房间任务登记新连接的发送通道 `tx`
+    pub async fn handle_reconnect(
+        lobby: &mut impl Lobby,
+        session_token: &str,
+        room_id: RoomId,
+        last_seq: Option<u64>,
+        tx: Option<mpsc::Sender<ServerMessage>>,
+    ) -> Option<ServerMessage> {
+        let (player_id, room) = {
+            let state = lobby.lock().await;
+            // 凭会话令牌确认身份
+            let Some(player_id) = state.sessions.validate(session_token) else {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::InvalidSession,
+                    message: "会话已失效，请重新登录".to_string(),
+                });
+            };
+
+            // 检查玩家是否存在
+            if !state.players.read().exists(player_id) {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::PlayerNotFound,
+                    message: "玩家不存在".to_string(),
+                });
+            }
+            (player_id, state.rooms.get(room_id)?)
+        };
+
+        // 由房间任务生成快照并恢复玩家状态
+        let response = room
+            .call(move |actor| actor.reconnect(player_id, last_seq, tx))
+            .await
+            .flatten();
+
+        if matches!(response, Some(ServerMessage::ReconnectSuccess { .. })) {
+            let mut state = lobby.lock().await;
+            state.sessions.resume(player_id);
+            state.disconnect_timeouts.remove(&player_id);
+            state.metrics.reconnects.inc();
+        }
+        response
     }
 
     /// 处理创建房间
@@ -244,27 +799,73 @@ impl MessageHandler {
         player_id: PlayerId,
         room_type: RoomType,
         preferred_side: Option<Side>,
+        mut settings: RoomSettings,
     ) -> Option<ServerMessage> {
+        if let Some(error) = Self::check_time_control(&settings.time_control) {
+            return Some(error);
+        }
+
+        // 标准开局不算自定义局面
+        settings.custom_fen = settings.custom_fen.filter(|fen| fen.trim() != INITIAL_FEN);
+        if let Err(reason) = settings.validate() {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::InvalidRoomSettings,
+                message: reason.to_string(),
+            });
+        }
+
         // 检查玩家是否已在房间中
-        if let Some(player) = state.players.get(player_id) {
-            if matches!(player.status, PlayerStatus::InRoom(_)) {
-                return Some(ServerMessage::Error {
-                    code: ErrorCode::AlreadyInRoom,
-                    message: "已在房间中".to_string(),
-                });
-            }
+        if matches!(
+            state.players.status(player_id),
+            Some(PlayerStatus::InRoom(_) | PlayerStatus::Spectating(_))
+        ) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AlreadyInRoom,
+                message: "已在房间中".to_string(),
+            });
+        }
+
+        // 计分对局仅限注册账号的玩家对战
+        let rated = settings.rated && room_type == RoomType::PvP;
+        if rated && state.players.read().get(player_id).is_none_or(|p| p.is_guest()) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AccountRequired,
+                message: "计分对局需要注册账号".to_string(),
+            });
+        }
+
+        if let Some(error) = Self::check_bot_rated(state, player_id, rated)
+            .or_else(|| Self::check_bot_capacity(state, player_id))
+        {
+            return Some(error);
         }
 
+        // 自己开房即退出匹配
+        state.matchmaker.remove(player_id);
+
         // 创建房间
-        let room_id = state.rooms.create(room_type);
-        let room = state.rooms.get_mut(room_id)?;
+        let mut room = Room::new(state.rooms.generate_id(), room_type);
+        let room_id = room.id;
+        room.rated = rated;
+        room.time_control = settings.time_control;
+        room.rules = settings.rules;
+        room.custom_fen = settings.custom_fen;
+
+        // 私人房间只对 PvP 有意义
+        if room_type == RoomType::PvP {
+            room.key = match settings.access {
+                RoomAccess::Public => None,
+                RoomAccess::Invite => Some(RoomKey::Invite(state.rooms.generate_invite_code())),
+                RoomAccess::Password(password) => Some(RoomKey::Password(password)),
+            };
+        }
+        let invite_code = room.summary().invite_code;
 
         // 玩家加入房间
         let your_side = room.add_player(player_id, preferred_side)?;
-        state.players.set_status(player_id, PlayerStatus::InRoom(room_id));
 
         // 如果是 PvE，AI 自动加入并开始游戏
-        if let RoomType::PvE(difficulty) = room_type {
+        let response = if let RoomType::PvE(difficulty) = room_type {
             // AI 作为对方（玩家默认红方）
             // 使用专用的 AI_PLAYER_ID 避免与真实玩家 ID 冲突
             if your_side == Side::Red {
@@ -275,866 +876,2842 @@ impl MessageHandler {
             room.start_game();
 
             // 返回游戏开始消息
-            let game_state = room.game_state.clone()?;
-            let red_player = if your_side == Side::Red {
-                state.players.get_nickname(player_id).unwrap_or("玩家").to_string()
-            } else {
-                format!("AI ({:?})", difficulty)
-            };
-            let black_player = if your_side == Side::Black {
-                state.players.get_nickname(player_id).unwrap_or("玩家").to_string()
-            } else {
-                format!("AI ({:?})", difficulty)
+            let nickname = state.players.nickname(player_id).unwrap_or_else(|| "玩家".to_string());
+            let ai_name = format!("AI ({:?})", difficulty);
+            let (red_player, black_player) = match your_side {
+                Side::Red => (nickname, ai_name),
+                Side::Black => (ai_name, nickname),
             };
 
-            return Some(ServerMessage::GameStarted {
-                initial_state: game_state,
+            ServerMessage::GameStarted {
+                initial_state: room.game_state.clone()?,
                 your_side,
                 red_player,
                 black_player,
-            });
-        }
+                initial_fen: room.initial_fen.clone(),
+                moves: room.move_records.clone(),
+            }
+        } else {
+            ServerMessage::RoomCreated { room_id, your_side, invite_code }
+        };
+
+        state.rooms.spawn(room, state.room_services());
+        state.players.write().set_status(player_id, PlayerStatus::InRoom(room_id));
 
-        Some(ServerMessage::RoomCreated { room_id, your_side })
+        Some(response)
     }
 
     /// 处理加入房间
-    fn handle_join_room(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
+    async fn handle_join_room(
+        lobby: &mut impl Lobby,
         player_id: PlayerId,
         room_id: RoomId,
+        password: Option<String>,
     ) -> Option<ServerMessage> {
-        // 检查玩家是否已在房间中
-        if let Some(player) = state.players.get(player_id) {
-            if matches!(player.status, PlayerStatus::InRoom(_)) {
+        let room = {
+            let state = lobby.lock().await;
+            // 检查玩家是否已在房间中
+            if matches!(
+                state.players.status(player_id),
+                Some(PlayerStatus::InRoom(_) | PlayerStatus::Spectating(_))
+            ) {
                 return Some(ServerMessage::Error {
                     code: ErrorCode::AlreadyInRoom,
                     message: "已在房间中".to_string(),
                 });
             }
-        }
 
-        // 检查房间是否存在
-        let room = match state.rooms.get(room_id) {
-            Some(r) => r,
-            None => {
-                return Some(ServerMessage::Error {
-                    code: ErrorCode::RoomNotFound,
-                    message: "房间不存在".to_string(),
-                });
+            let room = state.rooms.get(room_id);
+            if let Some(room) = &room {
+                if let Some(error) = Self::check_bot_rated(&state, player_id, room.summary().rated)
+                    .or_else(|| Self::check_bot_capacity(&state, player_id))
+                {
+                    return Some(error);
+                }
             }
+            room
         };
 
-        // 检查房间状态
-        if room.state != RoomState::Waiting {
-            return Some(ServerMessage::Error {
-                code: ErrorCode::RoomClosed,
-                message: "房间不可加入".to_string(),
-            });
+        // 房间检查、入座和开局由房间任务完成
+        let joined = match room {
+            Some(room) => room.call(move |actor| actor.join(player_id, password.as_deref())).await,
+            None => None,
+        };
+
+        match joined {
+            Some(Ok(side)) => {
+                let mut state = lobby.lock().await;
+                state.players.write().set_status(player_id, PlayerStatus::InRoom(room_id));
+                state.matchmaker.remove(player_id);
+                Some(ServerMessage::RoomJoined { room_id, side })
+            }
+            Some(Err(error)) => Some(*error),
+            None => Some(ServerMessage::Error {
+                code: ErrorCode::RoomNotFound,
+                message: "房间不存在".to_string(),
+            }),
         }
+    }
 
-        // 检查房间是否已满
-        if room.is_full() {
+    /// 处理凭邀请码加入私人房间
+    async fn handle_join_by_invite(lobby: &mut impl Lobby, player_id: PlayerId, code: &str) -> Option<ServerMessage> {
+        let Some(room_id) = lobby.lock().await.rooms.find_by_invite(code.trim()) else {
             return Some(ServerMessage::Error {
-                code: ErrorCode::RoomFull,
-                message: "房间已满".to_string(),
+                code: ErrorCode::RoomAccessDenied,
+                message: "邀请码无效".to_string(),
             });
-        }
-
-        // 获取房主 ID
-        let opponent_id = room.red_player.or(room.black_player);
-
-        // 获取加入者昵称
-        let joiner_nickname = state.players.get_nickname(player_id).unwrap_or("玩家").to_string();
+        };
+        Self::handle_join_room(lobby, player_id, room_id, Some(code.trim().to_string())).await
+    }
 
-        // 加入房间
-        let room = state.rooms.get_mut(room_id)?;
-        let side = room.add_player(player_id, None)?;
-        state.players.set_status(player_id, PlayerStatus::InRoom(room_id));
+    /// 校验时间控制参数
+    fn check_time_control(time_control: &TimeControl) -> Option<ServerMessage> {
+        time_control.validate().err().map(|reason| ServerMessage::Error {
+            code: ErrorCode::InvalidTimeControl,
+            message: reason.to_string(),
+        })
+    }
 
-        // 通知房主有人加入
-        if let Some(opponent_id) = opponent_id {
-            pending.send(opponent_id, ServerMessage::OpponentJoined { nickname: joiner_nickname });
-        }
-
-        // 如果房间满了，开始游戏
-        if room.is_full() {
-            room.start_game();
-
-            let game_state = room.game_state.clone()?;
-            let red_id = room.red_player?;
-            let black_id = room.black_player?;
-            let red_player = state.players.get_nickname(red_id).unwrap_or("玩家").to_string();
-            let black_player = state.players.get_nickname(black_id).unwrap_or("玩家").to_string();
-
-            // 通知双方游戏开始
-            pending.send(
-                red_id,
-                ServerMessage::GameStarted {
-                    initial_state: game_state.clone(),
-                    your_side: Side::Red,
-                    red_player: red_player.clone(),
-                    black_player: black_player.clone(),
-                },
-            );
-
-            pending.send(
-                black_id,
-                ServerMessage::GameStarted {
-                    initial_state: game_state,
-                    your_side: Side::Black,
-                    red_player,
-                    black_player,
-                },
-            );
-        }
-
-        Some(ServerMessage::RoomJoined { room_id, side })
-    }
-
-    /// 处理离开房间
-    fn handle_leave_room(
+    /// 处理进入匹配队列
+    fn handle_enter_queue(
         state: &mut ServerState,
-        pending: &mut PendingMessages,
         player_id: PlayerId,
+        time_control: TimeControl,
+        rated: bool,
     ) -> Option<ServerMessage> {
-        // 查找玩家所在房间
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
-
-        let side = room.get_player_side(player_id)?;
-        let opponent_id = room.get_opponent_id(player_id);
-        let is_playing = room.state == RoomState::Playing;
-
-        // 如果游戏进行中，离开者判负
-        if is_playing {
-            let result = match side {
-                Side::Red => GameResult::BlackWin(WinReason::Disconnect),
-                Side::Black => GameResult::RedWin(WinReason::Disconnect),
-            };
-
-            // 通知对手胜利
-            if let Some(opponent_id) = opponent_id {
-                pending.send(opponent_id, ServerMessage::GameOver { result: result.clone() });
-            }
-
-            // 更新房间状态
-            let room = state.rooms.get_mut(room_id)?;
-            room.finish(result);
+        if let Some(error) = Self::check_time_control(&time_control) {
+            return Some(error);
         }
 
-        // 移除玩家
-        let room = state.rooms.get_mut(room_id)?;
-        room.remove_player(player_id);
-        state.players.set_status(player_id, PlayerStatus::Online);
+        let (status, guest) = {
+            let players = state.players.read();
+            let player = players.get(player_id)?;
+            (player.status, player.is_guest())
+        };
+        if !matches!(status, PlayerStatus::Online) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AlreadyInRoom,
+                message: "已在房间中".to_string(),
+            });
+        }
+        if rated && guest {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AccountRequired,
+                message: "计分对局需要注册账号".to_string(),
+            });
+        }
 
-        // 如果房间空了，销毁房间
-        if room.red_player.is_none() && room.black_player.is_none() {
-            state.rooms.remove(room_id);
+        if let Some(error) = Self::check_bot_rated(state, player_id, rated)
+            .or_else(|| Self::check_bot_capacity(state, player_id))
+        {
+            return Some(error);
         }
 
-        None
+        // 游客按初始等级分匹配
+        let rating = state
+            .player_rating(player_id)
+            .map_or(crate::ratings::DEFAULT_RATING as i32, |r| r.rating);
+        let account_id = state.players.account_id(player_id);
+        state
+            .matchmaker
+            .enqueue(QueueEntry::new(player_id, account_id, rating, time_control, rated));
+
+        state.matchmaker.status(player_id, Instant::now())
     }
 
-    /// 处理房间列表
-    fn handle_list_rooms(state: &ServerState) -> Option<ServerMessage> {
-        let rooms: Vec<RoomInfo> = state.rooms.list_joinable()
-            .iter()
-            .map(|r| {
-                let red_name = r.red_player.and_then(|id| state.players.get_nickname(id).map(|s| s.to_string()));
-                let black_name = r.black_player.and_then(|id| state.players.get_nickname(id).map(|s| s.to_string()));
-                r.info(red_name, black_name)
-            })
-            .collect();
+    /// 执行一轮匹配：为配对成功的玩家开局，并向等待中的玩家推送队列状态
+    pub async fn run_matchmaking(lobby: &mut impl Lobby) {
+        let announced: Vec<_> = {
+            let mut state = lobby.lock().await;
+            let now = Instant::now();
+            // 配对前移出已不在大厅的玩家，配对成功的双方都能开局
+            let players = state.players.clone();
+            state
+                .matchmaker
+                .retain_available(|id| matches!(players.status(id), Some(PlayerStatus::Online)));
+            let announced = state
+                .matchmaker
+                .find_matches(now)
+                .into_iter()
+                .map(|pairing| Self::start_matched_game(&mut state, pairing))
+                .collect();
+            for (player_id, status) in state.matchmaker.due_statuses(now) {
+                state.players.send(player_id, status);
+            }
+            announced
+        };
+        Self::wait_announced(announced).await;
+    }
 
-        Some(ServerMessage::RoomList { rooms })
+    /// 等待新开对局的开局消息发出（不持有大厅锁）
+    async fn wait_announced(announced: Vec<oneshot::Receiver<()>>) {
+        for announced in announced {
+            let _ = announced.await;
+        }
     }
 
-    /// 处理走棋
-    fn handle_make_move(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
+    /// 处理挑战
+    ///
+    /// 机器人有空闲连接时立即开局；其他玩家收到挑战，等待对方回应
+    async fn handle_challenge(
+        lobby: &mut impl Lobby,
         player_id: PlayerId,
-        from: Position,
-        to: Position,
+        nickname: &str,
+        time_control: TimeControl,
+        rules: RuleSet,
+        rated: bool,
     ) -> Option<ServerMessage> {
-        // 查找玩家所在房间
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
-
-        // 检查游戏状态
-        if room.state != RoomState::Playing {
+        if let Some(error) = Self::check_time_control(&time_control) {
+            return Some(error);
+        }
+        let mut state = lobby.lock().await;
+        let (status, account_id) = {
+            let players = state.players.read();
+            let player = players.get(player_id)?;
+            (player.status, player.account_id)
+        };
+        if status != PlayerStatus::Online {
             return Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "游戏未开始".to_string(),
+                code: ErrorCode::AlreadyInRoom,
+                message: "已在房间中".to_string(),
             });
         }
-
-        // 检查是否轮到该玩家
-        let player_side = room.get_player_side(player_id)?;
-        let game_state = room.game_state.as_ref()?;
-        if game_state.current_turn != player_side {
+        if rated && account_id.is_none() {
             return Some(ServerMessage::Error {
-                code: ErrorCode::NotYourTurn,
-                message: "不是你的回合".to_string(),
+                code: ErrorCode::AccountRequired,
+                message: "计分对局需要注册账号".to_string(),
             });
         }
 
-        // 获取 PvE 难度（如果是 PvE 模式）
-        let pve_difficulty = match room.room_type {
-            RoomType::PvE(difficulty) => Some(difficulty),
-            _ => None,
+        // 目标玩家的连接（机器人可能有多个）
+        let sessions: Vec<(PlayerId, PlayerStatus, Option<bool>)> = {
+            let players = state.players.read();
+            let targets: Vec<_> = players.all().filter(|p| p.nickname == nickname && p.id != player_id).collect();
+            // 同一账号的其他连接（如机器人的另一个连接）也是自己
+            if account_id.is_some() && targets.iter().any(|p| p.account_id == account_id) {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::ChallengeUnavailable,
+                    message: "不能挑战自己".to_string(),
+                });
+            }
+            targets.iter().map(|p| (p.id, p.status, p.bot.map(|bot| bot.accept_challenges))).collect()
         };
+        if sessions.is_empty() {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::PlayerNotFound,
+                message: "玩家不在线".to_string(),
+            });
+        }
+        if let [(target, _, None)] = sessions[..] {
+            return Self::issue_challenge(&mut state, player_id, target, time_control, rules, rated);
+        }
+        if !sessions.iter().any(|&(_, _, accepts)| accepts == Some(true)) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::ChallengeUnavailable,
+                message: "对方不接受挑战".to_string(),
+            });
+        }
 
-        // 执行走棋
-        let room = state.rooms.get_mut(room_id)?;
-        let mv = Move::new(from, to);
-        if let Err(msg) = room.make_move(mv) {
+        // 目标机器人的一个空闲连接
+        let target = sessions
+            .iter()
+            .find(|&&(id, status, _)| status == PlayerStatus::Online && !state.matchmaker.contains(id))
+            .map(|&(id, _, _)| id);
+        let Some(bot_id) = target else {
             return Some(ServerMessage::Error {
-                code: ErrorCode::InvalidMove,
-                message: msg.to_string(),
+                code: ErrorCode::BotBusy,
+                message: "机器人暂无空闲连接".to_string(),
             });
+        };
+        for id in [player_id, bot_id] {
+            if let Some(error) = Self::check_bot_rated(&state, id, rated)
+                .or_else(|| Self::check_bot_capacity(&state, id))
+            {
+                return Some(error);
+            }
         }
 
-        // 生成中文记谱
-        let new_state = room.game_state.clone()?;
-        let last_move = *room.move_history.last()?;
-        let notation = Notation::to_chinese(&new_state.board, &last_move).unwrap_or_default();
+        let (room_id, announced) = Self::start_challenge_game(&mut state, player_id, bot_id, time_control, rules, rated);
+        drop(state);
+        tracing::info!("挑战 {} 被接受: 房间 {}", nickname, room_id);
+        let _ = announced.await;
+        None
+    }
 
-        // 获取时间信息
-        let (red_time_ms, black_time_ms) = if let Some(timer) = &room.timer {
-            (timer.red_time_ms(), timer.black_time_ms())
-        } else {
-            (0, 0)
+    /// 向玩家发出挑战，等待对方回应
+    fn issue_challenge(
+        state: &mut ServerState,
+        player_id: PlayerId,
+        target: PlayerId,
+        time_control: TimeControl,
+        rules: RuleSet,
+        rated: bool,
+    ) -> Option<ServerMessage> {
+        let (status, guest) = {
+            let players = state.players.read();
+            let player = players.get(target)?;
+            (player.status, player.is_guest())
+        };
+        if status != PlayerStatus::Online {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::ChallengeUnavailable,
+                message: "对方不在大厅，暂时无法接受挑战".to_string(),
+            });
+        }
+        if rated && guest {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AccountRequired,
+                message: "对方是游客，不能下计分对局".to_string(),
+            });
+        }
+        if let Some(error) = Self::check_bot_rated(state, player_id, rated)
+            .or_else(|| Self::check_bot_capacity(state, player_id))
+        {
+            return Some(error);
+        }
+
+        let now = Instant::now();
+        let challenge = match state.challenges.issue(player_id, target, time_control, rules, rated, now) {
+            Ok(challenge) => challenge.clone(),
+            Err(ChallengeError::Duplicate) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::ChallengeUnavailable,
+                    message: "已向对方发出挑战，请等待回应".to_string(),
+                });
+            }
+            Err(ChallengeError::TooMany) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::RateLimited,
+                    message: format!("同时发出的挑战不能超过 {} 个", MAX_OUTGOING_CHALLENGES),
+                });
+            }
         };
+        let info = Self::challenge_info(state, &challenge, now);
+        state.players.send(target, ServerMessage::ChallengeReceived { challenge: info.clone() });
+        Some(ServerMessage::ChallengeSent { challenge: info })
+    }
 
-        // 检查游戏是否结束
-        let game_over = room.check_game_over();
+    /// 挑战信息（附双方昵称）
+    fn challenge_info(state: &ServerState, challenge: &Challenge, now: Instant) -> ChallengeInfo {
+        let nickname = |id| state.players.nickname(id).unwrap_or_default();
+        challenge.info(nickname(challenge.from), nickname(challenge.to), now)
+    }
 
-        // 广播走棋消息
-        pending.broadcast(room_id, ServerMessage::MoveMade {
-            from,
-            to,
-            new_state: new_state.clone(),
-            notation,
-        });
+    fn challenge_not_found() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::ChallengeNotFound,
+            message: "挑战不存在或已结束".to_string(),
+        }
+    }
 
-        // 发送时间更新
-        pending.broadcast(room_id, ServerMessage::TimeUpdate {
-            red_time_ms,
-            black_time_ms,
-        });
+    /// 回应挑战：接受则开局，拒绝则通知发起方
+    async fn handle_respond_challenge(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        challenge_id: ChallengeId,
+        accept: bool,
+    ) -> Option<ServerMessage> {
+        let mut state = lobby.lock().await;
+        if state.challenges.get(challenge_id).is_none_or(|c| c.to != player_id) {
+            return Some(Self::challenge_not_found());
+        }
+        if accept && state.players.status(player_id) != Some(PlayerStatus::Online) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AlreadyInRoom,
+                message: "已在房间中".to_string(),
+            });
+        }
+        let challenge = state.challenges.take(challenge_id)?;
+        let nickname = state.players.nickname(player_id).unwrap_or_default();
 
-        // 处理游戏结束
-        if let Some(result) = game_over {
-            let room = state.rooms.get_mut(room_id)?;
-            room.finish(result.clone());
-            pending.broadcast(room_id, ServerMessage::GameOver { result });
+        if !accept {
+            let reason = format!("{} 拒绝了挑战", nickname);
+            state.players.send(challenge.from, ServerMessage::ChallengeClosed { challenge_id, reason });
             return None;
         }
 
-        // PvE 模式：AI 自动走棋（在独立线程中执行，不阻塞其他异步任务）
-        if let Some(difficulty) = pve_difficulty {
-            // 获取当前状态版本和游戏状态
-            let room = state.rooms.get(room_id)?;
-            let version_before = room.version;
-            let game_state_for_ai = room.game_state.clone()?;
-            
-            // 在阻塞线程池中运行 AI 计算
-            // 使用 block_in_place 允许 tokio 在等待期间处理其他任务
-            let ai_result = tokio::task::block_in_place(|| {
-                let mut engine = AiEngine::from_difficulty(difficulty);
-                engine.search(&game_state_for_ai)
+        // 发起方可能已开始其他对局
+        let available = state.players.status(challenge.from) == Some(PlayerStatus::Online)
+            && Self::check_bot_capacity(&state, challenge.from).is_none();
+        if !available {
+            let reason = format!("{} 接受挑战时你不在大厅", nickname);
+            state.players.send(challenge.from, ServerMessage::ChallengeClosed { challenge_id, reason });
+            return Some(ServerMessage::ChallengeClosed {
+                challenge_id,
+                reason: "对方暂时无法开始对局".to_string(),
             });
-            
-            // 处理 AI 结果
-            match ai_result {
-                Some(ai_move) => {
-                    Self::apply_ai_move(state, pending, room_id, ai_move, version_before);
-                }
-                None => {
-                    // AI 无法走棋，判定 AI 负
-                    tracing::warn!("AI 无法找到合法走法，判定 AI 负");
-                    Self::ai_loses(state, pending, room_id);
-                }
-            }
         }
 
+        let (room_id, announced) = Self::start_challenge_game(
+            &mut state,
+            challenge.from,
+            player_id,
+            challenge.time_control,
+            challenge.rules,
+            challenge.rated,
+        );
+        drop(state);
+        tracing::info!("{} 接受了挑战 {}: 房间 {}", nickname, challenge_id, room_id);
+        let _ = announced.await;
         None
     }
 
-    /// 应用 AI 走法（带版本检查）
-    fn apply_ai_move(
+    /// 撤回自己发出的挑战
+    fn handle_cancel_challenge(
         state: &mut ServerState,
-        pending: &mut PendingMessages,
-        room_id: RoomId,
-        ai_move: Move,
-        version_before: u64,
-    ) {
-        let room = match state.rooms.get(room_id) {
-            Some(r) => r,
-            None => return,
-        };
+        player_id: PlayerId,
+        challenge_id: ChallengeId,
+    ) -> Option<ServerMessage> {
+        if state.challenges.get(challenge_id).is_none_or(|c| c.from != player_id) {
+            return Some(Self::challenge_not_found());
+        }
+        let challenge = state.challenges.take(challenge_id)?;
+        let nickname = state.players.nickname(player_id).unwrap_or_default();
+        let reason = format!("{} 撤回了挑战", nickname);
+        state.players.send(challenge.to, ServerMessage::ChallengeClosed { challenge_id, reason });
+        Some(ServerMessage::ChallengeClosed {
+            challenge_id,
+            reason: "挑战已撤回".to_string(),
+        })
+    }
 
-        // 检查版本号，确保状态未改变
-        if room.version != version_before {
-            tracing::warn!("AI 计算期间游戏状态已改变，丢弃 AI 走法");
-            return;
+    /// 撤回涉及某玩家的所有挑战并通知另一方
+    fn close_challenges_of(state: &mut ServerState, player_id: PlayerId, reason: &str) {
+        for challenge in state.challenges.take_involving(player_id) {
+            let other = if challenge.from == player_id { challenge.to } else { challenge.from };
+            let closed = ServerMessage::ChallengeClosed {
+                challenge_id: challenge.id,
+                reason: reason.to_string(),
+            };
+            state.players.send(other, closed);
         }
+    }
 
-        // 检查游戏状态
-        if room.state != RoomState::Playing {
-            return;
+    /// 撤回过期的挑战
+    pub async fn expire_challenges(lobby: &mut impl Lobby) {
+        let mut state = lobby.lock().await;
+        for challenge in state.challenges.take_expired(Instant::now()) {
+            for player_id in [challenge.from, challenge.to] {
+                let closed = ServerMessage::ChallengeClosed {
+                    challenge_id: challenge.id,
+                    reason: "挑战已过期".to_string(),
+                };
+                state.players.send(player_id, closed);
+            }
+        }
+    }
+
+    /// 为接受挑战的双方开局（随机分配红黑），双方的其他挑战随之撤回
+    fn start_challenge_game(
+        state: &mut ServerState,
+        challenger: PlayerId,
+        challenged: PlayerId,
+        time_control: TimeControl,
+        rules: RuleSet,
+        rated: bool,
+    ) -> (RoomId, oneshot::Receiver<()>) {
+        for id in [challenger, challenged] {
+            state.matchmaker.remove(id);
+            Self::close_challenges_of(state, id, "对方已开始其他对局");
         }
+        let (red, black) = if rand::random::<bool>() {
+            (challenger, challenged)
+        } else {
+            (challenged, challenger)
+        };
+        Self::open_game(state, red, black, |room| {
+            room.rated = rated;
+            room.time_control = time_control;
+            room.rules = rules;
+        })
+    }
 
-        // 执行 AI 走棋
-        let room = match state.rooms.get_mut(room_id) {
-            Some(r) => r,
-            None => return,
+    /// 玩家的注册账号（游客为 None）
+    fn account_of(state: &ServerState, player_id: PlayerId) -> Option<AccountId> {
+        state.players.account_id(player_id)
+    }
+
+    /// 在阻塞线程池中处理读写账号库的请求，不持大厅锁
+    ///
+    /// `f` 只使用账号库和玩家登记，需要的其他大厅数据在调用前取出
+    async fn account_request<T, F>(lobby: &mut impl Lobby, f: F) -> T
+    where
+        F: FnOnce(&AccountStore, &PlayerRegistry) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (accounts, players) = {
+            let state = lobby.lock().await;
+            (state.accounts.clone(), state.players.clone())
         };
+        accounts.blocking(move |accounts| f(accounts, &players)).await
+    }
 
-        if room.make_move(ai_move).is_err() {
-            tracing::error!("AI 走棋失败: {:?}，判定 AI 负", ai_move);
-            // AI 走棋失败，判定 AI 负
-            let result = GameResult::RedWin(WinReason::Resign);
-            room.finish(result.clone());
-            pending.broadcast(room_id, ServerMessage::GameOver { result });
-            return;
+    fn friends_need_account() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::AccountRequired,
+            message: "游客不能使用好友功能".to_string(),
         }
+    }
 
-        // 重置计时器起点（AI 思考时间不应计入玩家时间）
-        if let Some(timer) = &mut room.timer {
-            timer.reset_turn_start();
+    /// 好友操作的双方：(自己的账号, 按昵称找到的对方账号)
+    fn friend_pair(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        nickname: &str,
+    ) -> Result<(AccountId, Account), Box<ServerMessage>> {
+        let account_id = players.account_id(player_id).ok_or_else(|| Box::new(Self::friends_need_account()))?;
+        match accounts.find(nickname) {
+            Ok(Some(target)) => Ok((account_id, target)),
+            Ok(None) => Err(Box::new(ServerMessage::Error {
+                code: ErrorCode::PlayerNotFound,
+                message: "玩家不存在".to_string(),
+            })),
+            Err(e) => Err(Box::new(Self::account_error(e))),
+        }
+    }
+
+    fn invalid_friend_request(message: &str) -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::InvalidFriendRequest,
+            message: message.to_string(),
         }
+    }
 
-        // 生成中文记谱
-        let new_state = match room.game_state.clone() {
-            Some(s) => s,
-            None => return,
+    /// 好友列表和待处理的好友请求
+    fn friend_list(accounts: &AccountStore, players: &PlayerRegistry, account_id: AccountId) -> ServerMessage {
+        let (friends, (incoming, outgoing)) = match accounts
+            .friends(account_id)
+            .and_then(|friends| Ok((friends, accounts.friend_requests(account_id)?)))
+        {
+            Ok(lists) => lists,
+            Err(e) => return Self::account_error(e),
         };
-        let notation = Notation::to_chinese(&new_state.board, &ai_move).unwrap_or_default();
+        let presences = friends::account_presences(&players.read());
+        let nicknames = |accounts: Vec<Account>| accounts.into_iter().map(|a| a.nickname).collect();
+        ServerMessage::FriendList {
+            friends: friends
+                .into_iter()
+                .map(|friend| FriendInfo {
+                    presence: presences.get(&friend.id).map_or(Presence::Offline, |&(_, presence)| presence),
+                    nickname: friend.nickname,
+                })
+                .collect(),
+            incoming: nicknames(incoming),
+            outgoing: nicknames(outgoing),
+        }
+    }
 
-        // 获取时间信息
-        let (red_time_ms, black_time_ms) = if let Some(timer) = &room.timer {
-            (timer.red_time_ms(), timer.black_time_ms())
-        } else {
-            (0, 0)
+    /// 新成为好友时把自己的在线状态推送给对方
+    fn announce_new_friend(players: &PlayerRegistry, account_id: AccountId, friend_id: AccountId) {
+        let presences = friends::account_presences(&players.read());
+        let Some((nickname, presence)) = presences.get(&account_id).cloned() else {
+            return;
         };
+        players.send_to_account(friend_id, ServerMessage::FriendPresence { nickname, presence });
+    }
 
-        // 检查游戏是否结束
-        let game_over = room.check_game_over();
+    /// 发送好友请求
+    fn handle_add_friend(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        nickname: &str,
+    ) -> Option<ServerMessage> {
+        let (account_id, target) = match Self::friend_pair(accounts, players, player_id, nickname) {
+            Ok(pair) => pair,
+            Err(error) => return Some(*error),
+        };
+        if target.id == account_id {
+            return Some(Self::invalid_friend_request("不能添加自己为好友"));
+        }
 
-        // 广播 AI 走棋消息
-        pending.broadcast(room_id, ServerMessage::MoveMade {
-            from: ai_move.from,
-            to: ai_move.to,
-            new_state,
-            notation,
-        });
+        match accounts.request_friend(account_id, target.id) {
+            Ok(FriendRequestOutcome::Sent) => {
+                let nickname = players.nickname(player_id).unwrap_or_default();
+                players.send_to_account(target.id, ServerMessage::FriendRequestReceived { nickname });
+            }
+            Ok(FriendRequestOutcome::Accepted) => {
+                Self::announce_new_friend(players, account_id, target.id);
+            }
+            Ok(FriendRequestOutcome::AlreadyFriends) => {
+                return Some(Self::invalid_friend_request("已经是好友"));
+            }
+            Ok(FriendRequestOutcome::AlreadyRequested) => {
+                return Some(Self::invalid_friend_request("已发出好友请求，请等待对方回应"));
+            }
+            Ok(FriendRequestOutcome::LimitReached) => {
+                return Some(Self::invalid_friend_request(&format!("好友数已达上限 ({})", MAX_FRIENDS)));
+            }
+            Err(e) => return Some(Self::account_error(e)),
+        }
+        Some(Self::friend_list(accounts, players, account_id))
+    }
 
-        // 发送时间更新
-        pending.broadcast(room_id, ServerMessage::TimeUpdate {
-            red_time_ms,
-            black_time_ms,
-        });
+    /// 回应好友请求
+    fn handle_respond_friend_request(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        nickname: &str,
+        accept: bool,
+    ) -> Option<ServerMessage> {
+        let (account_id, target) = match Self::friend_pair(accounts, players, player_id, nickname) {
+            Ok(pair) => pair,
+            Err(error) => return Some(*error),
+        };
+        if accept {
+            match accounts.friend_limit_reached(account_id, target.id) {
+                Ok(false) => {}
+                Ok(true) => return Some(Self::invalid_friend_request(&format!("好友数已达上限 ({})", MAX_FRIENDS))),
+                Err(e) => return Some(Self::account_error(e)),
+            }
+        }
 
-        // 处理游戏结束
-        if let Some(result) = game_over {
-            let room = match state.rooms.get_mut(room_id) {
-                Some(r) => r,
-                None => return,
-            };
-            room.finish(result.clone());
-            pending.broadcast(room_id, ServerMessage::GameOver { result });
+        match accounts.respond_friend_request(account_id, target.id, accept) {
+            Ok(true) if accept => Self::announce_new_friend(players, account_id, target.id),
+            Ok(true) => {}
+            Ok(false) => return Some(Self::invalid_friend_request("没有该玩家的好友请求")),
+            Err(e) => return Some(Self::account_error(e)),
         }
+        Some(Self::friend_list(accounts, players, account_id))
     }
 
-    /// AI 失败，判定 AI 负（玩家胜）
-    fn ai_loses(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
-        room_id: RoomId,
-    ) {
-        let room = match state.rooms.get_mut(room_id) {
-            Some(r) => r,
-            None => return,
+    /// 删除好友
+    fn handle_remove_friend(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        nickname: &str,
+    ) -> Option<ServerMessage> {
+        let (account_id, target) = match Self::friend_pair(accounts, players, player_id, nickname) {
+            Ok(pair) => pair,
+            Err(error) => return Some(*error),
         };
 
-        if room.state != RoomState::Playing {
+        match accounts.remove_friend(account_id, target.id) {
+            Ok(true) => {
+                let nickname = players.nickname(player_id).unwrap_or_default();
+                players.send_to_account(target.id, ServerMessage::FriendRemoved { nickname });
+            }
+            Ok(false) => return Some(Self::invalid_friend_request("对方不是你的好友")),
+            Err(e) => return Some(Self::account_error(e)),
+        }
+        Some(Self::friend_list(accounts, players, account_id))
+    }
+
+    /// 推送好友在线状态的变化
+    ///
+    /// 只读取玩家登记和账号库，不需要大厅锁；好友列表在阻塞线程池中查询
+    pub async fn push_presence(players: &PlayerRegistry, accounts: &Arc<AccountStore>, presence: &mut PresenceTracker) {
+        let changes = presence.update(friends::account_presences(&players.read()));
+        if changes.is_empty() {
             return;
         }
+        let changes = accounts
+            .blocking(move |accounts| {
+                changes
+                    .into_iter()
+                    .filter_map(|(account_id, nickname, presence)| match accounts.friends(account_id) {
+                        Ok(friends) => Some((friends, nickname, presence)),
+                        Err(e) => {
+                            tracing::error!("读取好友列表失败: {}", e);
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        for (friends, nickname, presence) in changes {
+            for friend in friends {
+                let update = ServerMessage::FriendPresence { nickname: nickname.clone(), presence };
+                players.send_to_account(friend.id, update);
+            }
+        }
+    }
 
-        // 玩家是红方，AI 是黑方，所以红方胜
-        let result = GameResult::RedWin(WinReason::Resign);
-        room.finish(result.clone());
-        pending.broadcast(room_id, ServerMessage::GameOver { result });
+    fn correspondence_need_account() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::AccountRequired,
+            message: "游客不能下通信对局".to_string(),
+        }
     }
 
-    /// 处理悔棋请求
-    fn handle_request_undo(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
+    fn correspondence_not_found() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::GameNotFound,
+            message: "通信对局不存在".to_string(),
+        }
+    }
+
+    fn correspondence_error(error: CorrespondenceError) -> ServerMessage {
+        ServerMessage::Error {
+            code: error.code(),
+            message: error.message().to_string(),
+        }
+    }
+
+    /// 玩家参与的一局通信对局：(账号, 执子方, 对局)
+    fn my_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
         player_id: PlayerId,
-    ) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
+        game_id: CorrespondenceId,
+    ) -> Result<(AccountId, Side, CorrespondenceGame), Box<ServerMessage>> {
+        let account_id = players.account_id(player_id).ok_or_else(|| Box::new(Self::correspondence_need_account()))?;
+        let game = match accounts.correspondence_game(game_id) {
+            Ok(Some(game)) => game,
+            Ok(None) => return Err(Box::new(Self::correspondence_not_found())),
+            Err(e) => return Err(Box::new(Self::account_error(e))),
+        };
+        match game.side_of(account_id) {
+            Some(side) => Ok((account_id, side, game)),
+            None => Err(Box::new(Self::correspondence_not_found())),
+        }
+    }
 
-        if room.state != RoomState::Playing {
-            return Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "游戏未开始".to_string(),
-            });
+    /// `side` 一方视角的对局更新
+    fn correspondence_update(
+        game: &CorrespondenceGame,
+        side: Side,
+        rating_changes: &[RatingChange],
+    ) -> Result<ServerMessage, CorrespondenceError> {
+        Ok(ServerMessage::CorrespondenceUpdate {
+            game: game.info(side)?,
+            rating_changes: rating_changes.to_vec(),
+        })
+    }
+
+    /// 把对局变化推送给对方的在线连接，返回给 `side` 一方的更新
+    fn announce_correspondence(
+        players: &PlayerRegistry,
+        game: &CorrespondenceGame,
+        side: Side,
+        rating_changes: &[RatingChange],
+    ) -> Option<ServerMessage> {
+        let updates = Self::correspondence_update(game, side, rating_changes)
+            .and_then(|mine| Ok((mine, Self::correspondence_update(game, side.opponent(), rating_changes)?)));
+        match updates {
+            Ok((mine, theirs)) => {
+                players.send_to_account(game.account(side.opponent()), theirs);
+                Some(mine)
+            }
+            Err(e) => Some(Self::correspondence_error(e)),
         }
+    }
 
-        if room.move_history.is_empty() {
+    /// 邀请玩家下通信对局（对方可以不在线），执子方随机
+    fn handle_create_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        nickname: &str,
+        days_per_move: u32,
+        rated: bool,
+    ) -> Option<ServerMessage> {
+        let Some(account_id) = players.account_id(player_id) else {
+            return Some(Self::correspondence_need_account());
+        };
+        if !(1..=MAX_CORRESPONDENCE_DAYS).contains(&days_per_move) {
             return Some(ServerMessage::Error {
-                code: ErrorCode::UndoNotAllowed,
-                message: "没有可悔的棋".to_string(),
+                code: ErrorCode::InvalidTimeControl,
+                message: format!("每步天数需为 1-{} 天", MAX_CORRESPONDENCE_DAYS),
             });
         }
 
-        let player_side = room.get_player_side(player_id)?;
-        let is_pve = matches!(room.room_type, RoomType::PvE(_));
-        let opponent_id = room.get_opponent_id(player_id);
-
-        // PvE 模式直接悔棋
-        if is_pve {
-            let room = state.rooms.get_mut(room_id)?;
-            
-            // PvE 模式需要撤回两步：玩家一步 + AI一步
-            // 确保悔棋后轮到玩家
-            let mut undo_count = 0;
-            let target_count = if room.move_history.len() >= 2 { 2 } else { 1 };
-            
-            for _ in 0..target_count {
-                if room.undo_move().is_ok() {
-                    undo_count += 1;
-                } else {
-                    break;
-                }
+        let target = match accounts.find(nickname) {
+            Ok(Some(target)) if target.id != account_id => target,
+            Ok(Some(_)) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::ChallengeUnavailable,
+                    message: "不能邀请自己".to_string(),
+                });
             }
-            
-            if undo_count > 0 {
-                let new_state = room.game_state.clone()?;
-                return Some(ServerMessage::UndoApproved { new_state });
-            } else {
+            Ok(None) => {
                 return Some(ServerMessage::Error {
-                    code: ErrorCode::UndoNotAllowed,
-                    message: "悔棋失败".to_string(),
+                    code: ErrorCode::PlayerNotFound,
+                    message: "玩家不存在".to_string(),
                 });
             }
+            Err(e) => return Some(Self::account_error(e)),
+        };
+        for (id, whose) in [(account_id, "你"), (target.id, "对方")] {
+            match accounts.count_correspondence(id) {
+                Ok(count) if count >= MAX_CORRESPONDENCE_GAMES => {
+                    return Some(ServerMessage::Error {
+                        code: ErrorCode::CorrespondenceLimitReached,
+                        message: format!("{}的通信对局数已达上限 ({})", whose, MAX_CORRESPONDENCE_GAMES),
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Self::account_error(e)),
+            }
         }
 
-        // PvP 模式需要对方同意
-        let room = state.rooms.get_mut(room_id)?;
-        room.undo_requested_by = Some(player_side);
-        
-        // 通知对手
-        if let Some(opponent_id) = opponent_id {
-            pending.send(opponent_id, ServerMessage::UndoRequested { by: player_side });
+        let me = (account_id, players.nickname(player_id).unwrap_or_default());
+        let opponent = (target.id, target.nickname);
+        let (red, black, side) = if rand::random::<bool>() {
+            (me, opponent, Side::Red)
+        } else {
+            (opponent, me, Side::Black)
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut game = CorrespondenceGame::invite(red, black, side, days_per_move, rated, now);
+        if let Err(e) = accounts.create_correspondence(&mut game) {
+            return Some(Self::account_error(e));
         }
-
-        None
+        Self::announce_correspondence(players, &game, side, &[])
     }
 
-    /// 处理悔棋响应
-    fn handle_respond_undo(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
+    /// 回应通信对局邀请：被邀请方接受或拒绝，邀请方可撤回
+    fn handle_respond_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
         player_id: PlayerId,
+        game_id: CorrespondenceId,
         accept: bool,
     ) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
-
-        // 检查是否有悔棋请求
-        let requester_side = room.undo_requested_by?;
-        let requester_id = room.get_player_id(requester_side)?;
+        let (_, side, mut game) = match Self::my_correspondence(accounts, players, player_id, game_id) {
+            Ok(found) => found,
+            Err(error) => return Some(*error),
+        };
+        let Some(invited_by) = game.invited_by else {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::ChallengeNotFound,
+                message: "邀请已被接受".to_string(),
+            });
+        };
 
-        if accept {
-            let room = state.rooms.get_mut(room_id)?;
-            if room.undo_move().is_ok() {
-                let new_state = room.game_state.clone()?;
-                pending.broadcast(room_id, ServerMessage::UndoApproved { new_state });
+        if !accept {
+            if let Err(e) = accounts.delete_correspondence(game_id) {
+                return Some(Self::account_error(e));
             }
-        } else {
-            // 通知请求方被拒绝
-            pending.send(requester_id, ServerMessage::UndoRejected);
+            let nickname = players.nickname(player_id).unwrap_or_default();
+            let (reason, response) = if side == invited_by {
+                (format!("{} 撤回了通信对局邀请", nickname), "邀请已撤回")
+            } else {
+                (format!("{} 拒绝了通信对局邀请", nickname), "已拒绝邀请")
+            };
+            let closed = ServerMessage::CorrespondenceClosed { game_id, reason };
+            players.send_to_account(game.account(side.opponent()), closed);
+            return Some(ServerMessage::CorrespondenceClosed {
+                game_id,
+                reason: response.to_string(),
+            });
         }
 
-        let room = state.rooms.get_mut(room_id)?;
-        room.undo_requested_by = None;
-        None
+        if side == invited_by {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::ChallengeNotFound,
+                message: "等待对方接受邀请".to_string(),
+            });
+        }
+        game.accept(chrono::Utc::now().timestamp_millis());
+        if let Err(e) = accounts.save_correspondence(&game) {
+            return Some(Self::account_error(e));
+        }
+        Self::announce_correspondence(players, &game, side, &[])
     }
 
-    /// 处理认输
-    fn handle_resign(
-        state: &mut ServerState,
-        pending: &mut PendingMessages,
+    /// 列出自己的通信对局（含待接受的邀请）
+    fn handle_list_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
         player_id: PlayerId,
     ) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
+        let Some(account_id) = players.account_id(player_id) else {
+            return Some(Self::correspondence_need_account());
+        };
+        let games = match accounts.correspondence_games(account_id) {
+            Ok(games) => games,
+            Err(e) => return Some(Self::account_error(e)),
+        };
+        let games = games
+            .iter()
+            .filter_map(|game| {
+                let info = game.side_of(account_id).map(|side| game.info(side))?;
+                info.inspect_err(|e| tracing::error!("通信对局 {}: {}", game.id, e.message())).ok()
+            })
+            .collect();
+        Some(ServerMessage::CorrespondenceGames { games })
+    }
 
-        if room.state != RoomState::Playing {
-            return Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "游戏未开始".to_string(),
-            });
+    /// 获取一局通信对局的完整走法
+    fn handle_get_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        game_id: CorrespondenceId,
+    ) -> Option<ServerMessage> {
+        let (_, side, game) = match Self::my_correspondence(accounts, players, player_id, game_id) {
+            Ok(found) => found,
+            Err(error) => return Some(*error),
+        };
+        match game.info(side) {
+            Ok(info) => Some(ServerMessage::CorrespondenceDetails {
+                game: info,
+                initial_fen: game.record.initial_fen,
+                moves: game.record.moves,
+            }),
+            Err(e) => Some(Self::correspondence_error(e)),
+        }
+    }
+
+    /// 在通信对局中走一步
+    fn handle_correspondence_move(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        metrics: &ServerMetrics,
+        player_id: PlayerId,
+        game_id: CorrespondenceId,
+        from: Position,
+        to: Position,
+    ) -> Option<ServerMessage> {
+        let (_, side, mut game) = match Self::my_correspondence(accounts, players, player_id, game_id) {
+            Ok(found) => found,
+            Err(error) => return Some(*error),
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        let result = match game.play(side, Move::new(from, to), now) {
+            Ok(result) => result,
+            Err(e) => return Some(Self::correspondence_error(e)),
+        };
+        metrics.moves.inc();
+        Self::commit_correspondence(accounts, players, game, side, result, now)
+    }
+
+    /// 在通信对局中认输
+    fn handle_resign_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        game_id: CorrespondenceId,
+    ) -> Option<ServerMessage> {
+        let (_, side, mut game) = match Self::my_correspondence(accounts, players, player_id, game_id) {
+            Ok(found) => found,
+            Err(error) => return Some(*error),
+        };
+        let result = match game.resign(side) {
+            Ok(result) => result,
+            Err(e) => return Some(Self::correspondence_error(e)),
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        Self::commit_correspondence(accounts, players, game, side, Some(result), now)
+    }
+
+    /// 保存 `side` 一方操作后的对局（结束时结算并归档）并通知双方
+    fn commit_correspondence(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        game: CorrespondenceGame,
+        side: Side,
+        result: Option<GameResult>,
+        now: i64,
+    ) -> Option<ServerMessage> {
+        let saved = match &result {
+            Some(result) => accounts.finish_correspondence(&game, result, now),
+            None => accounts.save_correspondence(&game).map(|()| Vec::new()),
+        };
+        match saved {
+            Ok(rating_changes) => Self::announce_correspondence(players, &game, side, &rating_changes),
+            Err(e) => Some(Self::account_error(e)),
+        }
+    }
+
+    /// 处理超过期限的通信对局：邀请自动撤回，走子方判负
+    ///
+    /// 只读写账号库并推送消息，不需要大厅锁；数据库操作在阻塞线程池中进行
+    pub async fn check_correspondence_deadlines(players: &PlayerRegistry, accounts: &Arc<AccountStore>) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let notices = accounts.blocking(move |accounts| Self::settle_overdue_correspondence(accounts, now)).await;
+        for (account_id, msg) in notices {
+            players.send_to_account(account_id, msg);
+        }
+    }
+
+    /// 撤回过期的邀请并判负超时的对局，返回要推送给各账号的消息
+    fn settle_overdue_correspondence(accounts: &AccountStore, now: i64) -> Vec<(AccountId, ServerMessage)> {
+        let mut notices = Vec::new();
+        let overdue = match accounts.overdue_correspondence(now) {
+            Ok(overdue) => overdue,
+            Err(e) => {
+                tracing::error!("读取通信对局失败: {}", e);
+                return notices;
+            }
+        };
+
+        for mut game in overdue {
+            if game.invited_by.is_some() {
+                if let Err(e) = accounts.delete_correspondence(game.id) {
+                    tracing::error!("删除通信对局 {} 失败: {}", game.id, e);
+                    continue;
+                }
+                for account_id in [game.red_account, game.black_account] {
+                    let closed = ServerMessage::CorrespondenceClosed {
+                        game_id: game.id,
+                        reason: "通信对局邀请已过期".to_string(),
+                    };
+                    notices.push((account_id, closed));
+                }
+                continue;
+            }
+
+            let finished = game
+                .forfeit()
+                .map_err(|e| e.message().to_string())
+                .and_then(|result| {
+                    accounts.finish_correspondence(&game, &result, now).map_err(|e| e.to_string())
+                });
+            let rating_changes = match finished {
+                Ok(rating_changes) => rating_changes,
+                Err(e) => {
+                    // 无法判负的对局直接删除，避免每次检查都重试
+                    tracing::error!("通信对局 {} 超时判负失败，已删除: {}", game.id, e);
+                    let _ = accounts.delete_correspondence(game.id);
+                    continue;
+                }
+            };
+            tracing::info!("通信对局 {} 超时判负", game.id);
+            for side in [Side::Red, Side::Black] {
+                if let Ok(update) = Self::correspondence_update(&game, side, &rating_changes) {
+                    notices.push((game.account(side), update));
+                }
+            }
+        }
+        notices
+    }
+
+    /// 为配对的玩家创建房间并开局，返回等待开局消息发出的接收端
+    fn start_matched_game(state: &mut ServerState, pairing: Pairing) -> oneshot::Receiver<()> {
+        let (room_id, announced) = Self::open_game(state, pairing.red, pairing.black, |room| {
+            room.rated = pairing.rated;
+            room.time_control = pairing.time_control;
+        });
+        tracing::info!("匹配成功: 房间 {} 红方 {} 黑方 {}", room_id, pairing.red, pairing.black);
+        announced
+    }
+
+    /// 为大厅中的双方开设对局房间并开局（双方收到 MatchFound 和 GameStarted）
+    ///
+    /// 开局消息在持锁时交给房间任务，返回的接收端在释放大厅锁后等待
+    fn open_game(
+        state: &mut ServerState,
+        red: PlayerId,
+        black: PlayerId,
+        configure: impl FnOnce(&mut Room),
+    ) -> (RoomId, oneshot::Receiver<()>) {
+        let mut room = Room::new(state.rooms.generate_id(), RoomType::PvP);
+        let room_id = room.id;
+        configure(&mut room);
+        room.add_player(red, Some(Side::Red));
+        room.add_player(black, Some(Side::Black));
+        room.start_game();
+        let room = state.rooms.spawn(room, state.room_services());
+
+        for (player_id, your_side) in [(red, Side::Red), (black, Side::Black)] {
+            state.players.write().set_status(player_id, PlayerStatus::InRoom(room_id));
+            state.players.send(player_id, ServerMessage::MatchFound { room_id, your_side });
+        }
+
+        (room_id, room.post(|actor| actor.announce_game_start()))
+    }
+
+    /// 处理离开房间
+    async fn handle_leave_room(lobby: &mut impl Lobby, player_id: PlayerId) -> Option<ServerMessage> {
+        let (room_id, room) = {
+            let state = lobby.lock().await;
+            let room_id = match state.players.status(player_id)? {
+                PlayerStatus::Spectating(_) => None,
+                PlayerStatus::InRoom(room_id) | PlayerStatus::Disconnected(room_id) => Some(room_id),
+                PlayerStatus::Online => return None,
+            };
+            (room_id, room_id.and_then(|room_id| state.rooms.get(room_id)))
+        };
+        // 观战者离开房间即停止观战
+        let Some(room_id) = room_id else {
+            return Self::handle_stop_spectating(lobby, player_id).await;
+        };
+
+        // 对局中离开判负由房间任务处理
+        let closed = room?.call(move |actor| actor.leave(player_id)).await.flatten();
+        let mut state = lobby.lock().await;
+        state.players.write().set_status(player_id, PlayerStatus::Online);
+
+        // 如果房间空了，销毁房间并送走观战者
+        if let Some(spectators) = closed {
+            state.rooms.remove(room_id);
+            for spectator_id in spectators {
+                state.players.write().set_status(spectator_id, PlayerStatus::Online);
+                state.players.send(spectator_id, ServerMessage::SpectateStopped { room_id });
+            }
+        }
+
+        None
+    }
+
+    /// 处理观战
+    async fn handle_spectate_room(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        room_id: RoomId,
+    ) -> Option<ServerMessage> {
+        let room_not_found = || ServerMessage::Error {
+            code: ErrorCode::RoomNotFound,
+            message: "房间不存在".to_string(),
+        };
+        let room = {
+            let state = lobby.lock().await;
+            if !matches!(state.players.status(player_id)?, PlayerStatus::Online) {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::AlreadyInRoom,
+                    message: "已在房间中".to_string(),
+                });
+            }
+            let Some(room) = state.rooms.get(room_id) else {
+                return Some(room_not_found());
+            };
+            room
+        };
+
+        match room.call(move |actor| actor.spectate(player_id)).await {
+            Some(Ok(started)) => {
+                let mut state = lobby.lock().await;
+                state.players.write().set_status(player_id, PlayerStatus::Spectating(room_id));
+                state.matchmaker.remove(player_id);
+                Some(started)
+            }
+            Some(Err(error)) => Some(*error),
+            None => Some(room_not_found()),
+        }
+    }
+
+    /// 处理停止观战
+    async fn handle_stop_spectating(lobby: &mut impl Lobby, player_id: PlayerId) -> Option<ServerMessage> {
+        let (room_id, room) = {
+            let state = lobby.lock().await;
+            let room_id = match state.players.status(player_id)? {
+                PlayerStatus::Spectating(room_id) => room_id,
+                _ => return None,
+            };
+            (room_id, state.rooms.get(room_id))
+        };
+
+        if let Some(room) = room {
+            room.call(move |actor| actor.stop_spectating(player_id)).await;
+        }
+        lobby.lock().await.players.write().set_status(player_id, PlayerStatus::Online);
+
+        Some(ServerMessage::SpectateStopped { room_id })
+    }
+
+    /// 处理聊天消息
+    async fn handle_send_chat(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        channel: ChatChannel,
+        text: &str,
+    ) -> Option<ServerMessage> {
+        let mut state = lobby.lock().await;
+        let (status, from) = {
+            let players = state.players.read();
+            let player = players.get(player_id)?;
+            (player.status, player.nickname.clone())
+        };
+
+        let Some(text) = chat::validate_text(text) else {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::InvalidChatMessage,
+                message: format!("消息不能为空且不超过 {} 个字符", protocol::MAX_CHAT_LEN),
+            });
+        };
+
+        // 大厅频道发给大厅中的玩家；房间和观战区频道交给房间任务投递
+        let room = match (channel, status) {
+            (ChatChannel::Lobby, PlayerStatus::Online) => None,
+            (ChatChannel::Room, PlayerStatus::InRoom(room_id))
+            | (ChatChannel::Spectators, PlayerStatus::InRoom(room_id) | PlayerStatus::Spectating(room_id)) => {
+                Some(state.rooms.get(room_id)?)
+            }
+            _ => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::ChatNotAllowed,
+                    message: "不能在该频道发言".to_string(),
+                });
+            }
+        };
+
+        if !state.chat_limiter.check(player_id, Instant::now()) {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::RateLimited,
+                message: "发言过于频繁，请稍后再试".to_string(),
+            });
+        }
+
+        let message = ChatMessage {
+            channel,
+            from_id: player_id,
+            from,
+            text: state.chat_filter.apply(text),
+            sent_at: chat::now_millis(),
+        };
+
+        match room {
+            Some(room) => {
+                drop(state);
+                room.call(move |actor| actor.deliver_chat(message)).await;
+            }
+            None => {
+                let recipients: Vec<PlayerId> = {
+                    let players = state.players.read();
+                    players
+                        .lobby_players()
+                        .into_iter()
+                        .filter(|&id| !players.get(id).is_some_and(|p| p.has_muted(player_id)))
+                        .collect()
+                };
+                for recipient in recipients {
+                    state.players.send(recipient, ServerMessage::Chat(message.clone()));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// 处理屏蔽聊天
+    fn handle_set_muted(
+        state: &mut ServerState,
+        player_id: PlayerId,
+        target: PlayerId,
+        muted: bool,
+    ) -> Option<ServerMessage> {
+        if target == player_id {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::PlayerNotFound,
+                message: "不能屏蔽自己".to_string(),
+            });
+        }
+
+        let mut players = state.players.write();
+        let player = players.get_mut(player_id)?;
+        if muted {
+            player.muted.insert(target);
+        } else {
+            player.muted.remove(&target);
+        }
+
+        Some(ServerMessage::MuteUpdated { player_id: target, muted })
+    }
+
+    /// 处理房间列表
+    fn handle_list_rooms(state: &ServerState) -> Option<ServerMessage> {
+        let rooms: Vec<RoomInfo> = state.rooms.list_visible()
+            .iter()
+            .map(|r| {
+                let red = r.red_player.and_then(|id| state.player_label(id));
+                let black = r.black_player.and_then(|id| state.player_label(id));
+                r.info(red, black)
+            })
+            .collect();
+
+        Some(ServerMessage::RoomList { rooms })
+    }
+
+    /// 处理历史对局查询
+    fn handle_list_my_games(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        page: u32,
+        page_size: u32,
+        filter: GameHistoryFilter,
+    ) -> Option<ServerMessage> {
+        let Some(account_id) = players.account_id(player_id) else {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AccountRequired,
+                message: "游客没有历史对局记录".to_string(),
+            });
+        };
+
+        let page_size = page_size.clamp(1, MAX_HISTORY_PAGE_SIZE);
+        match accounts.list_games(account_id, &filter, page, page_size) {
+            Ok((games, total)) => Some(ServerMessage::MyGames { games, page, total }),
+            Err(e) => Some(Self::account_error(e)),
+        }
+    }
+
+    /// 处理历史对局棋谱获取（只能获取自己参与的对局）
+    fn handle_fetch_game(
+        accounts: &AccountStore,
+        players: &PlayerRegistry,
+        player_id: PlayerId,
+        game_id: u64,
+    ) -> Option<ServerMessage> {
+        let Some(account_id) = players.account_id(player_id) else {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::AccountRequired,
+                message: "游客没有历史对局记录".to_string(),
+            });
+        };
+
+        match accounts.fetch_game(account_id, game_id) {
+            Ok(Some(record_json)) => Some(ServerMessage::GameFetched { game_id, record_json }),
+            Ok(None) => Some(ServerMessage::Error {
+                code: ErrorCode::GameNotFound,
+                message: "对局记录不存在".to_string(),
+            }),
+            Err(e) => Some(Self::account_error(e)),
+        }
+    }
+
+    /// 注册账号玩家的参赛身份
+    fn entrant(state: &ServerState, player_id: PlayerId) -> Option<Entrant> {
+        let players = state.players.read();
+        let player = players.get(player_id)?;
+        Some(Entrant {
+            account_id: player.account_id?,
+            nickname: player.nickname.clone(),
+        })
+    }
+
+    fn tournament_not_found() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::TournamentNotFound,
+            message: "比赛不存在".to_string(),
+        }
+    }
+
+    fn tournament_account_required() -> ServerMessage {
+        ServerMessage::Error {
+            code: ErrorCode::AccountRequired,
+            message: "参加比赛需要注册账号".to_string(),
+        }
+    }
+
+    /// 取得比赛的保存顺序锁（调用时不能持有大厅锁）
+    async fn lock_tournament_saves(lobby: &mut impl Lobby) -> tokio::sync::OwnedMutexGuard<()> {
+        let saves = lobby.lock().await.tournament_saves.clone();
+        saves.lock_owned().await
+    }
+
+    /// 在阻塞线程池中保存比赛
+    async fn save_tournament(accounts: &Arc<AccountStore>, tournament: Tournament) -> Result<(), AccountError> {
+        accounts.blocking(move |accounts| accounts.save_tournament(&tournament)).await
+    }
+
+    /// 处理创建比赛（创建者为组织者，不自动报名）
+    async fn handle_create_tournament(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        name: String,
+        format: TournamentFormat,
+        time_control: TimeControl,
+        rules: RuleSet,
+    ) -> Option<ServerMessage> {
+        let _saves = Self::lock_tournament_saves(lobby).await;
+        let (accounts, tournament) = {
+            let state = lobby.lock().await;
+            let Some(organizer) = Self::entrant(&state, player_id) else {
+                return Some(Self::tournament_account_required());
+            };
+            let name = name.trim().to_string();
+            if name.is_empty() || name.chars().count() > MAX_TOURNAMENT_NAME_LEN {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::TournamentNotAllowed,
+                    message: format!("比赛名称不能为空且不超过 {} 个字符", MAX_TOURNAMENT_NAME_LEN),
+                });
+            }
+            if matches!(format, TournamentFormat::Swiss { rounds } if !(1..=MAX_SWISS_ROUNDS).contains(&rounds)) {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::TournamentNotAllowed,
+                    message: format!("瑞士制轮数须在 1 到 {} 之间", MAX_SWISS_ROUNDS),
+                });
+            }
+            if let Err(e) = time_control.validate() {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::InvalidTimeControl,
+                    message: e.to_string(),
+                });
+            }
+
+            let id = state.tournaments.next_id();
+            (state.accounts.clone(), Tournament::new(id, name, organizer, format, time_control, rules))
+        };
+
+        if let Err(e) = Self::save_tournament(&accounts, tournament.clone()).await {
+            return Some(Self::account_error(e));
+        }
+        tracing::info!("玩家 {} 创建比赛 {}: {}", player_id, tournament.id, tournament.name);
+        let details = tournament.details();
+        lobby.lock().await.tournaments.insert(tournament);
+        Some(details)
+    }
+
+    /// 修改比赛并保存，成功时返回比赛详情
+    ///
+    /// 在副本上修改，保存成功后才生效；保存期间持有保存顺序锁，不持大厅锁
+    async fn update_tournament(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        tournament_id: TournamentId,
+        update: impl FnOnce(&mut Tournament, Entrant) -> Result<(), TournamentError> + Send,
+    ) -> Option<ServerMessage> {
+        let _saves = Self::lock_tournament_saves(lobby).await;
+        let (accounts, updated) = {
+            let state = lobby.lock().await;
+            let Some(entrant) = Self::entrant(&state, player_id) else {
+                return Some(Self::tournament_account_required());
+            };
+            let Some(tournament) = state.tournaments.get(tournament_id) else {
+                return Some(Self::tournament_not_found());
+            };
+            let mut updated = tournament.clone();
+            if let Err(e) = update(&mut updated, entrant) {
+                return Some(e.into());
+            }
+            (state.accounts.clone(), updated)
+        };
+
+        if let Err(e) = Self::save_tournament(&accounts, updated.clone()).await {
+            return Some(Self::account_error(e));
+        }
+        let details = updated.details();
+        lobby.lock().await.tournaments.insert(updated);
+        Some(details)
+    }
+
+    /// 处理开赛（仅组织者），第一轮的配对推送给所有参赛者
+    async fn handle_start_tournament(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        tournament_id: TournamentId,
+    ) -> Option<ServerMessage> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let response = Self::update_tournament(lobby, player_id, tournament_id, |t, entrant| {
+            t.start(entrant.account_id, now)
+        })
+        .await?;
+        if matches!(response, ServerMessage::TournamentDetails { .. }) {
+            tracing::info!("比赛 {} 开赛", tournament_id);
+            Self::notify_entrants(&*lobby.lock().await, tournament_id, Some(player_id));
+        }
+        Some(response)
+    }
+
+    /// 向在线的参赛者推送比赛详情
+    fn notify_entrants(state: &ServerState, tournament_id: TournamentId, except: Option<PlayerId>) {
+        let Some(tournament) = state.tournaments.get(tournament_id) else {
+            return;
+        };
+        let recipients: Vec<PlayerId> = {
+            let players = state.players.read();
+            tournament
+                .entrants
+                .iter()
+                .filter_map(|e| players.find_by_account(e.account_id))
+                .filter(|&id| Some(id) != except)
+                .collect()
+        };
+        let details = tournament.details();
+        for player_id in recipients {
+            state.players.send(player_id, details.clone());
+        }
+    }
+
+    /// 推进进行中的比赛（定期调用）
+    ///
+    /// 记录结束对局的结果；双方都在大厅时开设计分房间；房间被关闭而未出结果的对局
+    /// 等双方回到大厅后重下；本轮开始超过 [`NO_SHOW_TIMEOUT`] 仍未到场的一方判负；
+    /// 一轮全部出结果后进入下一轮或结束比赛
+    pub async fn run_tournaments(lobby: &mut impl Lobby) {
+        let _saves = Self::lock_tournament_saves(lobby).await;
+        let mut guard = lobby.lock().await;
+        let state = &mut *guard;
+        let mut finished = Vec::new();
+        while let Ok(game) = state.finished_games.try_recv() {
+            finished.push(game);
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let no_show_ms = NO_SHOW_TIMEOUT.as_millis() as i64;
+
+        let mut to_start = Vec::new();
+        let mut to_save = Vec::new();
+        let mut to_notify = Vec::new();
+        for tournament in state.tournaments.running_mut() {
+            let mut changed = false;
+            for game in &finished {
+                changed |= tournament.record_result(game.room_id, (&game.result).into());
+            }
+
+            let players = state.players.read();
+            let player_ids: Vec<Option<PlayerId>> = tournament
+                .entrants
+                .iter()
+                .map(|e| players.find_by_account(e.account_id))
+                .collect();
+            let player_of = |i: usize| player_ids[i];
+            let in_lobby = |id: Option<PlayerId>| {
+                id.and_then(|id| players.get(id)).is_some_and(|p| p.status == PlayerStatus::Online)
+            };
+            let present = |id: Option<PlayerId>| {
+                id.and_then(|id| players.get(id))
+                    .is_some_and(|p| !matches!(p.status, PlayerStatus::Disconnected(_)))
+            };
+            let overdue = now - tournament.round_started_at >= no_show_ms;
+            let (tournament_id, round) = (tournament.id, tournament.current_round);
+
+            let mut pending = Vec::new();
+            for (index, game) in tournament.current_games_mut().iter_mut().enumerate() {
+                if game.result.is_some() {
+                    continue;
+                }
+                if let Some(room_id) = game.room_id {
+                    if state.rooms.get(room_id).is_some() {
+                        continue;
+                    }
+                    tracing::warn!("比赛 {} 的房间 {} 已关闭，对局将重下", tournament_id, room_id);
+                    game.room_id = None;
+                }
+                pending.push((index, game.red, game.black));
+            }
+
+            for (index, red, black) in pending {
+                let (red_id, black_id) = (player_of(red), black.and_then(player_of));
+                if in_lobby(red_id) && in_lobby(black_id) {
+                    if let (Some(red_id), Some(black_id)) = (red_id, black_id) {
+                        to_start.push((tournament_id, round, index, red_id, black_id));
+                    }
+                    continue;
+                }
+                if !overdue {
+                    continue;
+                }
+                let result = match (present(red_id), present(black_id)) {
+                    (false, false) => TournamentGameResult::DoubleForfeit,
+                    (false, true) => TournamentGameResult::RedForfeit,
+                    (true, false) => TournamentGameResult::BlackForfeit,
+                    // 双方在线但有人还在别的房间，继续等待
+                    (true, true) => continue,
+                };
+                tracing::info!("比赛 {} 第 {} 轮对局判定 {:?}", tournament_id, round, result);
+                tournament.current_games_mut()[index].result = Some(result);
+                changed = true;
+            }
+            drop(players);
+
+            if tournament.advance(now) {
+                changed = true;
+                to_notify.push(tournament_id);
+            }
+            if changed {
+                to_save.push(tournament_id);
+            }
+        }
+
+        let mut announced = Vec::new();
+        for (tournament_id, round, index, red_id, black_id) in to_start {
+            // 同一玩家可能同时有多个比赛的对局待开，开过一局后不再可用
+            let available = |id| matches!(state.players.status(id), Some(PlayerStatus::Online));
+            if !available(red_id) || !available(black_id) {
+                continue;
+            }
+            let Some(tournament) = state.tournaments.get(tournament_id) else {
+                continue;
+            };
+            let (time_control, rules) = (tournament.time_control, tournament.rules);
+            state.matchmaker.remove(red_id);
+            state.matchmaker.remove(black_id);
+            let (room_id, room_announced) = Self::open_game(state, red_id, black_id, |room| {
+                room.rated = true;
+                room.time_control = time_control;
+                room.rules = rules;
+                room.tournament = Some(tournament_id);
+            });
+            announced.push(room_announced);
+            tracing::info!("比赛 {} 第 {} 轮开局: 房间 {}", tournament_id, round, room_id);
+            if let Some(tournament) = state.tournaments.get_mut(tournament_id) {
+                tournament.current_games_mut()[index].room_id = Some(room_id);
+            }
+        }
+
+        for tournament_id in to_notify {
+            Self::notify_entrants(state, tournament_id, None);
+        }
+        let to_save: Vec<Tournament> = to_save
+            .into_iter()
+            .filter_map(|tournament_id| state.tournaments.get(tournament_id).cloned())
+            .collect();
+        let accounts = state.accounts.clone();
+        drop(guard);
+
+        // 持有保存顺序锁，保存的副本不会被更早的副本覆盖
+        accounts
+            .blocking(move |accounts| {
+                for tournament in to_save {
+                    if let Err(e) = accounts.save_tournament(&tournament) {
+                        tracing::error!("比赛 {} 保存失败: {}", tournament.id, e);
+                    }
+                }
+            })
+            .await;
+        Self::wait_announced(announced).await;
+    }
+
+    /// 处理加载棋局
+    async fn handle_load_game(
+        lobby: &mut impl Lobby,
+        player_id: PlayerId,
+        game_id: String,
+    ) -> Option<ServerMessage> {
+        let storage = {
+            let state = lobby.lock().await;
+            if let Some(error) = Self::check_not_in_room(&state, player_id) {
+                return Some(error);
+            }
+            state.storage.clone()
+        };
+
+        // 在阻塞线程池中读取棋谱，不持大厅锁
+        let loaded = tokio::task::spawn_blocking(move || storage.load_game(&game_id))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        let mut guard = lobby.lock().await;
+        let state = &mut *guard;
+        // 读取期间可能已进入房间
+        if let Some(error) = Self::check_not_in_room(state, player_id) {
+            return Some(error);
+        }
+        let record = match loaded {
+            Ok(record) => record,
+            Err(e) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::RoomNotFound,
+                    message: format!("加载失败: {}", e),
+                });
+            }
+        };
+
+        // 检查是否是保存的进行中棋局
+        let save_info = match record.save_info {
+            Some(info) => info,
+            None => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::GameAlreadyOver,
+                    message: "只能加载进行中的棋局".to_string(),
+                });
+            }
+        };
+
+        // 创建新房间用于加载的棋局
+        let room_type = if record.metadata.black_player == "AI" || record.metadata.red_player == "AI" {
+            // 从保存的难度恢复，如果没有则默认中等
+            let difficulty = record.metadata.ai_difficulty.as_ref()
+                .and_then(|d| match d.as_str() {
+                    "Easy" | "简单" => Some(protocol::Difficulty::Easy),
+                    "Medium" | "中等" => Some(protocol::Difficulty::Medium),
+                    "Hard" | "困难" => Some(protocol::Difficulty::Hard),
+                    _ => None,
+                })
+                .unwrap_or(protocol::Difficulty::Medium);
+            RoomType::PvE(difficulty)
+        } else {
+            RoomType::PvP
+        };
+
+        let mut room = Room::new(state.rooms.generate_id(), room_type);
+        let room_id = room.id;
+
+        // 设置玩家
+        let your_side = if record.metadata.red_player != "AI" {
+            room.red_player = Some(player_id);
+            if record.metadata.black_player == "AI" {
+                room.black_player = Some(protocol::AI_PLAYER_ID);
+            }
+            Side::Red
+        } else {
+            room.black_player = Some(player_id);
+            room.red_player = Some(protocol::AI_PLAYER_ID);
+            Side::Black
+        };
+
+        // 恢复游戏状态
+        // 从棋谱的初始局面开始重放走法来恢复棋盘状态
+        if room.start_game_from_fen(&record.initial_fen).is_err() {
+            return Some(ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: "棋谱初始局面无效".to_string(),
+            });
+        }
+
+        // 重放走法
+        for move_record in &record.moves {
+            if let (Some(from_pos), Some(to_pos)) = (move_record.from_position(), move_record.to_position()) {
+                let mv = Move::new(from_pos, to_pos);
+                if room.make_move(mv).is_err() {
+                    // 如果重放失败，返回错误
+                    return Some(ServerMessage::Error {
+                        code: ErrorCode::InternalError,
+                        message: "棋谱数据损坏".to_string(),
+                    });
+                }
+            }
+        }
+
+        // 保留棋谱中每步的时间数据
+        for (restored, saved) in room.move_records.iter_mut().zip(&record.moves) {
+            restored.timestamp = saved.timestamp;
+            restored.time_left_ms = saved.time_left_ms;
+        }
+
+        // 设置时间（在重放走法后设置，并重置 turn_start）
+        if let Some(timer) = &mut room.timer {
+            timer.set_times(save_info.red_time_remaining_ms, save_info.black_time_remaining_ms);
+            timer.reset_turn_start();
+        }
+
+        let response = ServerMessage::GameLoaded {
+            room_id,
+            game_state: room.game_state.clone()?,
+            your_side,
+            initial_fen: room.initial_fen.clone(),
+            moves: room.move_records.clone(),
+            red_time_ms: save_info.red_time_remaining_ms,
+            black_time_ms: save_info.black_time_remaining_ms,
+        };
+
+        // 加入房间
+        state.rooms.spawn(room, state.room_services());
+        state.players.write().set_status(player_id, PlayerStatus::InRoom(room_id));
+
+        Some(response)
+    }
+
+    /// 加载棋局前玩家需要在大厅中
+    fn check_not_in_room(state: &ServerState, player_id: PlayerId) -> Option<ServerMessage> {
+        let status = state.players.status(player_id);
+        matches!(status, Some(PlayerStatus::InRoom(_) | PlayerStatus::Spectating(_))).then(|| ServerMessage::Error {
+            code: ErrorCode::AlreadyInRoom,
+            message: "请先离开当前房间".to_string(),
+        })
+    }
+
+    /// 处理玩家断线
+    pub async fn handle_disconnect(lobby: &mut impl Lobby, player_id: PlayerId) {
+        let disconnected = {
+            let mut state = lobby.lock().await;
+            // 先移除连接：等待房间期间玩家可能已重连并登记了新连接
+            state.players.detach(player_id);
+            // 标记玩家断线
+            let disconnected_from = state.players.write().disconnect(player_id);
+            disconnected_from.map(|room_id| {
+                // 设置断线超时，会话令牌同时到期
+                let reconnect_timeout = state.config.reconnect_timeout();
+                state.disconnect_timeouts.insert(player_id, Instant::now() + reconnect_timeout);
+                state.sessions.suspend(player_id, reconnect_timeout);
+                (state.rooms.get(room_id), reconnect_timeout.as_secs() as u32)
+            })
+        };
+
+        match disconnected {
+            // 通知对手，PvE 模式自动暂停
+            Some((room, timeout_secs)) => {
+                if let Some(room) = room {
+                    room.call(move |actor| actor.player_disconnected(player_id, timeout_secs)).await;
+                }
+            }
+            // 不在房间中无需保留，视为注销
+            None => Self::remove_player(lobby, player_id).await,
+        }
+    }
+
+    /// 管理员请出玩家：按注销处理（对局中判负），连接在收到通知后关闭
+    ///
+    /// 玩家不存在时返回 false
+    pub async fn kick_player(lobby: &mut impl Lobby, player_id: PlayerId, reason: String) -> bool {
+        {
+            let state = lobby.lock().await;
+            if !state.players.read().exists(player_id) {
+                return false;
+            }
+            state.players.send(player_id, ServerMessage::Kicked { reason });
+        }
+        Self::handle_leave_room(lobby, player_id).await;
+        Self::remove_player(lobby, player_id).await;
+        lobby.lock().await.players.detach(player_id);
+        true
+    }
+
+    /// 管理员关闭房间：对局作废，玩家与观战者回到大厅
+    ///
+    /// 房间不存在时返回 false
+    pub async fn close_room(lobby: &mut impl Lobby, room_id: RoomId, reason: String) -> bool {
+        let Some(room) = lobby.lock().await.rooms.remove(room_id) else {
+            return false;
+        };
+        let members = room.call(|actor| actor.close()).await.unwrap_or_default();
+        let state = lobby.lock().await;
+        for player_id in members {
+            state.players.write().set_status(player_id, PlayerStatus::Online);
+            let closed = ServerMessage::RoomClosed { room_id, reason: reason.clone() };
+            state.players.send(player_id, closed);
+        }
+        true
+    }
+
+    /// 检查断线超时：超时的玩家按离开房间处理（对局中判负），然后移除
+    pub async fn check_disconnect_timeouts(lobby: &mut impl Lobby) {
+        let timed_out: Vec<PlayerId> = {
+            let mut state = lobby.lock().await;
+            let now = Instant::now();
+            let timed_out: Vec<PlayerId> = state
+                .disconnect_timeouts
+                .iter()
+                .filter(|&(_, &timeout)| now >= timeout)
+                .map(|(&player_id, _)| player_id)
+                .collect();
+            for player_id in &timed_out {
+                state.metrics.disconnect_timeouts.inc();
+                state.disconnect_timeouts.remove(player_id);
+            }
+            timed_out
+        };
+
+        for player_id in timed_out {
+            // 释放大厅锁期间可能已经重连
+            let status = lobby.lock().await.players.status(player_id);
+            if !matches!(status, Some(PlayerStatus::Disconnected(_))) {
+                continue;
+            }
+            Self::handle_leave_room(lobby, player_id).await;
+            Self::remove_player(lobby, player_id).await;
+        }
+        lobby.lock().await.sessions.purge_expired();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room_actor::CLOCK_SYNC_INTERVAL;
+    use protocol::{
+        Difficulty, DrawReason, GameRecord, GameResult, Position, RoomState, WinReason, INITIAL_TIME_MS,
+    };
+
+    /// 使用内存账号库的服务器状态
+    fn test_state() -> ServerState {
+        let accounts = AccountStore::open_in_memory().unwrap();
+        ServerState::with_accounts(ServerConfig::default(), accounts).unwrap()
+    }
+
+    /// 以游客身份登录
+    async fn guest(state: &mut ServerState, nickname: &str) -> PlayerId {
+        match MessageHandler::handle_login(state, nickname.to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            other => panic!("Login failed: {:?}", other),
+        }
+    }
+
+    /// 公开房间、默认规则的建房设置
+    fn settings(rated: bool, time_control: TimeControl) -> RoomSettings {
+        RoomSettings {
+            rated,
+            time_control,
+            ..RoomSettings::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let mut state = test_state();
+        
+        let result = MessageHandler::handle_login(&mut state, "玩家1".to_string(), None).await;
+        assert!(matches!(result, Some(ServerMessage::LoginSuccess { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_login_duplicate_nickname() {
+        let mut state = test_state();
+        
+        let _ = MessageHandler::handle_login(&mut state, "玩家1".to_string(), None).await;
+        let result = MessageHandler::handle_login(&mut state, "玩家1".to_string(), None).await;
+        
+        // 重复昵称应该失败
+        assert!(matches!(result, Some(ServerMessage::Error { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_register_and_login() {
+        let mut state = test_state();
+
+        let register = ClientMessage::Register {
+            nickname: "会员".to_string(),
+            password: "secret123".to_string(),
+        };
+        let player_id = match MessageHandler::handle(&mut state, 0, register).await {
+            Some(ServerMessage::LoginSuccess { player_id, guest, .. }) => {
+                assert!(!guest);
+                player_id
+            }
+            other => panic!("Register failed: {:?}", other),
+        };
+
+        // 已登录时不能重复登录
+        let again = ClientMessage::Login {
+            nickname: "其他".to_string(),
+            password: None,
+        };
+        assert!(matches!(
+            MessageHandler::handle(&mut state, player_id, again).await,
+            Some(ServerMessage::Error { code: ErrorCode::AlreadyLoggedIn, .. })
+        ));
+
+        // 下线后用密码重新登录
+        state.players.write().remove(player_id);
+        let result = MessageHandler::handle_login(&mut state, "会员".to_string(), Some("wrong-pass".to_string())).await;
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::InvalidCredentials, .. })));
+        let result = MessageHandler::handle_login(&mut state, "会员".to_string(), Some("secret123".to_string())).await;
+        assert!(matches!(result, Some(ServerMessage::LoginSuccess { guest: false, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_login_entry_attaches_connection() {
+        let state = RwLock::new(test_state());
+        state.read().await.accounts.register("会员", "secret123").unwrap();
+        let login = |password: &str| ClientMessage::Login {
+            nickname: "会员".to_string(),
+            password: Some(password.to_string()),
+        };
+
+        let (tx, _rx) = mpsc::channel(16);
+        let result = MessageHandler::login(&state, login("wrong-pass"), tx.clone()).await;
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::InvalidCredentials, .. })));
+        let player_id = match MessageHandler::login(&state, login("secret123"), tx).await {
+            Some(ServerMessage::LoginSuccess { player_id, guest: false, .. }) => player_id,
+            other => panic!("Login failed: {:?}", other),
+        };
+        assert!(state.read().await.players.is_connected(player_id));
+
+        // 注册同样经由入口登记连接
+        let (tx, _rx) = mpsc::channel(16);
+        let register = ClientMessage::Register { nickname: "新人".to_string(), password: "secret123".to_string() };
+        let player_id = match MessageHandler::login(&state, register, tx).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            other => panic!("Register failed: {:?}", other),
+        };
+        assert!(state.read().await.players.is_connected(player_id));
+    }
+
+    #[tokio::test]
+    async fn test_registered_nickname_reserved() {
+        let mut state = test_state();
+        state.accounts.register("会员", "secret123").unwrap();
+
+        let result = MessageHandler::handle_login(&mut state, "会员".to_string(), None).await;
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::NicknameReserved, .. })));
+
+        // 游客不能修改密码
+        let guest_id = match MessageHandler::handle_login(&mut state, "游客".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, guest: true, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+        let change = ClientMessage::ChangePassword {
+            old_password: "secret123".to_string(),
+            new_password: "newsecret".to_string(),
+        };
+        assert!(matches!(
+            MessageHandler::handle(&mut state, guest_id, change).await,
+            Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_register_nickname_of_online_guest() {
+        let mut state = test_state();
+        guest(&mut state, "甲").await;
+
+        // 昵称被在线游客占用时不创建账号
+        let register = ClientMessage::Register { nickname: "甲".to_string(), password: "secret123".to_string() };
+        let response = MessageHandler::handle(&mut state, 0, register).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::NicknameOccupied, .. })));
+        assert!(!state.accounts.is_registered("甲").unwrap());
+
+        // 注册中的昵称游客不能抢先登录
+        state.registering.insert("乙".to_string());
+        let result = MessageHandler::handle_login(&mut state, "乙".to_string(), None).await;
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::NicknameReserved, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_guests_disabled() {
+        let config = ServerConfig {
+            allow_guests: false,
+            ..Default::default()
+        };
+        let mut state =
+            ServerState::with_accounts(config, AccountStore::open_in_memory().unwrap()).unwrap();
+
+        let result = MessageHandler::handle_login(&mut state, "游客".to_string(), None).await;
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
+    }
+
+    /// 注册并登录账号，返回玩家 ID
+    async fn register(state: &mut ServerState, nickname: &str) -> PlayerId {
+        let msg = ClientMessage::Register {
+            nickname: nickname.to_string(),
+            password: "secret123".to_string(),
+        };
+        match MessageHandler::handle(state, 0, msg).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            other => panic!("Register failed: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rated_game_updates_ratings() {
+        let mut state = test_state();
+        let (red_tx, mut red_rx) = mpsc::channel(16);
+
+        let red_id = register(&mut state, "红方").await;
+        let black_id = register(&mut state, "黑方").await;
+        state.players.attach(red_id, red_tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(true, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            other => panic!("Create room failed: {:?}", other),
+        };
+
+        // 游客不能加入计分房间
+        let guest_id = match MessageHandler::handle_login(&mut state, "游客".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+        let response = MessageHandler::handle(&mut state, guest_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
+
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let info = state.rooms.get(room_id).unwrap().summary().info(state.player_label(red_id), state.player_label(black_id));
+        assert!(info.rated);
+        assert_eq!(info.red_rating.map(|r| r.rating), Some(1500));
+
+        MessageHandler::handle(&mut state, black_id, ClientMessage::Resign).await;
+
+        let mut changes = None;
+        while let Ok(msg) = red_rx.try_recv() {
+            if let ServerMessage::Sequenced(event) = msg {
+                if let ServerMessage::GameOver { rating_changes, .. } = event.message {
+                    changes = Some(rating_changes);
+                }
+            }
+        }
+        let changes = changes.expect("红方应收到 GameOver");
+        assert_eq!(changes.len(), 2);
+        let red_change = changes.iter().find(|c| c.side == Side::Red).unwrap();
+        assert!(red_change.after.rating > red_change.before.rating);
+        assert!(red_change.after.provisional);
+
+        // 等级分已持久化
+        assert_eq!(state.player_rating(red_id), Some(red_change.after));
+    }
+
+    #[tokio::test]
+    async fn test_finished_game_archived() {
+        let mut state = test_state();
+        let red_id = register(&mut state, "红方").await;
+        let black_id = register(&mut state, "黑方").await;
+        let outsider_id = register(&mut state, "路人").await;
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::minutes(15).with_increment(10))) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            other => panic!("Create room failed: {:?}", other),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let mv = ClientMessage::MakeMove { from: Position::new_unchecked(7, 2), to: Position::new_unchecked(4, 2) };
+        MessageHandler::handle(&mut state, red_id, mv).await;
+        MessageHandler::handle(&mut state, black_id, ClientMessage::Resign).await;
+
+        let list = ClientMessage::ListMyGames { page: 0, page_size: 10, filter: GameHistoryFilter::default() };
+        let game = match MessageHandler::handle(&mut state, black_id, list).await {
+            Some(ServerMessage::MyGames { games, total: 1, .. }) => games[0].clone(),
+            other => panic!("ListMyGames failed: {:?}", other),
+        };
+        assert_eq!(game.your_side, Side::Black);
+        assert_eq!(game.result, GameResult::RedWin(WinReason::Resign));
+        assert_eq!(game.time_control, "15+10");
+        assert_eq!(game.move_count, 1);
+
+        let fetch = ClientMessage::FetchGame { game_id: game.game_id };
+        let record = match MessageHandler::handle(&mut state, red_id, fetch.clone()).await {
+            Some(ServerMessage::GameFetched { record_json, .. }) => GameRecord::from_json(&record_json).unwrap(),
+            other => panic!("FetchGame failed: {:?}", other),
+        };
+        assert_eq!(record.metadata.result, Some(GameResult::RedWin(WinReason::Resign)));
+        assert_eq!(record.metadata.black_player, "黑方");
+        assert!(record.moves[0].time_left_ms.is_some());
+        assert!(record.save_info.is_some());
+
+        // 只能获取自己参与的对局
+        let response = MessageHandler::handle(&mut state, outsider_id, fetch).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::GameNotFound, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_private_room_settings() {
+        let mut state = test_state();
+        let red_id = register(&mut state, "红方").await;
+        let black_id = register(&mut state, "黑方").await;
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b 0 1";
+
+        // 计分对局不能自定义局面
+        let mut custom = RoomSettings {
+            rated: true,
+            rules: RuleSet::tournament(),
+            custom_fen: Some(fen.to_string()),
+            access: RoomAccess::Invite,
+            ..RoomSettings::default()
+        };
+        let result = MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), custom.clone());
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::InvalidRoomSettings, .. })));
+
+        custom.rated = false;
+        let (room_id, code) = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), custom) {
+            Some(ServerMessage::RoomCreated { room_id, invite_code: Some(code), .. }) => (room_id, code),
+            other => panic!("Create room failed: {:?}", other),
+        };
+
+        // 私人房间不在大厅列表中，没有邀请码不能加入
+        assert!(matches!(
+            MessageHandler::handle(&mut state, black_id, ClientMessage::ListRooms).await,
+            Some(ServerMessage::RoomList { rooms }) if rooms.is_empty()
+        ));
+        let response = MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::RoomAccessDenied, .. })));
+        let join = ClientMessage::JoinByInvite { code: code.to_lowercase() };
+        let response = MessageHandler::handle(&mut state, black_id, join).await;
+        assert!(matches!(response, Some(ServerMessage::RoomJoined { side: Side::Black, .. })));
+
+        // 设置体现在房间信息和棋谱中，对局从自定义局面开始
+        let room = state.rooms.get(room_id).unwrap();
+        let info = room.summary().info(None, None);
+        assert!(info.private);
+        assert_eq!(info.rules, RuleSet::tournament());
+        assert_eq!(info.custom_fen.as_deref(), Some(fen));
+        let record = room.with_room(|room| room.generate_game_record("红方", "黑方")).await.flatten().unwrap();
+        assert_eq!(record.initial_fen, fen);
+        assert_eq!(record.metadata.rules, Some(RuleSet::tournament()));
+        assert_eq!(record.metadata.rated, Some(false));
+        let turn = room.with_room(|room| room.game_state.as_ref().map(|board| board.current_turn)).await.flatten();
+        assert_eq!(turn, Some(Side::Black));
+    }
+
+    #[tokio::test]
+    async fn test_guest_cannot_create_rated_room() {
+        let mut state = test_state();
+        let player_id = match MessageHandler::handle_login(&mut state, "游客".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+
+        let result = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(true, TimeControl::default()));
+        assert!(matches!(result, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_matchmaking_starts_game() {
+        let mut state = test_state();
+        let (tx1, mut rx1) = mpsc::channel(16);
+        let (tx2, mut rx2) = mpsc::channel(16);
+
+        let player1 = register(&mut state, "棋手甲").await;
+        let player2 = register(&mut state, "棋手乙").await;
+        state.players.attach(player1, tx1);
+        state.players.attach(player2, tx2);
+
+        for player_id in [player1, player2] {
+            let msg = ClientMessage::EnterQueue {
+                time_control: TimeControl::default(),
+                rated: true,
+            };
+            let response = MessageHandler::handle(&mut state, player_id, msg).await;
+            assert!(matches!(response, Some(ServerMessage::QueueStatus { .. })));
+        }
+
+        MessageHandler::run_matchmaking(&mut state).await;
+        assert!(state.matchmaker.is_empty());
+
+        let mut sides = Vec::new();
+        for rx in [&mut rx1, &mut rx2] {
+            let mut matched = None;
+            let mut started = false;
+            while let Ok(msg) = rx.try_recv() {
+                match msg {
+                    ServerMessage::MatchFound { room_id, your_side } => matched = Some((room_id, your_side)),
+                    ServerMessage::Sequenced(event) => {
+                        started |= matches!(event.message, ServerMessage::GameStarted { .. });
+                    }
+                    _ => {}
+                }
+            }
+            let (room_id, side) = matched.expect("应收到 MatchFound");
+            assert!(started, "应收到 GameStarted");
+            let room = state.rooms.get(room_id).unwrap().summary();
+            assert!(room.rated);
+            assert_eq!(room.state, RoomState::Playing);
+            sides.push(side);
         }
+        assert_ne!(sides[0], sides[1]);
+    }
 
-        let player_side = room.get_player_side(player_id)?;
-        let result = match player_side {
-            Side::Red => GameResult::BlackWin(WinReason::Resign),
-            Side::Black => GameResult::RedWin(WinReason::Resign),
+    #[tokio::test]
+    async fn test_leave_queue() {
+        let mut state = test_state();
+        let player_id = match MessageHandler::handle_login(&mut state, "游客".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
         };
 
-        let room = state.rooms.get_mut(room_id)?;
-        room.finish(result.clone());
-        pending.broadcast(room_id, ServerMessage::GameOver { result });
+        // 游客只能进入休闲队列
+        let rated = ClientMessage::EnterQueue {
+            time_control: TimeControl::default(),
+            rated: true,
+        };
+        let response = MessageHandler::handle(&mut state, player_id, rated).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
 
-        None
+        let casual = ClientMessage::EnterQueue {
+            time_control: TimeControl::default(),
+            rated: false,
+        };
+        MessageHandler::handle(&mut state, player_id, casual).await;
+        assert!(state.matchmaker.contains(player_id));
+
+        let response = MessageHandler::handle(&mut state, player_id, ClientMessage::LeaveQueue).await;
+        assert!(matches!(response, Some(ServerMessage::QueueLeft)));
+        assert!(!state.matchmaker.contains(player_id));
     }
 
-    /// 处理暂停
-    fn handle_pause(state: &mut ServerState, player_id: PlayerId) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get_mut(room_id)?;
+    #[tokio::test]
+    async fn test_tournament_flow() {
+        let mut state = test_state();
+        let organizer = register(&mut state, "组织者").await;
+        let player = register(&mut state, "选手").await;
+        let (tx, mut rx) = mpsc::channel(32);
+        state.players.attach(player, tx);
+
+        let create = ClientMessage::CreateTournament {
+            name: "周赛".to_string(),
+            format: TournamentFormat::RoundRobin,
+            time_control: TimeControl::default(),
+            rules: RuleSet::tournament(),
+        };
+        let tournament_id = match MessageHandler::handle(&mut state, organizer, create).await {
+            Some(ServerMessage::TournamentDetails { tournament, .. }) => tournament.id,
+            other => panic!("Create tournament failed: {:?}", other),
+        };
 
-        if room.pause() {
-            Some(ServerMessage::GamePaused)
-        } else {
-            Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "无法暂停".to_string(),
-            })
+        let guest = MessageHandler::handle_login(&mut state, "游客".to_string(), None).await;
+        let Some(ServerMessage::LoginSuccess { player_id: guest, .. }) = guest else {
+            panic!("Login failed");
+        };
+        let join = ClientMessage::JoinTournament { tournament_id };
+        let response = MessageHandler::handle(&mut state, guest, join.clone()).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
+        for player_id in [organizer, player] {
+            let response = MessageHandler::handle(&mut state, player_id, join.clone()).await;
+            assert!(matches!(response, Some(ServerMessage::TournamentDetails { .. })));
         }
-    }
 
-    /// 处理继续
-    fn handle_resume(state: &mut ServerState, player_id: PlayerId) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get_mut(room_id)?;
+        // 只有组织者可以开赛
+        let start = ClientMessage::StartTournament { tournament_id };
+        let response = MessageHandler::handle(&mut state, player, start.clone()).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::TournamentNotAllowed, .. })));
+        let response = MessageHandler::handle(&mut state, organizer, start).await;
+        assert!(matches!(response, Some(ServerMessage::TournamentDetails { .. })));
+        let response = MessageHandler::handle(&mut state, guest, join).await;
+        assert!(matches!(response, Some(ServerMessage::Error { .. })));
+
+        // 双方都在大厅，开设计分房间
+        MessageHandler::run_tournaments(&mut state).await;
+        let Some(PlayerStatus::InRoom(room_id)) = state.players.status(player) else {
+            panic!("比赛对局应已开局");
+        };
+        assert!(state.rooms.get(room_id).unwrap().summary().rated);
 
-        if room.resume() {
-            Some(ServerMessage::GameResumed)
-        } else {
-            Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "无法继续".to_string(),
-            })
-        }
-    }
+        // 比赛规则不允许悔棋
+        let response = MessageHandler::handle(&mut state, player, ClientMessage::RequestUndo).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::UndoNotAllowed, .. })));
 
-    /// 处理保存棋局
-    fn handle_save_game(state: &mut ServerState, player_id: PlayerId) -> Option<ServerMessage> {
-        let room_id = state.rooms.find_player_room(player_id)?;
-        let room = state.rooms.get(room_id)?;
+        MessageHandler::handle(&mut state, organizer, ClientMessage::Resign).await;
+        MessageHandler::run_tournaments(&mut state).await;
 
-        // 检查游戏状态
-        if room.state != RoomState::Playing && room.state != RoomState::Paused {
-            return Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "只能保存进行中的棋局".to_string(),
-            });
+        let tournament = state.tournaments.get(tournament_id).unwrap();
+        assert_eq!(tournament.status, protocol::TournamentStatus::Finished);
+        let standings = tournament.standings();
+        assert_eq!(standings[0].nickname, "选手");
+        assert_eq!(standings[0].score, 1.0);
+
+        // 参赛者收到了开赛和结束的比赛详情
+        let mut details = 0;
+        while let Ok(msg) = rx.try_recv() {
+            details += matches!(msg, ServerMessage::TournamentDetails { .. }) as u32;
         }
+        assert_eq!(details, 2);
 
-        // 获取玩家名称
-        let red_name = if let Some(red_id) = room.red_player {
-            if red_id == protocol::AI_PLAYER_ID {
-                "AI".to_string()
-            } else {
-                state.players.get_nickname(red_id).unwrap_or("未知").to_string()
-            }
-        } else {
-            "空".to_string()
-        };
+        // 比赛已持久化
+        let reloaded = TournamentManager::load(&state.accounts, 0).unwrap();
+        assert_eq!(reloaded.get(tournament_id).unwrap().status, protocol::TournamentStatus::Finished);
+    }
 
-        let black_name = if let Some(black_id) = room.black_player {
-            if black_id == protocol::AI_PLAYER_ID {
-                "AI".to_string()
-            } else {
-                state.players.get_nickname(black_id).unwrap_or("未知").to_string()
-            }
-        } else {
-            "空".to_string()
-        };
-
-        // 生成棋谱记录
-        if let Some(mut record) = room.generate_game_record(&red_name, &black_name) {
-            let (red_time, black_time) = room.get_time_state();
-            
-            // 保存 AI 难度
-            if let RoomType::PvE(difficulty) = room.room_type {
-                let difficulty_str = match difficulty {
-                    protocol::Difficulty::Easy => "Easy",
-                    protocol::Difficulty::Medium => "Medium",
-                    protocol::Difficulty::Hard => "Hard",
-                    protocol::Difficulty::Custom { depth, time_limit_ms } => {
-                        // 保存自定义难度信息
-                        record.set_ai_difficulty(&format!("Custom(depth={},time={}ms)", depth, time_limit_ms));
-                        "Custom"
-                    }
-                };
-                if !matches!(difficulty, protocol::Difficulty::Custom { .. }) {
-                    record.set_ai_difficulty(difficulty_str);
-                }
-            }
-            
-            // 保存到文件
-            match state.storage.save_game(
-                &red_name,
-                &black_name,
-                &mut record,
-                room.game_state.as_ref()?,
-                red_time,
-                black_time,
-            ) {
-                Ok(game_id) => Some(ServerMessage::GameSaved { game_id }),
-                Err(e) => Some(ServerMessage::Error {
-                    code: ErrorCode::InternalError,
-                    message: format!("保存失败: {}", e),
-                }),
+    #[tokio::test]
+    async fn test_bot_accepts_challenges_within_limit() {
+        let mut state = test_state();
+        let settings = crate::bots::BotSettings { max_games: 1, accept_challenges: true };
+        let (_, token) = state.accounts.create_bot("机器人", settings).unwrap();
+
+        // 同一机器人可建立多个连接
+        let mut bot_sessions = Vec::new();
+        for _ in 0..2 {
+            let login = ClientMessage::BotLogin { token: token.clone() };
+            match MessageHandler::handle(&mut state, 0, login).await {
+                Some(ServerMessage::LoginSuccess { player_id, guest: false, .. }) => bot_sessions.push(player_id),
+                other => panic!("Bot login failed: {:?}", other),
             }
-        } else {
-            Some(ServerMessage::Error {
-                code: ErrorCode::GameNotStarted,
-                message: "无法生成棋谱".to_string(),
-            })
         }
+        let bad_login = ClientMessage::BotLogin { token: "1.00".to_string() };
+        let response = MessageHandler::handle(&mut state, 0, bad_login).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::InvalidCredentials, .. })));
+
+        // 机器人默认不下计分对局
+        let enqueue = ClientMessage::EnterQueue { time_control: TimeControl::default(), rated: true };
+        let response = MessageHandler::handle(&mut state, bot_sessions[0], enqueue).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::BotNotAllowed, .. })));
+
+        let human = register(&mut state, "棋手").await;
+        let challenge = |rated| ClientMessage::Challenge {
+            nickname: "机器人".to_string(),
+            time_control: TimeControl::default(),
+            rated,
+            rules: RuleSet::default(),
+        };
+        let response = MessageHandler::handle(&mut state, human, challenge(true)).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::BotNotAllowed, .. })));
+        assert!(MessageHandler::handle(&mut state, human, challenge(false)).await.is_none());
+        let Some(PlayerStatus::InRoom(room_id)) = state.players.status(human) else {
+            panic!("挑战应立即开局");
+        };
+        let summary = state.rooms.get(room_id).unwrap().summary();
+        assert!(!summary.rated);
+        let info = summary.info(
+            summary.red_player.and_then(|id| state.player_label(id)),
+            summary.black_player.and_then(|id| state.player_label(id)),
+        );
+        assert!(info.red_bot != info.black_bot);
+
+        // 另一个连接仍空闲，但机器人已达对局上限
+        let other = register(&mut state, "另一位").await;
+        let response = MessageHandler::handle(&mut state, other, challenge(false)).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::BotBusy, .. })));
+
+        // 达到上限的机器人也不能发起挑战
+        let to_human = |nickname: &str| ClientMessage::Challenge {
+            nickname: nickname.to_string(),
+            time_control: TimeControl::default(),
+            rated: false,
+            rules: RuleSet::default(),
+        };
+        let idle_bot = *bot_sessions
+            .iter()
+            .find(|&&id| state.players.status(id) == Some(PlayerStatus::Online))
+            .unwrap();
+        let response = MessageHandler::handle(&mut state, idle_bot, to_human("另一位")).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::BotBusy, .. })));
+
+        // 机器人不能挑战自己的另一个连接
+        let response = MessageHandler::handle(&mut state, idle_bot, to_human("机器人")).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::ChallengeUnavailable, .. })));
+
+        // 普通玩家不会自动接受挑战，需等待对方回应
+        let third = register(&mut state, "第三位").await;
+        let response = MessageHandler::handle(&mut state, other, to_human("第三位")).await;
+        assert!(matches!(response, Some(ServerMessage::ChallengeSent { .. })));
+        assert_eq!(state.players.status(third), Some(PlayerStatus::Online));
     }
 
-    /// 处理加载棋局
-    fn handle_load_game(
-        state: &mut ServerState,
-        _pending: &mut PendingMessages,
-        player_id: PlayerId,
-        game_id: String,
-    ) -> Option<ServerMessage> {
-        // 检查玩家是否在房间中
-        if let Some(_room_id) = state.rooms.find_player_room(player_id) {
-            return Some(ServerMessage::Error {
-                code: ErrorCode::AlreadyInRoom,
-                message: "请先离开当前房间".to_string(),
-            });
-        }
-
-        // 加载棋谱
-        let record = match state.storage.load_game(&game_id) {
-            Ok(record) => record,
-            Err(e) => {
-                return Some(ServerMessage::Error {
-                    code: ErrorCode::RoomNotFound,
-                    message: format!("加载失败: {}", e),
-                });
-            }
+    #[tokio::test]
+    async fn test_challenge_between_players() {
+        let mut state = test_state();
+        let red = register(&mut state, "甲").await;
+        let black = register(&mut state, "乙").await;
+        let third = register(&mut state, "丙").await;
+        let (tx, mut rx) = mpsc::channel(32);
+        state.players.attach(black, tx);
+        let (tx, mut challenger_rx) = mpsc::channel(32);
+        state.players.attach(red, tx);
+
+        let challenge = |nickname: &str| ClientMessage::Challenge {
+            nickname: nickname.to_string(),
+            time_control: TimeControl::default(),
+            rated: true,
+            rules: RuleSet { allow_undo: false, ..RuleSet::default() },
+        };
+        let Some(ServerMessage::ChallengeSent { challenge: sent }) =
+            MessageHandler::handle(&mut state, red, challenge("乙")).await
+        else {
+            panic!("挑战应已发出");
         };
+        assert_eq!((sent.challenger.as_str(), sent.challenged.as_str()), ("甲", "乙"));
+        let response = MessageHandler::handle(&mut state, red, challenge("乙")).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::ChallengeUnavailable, .. })));
+        let Ok(ServerMessage::ChallengeReceived { challenge: received }) = rx.try_recv() else {
+            panic!("对方应收到挑战");
+        };
+        assert_eq!(received, sent);
+
+        // 拒绝后通知发起方，挑战不能再回应
+        let decline = ClientMessage::RespondChallenge { challenge_id: sent.id, accept: false };
+        assert!(MessageHandler::handle(&mut state, black, decline.clone()).await.is_none());
+        assert!(matches!(challenger_rx.try_recv(), Ok(ServerMessage::ChallengeClosed { .. })));
+        let response = MessageHandler::handle(&mut state, black, decline).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::ChallengeNotFound, .. })));
+
+        // 第三方的挑战在双方开局后撤回
+        let Some(ServerMessage::ChallengeSent { challenge: pending }) =
+            MessageHandler::handle(&mut state, third, challenge("乙")).await
+        else {
+            panic!("挑战应已发出");
+        };
+        let Some(ServerMessage::ChallengeSent { challenge: sent }) =
+            MessageHandler::handle(&mut state, red, challenge("乙")).await
+        else {
+            panic!("挑战应已发出");
+        };
+        let accept = ClientMessage::RespondChallenge { challenge_id: sent.id, accept: true };
+        assert!(MessageHandler::handle(&mut state, black, accept).await.is_none());
+        assert!(state.challenges.get(pending.id).is_none());
 
-        // 检查是否是保存的进行中棋局
-        let save_info = match record.save_info {
-            Some(info) => info,
-            None => {
-                return Some(ServerMessage::Error {
-                    code: ErrorCode::GameAlreadyOver,
-                    message: "只能加载进行中的棋局".to_string(),
-                });
-            }
+        let Some(PlayerStatus::InRoom(room_id)) = state.players.status(red) else {
+            panic!("接受挑战应开局");
         };
+        assert_eq!(state.players.status(black), Some(PlayerStatus::InRoom(room_id)));
+        let room = state.rooms.get(room_id).unwrap();
+        let (rated, allow_undo) = room.with_room(|room| (room.rated, room.rules.allow_undo)).await.unwrap();
+        assert!(rated && !allow_undo);
+    }
 
-        // 创建新房间用于加载的棋局
-        let room_type = if record.metadata.black_player == "AI" || record.metadata.red_player == "AI" {
-            // 从保存的难度恢复，如果没有则默认中等
-            let difficulty = record.metadata.ai_difficulty.as_ref()
-                .and_then(|d| match d.as_str() {
-                    "Easy" | "简单" => Some(protocol::Difficulty::Easy),
-                    "Medium" | "中等" => Some(protocol::Difficulty::Medium),
-                    "Hard" | "困难" => Some(protocol::Difficulty::Hard),
-                    _ => None,
-                })
-                .unwrap_or(protocol::Difficulty::Medium);
-            RoomType::PvE(difficulty)
-        } else {
-            RoomType::PvP
+    #[tokio::test]
+    async fn test_friends_and_presence() {
+        let mut state = test_state();
+        let a = register(&mut state, "甲").await;
+        let b = register(&mut state, "乙").await;
+        let (tx, mut rx) = mpsc::channel(32);
+        state.players.attach(b, tx);
+        let mut presence = PresenceTracker::new();
+        MessageHandler::push_presence(&state.players, &state.accounts, &mut presence).await;
+
+        let add = |nickname: &str| ClientMessage::AddFriend { nickname: nickname.to_string() };
+        let response = MessageHandler::handle(&mut state, a, add("乙")).await;
+        assert!(matches!(response, Some(ServerMessage::FriendList { ref outgoing, .. }) if outgoing == &["乙"]));
+        assert!(matches!(rx.try_recv(), Ok(ServerMessage::FriendRequestReceived { ref nickname }) if nickname == "甲"));
+        let response = MessageHandler::handle(&mut state, a, add("甲")).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::InvalidFriendRequest, .. })));
+
+        let respond = ClientMessage::RespondFriendRequest { nickname: "甲".to_string(), accept: true };
+        let Some(ServerMessage::FriendList { friends, incoming, .. }) =
+            MessageHandler::handle(&mut state, b, respond).await
+        else {
+            panic!("应返回好友列表");
         };
+        assert!(incoming.is_empty());
+        assert_eq!(friends, [FriendInfo { nickname: "甲".to_string(), presence: Presence::Online }]);
 
-        let room_id = state.rooms.create(room_type);
-        let room = state.rooms.get_mut(room_id)?;
+        // 好友的状态变化推送给对方
+        let guest = match MessageHandler::handle_login(&mut state, "游客".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+        let response = MessageHandler::handle(&mut state, guest, ClientMessage::ListFriends).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::AccountRequired, .. })));
+        MessageHandler::handle(&mut state, a, ClientMessage::Logout).await;
+        MessageHandler::push_presence(&state.players, &state.accounts, &mut presence).await;
+        let pushed: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert!(pushed.iter().any(|m| matches!(
+            m,
+            ServerMessage::FriendPresence { nickname, presence: Presence::Offline } if nickname == "甲"
+        )));
+
+        let remove = ClientMessage::RemoveFriend { nickname: "甲".to_string() };
+        let response = MessageHandler::handle(&mut state, b, remove.clone()).await;
+        assert!(matches!(response, Some(ServerMessage::FriendList { ref friends, .. }) if friends.is_empty()));
+        let response = MessageHandler::handle(&mut state, b, remove).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::InvalidFriendRequest, .. })));
+    }
 
-        // 设置玩家
-        let your_side = if record.metadata.red_player != "AI" {
-            room.red_player = Some(player_id);
-            if record.metadata.black_player == "AI" {
-                room.black_player = Some(protocol::AI_PLAYER_ID);
-            }
-            Side::Red
-        } else {
-            room.black_player = Some(player_id);
-            room.red_player = Some(protocol::AI_PLAYER_ID);
-            Side::Black
+    #[tokio::test]
+    async fn test_correspondence_game() {
+        let mut state = test_state();
+        let a = register(&mut state, "甲").await;
+        let b = register(&mut state, "乙").await;
+        let (tx, mut rx) = mpsc::channel(32);
+        state.players.attach(b, tx);
+
+        let invite = |days_per_move| ClientMessage::CreateCorrespondence {
+            nickname: "乙".to_string(),
+            days_per_move,
+            rated: true,
+        };
+        let response = MessageHandler::handle(&mut state, a, invite(MAX_CORRESPONDENCE_DAYS + 1)).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::InvalidTimeControl, .. })));
+        let Some(ServerMessage::CorrespondenceUpdate { game: sent, .. }) =
+            MessageHandler::handle(&mut state, a, invite(3)).await
+        else {
+            panic!("邀请应已发出");
+        };
+        let Ok(ServerMessage::CorrespondenceUpdate { game: received, .. }) = rx.try_recv() else {
+            panic!("对方应收到邀请");
+        };
+        assert_eq!((received.id, received.your_side), (sent.id, sent.your_side.opponent()));
+        assert_eq!(received.invited_by, Some(sent.your_side));
+
+        // 邀请方不能替对方接受，走棋要等对方接受
+        let accept = ClientMessage::RespondCorrespondence { game_id: sent.id, accept: true };
+        let response = MessageHandler::handle(&mut state, a, accept.clone()).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::ChallengeNotFound, .. })));
+        let Some(ServerMessage::CorrespondenceUpdate { game, .. }) = MessageHandler::handle(&mut state, b, accept).await
+        else {
+            panic!("接受邀请应返回对局");
+        };
+        assert!(game.invited_by.is_none());
+
+        // 红方走棋不需要在房间中，断线后对方仍可查询并回应
+        let red = if sent.your_side == Side::Red { a } else { b };
+        let black = if red == a { b } else { a };
+        let play = |from: (u8, u8), to: (u8, u8)| ClientMessage::CorrespondenceMove {
+            game_id: sent.id,
+            from: Position::new_unchecked(from.0, from.1),
+            to: Position::new_unchecked(to.0, to.1),
+        };
+        let response = MessageHandler::handle(&mut state, black, play((1, 9), (2, 7))).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::NotYourTurn, .. })));
+        let response = MessageHandler::handle(&mut state, red, play((7, 2), (4, 2))).await;
+        assert!(matches!(response, Some(ServerMessage::CorrespondenceUpdate { ref game, .. }) if game.move_count == 1));
+        MessageHandler::handle(&mut state, red, ClientMessage::Logout).await;
+
+        let Some(ServerMessage::CorrespondenceGames { games }) =
+            MessageHandler::handle(&mut state, black, ClientMessage::ListCorrespondence).await
+        else {
+            panic!("应返回通信对局列表");
         };
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].last_move.as_deref(), Some("炮二平五"));
+        assert_eq!(games[0].game_state.current_turn, Side::Black);
+        let details = ClientMessage::GetCorrespondence { game_id: sent.id };
+        let response = MessageHandler::handle(&mut state, black, details).await;
+        assert!(matches!(response, Some(ServerMessage::CorrespondenceDetails { ref moves, .. }) if moves.len() == 1));
+        assert!(MessageHandler::handle(&mut state, black, play((1, 9), (2, 7))).await.is_some());
+
+        // 超过期限未走的一方判负，对局归档并结算等级分
+        let mut game = state.accounts.correspondence_game(sent.id).unwrap().unwrap();
+        game.deadline = 0;
+        state.accounts.save_correspondence(&game).unwrap();
+        MessageHandler::check_correspondence_deadlines(&state.players, &state.accounts).await;
+        assert!(state.accounts.correspondence_game(sent.id).unwrap().is_none());
+        let pushed: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        if black == b {
+            assert!(pushed.iter().any(|m| matches!(
+                m,
+                ServerMessage::CorrespondenceUpdate { game, rating_changes }
+                    if game.result == Some(GameResult::BlackWin(WinReason::Timeout)) && rating_changes.len() == 2
+            )));
+        }
+        let list = ClientMessage::ListMyGames { page: 0, page_size: 10, filter: GameHistoryFilter::default() };
+        let response = MessageHandler::handle(&mut state, black, list).await;
+        assert!(matches!(response, Some(ServerMessage::MyGames { total: 1, .. })));
+    }
 
-        // 恢复游戏状态
-        // 从初始状态开始重放走法来恢复棋盘状态
-        room.start_game();
+    #[tokio::test]
+    async fn test_close_room_returns_everyone_to_lobby() {
+        let mut state = test_state();
+        let red_id = guest(&mut state, "红方").await;
+        let black_id = guest(&mut state, "黑方").await;
+        let spectator_id = guest(&mut state, "观众").await;
+        let (tx, mut rx) = mpsc::channel(16);
+        state.players.attach(spectator_id, tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        MessageHandler::handle(&mut state, spectator_id, ClientMessage::SpectateRoom { room_id }).await;
 
-        // 重放走法
-        for move_record in &record.moves {
-            if let (Some(from_pos), Some(to_pos)) = (move_record.from_position(), move_record.to_position()) {
-                let mv = Move::new(from_pos, to_pos);
-                if room.make_move(mv).is_err() {
-                    // 如果重放失败，返回错误
-                    state.rooms.remove(room_id);
-                    return Some(ServerMessage::Error {
-                        code: ErrorCode::InternalError,
-                        message: "棋谱数据损坏".to_string(),
-                    });
-                }
-            }
-        }
-        
-        // 设置时间（在重放走法后设置，并重置 turn_start）
-        if let Some(timer) = &mut room.timer {
-            timer.set_times(save_info.red_time_remaining_ms, save_info.black_time_remaining_ms);
-            timer.reset_turn_start();
+        assert!(MessageHandler::close_room(&mut state, room_id, "维护".to_string()).await);
+        assert!(state.rooms.get(room_id).is_none());
+        for player_id in [red_id, black_id, spectator_id] {
+            assert_eq!(state.players.status(player_id), Some(PlayerStatus::Online));
         }
+        let closed = std::iter::from_fn(|| rx.try_recv().ok())
+            .any(|msg| matches!(msg, ServerMessage::RoomClosed { room_id: id, .. } if id == room_id));
+        assert!(closed);
 
-        // 加入房间
-        state.players.set_status(player_id, PlayerStatus::InRoom(room_id));
-
-        Some(ServerMessage::GameLoaded {
-            room_id,
-            game_state: room.game_state.clone()?,
-            your_side,
-        })
+        assert!(!MessageHandler::close_room(&mut state, room_id, "维护".to_string()).await);
     }
 
-    /// 处理玩家断线
-    pub async fn handle_disconnect(state: &mut ServerState, player_id: PlayerId) {
-        let mut pending = PendingMessages::new();
-
-        // 标记玩家断线
-        if let Some(room_id) = state.players.disconnect(player_id) {
-            // 设置断线超时
-            state.disconnect_timeouts.insert(
-                player_id,
-                Instant::now() + Duration::from_secs(DISCONNECT_TIMEOUT_SECS),
-            );
-
-            // 获取房间信息
-            if let Some(room) = state.rooms.get(room_id) {
-                let opponent_id = room.get_opponent_id(player_id);
-                let is_pve = matches!(room.room_type, RoomType::PvE(_));
-
-                // 通知对手
-                if let Some(opponent_id) = opponent_id {
-                    pending.send(
-                        opponent_id,
-                        ServerMessage::OpponentDisconnected {
-                            timeout_secs: DISCONNECT_TIMEOUT_SECS as u32,
-                        },
-                    );
-                }
+    #[tokio::test]
+    async fn test_spectator_follows_game() {
+        let mut state = test_state();
+        let red_id = guest(&mut state, "红方").await;
+        let black_id = guest(&mut state, "黑方").await;
+        let spectator_id = guest(&mut state, "观众").await;
+        let (tx, mut rx) = mpsc::channel(16);
+        state.players.attach(spectator_id, tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
 
-                // PvE 模式自动暂停
-                if is_pve {
-                    if let Some(room) = state.rooms.get_mut(room_id) {
-                        room.pause();
-                    }
-                }
+        // 对局开始前不能观战
+        let response = MessageHandler::handle(&mut state, spectator_id, ClientMessage::SpectateRoom { room_id }).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::GameNotStarted, .. })));
+
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let response = MessageHandler::handle(&mut state, spectator_id, ClientMessage::SpectateRoom { room_id }).await;
+        match response {
+            Some(ServerMessage::SpectateStarted { red_player, black_player, moves, .. }) => {
+                assert_eq!(red_player, "红方");
+                assert_eq!(black_player, "黑方");
+                assert!(moves.is_empty());
+            }
+            other => panic!("Expected spectate started: {:?}", other),
+        }
+        assert_eq!(state.rooms.get(room_id).unwrap().summary().spectators, 1);
+
+        // 观战者收到走棋广播
+        MessageHandler::handle(
+            &mut state,
+            red_id,
+            ClientMessage::MakeMove {
+                from: Position::new_unchecked(7, 2),
+                to: Position::new_unchecked(4, 2),
+            },
+        )
+        .await;
+        let mut saw_move = false;
+        while let Ok(msg) = rx.try_recv() {
+            if let ServerMessage::Sequenced(event) = msg {
+                saw_move |= matches!(event.message, ServerMessage::MoveMade { .. });
             }
         }
+        assert!(saw_move);
 
-        // 移除连接
-        state.connections.remove(&player_id);
+        // 观战者不能操作棋局
+        let response = MessageHandler::handle(&mut state, spectator_id, ClientMessage::Resign).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::SpectatorReadOnly, .. })));
+        assert_eq!(state.rooms.get(room_id).unwrap().summary().state, RoomState::Playing);
 
-        // 发送待发送的消息
-        pending.flush(state).await;
+        let response = MessageHandler::handle(&mut state, spectator_id, ClientMessage::StopSpectating).await;
+        assert!(matches!(response, Some(ServerMessage::SpectateStopped { .. })));
+        assert_eq!(state.rooms.get(room_id).unwrap().summary().spectators, 0);
+        assert!(!state.players.read().is_spectating(spectator_id));
     }
 
-    /// 检查断线超时
-    pub async fn check_disconnect_timeouts(state: &mut ServerState) {
-        let now = Instant::now();
-        let mut timed_out = Vec::new();
+    /// 取出通道中的聊天消息
+    #[tokio::test]
+    async fn test_clock_flag_fall() {
+        let mut state = test_state();
+        let red_id = guest(&mut state, "红方").await;
+        let black_id = guest(&mut state, "黑方").await;
+        let (tx, mut rx) = mpsc::channel(16);
+        state.players.attach(black_id, tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
 
-        for (&player_id, &timeout) in &state.disconnect_timeouts {
-            if now >= timeout {
-                timed_out.push(player_id);
+        // 开局后推送权威时间（临时消息，不进入重放缓冲）
+        let mut synced = false;
+        while let Ok(msg) = rx.try_recv() {
+            synced |= matches!(msg, ServerMessage::TimeUpdate { .. });
+        }
+        assert!(synced);
+        let room = state.rooms.get(room_id).unwrap();
+        let next_check = room.call(|actor| actor.run_clock(Instant::now())).await.flatten();
+        assert!(next_check.is_some_and(|wait| wait <= CLOCK_SYNC_INTERVAL));
+        assert!(rx.try_recv().is_err());
+
+        // 红方不走棋也会超时判负
+        room.with_room(|room| room.timer.as_mut().unwrap().set_times(0, INITIAL_TIME_MS)).await;
+        assert_eq!(room.summary().state, RoomState::Finished);
+
+        let mut game_over = None;
+        while let Ok(msg) = rx.try_recv() {
+            if let ServerMessage::Sequenced(event) = msg {
+                if let ServerMessage::GameOver { result, .. } = event.message {
+                    game_over = Some(result);
+                }
             }
         }
+        assert_eq!(game_over, Some(GameResult::BlackWin(WinReason::Timeout)));
+    }
 
-        let mut pending = PendingMessages::new();
-
-        for player_id in timed_out {
-            state.disconnect_timeouts.remove(&player_id);
-            
-            // 查找玩家房间并判负
-            if let Some(room_id) = state.rooms.find_player_room(player_id) {
-                if let Some(room) = state.rooms.get(room_id) {
-                    if room.state == RoomState::Playing {
-                        let player_side = room.get_player_side(player_id);
-                        let opponent_id = room.get_opponent_id(player_id);
-
-                        if let Some(side) = player_side {
-                            let result = match side {
-                                Side::Red => GameResult::BlackWin(WinReason::Disconnect),
-                                Side::Black => GameResult::RedWin(WinReason::Disconnect),
-                            };
-
-                            // 更新房间状态
-                            if let Some(room) = state.rooms.get_mut(room_id) {
-                                room.finish(result.clone());
-                            }
-
-                            // 通知对手胜利
-                            if let Some(opponent_id) = opponent_id {
-                                pending.send(opponent_id, ServerMessage::GameOver { result });
-                            }
-                        }
-                    }
+    #[tokio::test]
+    async fn test_draw_offer() {
+        let mut state = test_state();
+        let red_id = guest(&mut state, "红方").await;
+        let black_id = guest(&mut state, "黑方").await;
+        let (red_tx, mut red_rx) = mpsc::channel(32);
+        let (black_tx, mut black_rx) = mpsc::channel(32);
+        state.players.attach(red_id, red_tx);
+        state.players.attach(black_id, black_tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let events = |rx: &mut mpsc::Receiver<ServerMessage>| {
+            let mut events = Vec::new();
+            while let Ok(msg) = rx.try_recv() {
+                if let ServerMessage::Sequenced(event) = msg {
+                    events.push(event.message);
                 }
             }
+            events
+        };
+        events(&mut black_rx);
+
+        // 提和后对方收到通知，不能重复提和
+        assert!(MessageHandler::handle(&mut state, red_id, ClientMessage::OfferDraw).await.is_none());
+        assert!(events(&mut black_rx).iter().any(|m| matches!(m, ServerMessage::DrawOffered { by: Side::Red })));
+        let response = MessageHandler::handle(&mut state, red_id, ClientMessage::OfferDraw).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::DrawOfferNotAllowed, .. })));
+
+        // 提和方走棋后提和自动撤回
+        let mv = ClientMessage::MakeMove {
+            from: Position::new_unchecked(7, 2),
+            to: Position::new_unchecked(4, 2),
+        };
+        MessageHandler::handle(&mut state, red_id, mv).await;
+        let response = MessageHandler::handle(&mut state, black_id, ClientMessage::RespondDraw { accept: true }).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::DrawOfferNotAllowed, .. })));
+
+        // 拒绝后通知提和方，频繁提和受限
+        events(&mut red_rx);
+        MessageHandler::handle(&mut state, black_id, ClientMessage::OfferDraw).await;
+        MessageHandler::handle(&mut state, red_id, ClientMessage::RespondDraw { accept: false }).await;
+        assert!(events(&mut black_rx).iter().any(|m| matches!(m, ServerMessage::DrawDeclined)));
+        let response = MessageHandler::handle(&mut state, black_id, ClientMessage::OfferDraw).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::RateLimited, .. })));
+
+        // 接受提和，和棋结束
+        let room = state.rooms.get(room_id).unwrap();
+        room.with_room(|room| room.draw_offers.pending = Some(Side::Black)).await;
+        MessageHandler::handle(&mut state, red_id, ClientMessage::RespondDraw { accept: true }).await;
+        assert_eq!(room.summary().state, RoomState::Finished);
+        assert!(events(&mut red_rx).iter().any(|m| matches!(
+            m,
+            ServerMessage::GameOver { result: GameResult::Draw(DrawReason::Agreement), .. }
+        )));
+    }
 
-            // 移除玩家
-            state.players.remove(player_id);
+    fn drain_chat(rx: &mut mpsc::Receiver<ServerMessage>) -> Vec<ChatMessage> {
+        let mut messages = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            if let ServerMessage::Chat(message) = msg {
+                messages.push(message);
+            }
         }
-
-        pending.flush(state).await;
+        messages
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use protocol::Difficulty;
 
     #[tokio::test]
-    async fn test_login() {
-        let mut state = ServerState::new().unwrap();
-        
-        let result = MessageHandler::handle_login(&mut state, "玩家1".to_string());
-        assert!(matches!(result, Some(ServerMessage::LoginSuccess { .. })));
+    async fn test_room_chat_channels() {
+        let mut state = test_state();
+        state.chat_filter = ChatFilter::new(&["笨蛋"]);
+        let red_id = guest(&mut state, "红方").await;
+        let black_id = guest(&mut state, "黑方").await;
+        let spectator_id = guest(&mut state, "观众").await;
+        let (red_tx, mut red_rx) = mpsc::channel(32);
+        let (black_tx, mut black_rx) = mpsc::channel(32);
+        let (spectator_tx, mut spectator_rx) = mpsc::channel(32);
+        state.players.attach(red_id, red_tx);
+        state.players.attach(black_id, black_tx);
+        state.players.attach(spectator_id, spectator_tx);
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        MessageHandler::handle(&mut state, spectator_id, ClientMessage::SpectateRoom { room_id }).await;
+        drain_chat(&mut red_rx);
+
+        let chat = |channel, text: &str| ClientMessage::SendChat { channel, text: text.to_string() };
+
+        // 对局双方的发言所有人可见，并经过敏感词过滤
+        MessageHandler::handle(&mut state, red_id, chat(ChatChannel::Room, "你这个笨蛋")).await;
+        for rx in [&mut red_rx, &mut black_rx, &mut spectator_rx] {
+            let messages = drain_chat(rx);
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].text, "你这个**");
+        }
+
+        // 观战者不能在房间频道发言，观战区消息对局双方看不到
+        let response = MessageHandler::handle(&mut state, spectator_id, chat(ChatChannel::Room, "支招")).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::ChatNotAllowed, .. })));
+        MessageHandler::handle(&mut state, spectator_id, chat(ChatChannel::Spectators, "好棋")).await;
+        assert!(drain_chat(&mut red_rx).is_empty());
+        assert_eq!(drain_chat(&mut spectator_rx).len(), 1);
+
+        // 屏蔽对手后不再收到其消息
+        let response = MessageHandler::handle(
+            &mut state,
+            black_id,
+            ClientMessage::SetMuted { player_id: red_id, muted: true },
+        )
+        .await;
+        assert!(matches!(response, Some(ServerMessage::MuteUpdated { muted: true, .. })));
+        MessageHandler::handle(&mut state, red_id, chat(ChatChannel::Room, "再来")).await;
+        assert!(drain_chat(&mut black_rx).is_empty());
+        assert_eq!(drain_chat(&mut red_rx).len(), 1);
+
+        // 重连时同步聊天记录（不含观战区和已屏蔽的发送者）
+        let room = state.rooms.get(room_id).unwrap();
+        let history = room.call(move |actor| actor.chat_history_for(red_id, false)).await.unwrap();
+        assert_eq!(history.len(), 2);
+        let history = room.call(move |actor| actor.chat_history_for(black_id, false)).await.unwrap();
+        assert!(history.is_empty());
     }
 
     #[tokio::test]
-    async fn test_login_duplicate_nickname() {
-        let mut state = ServerState::new().unwrap();
-        
-        let _ = MessageHandler::handle_login(&mut state, "玩家1".to_string());
-        let result = MessageHandler::handle_login(&mut state, "玩家1".to_string());
-        
-        // 重复昵称应该失败
-        assert!(matches!(result, Some(ServerMessage::Error { .. })));
+    async fn test_chat_rate_limit_and_length() {
+        let mut state = test_state();
+        let player_id = match MessageHandler::handle_login(&mut state, "话痨".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+        let chat = |text: String| ClientMessage::SendChat { channel: ChatChannel::Lobby, text };
+
+        let response = MessageHandler::handle(&mut state, player_id, chat("长".repeat(protocol::MAX_CHAT_LEN + 1))).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::InvalidChatMessage, .. })));
+
+        for _ in 0..chat::CHAT_RATE_LIMIT {
+            let response = MessageHandler::handle(&mut state, player_id, chat("大家好".to_string())).await;
+            assert!(response.is_none());
+        }
+        let response = MessageHandler::handle(&mut state, player_id, chat("大家好".to_string())).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::RateLimited, .. })));
     }
 
     #[tokio::test]
     async fn test_create_room() {
-        let mut state = ServerState::new().unwrap();
+        let mut state = test_state();
         
         // 先登录
-        let login_result = MessageHandler::handle_login(&mut state, "玩家1".to_string());
+        let login_result = MessageHandler::handle_login(&mut state, "玩家1".to_string(), None).await;
         let player_id = match login_result {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
 
@@ -1144,17 +3721,53 @@ mod tests {
             player_id,
             RoomType::PvP,
             None,
+            settings(false, TimeControl::default()),
         );
 
         assert!(matches!(result, Some(ServerMessage::RoomCreated { .. })));
     }
 
+    #[tokio::test]
+    async fn test_create_room_time_control() {
+        let mut state = test_state();
+        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+
+        let invalid = TimeControl::minutes(10).with_move_limit(1);
+        let result = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, invalid));
+        assert!(matches!(
+            result,
+            Some(ServerMessage::Error { code: ErrorCode::InvalidTimeControl, .. })
+        ));
+
+        let control = TimeControl::minutes(5).with_increment(3).with_byoyomi(3, 30);
+        let result = MessageHandler::handle_create_room(
+            &mut state,
+            player_id,
+            RoomType::PvE(Difficulty::Easy),
+            Some(Side::Red),
+            settings(false, control),
+        );
+        assert!(matches!(result, Some(ServerMessage::GameStarted { .. })));
+
+        let room_id = match state.players.status(player_id) {
+            Some(PlayerStatus::InRoom(room_id)) => room_id,
+            _ => panic!("Not in room"),
+        };
+        let room = state.rooms.get(room_id).unwrap();
+        assert_eq!(room.with_room(|room| room.get_time_state().1).await, Some(5 * 60 * 1000));
+        let record = room.with_room(|room| room.generate_game_record("玩家", "AI")).await.flatten().unwrap();
+        assert_eq!(record.metadata.time_control.as_deref(), Some("5+3 3x30"));
+    }
+
     #[tokio::test]
     async fn test_create_pve_room() {
-        let mut state = ServerState::new().unwrap();
+        let mut state = test_state();
         
-        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string()) {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
 
@@ -1163,6 +3776,7 @@ mod tests {
             player_id,
             RoomType::PvE(Difficulty::Easy),
             Some(Side::Red),
+            settings(false, TimeControl::default()),
         );
 
         // PvE 房间直接返回 GameStarted
@@ -1171,20 +3785,20 @@ mod tests {
 
     #[tokio::test]
     async fn test_room_list() {
-        let mut state = ServerState::new().unwrap();
+        let mut state = test_state();
         
         // 创建几个房间
-        let player1_id = match MessageHandler::handle_login(&mut state, "玩家1".to_string()) {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+        let player1_id = match MessageHandler::handle_login(&mut state, "玩家1".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
-        let _ = MessageHandler::handle_create_room(&mut state, player1_id, RoomType::PvP, None);
+        let _ = MessageHandler::handle_create_room(&mut state, player1_id, RoomType::PvP, None, settings(false, TimeControl::default()));
 
-        let player2_id = match MessageHandler::handle_login(&mut state, "玩家2".to_string()) {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+        let player2_id = match MessageHandler::handle_login(&mut state, "玩家2".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
-        let _ = MessageHandler::handle_create_room(&mut state, player2_id, RoomType::PvP, None);
+        let _ = MessageHandler::handle_create_room(&mut state, player2_id, RoomType::PvP, None, settings(false, TimeControl::default()));
 
         // 获取房间列表
         let result = MessageHandler::handle_list_rooms(&state);
@@ -1196,17 +3810,49 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_lobby_unlocked_while_room_busy() {
+        let mut state = test_state();
+        let red = guest(&mut state, "红方").await;
+        let black = guest(&mut state, "黑方").await;
+        let room_id = match MessageHandler::handle_create_room(&mut state, red, RoomType::PvP, None, settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        let room = state.rooms.get(room_id).unwrap();
+        let state = Arc::new(RwLock::new(state));
+
+        // 房间任务忙时，等待它的加入请求不占用大厅锁
+        let busy = room.post(|_| std::thread::sleep(std::time::Duration::from_millis(300)));
+        let join = tokio::spawn({
+            let state = state.clone();
+            async move {
+                let join = ClientMessage::JoinRoom { room_id, password: None };
+                MessageHandler::dispatch(&state, black, join).await
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let lobby = tokio::time::timeout(std::time::Duration::from_millis(100), state.write()).await;
+        assert!(lobby.is_ok(), "大厅锁不应被等待房间的请求占用");
+        drop(lobby);
+
+        let _ = busy.await;
+        let response = join.await.unwrap();
+        assert!(matches!(response, Some(ServerMessage::RoomJoined { side: Side::Black, .. })));
+        assert_eq!(state.read().await.players.status(black), Some(PlayerStatus::InRoom(room_id)));
+    }
+
     #[tokio::test]
     async fn test_disconnect_sets_timeout() {
-        let mut state = ServerState::new().unwrap();
+        let mut state = test_state();
         
-        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string()) {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
 
         // 创建房间（玩家需要在房间中才会设置断线超时）
-        let _ = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None);
+        let _ = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, TimeControl::default()));
 
         // 断线
         MessageHandler::handle_disconnect(&mut state, player_id).await;
@@ -1215,20 +3861,190 @@ mod tests {
         assert!(state.disconnect_timeouts.contains_key(&player_id));
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_reconnect_during_disconnect_keeps_connection() {
+        let mut state = test_state();
+        let red = guest(&mut state, "红方").await;
+        let black = guest(&mut state, "黑方").await;
+        let room_id = match MessageHandler::handle_create_room(&mut state, red, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let token = state.sessions.issue(black);
+        let room = state.rooms.get(room_id).unwrap();
+        let state = Arc::new(RwLock::new(state));
+
+        // 房间任务忙时断线，通知房间的调用尚未完成
+        let busy = room.post(|_| std::thread::sleep(std::time::Duration::from_millis(200)));
+        let disconnect = tokio::spawn({
+            let state = state.clone();
+            async move { MessageHandler::handle_disconnect(&mut &*state, black).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // 此时重连，房间任务依次处理断线和重连
+        let (tx, _rx) = mpsc::channel(64);
+        let reconnect = tokio::spawn({
+            let state = state.clone();
+            let tx = tx.clone();
+            async move { MessageHandler::handle_reconnect(&mut &*state, &token, room_id, None, Some(tx)).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // 占住大厅锁，直到新连接登记后再让两个请求完成
+        let lobby = state.write().await;
+        let _ = busy.await;
+        while !lobby.players.is_attached(black, &tx) {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        drop(lobby);
+
+        disconnect.await.unwrap();
+        let response = reconnect.await.unwrap();
+        assert!(matches!(response, Some(ServerMessage::ReconnectSuccess { .. })));
+        let state = state.read().await;
+        assert!(state.players.is_attached(black, &tx));
+        assert_eq!(state.players.status(black), Some(PlayerStatus::InRoom(room_id)));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_requires_session_token() {
+        let mut state = test_state();
+
+        let (player_id, token) = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, session_token, .. }) => (player_id, session_token),
+            _ => panic!("Login failed"),
+        };
+        let room_id = match MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle_disconnect(&mut state, player_id).await;
+
+        // 猜测的令牌无法接管座位
+        let forged = ClientMessage::Reconnect {
+            session_token: format!("{}.00.00", player_id),
+            room_id,
+            last_seq: None,
+        };
+        assert!(matches!(
+            MessageHandler::handle(&mut state, 0, forged).await,
+            Some(ServerMessage::Error { code: ErrorCode::InvalidSession, .. })
+        ));
+
+        // 令牌到期后失效
+        state.sessions.suspend(player_id, std::time::Duration::ZERO);
+        let expired = ClientMessage::Reconnect { session_token: token.clone(), room_id, last_seq: None };
+        assert!(matches!(
+            MessageHandler::handle(&mut state, 0, expired).await,
+            Some(ServerMessage::Error { code: ErrorCode::InvalidSession, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_session() {
+        let mut state = test_state();
+
+        let (player_id, token) = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, session_token, .. }) => (player_id, session_token),
+            _ => panic!("Login failed"),
+        };
+        let room_id = match MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+
+        let response = MessageHandler::handle(&mut state, player_id, ClientMessage::Logout).await;
+        assert!(matches!(response, Some(ServerMessage::LoggedOut)));
+        assert!(!state.players.read().exists(player_id));
+        assert_eq!(state.sessions.validate(&token), None);
+        assert!(state.rooms.get(room_id).is_none());
+
+        // 昵称可以再次使用
+        let result = MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await;
+        assert!(matches!(result, Some(ServerMessage::LoginSuccess { .. })));
+    }
+
     #[tokio::test]
     async fn test_cannot_create_multiple_rooms() {
-        let mut state = ServerState::new().unwrap();
+        let mut state = test_state();
         
-        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string()) {
-            Some(ServerMessage::LoginSuccess { player_id }) => player_id,
+        let player_id = match MessageHandler::handle_login(&mut state, "玩家".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
             _ => panic!("Login failed"),
         };
 
         // 创建第一个房间
-        let _ = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None);
+        let _ = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, TimeControl::default()));
 
         // 尝试创建第二个房间应该失败
-        let result = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None);
+        let result = MessageHandler::handle_create_room(&mut state, player_id, RoomType::PvP, None, settings(false, TimeControl::default()));
         assert!(matches!(result, Some(ServerMessage::Error { .. })));
     }
+
+    #[tokio::test]
+    async fn test_reconnect_replays_missed_events() {
+        let mut state = test_state();
+
+        let red_id = match MessageHandler::handle_login(&mut state, "红方".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+        let black_id = match MessageHandler::handle_login(&mut state, "黑方".to_string(), None).await {
+            Some(ServerMessage::LoginSuccess { player_id, .. }) => player_id,
+            _ => panic!("Login failed"),
+        };
+
+        let room_id = match MessageHandler::handle_create_room(&mut state, red_id, RoomType::PvP, Some(Side::Red), settings(false, TimeControl::default())) {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            _ => panic!("Create room failed"),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        MessageHandler::handle(
+            &mut state,
+            red_id,
+            ClientMessage::MakeMove {
+                from: Position::new_unchecked(7, 2),
+                to: Position::new_unchecked(4, 2),
+            },
+        )
+        .await;
+
+        let black_token = state.sessions.issue(black_id);
+
+        // 黑方断线前已确认的序列号
+        let room = state.rooms.get(room_id).unwrap();
+        let acked_seq = room.with_room(|room| room.events.last_seq()).await.unwrap();
+        MessageHandler::handle_disconnect(&mut state, black_id).await;
+
+        // 断线期间红方请求悔棋
+        MessageHandler::handle(&mut state, red_id, ClientMessage::RequestUndo).await;
+
+        // 重连发生在新连接登录之前，此时尚无玩家 ID
+        let response = MessageHandler::handle(
+            &mut state,
+            0,
+            ClientMessage::Reconnect { session_token: black_token, room_id, last_seq: Some(acked_seq) },
+        )
+        .await;
+
+        match response {
+            Some(ServerMessage::ReconnectSuccess {
+                missed_events, replay_complete, undo_requested_by, last_seq, initial_fen, moves, ..
+            }) => {
+                assert!(replay_complete);
+                assert_eq!(initial_fen, protocol::INITIAL_FEN);
+                assert_eq!(moves.len(), 1);
+                assert_eq!(moves[0].notation, "炮二平五");
+                assert_eq!(undo_requested_by, Some(Side::Red));
+                // 只补发发给黑方的悔棋请求，发给红方的断线通知不应出现
+                assert_eq!(missed_events.len(), 1);
+                assert!(missed_events[0].seq > acked_seq);
+                assert!(matches!(missed_events[0].message, ServerMessage::UndoRequested { by: Side::Red }));
+                assert!(last_seq >= missed_events[0].seq);
+            }
+            _ => panic!("Expected reconnect success"),
+        }
+    }
 }
@@ -0,0 +1,260 @@
+//! 会话令牌
+//!
+//! 登录成功后为玩家签发不可猜测的会话令牌，重连时凭令牌恢复身份。
+//! 令牌格式：`<玩家 ID>.<随机数>.<HMAC-SHA256 签名>`，签名密钥在进程启动时随机生成，
+//! 服务端同时记录每个玩家当前有效的随机数，以便注销或过期后立即失效。
+//! 停服时密钥与对局中玩家的随机数随快照保存，重启后旧令牌仍可用于重连
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use protocol::PlayerId;
+
+use crate::accounts::{from_hex, to_hex};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名密钥长度（字节）
+const SECRET_LEN: usize = 32;
+
+/// 令牌随机数长度（字节）
+const NONCE_LEN: usize = 16;
+
+/// 会话记录
+#[derive(Debug, Clone)]
+struct Session {
+    nonce: [u8; NONCE_LEN],
+    /// 过期时间（None 表示连接中，不会过期）
+    expires_at: Option<Instant>,
+}
+
+/// 会话管理器
+pub struct SessionManager {
+    secret: [u8; SECRET_LEN],
+    sessions: HashMap<PlayerId, Session>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        let mut secret = [0u8; SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            secret,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// 为玩家签发新令牌（旧令牌随之失效）
+    pub fn issue(&mut self, player_id: PlayerId) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        self.sessions.insert(
+            player_id,
+            Session {
+                nonce,
+                expires_at: None,
+            },
+        );
+
+        let payload = format!("{}.{}", player_id, to_hex(&nonce));
+        let signature = self.sign(&payload);
+        format!("{}.{}", payload, to_hex(&signature))
+    }
+
+    /// 验证令牌，返回对应的玩家 ID
+    pub fn validate(&self, token: &str) -> Option<PlayerId> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = from_hex(signature)?;
+
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+
+        let (player_id, nonce) = payload.split_once('.')?;
+        let player_id: PlayerId = player_id.parse().ok()?;
+        let nonce = from_hex(nonce)?;
+
+        let session = self.sessions.get(&player_id)?;
+        if session.nonce[..] != nonce[..] {
+            return None;
+        }
+        if session.expires_at.is_some_and(|at| Instant::now() >= at) {
+            return None;
+        }
+        Some(player_id)
+    }
+
+    /// 玩家断线：令牌在 `ttl` 后过期
+    pub fn suspend(&mut self, player_id: PlayerId, ttl: Duration) {
+        if let Some(session) = self.sessions.get_mut(&player_id) {
+            session.expires_at = Some(Instant::now() + ttl);
+        }
+    }
+
+    /// 玩家重连：令牌恢复为长期有效
+    pub fn resume(&mut self, player_id: PlayerId) {
+        if let Some(session) = self.sessions.get_mut(&player_id) {
+            session.expires_at = None;
+        }
+    }
+
+    /// 注销令牌
+    pub fn revoke(&mut self, player_id: PlayerId) {
+        self.sessions.remove(&player_id);
+    }
+
+    /// 清理已过期的会话
+    pub fn purge_expired(&mut self) {
+        let now = Instant::now();
+        self.sessions
+            .retain(|_, session| session.expires_at.is_none_or(|at| now < at));
+    }
+
+    /// 签名密钥（十六进制，用于停服快照）
+    pub fn export_secret(&self) -> String {
+        to_hex(&self.secret)
+    }
+
+    /// 玩家当前会话的随机数（十六进制，用于停服快照）
+    pub fn export_nonce(&self, player_id: PlayerId) -> Option<String> {
+        self.sessions.get(&player_id).map(|session| to_hex(&session.nonce))
+    }
+
+    /// 从快照恢复签名密钥，格式不对时返回 false
+    pub fn restore_secret(&mut self, secret: &str) -> bool {
+        match from_hex(secret).and_then(|bytes| <[u8; SECRET_LEN]>::try_from(bytes).ok()) {
+            Some(secret) => {
+                self.secret = secret;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 从快照恢复会话，令牌在 `ttl` 后过期
+    pub fn restore(&mut self, player_id: PlayerId, nonce: &str, ttl: Duration) -> bool {
+        let Some(nonce) = from_hex(nonce).and_then(|bytes| <[u8; NONCE_LEN]>::try_from(bytes).ok()) else {
+            return false;
+        };
+        self.sessions.insert(
+            player_id,
+            Session {
+                nonce,
+                expires_at: Some(Instant::now() + ttl),
+            },
+        );
+        true
+    }
+
+    /// 有效会话数量
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// 是否没有会话
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC 接受任意长度密钥")
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_and_validate() {
+        let mut sessions = SessionManager::new();
+        let token = sessions.issue(42);
+
+        assert_eq!(sessions.validate(&token), Some(42));
+        assert_eq!(sessions.validate("42"), None);
+        assert_eq!(sessions.validate(""), None);
+    }
+
+    #[test]
+    fn test_forged_token_rejected() {
+        let mut sessions = SessionManager::new();
+        let token = sessions.issue(1);
+        sessions.issue(2);
+
+        // 篡改玩家 ID 后签名不再匹配
+        let forged = token.replacen("1.", "2.", 1);
+        assert_eq!(sessions.validate(&forged), None);
+
+        // 其他服务器实例签发的令牌无效
+        let mut other = SessionManager::new();
+        let foreign = other.issue(2);
+        assert_eq!(sessions.validate(&foreign), None);
+    }
+
+    #[test]
+    fn test_reissue_and_revoke() {
+        let mut sessions = SessionManager::new();
+        let old = sessions.issue(1);
+        let new = sessions.issue(1);
+
+        assert_eq!(sessions.validate(&old), None);
+        assert_eq!(sessions.validate(&new), Some(1));
+
+        sessions.revoke(1);
+        assert_eq!(sessions.validate(&new), None);
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut sessions = SessionManager::new();
+        let token = sessions.issue(1);
+
+        sessions.suspend(1, Duration::from_secs(60));
+        assert_eq!(sessions.validate(&token), Some(1));
+
+        sessions.suspend(1, Duration::ZERO);
+        assert_eq!(sessions.validate(&token), None);
+
+        sessions.resume(1);
+        assert_eq!(sessions.validate(&token), Some(1));
+
+        sessions.suspend(1, Duration::ZERO);
+        sessions.purge_expired();
+        assert_eq!(sessions.len(), 0);
+    }
+
+    #[test]
+    fn test_restore_from_snapshot() {
+        let mut sessions = SessionManager::new();
+        let token = sessions.issue(7);
+        let (secret, nonce) = (sessions.export_secret(), sessions.export_nonce(7).unwrap());
+
+        // 重启后的实例恢复密钥和会话，旧令牌仍然有效
+        let mut restarted = SessionManager::new();
+        assert_eq!(restarted.validate(&token), None);
+        assert!(restarted.restore_secret(&secret));
+        assert!(restarted.restore(7, &nonce, Duration::from_secs(60)));
+        assert_eq!(restarted.validate(&token), Some(7));
+
+        assert!(!restarted.restore_secret("abc"));
+        assert!(!restarted.restore(8, "zz", Duration::from_secs(60)));
+    }
+}
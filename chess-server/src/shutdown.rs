@@ -0,0 +1,65 @@
+//! 停服信号
+//!
+//! 由 SIGTERM、Ctrl+C 或管理接口触发。触发后接受连接的主循环停止接受新连接、
+//! 保存进行中的对局（见 `snapshot` 模块）并通知在线玩家后退出，管理接口随之关闭
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// 停服信号（克隆后共享同一个信号）
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { tx: Arc::new(tx) }
+    }
+
+    /// 触发停服
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// 是否已触发
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// 等待停服信号
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        // 发送端随自身一起存活，不会关闭
+        let _ = rx.wait_for(|&triggered| triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_after_trigger() {
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+
+        assert!(!shutdown.is_triggered());
+        shutdown.trigger();
+        waiter.await.unwrap();
+        // 触发之后再等待立即返回
+        shutdown.wait().await;
+        assert!(shutdown.is_triggered());
+    }
+}
@@ -0,0 +1,432 @@
+//! 停服快照
+//!
+//! 停服时暂停所有进行中的对局，把局面、走法、时钟、座位和对局玩家的会话保存到磁盘；
+//! 重启后恢复这些房间，玩家处于断线状态，凭原会话令牌在宽限期内重连即可继续对局，
+//! 超时未回来的一方按断线判负。快照含会话签名密钥，文件只允许本用户读写，恢复后即删除
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use protocol::{Move, MoveRecord, PlayerId, RoomId, RoomState, RoomType, RuleSet, TournamentId};
+
+use crate::accounts::AccountId;
+use crate::bots::BotSettings;
+use crate::game::{ClockSnapshot, GameTimer};
+use crate::player::{Player, PlayerStatus};
+use crate::room::{Room, RoomKey};
+use crate::server::ServerState;
+
+/// 房间快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub id: RoomId,
+    pub room_type: RoomType,
+    pub state: RoomState,
+    pub rated: bool,
+    pub rules: RuleSet,
+    pub tournament: Option<TournamentId>,
+    pub key: Option<RoomKey>,
+    pub custom_fen: Option<String>,
+    pub red_player: Option<PlayerId>,
+    pub black_player: Option<PlayerId>,
+    pub initial_fen: String,
+    pub moves: Vec<MoveRecord>,
+    pub clock: ClockSnapshot,
+}
+
+impl RoomSnapshot {
+    /// 记录房间当前的对局（时钟应已暂停）
+    pub fn capture(room: &Room) -> Self {
+        let clock = match &room.timer {
+            Some(timer) => timer.snapshot(),
+            None => GameTimer::with_control(room.time_control).snapshot(),
+        };
+        Self {
+            id: room.id,
+            room_type: room.room_type,
+            state: room.state,
+            rated: room.rated,
+            rules: room.rules,
+            tournament: room.tournament,
+            key: room.key.clone(),
+            custom_fen: room.custom_fen.clone(),
+            red_player: room.red_player,
+            black_player: room.black_player,
+            initial_fen: room.initial_fen.clone(),
+            moves: room.move_records.clone(),
+            clock,
+        }
+    }
+
+    /// 重放走法恢复房间，时钟处于暂停状态
+    pub fn restore(self) -> anyhow::Result<Room> {
+        let mut room = Room::new(self.id, self.room_type);
+        room.rated = self.rated;
+        room.rules = self.rules;
+        room.tournament = self.tournament;
+        room.key = self.key;
+        room.custom_fen = self.custom_fen;
+        room.red_player = self.red_player;
+        room.black_player = self.black_player;
+        room.start_game_from_fen(&self.initial_fen)
+            .with_context(|| format!("初始局面无效: {}", self.initial_fen))?;
+
+        for record in &self.moves {
+            let replayed = match (record.from_position(), record.to_position()) {
+                (Some(from), Some(to)) => room.make_move(Move::new(from, to)).is_ok(),
+                _ => false,
+            };
+            if !replayed {
+                anyhow::bail!("走法 {} 无法重放", record.notation);
+            }
+        }
+
+        // 保留原记谱中的时间数据，时钟以快照为准
+        room.move_records = self.moves;
+        let timer = GameTimer::from_snapshot(self.clock);
+        room.time_control = *timer.control();
+        room.timer = Some(timer);
+        room.state = self.state;
+        Ok(room)
+    }
+}
+
+/// 对局中玩家的快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: PlayerId,
+    pub nickname: String,
+    pub account_id: Option<AccountId>,
+    pub bot: Option<BotSettings>,
+    pub room_id: RoomId,
+    /// 会话随机数（十六进制）
+    pub session_nonce: String,
+}
+
+/// 停服快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSnapshot {
+    /// 保存时的 Unix 时间戳（毫秒）
+    pub saved_at: i64,
+    /// 会话签名密钥（十六进制）
+    pub session_secret: String,
+    pub players: Vec<PlayerSnapshot>,
+    pub rooms: Vec<RoomSnapshot>,
+}
+
+impl ServerSnapshot {
+    /// 写入磁盘（先写临时文件再改名，避免留下不完整的快照）
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("创建快照目录失败: {:?}", dir))?;
+        }
+        let temp = path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&temp)
+            .with_context(|| format!("创建快照文件失败: {:?}", temp))?;
+        file.write_all(&serde_json::to_vec(self)?)?;
+        file.sync_all()?;
+        fs::rename(&temp, path).with_context(|| format!("保存快照失败: {:?}", path))?;
+        Ok(())
+    }
+
+    /// 读取并删除快照（不存在时返回 None）
+    ///
+    /// 解析失败的快照改名为 `.bad` 保留，便于排查，也不会在下次启动时重复报错
+    pub fn take(path: &Path) -> anyhow::Result<Option<Self>> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("读取快照失败: {:?}", path)),
+        };
+        let snapshot = match serde_json::from_slice(&content) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                let bad = path.with_extension("bad");
+                fs::rename(path, &bad).with_context(|| format!("保留损坏的快照失败: {:?}", path))?;
+                return Err(e).with_context(|| format!("解析快照失败，已另存为 {:?}", bad));
+            }
+        };
+        fs::remove_file(path).with_context(|| format!("删除快照失败: {:?}", path))?;
+        Ok(Some(snapshot))
+    }
+}
+
+impl ServerState {
+    /// 停服：暂停所有进行中的对局并生成快照，房间任务随之结束
+    pub async fn suspend_games(&mut self) -> ServerSnapshot {
+        let mut rooms = Vec::new();
+        for summary in self.rooms.list_all() {
+            let Some(handle) = self.rooms.get(summary.id) else {
+                continue;
+            };
+            if let Some(Some(room)) = handle.call(|actor| actor.suspend()).await {
+                rooms.push(room);
+            }
+        }
+
+        // AI 不在玩家表中，自然跳过
+        let mut players = Vec::new();
+        let registry = self.players.read();
+        for room in &rooms {
+            for player_id in [room.red_player, room.black_player].into_iter().flatten() {
+                let (Some(player), Some(session_nonce)) =
+                    (registry.get(player_id), self.sessions.export_nonce(player_id))
+                else {
+                    continue;
+                };
+                players.push(PlayerSnapshot {
+                    id: player_id,
+                    nickname: player.nickname.clone(),
+                    account_id: player.account_id,
+                    bot: player.bot,
+                    room_id: room.id,
+                    session_nonce,
+                });
+            }
+        }
+        drop(registry);
+
+        ServerSnapshot {
+            saved_at: chrono::Utc::now().timestamp_millis(),
+            session_secret: self.sessions.export_secret(),
+            players,
+            rooms,
+        }
+    }
+
+    /// 重启后恢复快照中的对局，返回恢复的房间数
+    ///
+    /// 玩家以断线状态恢复，宽限期内凭原会话令牌重连；快照比宽限期还旧时整个丢弃
+    pub fn restore_games(&mut self, snapshot: ServerSnapshot) -> usize {
+        let grace = self.config.restore_grace();
+        let age_ms = chrono::Utc::now().timestamp_millis() - snapshot.saved_at;
+        if age_ms > grace.as_millis() as i64 {
+            tracing::warn!("停服快照已超过宽限期（{}s 前保存），不再恢复", age_ms / 1000);
+            return 0;
+        }
+        if !self.sessions.restore_secret(&snapshot.session_secret) {
+            tracing::warn!("停服快照中的会话密钥无效，不再恢复");
+            return 0;
+        }
+
+        let deadline = Instant::now() + grace;
+        for saved in snapshot.players {
+            let mut player = Player::new(saved.id, saved.nickname);
+            player.account_id = saved.account_id;
+            player.bot = saved.bot;
+            player.status = PlayerStatus::Disconnected(saved.room_id);
+            if !self.players.write().restore(player) {
+                tracing::warn!("恢复玩家 {} 失败：ID 或昵称已被占用", saved.id);
+                continue;
+            }
+            self.sessions.restore(saved.id, &saved.session_nonce, grace);
+            self.disconnect_timeouts.insert(saved.id, deadline);
+        }
+
+        let mut restored = 0;
+        for saved in snapshot.rooms {
+            let room_id = saved.id;
+            let room = match saved.restore() {
+                Ok(room) => room,
+                Err(e) => {
+                    tracing::warn!("恢复房间 {} 失败: {}", room_id, e);
+                    continue;
+                }
+            };
+
+            // 比赛对局重新关联到房间，避免被当作未开始的对局重排
+            if let Some(tournament_id) = room.tournament {
+                let players = self.players.read();
+                let account_of = |id: Option<PlayerId>| id.and_then(|id| players.get(id)?.account_id);
+                let linked = account_of(room.red_player)
+                    .zip(account_of(room.black_player))
+                    .zip(self.tournaments.get_mut(tournament_id))
+                    .is_some_and(|((red, black), tournament)| tournament.relink_room(room_id, red, black));
+                if !linked {
+                    tracing::warn!("房间 {} 找不到比赛 {} 中对应的对局", room_id, tournament_id);
+                }
+            }
+
+            self.rooms.spawn(room, self.room_services());
+            restored += 1;
+        }
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::AccountStore;
+    use crate::config::ServerConfig;
+    use crate::server::MessageHandler;
+    use protocol::{ClientMessage, ErrorCode, Position, ServerMessage, Side, TimeControl};
+
+    fn new_state() -> ServerState {
+        ServerState::with_accounts(ServerConfig::default(), AccountStore::open_in_memory().unwrap()).unwrap()
+    }
+
+    async fn login(state: &mut ServerState, nickname: &str) -> (PlayerId, String) {
+        match MessageHandler::handle(state, 0, ClientMessage::Login { nickname: nickname.to_string(), password: None }).await {
+            Some(ServerMessage::LoginSuccess { player_id, session_token, .. }) => (player_id, session_token),
+            other => panic!("Login failed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_room_snapshot_roundtrip() {
+        let mut room = Room::new(7, RoomType::PvP);
+        room.time_control = TimeControl::minutes(5).with_increment(3);
+        room.rules = RuleSet::tournament();
+        room.add_player(100, None);
+        room.add_player(200, None);
+        room.start_game();
+        room.make_move(Move::new(Position::new_unchecked(7, 2), Position::new_unchecked(4, 2)))
+            .unwrap();
+        room.timer.as_mut().unwrap().pause();
+
+        let json = serde_json::to_string(&RoomSnapshot::capture(&room)).unwrap();
+        let snapshot: RoomSnapshot = serde_json::from_str(&json).unwrap();
+        let restored = snapshot.restore().unwrap();
+
+        assert_eq!(restored.id, 7);
+        assert_eq!(restored.state, RoomState::Playing);
+        assert_eq!(restored.rules, RuleSet::tournament());
+        assert_eq!(restored.red_player, Some(100));
+        assert_eq!(restored.move_history, room.move_history);
+        assert_eq!(restored.move_records[0].time_left_ms, room.move_records[0].time_left_ms);
+        assert_eq!(restored.game_state.as_ref().unwrap().current_turn, Side::Black);
+
+        let timer = restored.timer.as_ref().unwrap();
+        assert!(timer.is_paused());
+        assert_eq!(timer.current_turn(), Side::Black);
+        assert_eq!(restored.get_time_state(), room.get_time_state());
+    }
+
+    #[test]
+    fn test_snapshot_file_is_taken_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("snapshot.json");
+        assert!(ServerSnapshot::take(&path).unwrap().is_none());
+
+        let snapshot = ServerSnapshot {
+            saved_at: 1,
+            session_secret: "00".repeat(32),
+            players: Vec::new(),
+            rooms: Vec::new(),
+        };
+        snapshot.save(&path).unwrap();
+        assert_eq!(ServerSnapshot::take(&path).unwrap().unwrap().saved_at, 1);
+        assert!(!path.exists());
+
+        // 损坏的快照另存为 .bad，不再被读取
+        fs::write(&path, b"{ truncated").unwrap();
+        assert!(ServerSnapshot::take(&path).is_err());
+        assert!(!path.exists());
+        assert!(path.with_extension("bad").exists());
+        assert!(ServerSnapshot::take(&path).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_games_survive_restart() {
+        let mut state = new_state();
+        let (red_id, red_token) = login(&mut state, "红方").await;
+        let (black_id, black_token) = login(&mut state, "黑方").await;
+        let create = ClientMessage::CreateRoom {
+            room_type: RoomType::PvP,
+            preferred_side: Some(Side::Red),
+            rated: false,
+            time_control: TimeControl::default(),
+            rules: RuleSet::default(),
+            initial_fen: None,
+            access: protocol::RoomAccess::Public,
+        };
+        let room_id = match MessageHandler::handle(&mut state, red_id, create).await {
+            Some(ServerMessage::RoomCreated { room_id, .. }) => room_id,
+            other => panic!("Create room failed: {:?}", other),
+        };
+        MessageHandler::handle(&mut state, black_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        let mv = ClientMessage::MakeMove { from: Position::new_unchecked(7, 2), to: Position::new_unchecked(4, 2) };
+        MessageHandler::handle(&mut state, red_id, mv).await;
+
+        // 停服后房间不再处理命令
+        let snapshot = state.suspend_games().await;
+        assert_eq!(snapshot.rooms.len(), 1);
+        assert_eq!(snapshot.players.len(), 2);
+        assert!(state.rooms.get(room_id).unwrap().with_room(|room| room.id).await.is_none());
+
+        // 重启后凭原令牌重连，双方都回来前时钟保持暂停
+        let mut state = new_state();
+        assert_eq!(state.restore_games(snapshot), 1);
+        assert_eq!(state.players.status(red_id), Some(PlayerStatus::Disconnected(room_id)));
+        let reconnect = |session_token: String| ClientMessage::Reconnect { session_token, room_id, last_seq: None };
+        match MessageHandler::handle(&mut state, 0, reconnect(red_token)).await {
+            Some(ServerMessage::ReconnectSuccess { your_side: Side::Red, moves, .. }) => assert_eq!(moves.len(), 1),
+            other => panic!("Reconnect failed: {:?}", other),
+        }
+        let room = state.rooms.get(room_id).unwrap();
+        let paused = |room: &mut Room| room.timer.as_ref().is_some_and(|timer| timer.is_paused());
+        assert_eq!(room.with_room(paused).await, Some(true));
+        let response = MessageHandler::handle(&mut state, 0, reconnect(black_token)).await;
+        assert!(matches!(response, Some(ServerMessage::ReconnectSuccess { your_side: Side::Black, .. })));
+        assert_eq!(room.with_room(paused).await, Some(false));
+
+        // 新玩家和新房间的 ID 不与恢复的重复
+        let (new_id, _) = login(&mut state, "新玩家").await;
+        assert!(new_id > black_id.max(red_id));
+        let response = MessageHandler::handle(&mut state, new_id, ClientMessage::JoinRoom { room_id, password: None }).await;
+        assert!(matches!(response, Some(ServerMessage::Error { code: ErrorCode::RoomClosed, .. })));
+        assert!(state.rooms.generate_id() > room_id);
+
+        // 人机对局
+        let mut state = new_state();
+        let (player_id, token) = login(&mut state, "玩家").await;
+        let create = ClientMessage::CreateRoom {
+            room_type: RoomType::PvE(protocol::Difficulty::Easy),
+            preferred_side: Some(Side::Black),
+            rated: false,
+            time_control: TimeControl::default(),
+            rules: RuleSet::default(),
+            initial_fen: None,
+            access: protocol::RoomAccess::Public,
+        };
+        MessageHandler::handle(&mut state, player_id, create).await;
+        let room_id = match state.players.status(player_id) {
+            Some(PlayerStatus::InRoom(room_id)) => room_id,
+            other => panic!("Create room failed: {:?}", other),
+        };
+
+        // 人机对局停服时整局暂停
+        let snapshot = state.suspend_games().await;
+        assert_eq!(snapshot.rooms.len(), 1);
+        assert_eq!(snapshot.rooms[0].state, RoomState::Paused);
+        let saved_moves = snapshot.rooms[0].moves.len();
+
+        // 恢复后玩家未归，AI 不走棋
+        let mut state = new_state();
+        assert_eq!(state.restore_games(snapshot), 1);
+        let room = state.rooms.get(room_id).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let progress = |room: &mut Room| (room.state, room.move_records.len());
+        assert_eq!(room.with_room(progress).await, Some((RoomState::Paused, saved_moves)));
+
+        // 玩家重连后仍是暂停状态，由玩家继续
+        let reconnect = ClientMessage::Reconnect { session_token: token, room_id, last_seq: None };
+        let response = MessageHandler::handle(&mut state, 0, reconnect).await;
+        assert!(matches!(response, Some(ServerMessage::ReconnectSuccess { your_side: Side::Black, .. })));
+        assert_eq!(room.with_room(|room| room.state).await, Some(RoomState::Paused));
+        let response = MessageHandler::handle(&mut state, player_id, ClientMessage::ResumeGame).await;
+        assert!(matches!(response, Some(ServerMessage::GameResumed)));
+        assert_eq!(room.with_room(|room| room.state).await, Some(RoomState::Playing));
+    }
+}
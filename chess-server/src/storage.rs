@@ -15,10 +15,15 @@ pub struct StorageManager {
 }
 
 impl StorageManager {
-    /// 创建存储管理器
+    /// 创建存储管理器（使用系统数据目录）
     pub fn new() -> Result<Self> {
-        let saves_dir = get_saves_directory()?;
-        
+        Self::with_dir(get_saves_directory()?)
+    }
+
+    /// 使用指定目录创建存储管理器
+    pub fn with_dir(saves_dir: impl Into<PathBuf>) -> Result<Self> {
+        let saves_dir = saves_dir.into();
+
         // 确保目录存在
         if !saves_dir.exists() {
             fs::create_dir_all(&saves_dir)
@@ -127,7 +132,7 @@ impl StorageManager {
         }
 
         // 按保存时间倒序排列
-        games.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+        games.sort_by_key(|g| std::cmp::Reverse(g.saved_at));
         Ok(games)
     }
 
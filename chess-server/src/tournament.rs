@@ -0,0 +1,722 @@
+//! 比赛
+//!
+//! 组织者创建比赛（单循环或瑞士制）后注册账号的玩家报名，开赛后每轮自动配对，
+//! 双方都在大厅时服务器为其开设计分房间。所有对局出结果后进入下一轮，
+//! 按积分和 Buchholz、Sonneborn-Berger 小分排名。比赛以 JSON 存入 `tournaments` 表，
+//! 重启后继续进行（进行中的对局房间不保留，双方回到大厅后重新开局）
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use protocol::{
+    ErrorCode, RoomId, RuleSet, ServerMessage, TimeControl, TournamentFormat,
+    TournamentGameResult, TournamentId, TournamentInfo, TournamentPairing, TournamentStanding,
+    TournamentStatus,
+};
+
+use crate::accounts::{AccountError, AccountId, AccountStore};
+
+/// 每轮开始后未到场的一方判负的等待时间
+pub const NO_SHOW_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// 开赛所需的最少人数
+pub const MIN_TOURNAMENT_PLAYERS: usize = 2;
+
+/// 比赛操作被拒的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TournamentError {
+    /// 已开赛，不能报名或退出
+    RegistrationClosed,
+    /// 已经报名
+    AlreadyJoined,
+    /// 没有报名
+    NotJoined,
+    /// 不是组织者
+    NotOrganizer,
+    /// 报名人数不足
+    NotEnoughPlayers,
+}
+
+impl TournamentError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TournamentError::RegistrationClosed => ErrorCode::TournamentRegistrationClosed,
+            _ => ErrorCode::TournamentNotAllowed,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            TournamentError::RegistrationClosed => "比赛已开赛",
+            TournamentError::AlreadyJoined => "已经报名",
+            TournamentError::NotJoined => "没有报名该比赛",
+            TournamentError::NotOrganizer => "只有组织者可以开赛",
+            TournamentError::NotEnoughPlayers => "报名人数不足",
+        }
+    }
+}
+
+impl From<TournamentError> for ServerMessage {
+    fn from(e: TournamentError) -> Self {
+        ServerMessage::Error {
+            code: e.code(),
+            message: e.message().to_string(),
+        }
+    }
+}
+
+/// 参赛者（注册账号）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entrant {
+    pub account_id: AccountId,
+    pub nickname: String,
+}
+
+/// 一局比赛对局（选手以报名序号表示）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TournamentGame {
+    pub red: usize,
+    /// 黑方（轮空时为 None）
+    pub black: Option<usize>,
+    /// 对局进行中的房间（不存入数据库，停服快照恢复房间时重新关联）
+    #[serde(skip)]
+    pub room_id: Option<RoomId>,
+    pub result: Option<TournamentGameResult>,
+}
+
+impl TournamentGame {
+    fn new(red: usize, black: Option<usize>) -> Self {
+        Self {
+            red,
+            black,
+            room_id: None,
+            result: black.is_none().then_some(TournamentGameResult::Bye),
+        }
+    }
+
+    /// 红、黑双方的得分（轮空只计红方）
+    fn points(&self) -> Option<(f32, f32)> {
+        Some(match self.result? {
+            TournamentGameResult::RedWin | TournamentGameResult::BlackForfeit | TournamentGameResult::Bye => {
+                (1.0, 0.0)
+            }
+            TournamentGameResult::BlackWin | TournamentGameResult::RedForfeit => (0.0, 1.0),
+            TournamentGameResult::Draw => (0.5, 0.5),
+            TournamentGameResult::DoubleForfeit => (0.0, 0.0),
+        })
+    }
+}
+
+/// 选手的战绩
+#[derive(Debug, Clone, Copy, Default)]
+struct Record {
+    score: f32,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+/// 比赛
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tournament {
+    pub id: TournamentId,
+    pub name: String,
+    pub organizer: Entrant,
+    pub format: TournamentFormat,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    pub status: TournamentStatus,
+    /// 参赛者（按报名顺序）
+    pub entrants: Vec<Entrant>,
+    /// 各轮对局（循环赛开赛时排好全部轮次，瑞士制每轮开始时配对）
+    pub rounds: Vec<Vec<TournamentGame>>,
+    /// 当前轮次（从 1 开始，未开赛为 0）
+    pub current_round: u32,
+    /// 当前轮开始时的 Unix 时间戳（毫秒）
+    pub round_started_at: i64,
+}
+
+impl Tournament {
+    pub fn new(
+        id: TournamentId,
+        name: String,
+        organizer: Entrant,
+        format: TournamentFormat,
+        time_control: TimeControl,
+        rules: RuleSet,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            organizer,
+            format,
+            time_control,
+            rules,
+            status: TournamentStatus::Registering,
+            entrants: Vec::new(),
+            rounds: Vec::new(),
+            current_round: 0,
+            round_started_at: 0,
+        }
+    }
+
+    /// 总轮数
+    pub fn total_rounds(&self) -> u32 {
+        match self.format {
+            TournamentFormat::Swiss { rounds } => rounds,
+            TournamentFormat::RoundRobin => {
+                let n = self.entrants.len() as u32;
+                if n < 2 {
+                    0
+                } else if n.is_multiple_of(2) {
+                    n - 1
+                } else {
+                    n
+                }
+            }
+        }
+    }
+
+    /// 账号的报名序号
+    pub fn entrant_index(&self, account_id: AccountId) -> Option<usize> {
+        self.entrants.iter().position(|e| e.account_id == account_id)
+    }
+
+    /// 报名
+    pub fn join(&mut self, entrant: Entrant) -> Result<(), TournamentError> {
+        if self.status != TournamentStatus::Registering {
+            return Err(TournamentError::RegistrationClosed);
+        }
+        if self.entrant_index(entrant.account_id).is_some() {
+            return Err(TournamentError::AlreadyJoined);
+        }
+        self.entrants.push(entrant);
+        Ok(())
+    }
+
+    /// 退出报名
+    pub fn leave(&mut self, account_id: AccountId) -> Result<(), TournamentError> {
+        if self.status != TournamentStatus::Registering {
+            return Err(TournamentError::RegistrationClosed);
+        }
+        let index = self.entrant_index(account_id).ok_or(TournamentError::NotJoined)?;
+        self.entrants.remove(index);
+        Ok(())
+    }
+
+    /// 开赛并排出第一轮，`now` 为 Unix 时间戳（毫秒）
+    pub fn start(&mut self, account_id: AccountId, now: i64) -> Result<(), TournamentError> {
+        if self.status != TournamentStatus::Registering {
+            return Err(TournamentError::RegistrationClosed);
+        }
+        if self.organizer.account_id != account_id {
+            return Err(TournamentError::NotOrganizer);
+        }
+        if self.entrants.len() < MIN_TOURNAMENT_PLAYERS {
+            return Err(TournamentError::NotEnoughPlayers);
+        }
+
+        self.status = TournamentStatus::Running;
+        if self.format == TournamentFormat::RoundRobin {
+            self.rounds = round_robin(self.entrants.len());
+        }
+        self.begin_round(now);
+        Ok(())
+    }
+
+    /// 进入下一轮（瑞士制此时配对）
+    fn begin_round(&mut self, now: i64) {
+        self.current_round += 1;
+        self.round_started_at = now;
+        if matches!(self.format, TournamentFormat::Swiss { .. }) {
+            let round = self.pair_swiss();
+            self.rounds.push(round);
+        }
+    }
+
+    /// 当前轮的对局
+    pub fn current_games_mut(&mut self) -> &mut [TournamentGame] {
+        match self.current_round.checked_sub(1) {
+            Some(i) if self.status == TournamentStatus::Running => &mut self.rounds[i as usize],
+            _ => &mut [],
+        }
+    }
+
+    /// 记录在房间中下完的对局结果，返回是否为本比赛的对局
+    pub fn record_result(&mut self, room_id: RoomId, result: TournamentGameResult) -> bool {
+        let Some(game) = self
+            .current_games_mut()
+            .iter_mut()
+            .find(|g| g.room_id == Some(room_id) && g.result.is_none())
+        else {
+            return false;
+        };
+        game.room_id = None;
+        game.result = Some(result);
+        true
+    }
+
+    /// 把从停服快照恢复的房间关联回本轮对应的对局，返回是否找到
+    pub fn relink_room(&mut self, room_id: RoomId, red: AccountId, black: AccountId) -> bool {
+        let index_of = |account_id: AccountId| self.entrants.iter().position(|e| e.account_id == account_id);
+        let (Some(red), Some(black)) = (index_of(red), index_of(black)) else {
+            return false;
+        };
+        let Some(game) = self
+            .current_games_mut()
+            .iter_mut()
+            .find(|g| g.red == red && g.black == Some(black) && g.result.is_none())
+        else {
+            return false;
+        };
+        game.room_id = Some(room_id);
+        true
+    }
+
+    /// 当前轮全部出结果后进入下一轮或结束比赛，返回是否有变化
+    pub fn advance(&mut self, now: i64) -> bool {
+        if self.status != TournamentStatus::Running
+            || self.current_games_mut().iter().any(|g| g.result.is_none())
+        {
+            return false;
+        }
+        if self.current_round >= self.total_rounds() {
+            self.status = TournamentStatus::Finished;
+        } else {
+            self.begin_round(now);
+        }
+        true
+    }
+
+    /// 瑞士制配对：按积分排序，轮空给排名最低且未轮空过的选手，
+    /// 其余选手依次与排名最接近的未交手对手配对（无法避开时允许重赛）
+    fn pair_swiss(&self) -> Vec<TournamentGame> {
+        let records = self.records();
+        let mut order: Vec<usize> = (0..self.entrants.len()).collect();
+        // 稳定排序：同分按报名顺序
+        order.sort_by(|&a, &b| records[b].score.total_cmp(&records[a].score));
+
+        let mut games = Vec::new();
+        if order.len() % 2 == 1 {
+            let had_bye = |i: usize| self.games().any(|g| g.red == i && g.black.is_none());
+            let pos = order.iter().rposition(|&i| !had_bye(i)).unwrap_or(order.len() - 1);
+            games.push(TournamentGame::new(order.remove(pos), None));
+        }
+
+        let met = |a: usize, b: usize| self.have_met(a, b);
+        let pairs = pair_without_rematch(&order, &met).unwrap_or_else(|| {
+            // 无法完全避开重赛时按排名相邻配对
+            order.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+        });
+
+        let reds = |i: usize| self.games().filter(|g| g.red == i && g.black.is_some()).count();
+        for (a, b) in pairs {
+            // 执红少的一方执红，相同则排名高的执红
+            let (red, black) = if reds(b) < reds(a) { (b, a) } else { (a, b) };
+            games.push(TournamentGame::new(red, Some(black)));
+        }
+        // 轮空放在最后展示
+        games.rotate_left(usize::from(self.entrants.len() % 2 == 1));
+        games
+    }
+
+    /// 已排出的所有对局
+    fn games(&self) -> impl Iterator<Item = &TournamentGame> {
+        self.rounds.iter().flatten()
+    }
+
+    /// 两名选手是否已经交手（含已排定的对局）
+    fn have_met(&self, a: usize, b: usize) -> bool {
+        self.games()
+            .any(|g| (g.red == a && g.black == Some(b)) || (g.red == b && g.black == Some(a)))
+    }
+
+    /// 各选手的战绩（只计已出结果的对局）
+    fn records(&self) -> Vec<Record> {
+        let mut records = vec![Record::default(); self.entrants.len()];
+        for game in self.games() {
+            let Some((red_points, black_points)) = game.points() else {
+                continue;
+            };
+            let mut tally = |i: usize, points: f32| {
+                let record = &mut records[i];
+                record.score += points;
+                match points {
+                    p if p >= 1.0 => record.wins += 1,
+                    p if p > 0.0 => record.draws += 1,
+                    _ => record.losses += 1,
+                }
+            };
+            tally(game.red, red_points);
+            if let Some(black) = game.black {
+                tally(black, black_points);
+            }
+        }
+        records
+    }
+
+    /// 排名（积分、Buchholz、Sonneborn-Berger 依次比较）
+    pub fn standings(&self) -> Vec<TournamentStanding> {
+        let records = self.records();
+        let mut buchholz = vec![0.0f32; self.entrants.len()];
+        let mut sonneborn_berger = vec![0.0f32; self.entrants.len()];
+        for game in self.games() {
+            let (Some(black), Some((red_points, black_points))) = (game.black, game.points()) else {
+                continue;
+            };
+            for (me, opponent, points) in [(game.red, black, red_points), (black, game.red, black_points)] {
+                let opponent_score = records[opponent].score;
+                buchholz[me] += opponent_score;
+                sonneborn_berger[me] += opponent_score * points;
+            }
+        }
+
+        let key = |i: usize| (records[i].score, buchholz[i], sonneborn_berger[i]);
+        let mut order: Vec<usize> = (0..self.entrants.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (ka, kb) = (key(a), key(b));
+            kb.0.total_cmp(&ka.0)
+                .then(kb.1.total_cmp(&ka.1))
+                .then(kb.2.total_cmp(&ka.2))
+                .then_with(|| self.entrants[a].nickname.cmp(&self.entrants[b].nickname))
+        });
+
+        let mut standings: Vec<TournamentStanding> = Vec::with_capacity(order.len());
+        for (pos, &i) in order.iter().enumerate() {
+            let rank = match pos.checked_sub(1) {
+                Some(prev) if key(order[prev]) == key(i) => standings[prev].rank,
+                _ => pos as u32 + 1,
+            };
+            let record = records[i];
+            standings.push(TournamentStanding {
+                rank,
+                nickname: self.entrants[i].nickname.clone(),
+                score: record.score,
+                wins: record.wins,
+                draws: record.draws,
+                losses: record.losses,
+                buchholz: buchholz[i],
+                sonneborn_berger: sonneborn_berger[i],
+            });
+        }
+        standings
+    }
+
+    /// 比赛概况
+    pub fn info(&self) -> TournamentInfo {
+        TournamentInfo {
+            id: self.id,
+            name: self.name.clone(),
+            organizer: self.organizer.nickname.clone(),
+            format: self.format,
+            time_control: self.time_control,
+            rules: self.rules,
+            status: self.status,
+            players: self.entrants.len() as u32,
+            current_round: self.current_round,
+            total_rounds: self.total_rounds(),
+        }
+    }
+
+    /// 比赛详情消息
+    pub fn details(&self) -> ServerMessage {
+        let nickname = |i: usize| self.entrants[i].nickname.clone();
+        let pairings = self
+            .rounds
+            .iter()
+            .enumerate()
+            .flat_map(|(round, games)| {
+                games.iter().map(move |game| TournamentPairing {
+                    round: round as u32 + 1,
+                    red: nickname(game.red),
+                    black: game.black.map(nickname),
+                    room_id: game.room_id,
+                    result: game.result,
+                })
+            })
+            .collect();
+        ServerMessage::TournamentDetails {
+            tournament: self.info(),
+            standings: self.standings(),
+            pairings,
+        }
+    }
+}
+
+/// 按排名顺序回溯配对：每名选手与排名最接近的未交手对手配对，找不到可行配对时返回 None
+fn pair_without_rematch(order: &[usize], met: &impl Fn(usize, usize) -> bool) -> Option<Vec<(usize, usize)>> {
+    let Some((&first, rest)) = order.split_first() else {
+        return Some(Vec::new());
+    };
+    for (j, &opponent) in rest.iter().enumerate() {
+        if met(first, opponent) {
+            continue;
+        }
+        let mut remaining = rest.to_vec();
+        remaining.remove(j);
+        if let Some(mut pairs) = pair_without_rematch(&remaining, met) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+/// 单循环赛程（轮转法）：选手数为奇数时补一个轮空位
+fn round_robin(players: usize) -> Vec<Vec<TournamentGame>> {
+    let mut slots: Vec<Option<usize>> = (0..players).map(Some).collect();
+    if players % 2 == 1 {
+        slots.push(None);
+    }
+    let n = slots.len();
+
+    let mut rounds = Vec::with_capacity(n - 1);
+    for round in 0..n - 1 {
+        let mut games = Vec::with_capacity(n / 2);
+        for i in 0..n / 2 {
+            let (mut a, mut b) = (slots[i], slots[n - 1 - i]);
+            // 固定位置的选手隔轮换色
+            if i == 0 && round % 2 == 1 {
+                std::mem::swap(&mut a, &mut b);
+            }
+            match (a, b) {
+                (Some(red), black) => games.push(TournamentGame::new(red, black)),
+                (None, Some(player)) => games.push(TournamentGame::new(player, None)),
+                (None, None) => {}
+            }
+        }
+        // 轮空放在最后展示
+        games.sort_by_key(|g| g.black.is_none());
+        rounds.push(games);
+        // 除第一个位置外顺时针轮转
+        slots[1..].rotate_right(1);
+    }
+    rounds
+}
+
+/// 比赛目录
+#[derive(Default)]
+pub struct TournamentManager {
+    tournaments: BTreeMap<TournamentId, Tournament>,
+}
+
+impl TournamentManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从账号库加载比赛，进行中比赛的当前轮从 `now` 重新计时
+    pub fn load(accounts: &AccountStore, now: i64) -> Result<Self, AccountError> {
+        let mut tournaments = BTreeMap::new();
+        for mut tournament in accounts.load_tournaments()? {
+            if tournament.status == TournamentStatus::Running {
+                tournament.round_started_at = now;
+            }
+            tournaments.insert(tournament.id, tournament);
+        }
+        Ok(Self { tournaments })
+    }
+
+    /// 新比赛的 ID
+    pub fn next_id(&self) -> TournamentId {
+        self.tournaments.keys().next_back().map_or(1, |id| id + 1)
+    }
+
+    pub fn insert(&mut self, tournament: Tournament) {
+        self.tournaments.insert(tournament.id, tournament);
+    }
+
+    pub fn get(&self, id: TournamentId) -> Option<&Tournament> {
+        self.tournaments.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: TournamentId) -> Option<&mut Tournament> {
+        self.tournaments.get_mut(&id)
+    }
+
+    /// 进行中的比赛
+    pub fn running_mut(&mut self) -> impl Iterator<Item = &mut Tournament> {
+        self.tournaments
+            .values_mut()
+            .filter(|t| t.status == TournamentStatus::Running)
+    }
+
+    /// 比赛列表（新创建的在前）
+    pub fn list(&self) -> Vec<TournamentInfo> {
+        self.tournaments.values().rev().map(Tournament::info).collect()
+    }
+}
+
+impl AccountStore {
+    /// 保存比赛（已存在则覆盖）
+    pub fn save_tournament(&self, tournament: &Tournament) -> Result<(), AccountError> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO tournaments (id, data) VALUES (?1, ?2)",
+            params![tournament.id as i64, serde_json::to_string(tournament)?],
+        )?;
+        Ok(())
+    }
+
+    /// 加载所有比赛
+    pub fn load_tournaments(&self) -> Result<Vec<Tournament>, AccountError> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare("SELECT data FROM tournaments ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut tournaments = Vec::new();
+        for data in rows {
+            tournaments.push(serde_json::from_str(&data?)?);
+        }
+        Ok(tournaments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tournament(format: TournamentFormat, players: usize) -> Tournament {
+        let entrant = |i: usize| Entrant {
+            account_id: i as AccountId + 1,
+            nickname: format!("棋手{}", i + 1),
+        };
+        let mut t = Tournament::new(1, "周赛".to_string(), entrant(0), format, TimeControl::default(), RuleSet::tournament());
+        for i in 0..players {
+            t.join(entrant(i)).unwrap();
+        }
+        t
+    }
+
+    /// 当前轮所有对局按 `result` 判定结果
+    fn finish_round(t: &mut Tournament, result: impl Fn(&TournamentGame) -> TournamentGameResult) {
+        for game in t.current_games_mut() {
+            if game.result.is_none() {
+                game.result = Some(result(game));
+            }
+        }
+        assert!(t.advance(0));
+    }
+
+    #[test]
+    fn test_round_robin_schedule() {
+        for players in [2, 5, 6] {
+            let mut t = tournament(TournamentFormat::RoundRobin, players);
+            t.start(1, 0).unwrap();
+            assert_eq!(t.rounds.len() as u32, t.total_rounds());
+
+            // 每两名选手恰好相遇一次，奇数人时每人轮空一次
+            for a in 0..players {
+                for b in a + 1..players {
+                    let met = t.games().filter(|g| {
+                        (g.red == a && g.black == Some(b)) || (g.red == b && g.black == Some(a))
+                    });
+                    assert_eq!(met.count(), 1, "{} 人赛 {} 与 {}", players, a, b);
+                }
+                let byes = t.games().filter(|g| g.red == a && g.black.is_none()).count();
+                assert_eq!(byes, players % 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_registration_rules() {
+        let mut t = tournament(TournamentFormat::RoundRobin, 1);
+        assert_eq!(t.start(2, 0), Err(TournamentError::NotOrganizer));
+        assert_eq!(t.start(1, 0), Err(TournamentError::NotEnoughPlayers));
+        let again = t.entrants[0].clone();
+        assert_eq!(t.join(again), Err(TournamentError::AlreadyJoined));
+
+        t.join(Entrant { account_id: 9, nickname: "后来者".to_string() }).unwrap();
+        t.start(1, 0).unwrap();
+        assert_eq!(t.leave(9), Err(TournamentError::RegistrationClosed));
+    }
+
+    #[test]
+    fn test_swiss_pairs_by_score_without_rematch() {
+        let mut t = tournament(TournamentFormat::Swiss { rounds: 3 }, 5);
+        t.start(1, 0).unwrap();
+
+        let mut byes = Vec::new();
+        for _ in 0..3 {
+            let games = t.current_games_mut();
+            assert_eq!(games.len(), 3);
+            byes.push(games.iter().find(|g| g.black.is_none()).unwrap().red);
+            // 红方全胜
+            finish_round(&mut t, |_| TournamentGameResult::RedWin);
+        }
+        assert_eq!(t.status, TournamentStatus::Finished);
+
+        // 没有人轮空两次，也没有重赛
+        byes.sort();
+        byes.dedup();
+        assert_eq!(byes.len(), 3);
+        for a in 0..5 {
+            for b in a + 1..5 {
+                let met = t.games().filter(|g| {
+                    (g.red == a && g.black == Some(b)) || (g.red == b && g.black == Some(a))
+                });
+                assert!(met.count() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_standings_tiebreaks() {
+        // 单循环 4 人：1 全胜；2、3、4 互相循环各胜一局
+        let mut t = tournament(TournamentFormat::RoundRobin, 4);
+        t.start(1, 0).unwrap();
+        let beats = |winner: usize, loser: usize| (winner, loser);
+        let outcomes = [beats(0, 1), beats(0, 2), beats(0, 3), beats(1, 2), beats(2, 3), beats(3, 1)];
+        while t.status == TournamentStatus::Running {
+            finish_round(&mut t, |g| {
+                let black = g.black.unwrap();
+                if outcomes.contains(&(g.red, black)) {
+                    TournamentGameResult::RedWin
+                } else {
+                    TournamentGameResult::BlackWin
+                }
+            });
+        }
+
+        let standings = t.standings();
+        assert_eq!(standings[0].nickname, "棋手1");
+        assert_eq!(standings[0].score, 3.0);
+        assert_eq!(standings[0].wins, 3);
+        // 其余三人同分同小分，名次并列
+        for row in &standings[1..] {
+            assert_eq!(row.score, 1.0);
+            assert_eq!(row.buchholz, 3.0 + 1.0 + 1.0);
+            assert_eq!(row.sonneborn_berger, 1.0);
+            assert_eq!(row.rank, 2);
+        }
+
+        // 和棋计半分，Sonneborn-Berger 计对手积分之半
+        let mut t = tournament(TournamentFormat::Swiss { rounds: 1 }, 2);
+        t.start(1, 0).unwrap();
+        finish_round(&mut t, |_| TournamentGameResult::Draw);
+        let standings = t.standings();
+        assert_eq!(standings[0].score, 0.5);
+        assert_eq!(standings[0].sonneborn_berger, 0.25);
+        assert_eq!(standings[1].rank, 1);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let accounts = AccountStore::open_in_memory().unwrap();
+        let mut t = tournament(TournamentFormat::Swiss { rounds: 2 }, 4);
+        t.start(1, 0).unwrap();
+        t.current_games_mut()[0].room_id = Some(42);
+        accounts.save_tournament(&t).unwrap();
+
+        let manager = TournamentManager::load(&accounts, 1000).unwrap();
+        let loaded = manager.get(1).unwrap();
+        assert_eq!(loaded.entrants, t.entrants);
+        assert_eq!(loaded.current_round, 1);
+        assert_eq!(loaded.round_started_at, 1000);
+        // 房间不随重启保留
+        assert!(loaded.rounds[0].iter().all(|g| g.room_id.is_none()));
+        assert_eq!(manager.next_id(), 2);
+    }
+}
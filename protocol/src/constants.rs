@@ -14,6 +14,36 @@ pub const BOARD_HEIGHT: usize = 10;
 /// 昵称最大长度
 pub const MAX_NICKNAME_LEN: usize = 20;
 
+/// 聊天消息最大长度（字符数）
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// 比赛名称最大长度（字符数）
+pub const MAX_TOURNAMENT_NAME_LEN: usize = 40;
+
+/// 私人房间邀请码长度
+pub const INVITE_CODE_LEN: usize = 6;
+
+/// 私人房间密码最大长度（字符数）
+pub const MAX_ROOM_PASSWORD_LEN: usize = 32;
+
+/// 好友数上限
+pub const MAX_FRIENDS: usize = 200;
+
+/// 挑战的有效时间（秒），过期未回应自动撤回
+pub const CHALLENGE_TIMEOUT_SECS: u64 = 60;
+
+/// 通信对局每步最多天数
+pub const MAX_CORRESPONDENCE_DAYS: u32 = 14;
+
+/// 每个账号同时进行（含待接受邀请）的通信对局上限
+pub const MAX_CORRESPONDENCE_GAMES: usize = 50;
+
+/// 瑞士制比赛轮数上限
+pub const MAX_SWISS_ROUNDS: u32 = 15;
+
+/// 历史对局每页最多条数
+pub const MAX_HISTORY_PAGE_SIZE: u32 = 50;
+
 /// 消息帧最大大小
 pub const MAX_FRAME_SIZE: usize = 65536;
 
@@ -35,6 +65,21 @@ pub const RECONNECT_TIMEOUT_SECS: u64 = 60;
 /// 每方初始时间（毫秒）- 10分钟
 pub const INITIAL_TIME_MS: u64 = 10 * 60 * 1000;
 
+/// 每方基本用时上限（毫秒）- 3小时
+pub const MAX_MAIN_TIME_MS: u64 = 3 * 60 * 60 * 1000;
+
+/// 每步加秒上限（毫秒）
+pub const MAX_INCREMENT_MS: u64 = 60 * 1000;
+
+/// 读秒次数上限
+pub const MAX_BYOYOMI_PERIODS: u32 = 10;
+
+/// 每次读秒时长上限（毫秒）
+pub const MAX_BYOYOMI_MS: u64 = 5 * 60 * 1000;
+
+/// 每步限时的最小值（毫秒）
+pub const MIN_MOVE_LIMIT_MS: u64 = 5 * 1000;
+
 /// AI 玩家 ID（使用最大值避免与真实玩家 ID 冲突）
 pub const AI_PLAYER_ID: u64 = u64::MAX;
 
@@ -87,5 +87,24 @@ pub enum ProtocolError {
     Chess(#[from] ChessError),
 }
 
+impl ProtocolError {
+    /// 错误类型的简短名称（用于日志和指标）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            Self::Bincode(_) => "bincode",
+            Self::Json(_) => "json",
+            Self::VersionMismatch { .. } => "version_mismatch",
+            Self::FrameTooLarge { .. } => "frame_too_large",
+            Self::ConnectionTimeout => "timeout",
+            Self::ConnectionClosed => "closed",
+            Self::NicknameEmpty => "nickname_empty",
+            Self::NicknameTooLong { .. } => "nickname_too_long",
+            Self::NicknameOccupied => "nickname_occupied",
+            Self::Chess(_) => "chess",
+        }
+    }
+}
+
 /// 协议操作结果类型
 pub type Result<T> = std::result::Result<T, ProtocolError>;
@@ -24,8 +24,12 @@ pub use constants::*;
 pub use error::{ChessError, ProtocolError, Result};
 pub use fen::{Fen, INITIAL_FEN};
 pub use message::{
-    ClientMessage, ServerMessage, ErrorCode, RoomInfo, RoomType, RoomState,
-    GameResult, WinReason, DrawReason, Difficulty, PlayerId, RoomId,
+    ClientMessage, ServerMessage, SequencedEvent, ErrorCode, RoomInfo, RoomType, RoomState,
+    GameResult, WinReason, DrawReason, Difficulty, PlayerId, RoomId, RatingInfo, RatingChange,
+    TimeControl, ByoyomiState, ChatChannel, ChatMessage, GameOutcome, GameHistoryFilter,
+    GameSummary, RuleSet, RoomAccess, TournamentId, TournamentFormat, TournamentStatus, TournamentInfo,
+    TournamentStanding, TournamentGameResult, TournamentPairing, ChallengeId, ChallengeInfo, Presence,
+    FriendInfo, CorrespondenceId, CorrespondenceGameInfo,
 };
 pub use moves::{Move, MoveGenerator};
 pub use notation::Notation;
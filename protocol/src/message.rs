@@ -3,7 +3,12 @@
 use serde::{Deserialize, Serialize};
 
 use crate::board::BoardState;
+use crate::constants::{
+    INITIAL_TIME_MS, MAX_BYOYOMI_MS, MAX_BYOYOMI_PERIODS, MAX_INCREMENT_MS, MAX_MAIN_TIME_MS,
+    MIN_MOVE_LIMIT_MS,
+};
 use crate::piece::{Position, Side};
+use crate::record::MoveRecord;
 
 /// 玩家 ID
 pub type PlayerId = u64;
@@ -68,6 +73,159 @@ impl Difficulty {
     }
 }
 
+/// 时间控制
+///
+/// 基本用时耗尽后进入读秒：每步须在一次读秒时间内走完，超过则消耗一次读秒，
+/// 读秒次数用完判负。加秒只在基本用时内生效
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimeControl {
+    /// 每方基本用时（毫秒）
+    pub main_time_ms: u64,
+    /// 每步加秒（毫秒，Fischer 计时）
+    pub increment_ms: u64,
+    /// 读秒次数
+    pub byoyomi_periods: u32,
+    /// 每次读秒时长（毫秒）
+    pub byoyomi_ms: u64,
+    /// 每步用时上限（毫秒），超过判负
+    pub move_limit_ms: Option<u64>,
+}
+
+impl TimeControl {
+    /// 每方若干分钟
+    pub fn minutes(minutes: u64) -> Self {
+        Self {
+            main_time_ms: minutes * 60 * 1000,
+            increment_ms: 0,
+            byoyomi_periods: 0,
+            byoyomi_ms: 0,
+            move_limit_ms: None,
+        }
+    }
+
+    /// 设置每步加秒
+    pub fn with_increment(mut self, seconds: u64) -> Self {
+        self.increment_ms = seconds * 1000;
+        self
+    }
+
+    /// 设置读秒（次数 × 秒数）
+    pub fn with_byoyomi(mut self, periods: u32, seconds: u64) -> Self {
+        self.byoyomi_periods = periods;
+        self.byoyomi_ms = seconds * 1000;
+        self
+    }
+
+    /// 设置每步用时上限
+    pub fn with_move_limit(mut self, seconds: u64) -> Self {
+        self.move_limit_ms = Some(seconds * 1000);
+        self
+    }
+
+    /// 是否有读秒
+    pub fn has_byoyomi(&self) -> bool {
+        self.byoyomi_periods > 0 && self.byoyomi_ms > 0
+    }
+
+    /// 校验参数是否在允许范围内
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.main_time_ms == 0 && !self.has_byoyomi() {
+            return Err("基本用时和读秒不能同时为零");
+        }
+        if self.main_time_ms > MAX_MAIN_TIME_MS {
+            return Err("基本用时过长");
+        }
+        if self.increment_ms > MAX_INCREMENT_MS {
+            return Err("加秒过长");
+        }
+        if self.byoyomi_periods > MAX_BYOYOMI_PERIODS || self.byoyomi_ms > MAX_BYOYOMI_MS {
+            return Err("读秒设置超出范围");
+        }
+        if self.move_limit_ms.is_some_and(|ms| ms < MIN_MOVE_LIMIT_MS) {
+            return Err("每步限时过短");
+        }
+        Ok(())
+    }
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            main_time_ms: INITIAL_TIME_MS,
+            increment_ms: 0,
+            byoyomi_periods: 0,
+            byoyomi_ms: 0,
+            move_limit_ms: None,
+        }
+    }
+}
+
+/// 棋谱中的记法：`分钟+加秒`，有读秒时追加 `次数x秒数`，有每步限时追加 `max秒数`
+///
+/// 例如 `10+0`、`15+10`、`5+0 3x30`、`10+0 max60`
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let minutes = self.main_time_ms as f64 / 60_000.0;
+        write!(f, "{}+{}", minutes, self.increment_ms / 1000)?;
+        if self.has_byoyomi() {
+            write!(f, " {}x{}", self.byoyomi_periods, self.byoyomi_ms / 1000)?;
+        }
+        if let Some(limit) = self.move_limit_ms {
+            write!(f, " max{}", limit / 1000)?;
+        }
+        Ok(())
+    }
+}
+
+/// 对局规则（对局中哪些操作被允许）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSet {
+    /// 是否允许请求悔棋
+    pub allow_undo: bool,
+    /// 是否允许提和
+    pub allow_draw_offers: bool,
+}
+
+impl RuleSet {
+    /// 正式比赛规则：不允许悔棋，允许提和
+    pub fn tournament() -> Self {
+        Self {
+            allow_undo: false,
+            allow_draw_offers: true,
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            allow_undo: true,
+            allow_draw_offers: true,
+        }
+    }
+}
+
+/// 房间的进入方式
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomAccess {
+    /// 公开房间，出现在大厅列表中
+    #[default]
+    Public,
+    /// 私人房间，凭服务器生成的邀请码加入
+    Invite,
+    /// 私人房间，凭房主设置的密码加入
+    Password(String),
+}
+
+/// 读秒状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByoyomiState {
+    /// 剩余读秒次数（含当前这一次）
+    pub periods_left: u32,
+    /// 是否已进入读秒
+    pub in_byoyomi: bool,
+}
+
 /// 游戏结果
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameResult {
@@ -105,6 +263,93 @@ pub enum DrawReason {
     FiftyMoves,
 }
 
+/// 等级分（展示用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatingInfo {
+    pub rating: i32,
+    /// 评级偏差（RD）
+    pub deviation: i32,
+    /// 是否仍在定级中
+    pub provisional: bool,
+}
+
+/// 一局计分对局后的等级分变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatingChange {
+    pub side: Side,
+    pub before: RatingInfo,
+    pub after: RatingInfo,
+}
+
+/// 对局结果（从某一方的角度）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameOutcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl GameOutcome {
+    /// 对局结果对 `side` 而言的胜负
+    pub fn of(result: &GameResult, side: Side) -> Self {
+        match (result, side) {
+            (GameResult::Draw(_), _) => GameOutcome::Draw,
+            (GameResult::RedWin(_), Side::Red) | (GameResult::BlackWin(_), Side::Black) => GameOutcome::Win,
+            _ => GameOutcome::Loss,
+        }
+    }
+}
+
+/// 历史对局筛选条件（字段为 None 表示不限）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameHistoryFilter {
+    pub outcome: Option<GameOutcome>,
+    pub rated: Option<bool>,
+    /// 对手昵称（精确匹配）
+    pub opponent: Option<String>,
+}
+
+/// 历史对局摘要
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub game_id: u64,
+    pub red_player: String,
+    pub black_player: String,
+    /// 查询者在该局中的执子方
+    pub your_side: Side,
+    pub result: GameResult,
+    pub rated: bool,
+    /// 时间控制（如 "15+10"）
+    pub time_control: String,
+    pub move_count: u32,
+    /// 结束时的 Unix 时间戳（毫秒）
+    pub finished_at: u64,
+}
+
+/// 聊天频道
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatChannel {
+    /// 大厅（所有不在房间中的玩家）
+    Lobby,
+    /// 房间（对局双方发言，观战者可见）
+    Room,
+    /// 观战区（仅观战者可见，对局双方也可向观战者发言）
+    Spectators,
+}
+
+/// 聊天消息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    pub from_id: PlayerId,
+    /// 发送者昵称
+    pub from: String,
+    /// 消息内容（已经过敏感词过滤）
+    pub text: String,
+    /// 发送时的 Unix 时间戳（毫秒）
+    pub sent_at: u64,
+}
+
 /// 房间信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomInfo {
@@ -113,6 +358,26 @@ pub struct RoomInfo {
     pub red_player: Option<String>,
     pub black_player: Option<String>,
     pub state: RoomState,
+    /// 是否为计分对局
+    pub rated: bool,
+    /// 红方等级分（游客为 None）
+    pub red_rating: Option<RatingInfo>,
+    /// 黑方等级分（游客为 None）
+    pub black_rating: Option<RatingInfo>,
+    /// 红方是否为机器人
+    pub red_bot: bool,
+    /// 黑方是否为机器人
+    pub black_bot: bool,
+    /// 观战人数
+    pub spectators: u32,
+    /// 时间控制
+    pub time_control: TimeControl,
+    /// 对局规则
+    pub rules: RuleSet,
+    /// 是否为私人房间（不在大厅列表中展示）
+    pub private: bool,
+    /// 自定义初始局面（None 表示标准开局）
+    pub custom_fen: Option<String>,
 }
 
 /// 房间状态
@@ -128,28 +393,296 @@ pub enum RoomState {
     Finished,
 }
 
+/// 挑战 ID
+pub type ChallengeId = u64;
+
+/// 好友的在线状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Presence {
+    /// 离线
+    Offline,
+    /// 在大厅
+    Online,
+    /// 在房间中（对局或观战）
+    InRoom(RoomId),
+    /// 对局中断线，等待重连
+    Disconnected,
+}
+
+/// 好友
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FriendInfo {
+    pub nickname: String,
+    pub presence: Presence,
+}
+
+/// 挑战（待对方回应）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeInfo {
+    pub id: ChallengeId,
+    /// 发起方昵称
+    pub challenger: String,
+    /// 被挑战方昵称
+    pub challenged: String,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    pub rated: bool,
+    /// 剩余有效时间（秒），过期自动撤回
+    pub expires_in_secs: u32,
+}
+
+/// 通信对局 ID
+pub type CorrespondenceId = u64;
+
+/// 通信对局（每步限若干天，查询者视角）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorrespondenceGameInfo {
+    pub id: CorrespondenceId,
+    pub red_player: String,
+    pub black_player: String,
+    pub your_side: Side,
+    pub rated: bool,
+    /// 每步天数
+    pub days_per_move: u32,
+    /// 发出邀请的一方（对方接受前为 Some）
+    pub invited_by: Option<Side>,
+    /// 当前局面
+    pub game_state: BoardState,
+    pub move_count: u32,
+    /// 上一步记谱
+    pub last_move: Option<String>,
+    /// 期限（Unix 时间戳，毫秒）：邀请的有效期，或走子方的走棋期限
+    pub deadline: u64,
+    /// 结果（进行中为 None）
+    pub result: Option<GameResult>,
+}
+
+/// 比赛 ID
+pub type TournamentId = u64;
+
+/// 比赛赛制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TournamentFormat {
+    /// 单循环：每两名选手之间赛一局
+    RoundRobin,
+    /// 瑞士制：每轮按积分配对，共 rounds 轮
+    Swiss { rounds: u32 },
+}
+
+/// 比赛状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TournamentStatus {
+    /// 报名中
+    Registering,
+    /// 进行中
+    Running,
+    /// 已结束
+    Finished,
+}
+
+/// 比赛概况
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TournamentInfo {
+    pub id: TournamentId,
+    pub name: String,
+    /// 组织者昵称
+    pub organizer: String,
+    pub format: TournamentFormat,
+    pub time_control: TimeControl,
+    pub rules: RuleSet,
+    pub status: TournamentStatus,
+    /// 报名人数
+    pub players: u32,
+    /// 当前轮次（从 1 开始，未开赛为 0）
+    pub current_round: u32,
+    /// 总轮数（未开赛时为预计轮数）
+    pub total_rounds: u32,
+}
+
+/// 比赛排名中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TournamentStanding {
+    /// 名次（从 1 开始，积分和小分都相同的选手名次相同）
+    pub rank: u32,
+    pub nickname: String,
+    /// 积分（胜 1，和 0.5，轮空 1）
+    pub score: f32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Buchholz 小分：所有对手积分之和
+    pub buchholz: f32,
+    /// Sonneborn-Berger 小分：所胜对手积分之和加所和对手积分之半
+    pub sonneborn_berger: f32,
+}
+
+/// 一局比赛对局的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TournamentGameResult {
+    RedWin,
+    BlackWin,
+    Draw,
+    /// 红方未到场，黑方胜
+    RedForfeit,
+    /// 黑方未到场，红方胜
+    BlackForfeit,
+    /// 双方均未到场，都记负
+    DoubleForfeit,
+    /// 轮空（记胜）
+    Bye,
+}
+
+impl From<&GameResult> for TournamentGameResult {
+    fn from(result: &GameResult) -> Self {
+        match result {
+            GameResult::RedWin(_) => TournamentGameResult::RedWin,
+            GameResult::BlackWin(_) => TournamentGameResult::BlackWin,
+            GameResult::Draw(_) => TournamentGameResult::Draw,
+        }
+    }
+}
+
+/// 比赛中一轮的一组配对
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TournamentPairing {
+    /// 轮次（从 1 开始）
+    pub round: u32,
+    pub red: String,
+    /// 黑方（轮空时为 None）
+    pub black: Option<String>,
+    /// 对局进行中的房间
+    pub room_id: Option<RoomId>,
+    /// 结果（未赛完为 None）
+    pub result: Option<TournamentGameResult>,
+}
+
 /// 客户端发送给服务端的消息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
     // === 身份认证 ===
-    /// 登录
-    Login { nickname: String },
+    /// 登录（password 为 None 表示游客登录）
+    Login {
+        nickname: String,
+        password: Option<String>,
+    },
+    /// 注册账号（成功后直接登录）
+    Register { nickname: String, password: String },
+    /// 机器人以 API 令牌登录（同一机器人可建立多个连接，各下一局）
+    BotLogin { token: String },
+    /// 修改密码（仅注册账号）
+    ChangePassword {
+        old_password: String,
+        new_password: String,
+    },
+    /// 注销登录（会话令牌随之失效）
+    Logout,
     /// 重连
-    Reconnect { player_id: PlayerId, room_id: RoomId },
+    Reconnect {
+        /// 登录时签发的会话令牌
+        session_token: String,
+        room_id: RoomId,
+        /// 客户端最后确认的房间事件序列号（None 表示没有本地状态）
+        last_seq: Option<u64>,
+    },
 
     // === 房间操作 ===
     /// 创建房间
     CreateRoom {
         room_type: RoomType,
         preferred_side: Option<Side>,
+        /// 是否计分（仅注册账号的玩家对战）
+        rated: bool,
+        /// 时间控制
+        time_control: TimeControl,
+        /// 对局规则（悔棋、提和）
+        rules: RuleSet,
+        /// 自定义初始局面 FEN（None 表示标准开局，计分对局不可自定义）
+        initial_fen: Option<String>,
+        /// 公开或私人房间
+        access: RoomAccess,
     },
     /// 加入房间
-    JoinRoom { room_id: RoomId },
+    JoinRoom {
+        room_id: RoomId,
+        /// 私人房间的密码或邀请码
+        password: Option<String>,
+    },
+    /// 凭邀请码加入私人房间
+    JoinByInvite { code: String },
     /// 离开房间
     LeaveRoom,
     /// 获取房间列表
     ListRooms,
 
+    // === 匹配 ===
+    /// 进入匹配队列
+    EnterQueue {
+        time_control: TimeControl,
+        /// 是否计分（仅注册账号）
+        rated: bool,
+    },
+    /// 退出匹配队列
+    LeaveQueue,
+
+    // === 挑战 ===
+    /// 向指定玩家发起挑战（机器人自动接受，其他玩家收到 `ChallengeReceived` 后回应）
+    Challenge {
+        nickname: String,
+        time_control: TimeControl,
+        /// 是否计分（仅注册账号）
+        rated: bool,
+        /// 对局规则（悔棋、提和）
+        rules: RuleSet,
+    },
+    /// 回应收到的挑战（接受后双方收到 `MatchFound`）
+    RespondChallenge { challenge_id: ChallengeId, accept: bool },
+    /// 撤回自己发出的挑战
+    CancelChallenge { challenge_id: ChallengeId },
+
+    // === 好友（仅注册账号） ===
+    /// 获取好友列表和待处理的好友请求
+    ListFriends,
+    /// 发送好友请求（对方已向自己发出请求时直接成为好友）
+    AddFriend { nickname: String },
+    /// 回应好友请求
+    RespondFriendRequest { nickname: String, accept: bool },
+    /// 删除好友
+    RemoveFriend { nickname: String },
+
+    // === 通信对局（仅注册账号） ===
+    /// 邀请玩家下通信对局（对方可以不在线，执子方随机）
+    CreateCorrespondence {
+        nickname: String,
+        days_per_move: u32,
+        rated: bool,
+    },
+    /// 回应通信对局邀请（邀请方可借此撤回邀请）
+    RespondCorrespondence { game_id: CorrespondenceId, accept: bool },
+    /// 列出自己进行中和待接受的通信对局
+    ListCorrespondence,
+    /// 获取一局通信对局的完整走法
+    GetCorrespondence { game_id: CorrespondenceId },
+    /// 在通信对局中走一步（不需要在房间中）
+    CorrespondenceMove {
+        game_id: CorrespondenceId,
+        from: Position,
+        to: Position,
+    },
+    /// 在通信对局中认输
+    ResignCorrespondence { game_id: CorrespondenceId },
+
+    // === 观战 ===
+    /// 观战进行中的对局
+    SpectateRoom { room_id: RoomId },
+    /// 停止观战
+    StopSpectating,
+
+    // === 聊天 ===
+    /// 发送聊天消息
+    SendChat { channel: ChatChannel, text: String },
+    /// 屏蔽或取消屏蔽某玩家的聊天消息
+    SetMuted { player_id: PlayerId, muted: bool },
+
     // === 游戏操作 ===
     /// 走棋
     MakeMove { from: Position, to: Position },
@@ -159,6 +692,10 @@ pub enum ClientMessage {
     RespondUndo { accept: bool },
     /// 认输
     Resign,
+    /// 提和
+    OfferDraw,
+    /// 响应对方的提和
+    RespondDraw { accept: bool },
 
     // === 人机专用 ===
     /// 暂停游戏
@@ -171,6 +708,33 @@ pub enum ClientMessage {
     SaveGame,
     /// 加载棋局
     LoadGame { game_id: String },
+    /// 查询自己的历史对局（仅注册账号，按结束时间倒序分页，page 从 0 开始）
+    ListMyGames {
+        page: u32,
+        page_size: u32,
+        filter: GameHistoryFilter,
+    },
+    /// 获取一局历史对局的完整棋谱
+    FetchGame { game_id: u64 },
+
+    // === 比赛 ===
+    /// 创建比赛（仅注册账号，创建者为组织者）
+    CreateTournament {
+        name: String,
+        format: TournamentFormat,
+        time_control: TimeControl,
+        rules: RuleSet,
+    },
+    /// 获取比赛列表
+    ListTournaments,
+    /// 报名参赛（仅注册账号，报名截止于开赛）
+    JoinTournament { tournament_id: TournamentId },
+    /// 退出报名（仅限开赛前）
+    LeaveTournament { tournament_id: TournamentId },
+    /// 开赛（仅组织者）
+    StartTournament { tournament_id: TournamentId },
+    /// 获取比赛详情
+    GetTournament { tournament_id: TournamentId },
 
     // === 心跳 ===
     /// 心跳请求
@@ -182,7 +746,17 @@ pub enum ClientMessage {
 pub enum ServerMessage {
     // === 身份认证 ===
     /// 登录成功
-    LoginSuccess { player_id: PlayerId },
+    LoginSuccess {
+        player_id: PlayerId,
+        /// 是否为游客
+        guest: bool,
+        /// 会话令牌（断线重连时使用，断线超过重连超时后失效）
+        session_token: String,
+    },
+    /// 已注销
+    LoggedOut,
+    /// 密码已修改
+    PasswordChanged,
     /// 重连成功
     ReconnectSuccess {
         room_id: RoomId,
@@ -190,11 +764,30 @@ pub enum ServerMessage {
         your_side: Side,
         red_time_ms: u64,
         black_time_ms: u64,
+        /// 初始局面 FEN
+        initial_fen: String,
+        /// 完整走法历史（含记谱和每步时间数据）
+        moves: Vec<MoveRecord>,
+        /// 快照对应的房间事件序列号
+        last_seq: u64,
+        /// 自客户端 `last_seq` 之后错过的事件
+        missed_events: Vec<SequencedEvent>,
+        /// 错过的事件是否完整（为 false 时客户端应以快照为准）
+        replay_complete: bool,
+        /// 待处理的悔棋请求方
+        undo_requested_by: Option<Side>,
+        /// 最近的房间聊天记录
+        chat_history: Vec<ChatMessage>,
     },
 
     // === 房间事件 ===
     /// 房间创建成功
-    RoomCreated { room_id: RoomId, your_side: Side },
+    RoomCreated {
+        room_id: RoomId,
+        your_side: Side,
+        /// 邀请码（仅邀请制私人房间）
+        invite_code: Option<String>,
+    },
     /// 加入房间成功
     RoomJoined { room_id: RoomId, side: Side },
     /// 房间列表
@@ -202,6 +795,86 @@ pub enum ServerMessage {
     /// 对手加入
     OpponentJoined { nickname: String },
 
+    // === 匹配 ===
+    /// 匹配队列状态（定期发送给等待中的玩家）
+    QueueStatus {
+        /// 队列中的玩家数
+        players_waiting: u32,
+        /// 已等待时间（秒）
+        waited_secs: u32,
+        /// 当前可接受的等级分差
+        rating_window: u32,
+    },
+    /// 已退出匹配队列
+    QueueLeft,
+    /// 匹配或挑战成功（随后收到 GameStarted）
+    MatchFound { room_id: RoomId, your_side: Side },
+
+    // === 挑战 ===
+    /// 挑战已发出，等待对方回应
+    ChallengeSent { challenge: ChallengeInfo },
+    /// 收到挑战
+    ChallengeReceived { challenge: ChallengeInfo },
+    /// 挑战已结束（被拒绝、撤回、过期或对方不再空闲）
+    ChallengeClosed { challenge_id: ChallengeId, reason: String },
+
+    // === 好友 ===
+    /// 好友列表（好友相关请求的响应）
+    FriendList {
+        friends: Vec<FriendInfo>,
+        /// 收到的好友请求（昵称）
+        incoming: Vec<String>,
+        /// 发出的好友请求（昵称）
+        outgoing: Vec<String>,
+    },
+    /// 收到好友请求
+    FriendRequestReceived { nickname: String },
+    /// 好友在线状态变化（新成为好友时也会推送）
+    FriendPresence { nickname: String, presence: Presence },
+    /// 被对方删除好友
+    FriendRemoved { nickname: String },
+
+    // === 通信对局 ===
+    /// 通信对局列表（按期限排列）
+    CorrespondenceGames { games: Vec<CorrespondenceGameInfo> },
+    /// 通信对局详情
+    CorrespondenceDetails {
+        game: CorrespondenceGameInfo,
+        initial_fen: String,
+        moves: Vec<MoveRecord>,
+    },
+    /// 通信对局有变化（新邀请、接受、走棋或结束；在线的双方都会收到）
+    CorrespondenceUpdate {
+        game: CorrespondenceGameInfo,
+        /// 计分对局结束时双方的等级分变化
+        rating_changes: Vec<RatingChange>,
+    },
+    /// 通信对局邀请已结束（被拒绝、撤回或过期）
+    CorrespondenceClosed { game_id: CorrespondenceId, reason: String },
+
+    // === 观战 ===
+    /// 开始观战（对局快照，之后的房间事件照常推送）
+    SpectateStarted {
+        room_id: RoomId,
+        game_state: BoardState,
+        red_player: String,
+        black_player: String,
+        red_time_ms: u64,
+        black_time_ms: u64,
+        /// 初始局面 FEN
+        initial_fen: String,
+        /// 完整走法历史（含记谱和每步时间数据）
+        moves: Vec<MoveRecord>,
+        /// 快照对应的房间事件序列号
+        last_seq: u64,
+        /// 最近的房间聊天记录
+        chat_history: Vec<ChatMessage>,
+    },
+    /// 观战结束（主动退出或房间关闭）
+    SpectateStopped { room_id: RoomId },
+    /// 房间被管理员关闭（进行中的对局作废）
+    RoomClosed { room_id: RoomId, reason: String },
+
     // === 游戏事件 ===
     /// 游戏开始
     GameStarted {
@@ -209,6 +882,10 @@ pub enum ServerMessage {
         your_side: Side,
         red_player: String,
         black_player: String,
+        /// 初始局面 FEN
+        initial_fen: String,
+        /// 已有的走法历史（新开局为空）
+        moves: Vec<MoveRecord>,
     },
     /// 走棋完成
     MoveMade {
@@ -223,16 +900,35 @@ pub enum ServerMessage {
     UndoApproved { new_state: BoardState },
     /// 悔棋被拒绝
     UndoRejected,
+    /// 对方提和（任一方走棋后自动撤回）
+    DrawOffered { by: Side },
+    /// 提和被拒绝
+    DrawDeclined,
     /// 游戏结束
-    GameOver { result: GameResult },
+    GameOver {
+        result: GameResult,
+        /// 等级分变化（非计分对局为空）
+        rating_changes: Vec<RatingChange>,
+    },
     /// 游戏暂停
     GamePaused,
     /// 游戏继续
     GameResumed,
 
+    // === 聊天 ===
+    /// 聊天消息
+    Chat(ChatMessage),
+    /// 屏蔽状态已更新
+    MuteUpdated { player_id: PlayerId, muted: bool },
+
     // === 时间 ===
-    /// 时间更新
-    TimeUpdate { red_time_ms: u64, black_time_ms: u64 },
+    /// 时间更新（读秒中时为当前这次读秒的剩余时间）
+    TimeUpdate {
+        red_time_ms: u64,
+        black_time_ms: u64,
+        red_byoyomi: ByoyomiState,
+        black_byoyomi: ByoyomiState,
+    },
 
     // === 断线重连 ===
     /// 对手断线
@@ -244,21 +940,68 @@ pub enum ServerMessage {
     /// 棋局保存成功
     GameSaved { game_id: String },
     /// 棋局加载成功
-    GameLoaded { 
+    GameLoaded {
         room_id: RoomId,
         game_state: BoardState,
         your_side: Side,
+        /// 初始局面 FEN
+        initial_fen: String,
+        /// 完整走法历史（含记谱和每步时间数据）
+        moves: Vec<MoveRecord>,
+        red_time_ms: u64,
+        black_time_ms: u64,
+    },
+    /// 历史对局列表
+    MyGames {
+        games: Vec<GameSummary>,
+        page: u32,
+        /// 符合筛选条件的总局数
+        total: u32,
+    },
+    /// 历史对局棋谱（GameRecord 的 JSON，可直接存为本地棋谱文件）
+    GameFetched { game_id: u64, record_json: String },
+
+    // === 比赛 ===
+    /// 比赛列表
+    TournamentList { tournaments: Vec<TournamentInfo> },
+    /// 比赛详情（比赛相关请求的响应；每轮开始和比赛结束时推送给参赛者）
+    TournamentDetails {
+        tournament: TournamentInfo,
+        standings: Vec<TournamentStanding>,
+        /// 所有已配对的对局（按轮次排列）
+        pairings: Vec<TournamentPairing>,
     },
 
     // === 心跳 ===
     /// 心跳响应
     Pong,
 
+    // === 系统 ===
+    /// 系统公告
+    Announcement { text: String },
+    /// 被管理员请出服务器（随后连接关闭）
+    Kicked { reason: String },
+    /// 服务器即将重启：进行中的对局已保存，重启后凭会话令牌在 grace_secs 秒内重连
+    ServerRestarting { grace_secs: u32 },
+
+    // === 事件序列 ===
+    /// 带序列号的房间事件
+    Sequenced(Box<SequencedEvent>),
+
     // === 错误 ===
     /// 错误消息
     Error { code: ErrorCode, message: String },
 }
 
+/// 带序列号的房间事件
+///
+/// 序列号在房间内递增，客户端重连时据此请求补发错过的事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub message: ServerMessage,
+}
+
 /// 错误码定义
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u16)]
@@ -274,6 +1017,12 @@ pub enum ErrorCode {
     NotInRoom = 103,
     /// 已在房间中
     AlreadyInRoom = 104,
+    /// 观战者不能操作棋局
+    SpectatorReadOnly = 105,
+    /// 私人房间的密码或邀请码错误
+    RoomAccessDenied = 106,
+    /// 房间设置无效（初始局面、密码等）
+    InvalidRoomSettings = 107,
 
     // === 游戏相关 (2xx) ===
     /// 不是你的回合
@@ -286,6 +1035,14 @@ pub enum ErrorCode {
     GameAlreadyOver = 203,
     /// 不允许悔棋
     UndoNotAllowed = 204,
+    /// 时间控制参数无效
+    InvalidTimeControl = 205,
+    /// 当前不能提和
+    DrawOfferNotAllowed = 206,
+    /// 历史对局不存在
+    GameNotFound = 207,
+    /// 通信对局数已达上限
+    CorrespondenceLimitReached = 208,
 
     // === 玩家相关 (3xx) ===
     /// 无效昵称
@@ -294,12 +1051,52 @@ pub enum ErrorCode {
     PlayerNotFound = 301,
     /// 昵称已被占用
     NicknameOccupied = 302,
+    /// 昵称或密码错误
+    InvalidCredentials = 303,
+    /// 昵称已被注册，需密码登录
+    NicknameReserved = 304,
+    /// 密码不符合要求
+    InvalidPassword = 305,
+    /// 需要注册账号
+    AccountRequired = 306,
+    /// 已经登录
+    AlreadyLoggedIn = 307,
+    /// 会话令牌无效或已过期
+    InvalidSession = 308,
+    /// 机器人不能进行该操作（如未开启机器人计分时的计分对局）
+    BotNotAllowed = 309,
+    /// 机器人同时进行的对局数已达上限
+    BotBusy = 310,
+    /// 对方不接受挑战
+    ChallengeUnavailable = 311,
+    /// 挑战不存在或已结束
+    ChallengeNotFound = 312,
+    /// 好友请求无效（如已是好友、重复请求、没有待处理的请求）
+    InvalidFriendRequest = 313,
+
+    // === 聊天相关 (4xx) ===
+    /// 消息为空或过长
+    InvalidChatMessage = 400,
+    /// 发送过于频繁
+    RateLimited = 401,
+    /// 无权在该频道发言
+    ChatNotAllowed = 402,
+
+    // === 比赛相关 (6xx) ===
+    /// 比赛不存在
+    TournamentNotFound = 600,
+    /// 比赛已开赛，不能报名或退出
+    TournamentRegistrationClosed = 601,
+    /// 无权操作或条件不满足（如非组织者开赛、人数不足）
+    TournamentNotAllowed = 602,
 
     // === 系统相关 (5xx) ===
     /// 内部错误
     InternalError = 500,
     /// 超时
     Timeout = 501,
+    /// 服务器连接数已满
+    ServerFull = 502,
 }
 
 impl std::fmt::Display for ErrorCode {
@@ -316,24 +1113,54 @@ mod tests {
     fn test_message_serialize() {
         let msg = ClientMessage::Login {
             nickname: "player1".to_string(),
+            password: Some("secret".to_string()),
         };
         let bytes = bincode::serialize(&msg).unwrap();
         let decoded: ClientMessage = bincode::deserialize(&bytes).unwrap();
         
         match decoded {
-            ClientMessage::Login { nickname } => assert_eq!(nickname, "player1"),
+            ClientMessage::Login { nickname, password } => {
+                assert_eq!(nickname, "player1");
+                assert_eq!(password.as_deref(), Some("secret"));
+            }
             _ => panic!("Wrong message type"),
         }
     }
 
     #[test]
     fn test_server_message_serialize() {
-        let msg = ServerMessage::LoginSuccess { player_id: 12345 };
+        let msg = ServerMessage::LoginSuccess {
+            player_id: 12345,
+            guest: false,
+            session_token: "12345.abcd.ef".to_string(),
+        };
         let bytes = bincode::serialize(&msg).unwrap();
         let decoded: ServerMessage = bincode::deserialize(&bytes).unwrap();
         
         match decoded {
-            ServerMessage::LoginSuccess { player_id } => assert_eq!(player_id, 12345),
+            ServerMessage::LoginSuccess { player_id, guest, session_token } => {
+                assert_eq!(player_id, 12345);
+                assert!(!guest);
+                assert_eq!(session_token, "12345.abcd.ef");
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_sequenced_event_serialize() {
+        let msg = ServerMessage::Sequenced(Box::new(SequencedEvent {
+            seq: 42,
+            message: ServerMessage::UndoRequested { by: Side::Black },
+        }));
+        let bytes = bincode::serialize(&msg).unwrap();
+        let decoded: ServerMessage = bincode::deserialize(&bytes).unwrap();
+
+        match decoded {
+            ServerMessage::Sequenced(event) => {
+                assert_eq!(event.seq, 42);
+                assert!(matches!(event.message, ServerMessage::UndoRequested { by: Side::Black }));
+            }
             _ => panic!("Wrong message type"),
         }
     }
@@ -345,4 +1172,27 @@ mod tests {
         let decoded: RoomType = bincode::deserialize(&bytes).unwrap();
         assert_eq!(decoded, room_type);
     }
+
+    #[test]
+    fn test_time_control() {
+        assert_eq!(TimeControl::default().to_string(), "10+0");
+        assert_eq!(TimeControl::minutes(15).with_increment(10).to_string(), "15+10");
+        assert_eq!(TimeControl::minutes(5).with_byoyomi(3, 30).to_string(), "5+0 3x30");
+        assert_eq!(TimeControl::minutes(10).with_move_limit(60).to_string(), "10+0 max60");
+
+        assert!(TimeControl::default().validate().is_ok());
+        assert!(TimeControl::minutes(0).with_byoyomi(5, 30).validate().is_ok());
+        assert!(TimeControl::minutes(0).validate().is_err());
+        assert!(TimeControl::minutes(10).with_move_limit(1).validate().is_err());
+        assert!(TimeControl::minutes(10).with_byoyomi(99, 30).validate().is_err());
+    }
+
+    #[test]
+    fn test_game_outcome() {
+        let red_win = GameResult::RedWin(WinReason::Checkmate);
+        assert_eq!(GameOutcome::of(&red_win, Side::Red), GameOutcome::Win);
+        assert_eq!(GameOutcome::of(&red_win, Side::Black), GameOutcome::Loss);
+        let draw = GameResult::Draw(DrawReason::Agreement);
+        assert_eq!(GameOutcome::of(&draw, Side::Black), GameOutcome::Draw);
+    }
 }
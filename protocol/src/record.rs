@@ -5,7 +5,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::message::GameResult;
+use crate::message::{GameResult, RuleSet};
 use crate::piece::Position;
 
 /// 棋谱版本
@@ -27,6 +27,12 @@ pub struct GameMetadata {
     /// AI 难度（PvE 模式）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ai_difficulty: Option<String>,
+    /// 是否为计分对局（联机对局）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rated: Option<bool>,
+    /// 对局规则（联机对局）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<RuleSet>,
 }
 
 /// 走法记录
@@ -129,6 +135,8 @@ impl GameRecord {
                 result: None,
                 time_control: Some("10+0".to_string()),
                 ai_difficulty: None,
+                rated: None,
+                rules: None,
             },
             initial_fen: crate::fen::INITIAL_FEN.to_string(),
             moves: Vec::new(),
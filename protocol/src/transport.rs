@@ -329,6 +329,7 @@ mod tests {
             // 发送消息
             conn.send(&ClientMessage::Login {
                 nickname: "test".to_string(),
+                password: None,
             })
             .await
             .unwrap();
@@ -336,7 +337,7 @@ mod tests {
             // 接收响应
             let msg: ServerMessage = conn.recv().await.unwrap();
             match msg {
-                ServerMessage::LoginSuccess { player_id } => assert_eq!(player_id, 1),
+                ServerMessage::LoginSuccess { player_id, .. } => assert_eq!(player_id, 1),
                 _ => panic!("Unexpected message"),
             }
         });
@@ -347,12 +348,16 @@ mod tests {
         // 接收消息
         let msg: ClientMessage = conn.recv().await.unwrap();
         match msg {
-            ClientMessage::Login { nickname } => assert_eq!(nickname, "test"),
+            ClientMessage::Login { nickname, .. } => assert_eq!(nickname, "test"),
             _ => panic!("Unexpected message"),
         }
 
         // 发送响应
-        conn.send(&ServerMessage::LoginSuccess { player_id: 1 })
+        conn.send(&ServerMessage::LoginSuccess {
+            player_id: 1,
+            guest: true,
+            session_token: String::new(),
+        })
             .await
             .unwrap();
 